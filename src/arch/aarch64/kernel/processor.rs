@@ -75,6 +75,11 @@ pub fn get_frequency() -> u16 {
 	0
 }
 
+/// Writes the known information of the processor as `name: value` lines.
+pub fn write_information(f: &mut dyn core::fmt::Write) -> core::fmt::Result {
+	writeln!(f, "frequency: {} MHz", get_frequency())
+}
+
 #[inline]
 pub fn get_timestamp() -> u64 {
 	0
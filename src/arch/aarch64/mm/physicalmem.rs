@@ -104,6 +104,11 @@ pub fn deallocate(physical_address: PhysAddr, size: usize) {
 		.deallocate(physical_address.as_usize(), size);
 }
 
+/// Returns the number of free bytes of physical memory.
+pub fn free_memory_size() -> usize {
+	PHYSICAL_FREE_LIST.lock().free_size()
+}
+
 pub fn print_information() {
 	PHYSICAL_FREE_LIST
 		.lock()
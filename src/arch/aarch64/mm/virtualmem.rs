@@ -139,6 +139,11 @@ pub fn deallocate(virtual_address: VirtAddr, size: usize) {
 	);
 }*/
 
+/// Returns the number of free bytes of the virtual address space of the kernel.
+pub fn free_size() -> usize {
+	KERNEL_FREE_LIST.lock().free_size()
+}
+
 pub fn print_information() {
 	KERNEL_FREE_LIST
 		.lock()
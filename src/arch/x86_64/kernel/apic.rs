@@ -307,6 +307,15 @@ pub extern "C" fn eoi() {
 	local_apic_write(IA32_X2APIC_EOI, APIC_EOI_ACK);
 }
 
+/// Returns the vector of the interrupt, which is currently serviced. Handlers installed for several
+/// interrupt lines use it to tell them apart, so it has to be called before `eoi`.
+pub fn in_service_vector() -> Option<u8> {
+	(0..8).rev().find_map(|i| {
+		let isr = local_apic_read(IA32_X2APIC_ISR0 + i);
+		(isr != 0).then(|| (32 * i + 31 - isr.leading_zeros()) as u8)
+	})
+}
+
 pub fn init() {
 	let boxed_irq = Box::new(IrqStatistics::new());
 	let boxed_irq_raw = Box::into_raw(boxed_irq);
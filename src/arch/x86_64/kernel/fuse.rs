@@ -1,354 +1,2279 @@
-use crate::arch::kernel::pci::get_filesystem_driver;
-use crate::syscalls::fs::{FileError, FilePerms, PosixFile, PosixFileSystem, SeekWhence};
+use crate::arch::mm::paging::{BasePageSize, PageSize};
+use crate::arch::mm::VirtAddr;
+use crate::drivers::fs::virtio_fs::VirtioFsDriver;
+use crate::synch::spinlock::{Spinlock, SpinlockIrqSave};
+use crate::syscalls::fs::lock::{self, LockTable, LockTables, OFFSET_MAX, PROCESS_LOCK_OWNER};
+use crate::syscalls::fs::pagecache::{self, PageKey, WriteBack, PAGE_CACHE, PAGE_SIZE};
+use crate::syscalls::fs::{
+	seek_position, DirEntry, FileAttr, FileError, FileLock, FilePerms, FileType, LockOwner,
+	LockType, PosixFile, PosixFileSystem, SeekWhence, Timespec,
+};
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::{fmt, u32, u8};
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::{fmt, ptr, u32, u8};
 
 // response out layout eg @ https://github.com/zargony/fuse-rs/blob/bf6d1cf03f3277e35b580f3c7b9999255d72ecf3/src/ll/request.rs#L44
 // op in/out sizes/layout: https://github.com/hanwen/go-fuse/blob/204b45dba899dfa147235c255908236d5fde2d32/fuse/opcode.go#L439
 // possible reponses for command: qemu/tools/virtiofsd/fuse_lowlevel.h
 
 const FUSE_ROOT_ID: u64 = 1;
-const MAX_READ_LEN: usize = 1024 * 64;
-const MAX_WRITE_LEN: usize = 1024 * 64;
+/// Pages per request, if the server does not announce `max_pages`
+const FUSE_DEFAULT_MAX_PAGES_PER_REQ: usize = 32;
+/// Upper bound of `fuse_init_out::max_pages`
+const FUSE_MAX_MAX_PAGES: usize = 256;
+const MAX_READDIR_LEN: usize = 1024 * 4;
+const MAX_READLINK_LEN: usize = 1024 * 4;
+
+/// Flags of `fuse_init_in` and `fuse_init_out`
+const FUSE_POSIX_LOCKS: u32 = 1 << 1;
+const FUSE_BIG_WRITES: u32 = 1 << 5;
+const FUSE_FLOCK_LOCKS: u32 = 1 << 10;
+const FUSE_MAX_PAGES: u32 = 1 << 22;
+const FUSE_MAP_ALIGNMENT: u32 = 1 << 26;
+
+/// Size of the file ranges, which are mapped into the DAX window at once
+const DAX_CHUNK_SIZE: usize = 2 * 1024 * 1024;
+
+/// Flags of `fuse_setupmapping_in`
+const FUSE_SETUPMAPPING_FLAG_READ: u64 = 1 << 1;
+
+/// Maximum number of ranges of a single FUSE_REMOVEMAPPING
+const FUSE_REMOVEMAPPING_MAX_ENTRY: usize = 4096 / core::mem::size_of::<fuse_removemapping_one>();
+
+/// Alignment of buffers, lengths and offsets of files opened with O_DIRECT
+const DIRECT_IO_ALIGN: usize = 512;
+
+/// Flags of `fuse_open_out::open_flags`
+const FOPEN_DIRECT_IO: u32 = 1 << 0;
+const FOPEN_KEEP_CACHE: u32 = 1 << 1;
+
+/// Flags of `fuse_lk_in::lk_flags`
+const FUSE_LK_FLOCK: u32 = 1 << 0;
+
+/// Flags of `fuse_release_in::release_flags`
+const FUSE_RELEASE_FLOCK_UNLOCK: u32 = 1 << 1;
+
+/// Values of `fuse_file_lock::typ`
+const F_RDLCK: u32 = 0;
+const F_WRLCK: u32 = 1;
+const F_UNLCK: u32 = 2;
+
+/// Values of `fuse_lseek_in::whence`
+const SEEK_DATA: u32 = 3;
+const SEEK_HOLE: u32 = 4;
 
 pub trait FuseInterface {
-	fn send_command<S, T>(&mut self, cmd: Cmd<S>, rsp: Option<Rsp<T>>) -> Option<Rsp<T>>
+	fn send_command<S, T>(&self, cmd: Cmd<S>, rsp: Option<Rsp<T>>) -> Option<Rsp<T>>
+	where
+		S: FuseIn + core::fmt::Debug,
+		T: FuseOut + core::fmt::Debug;
+
+	/// Sends a request, whose payload `data_out` follows the command, and receives the payload
+	/// of the reply into `data_in`. Both buffers are handed to the device without copying them.
+	fn send_command_with_data<S, T>(
+		&self,
+		cmd: Cmd<S>,
+		data_out: &[u8],
+		rsp: Rsp<T>,
+		data_in: &mut [u8],
+	) -> Option<Rsp<T>>
 	where
 		S: FuseIn + core::fmt::Debug,
 		T: FuseOut + core::fmt::Debug;
+
+	/// Sends a request, which the server does not reply to (e.g. FUSE_FORGET).
+	/// Such requests bypass the regular requests of other tasks.
+	fn send_oneway<S>(&self, cmd: Cmd<S>)
+	where
+		S: FuseIn + core::fmt::Debug;
+
+	/// Maximum number of buffer elements, which a single request may consist of
+	fn max_segments(&self) -> usize;
 }
 
-pub struct Fuse;
+/// The device, which carries the requests of a FUSE session
+pub type FuseDevice = SpinlockIrqSave<VirtioFsDriver>;
+
+pub struct Fuse {
+	/// Device of the session. Each device provides a separate filesystem.
+	dev: &'static FuseDevice,
+	/// Maximum payload of FUSE_READ, as negotiated with the server
+	max_read: usize,
+	/// Maximum payload of FUSE_WRITE, as negotiated with the server
+	max_write: usize,
+	/// Distinguishes the cached pages of this filesystem from those of other mounts
+	mount: u64,
+	/// Window, through which regular files are read, if DAX is enabled
+	dax: Option<Arc<DaxWindow>>,
+	/// The server keeps record locks (FUSE_POSIX_LOCKS)
+	posix_locks: bool,
+	/// The server keeps whole-file locks (FUSE_FLOCK_LOCKS)
+	flock_locks: bool,
+	/// Locks, which the server does not support, are only known to this kernel
+	local_locks: LockTables,
+}
 
 impl PosixFileSystem for Fuse {
-	fn open(&self, path: &str, perms: FilePerms) -> Result<Box<dyn PosixFile + Send>, FileError> {
-		let mut file = FuseFile {
-			fuse_nid: None,
-			fuse_fh: None,
-			offset: 0,
-		};
+	fn open(
+		&self,
+		path: &str,
+		perms: FilePerms,
+	) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
 		// 1.FUSE_INIT to create session
 		// Already done
 
 		// Differentiate between opening and creating new file, since fuse does not support O_CREAT on open.
-		if !perms.creat {
+		let (fuse_nid, fuse_fh, open_flags) = if !perms.creat {
 			// 2.FUSE_LOOKUP(FUSE_ROOT_ID, “foo”) -> nodeid
-			file.fuse_nid = self.lookup(path);
-
-			if file.fuse_nid == None {
-				warn!("Fuse lookup seems to have failed!");
-				return Err(FileError::ENOENT());
-			}
+			let node = self.lookup(path)?;
 
 			// 3.FUSE_OPEN(nodeid, O_RDONLY) -> fh
-			let (cmd, rsp) = create_open(file.fuse_nid.unwrap(), perms.raw);
-			let rsp = get_filesystem_driver()
-				.ok_or(FileError::ENOSYS())?
-				.lock()
-				.send_command(cmd, Some(rsp))
-				.unwrap();
+			let (cmd, rsp) = create_open(node.nid, perms.raw);
+			let rsp = send_request(self.dev, cmd, rsp)?;
 			trace!("Open answer {:?}", rsp);
-			file.fuse_fh = Some(rsp.rsp.fh);
+			(node.keep(), rsp.rsp.fh, rsp.rsp.open_flags)
 		} else {
 			// Create file (opens implicitly, returns results from both lookup and open calls)
-			let (cmd, rsp) = create_create(path, perms.raw, perms.mode);
-			let rsp = get_filesystem_driver()
-				.ok_or(FileError::ENOSYS())?
-				.lock()
-				.send_command(cmd, Some(rsp))
-				.unwrap();
+			let (parent, name) = self.lookup_parent(path)?;
+			let (cmd, rsp) = create_create(parent.nid, name, perms.raw, perms.mode)?;
+			let rsp = send_request(self.dev, cmd, rsp)?;
 			trace!("Create answer {:?}", rsp);
+			(
+				rsp.rsp.entry.nodeid,
+				rsp.rsp.open.fh,
+				rsp.rsp.open.open_flags,
+			)
+		};
 
-			file.fuse_nid = Some(rsp.rsp.entry.nodeid);
-			file.fuse_fh = Some(rsp.rsp.open.fh);
+		// Cached pages are only kept, if the server tells us that the file has not changed (FOPEN_KEEP_CACHE).
+		if open_flags & FOPEN_KEEP_CACHE == 0 {
+			PAGE_CACHE.lock().invalidate(self.mount, fuse_nid);
+		}
+		let direct = perms.directio || open_flags & FOPEN_DIRECT_IO != 0;
+		let dax = self.dax.as_ref().filter(|_| !direct).cloned();
+		let cached = !direct && dax.is_none() && PAGE_CACHE.lock().capacity() > 0;
+
+		// Reads through the DAX window must not exceed the end of the file, so its size is tracked.
+		if let Some(window) = &dax {
+			match getattr(self.dev, fuse_nid, Some(fuse_fh)) {
+				Ok(attr) => window.open(fuse_nid, attr.size as u64),
+				Err(err) => {
+					let (cmd, rsp) = create_release(fuse_nid, fuse_fh, None);
+					let _ = send_request(self.dev, cmd, rsp);
+					forget(self.dev, fuse_nid);
+					return Err(err);
+				}
+			}
 		}
 
-		Ok(Box::new(file))
+		Ok(Box::new(FuseFile {
+			dev: self.dev,
+			mount: self.mount,
+			fuse_nid,
+			fuse_fh,
+			append: perms.append,
+			direct: perms.directio,
+			cached,
+			dax,
+			writable: perms.write,
+			writeback: (cached && perms.write).then(|| {
+				Arc::new(FuseWriteBack {
+					dev: self.dev,
+					mount: self.mount,
+					fuse_nid,
+					fuse_fh,
+					max_write: self.max_write,
+				}) as Arc<dyn WriteBack>
+			}),
+			max_read: self.max_read,
+			max_write: self.max_write,
+			offset: AtomicUsize::new(0),
+			ra_next: AtomicU64::new(0),
+			ra_pages: AtomicUsize::new(1),
+			locks: FuseLocks {
+				posix: self.posix_locks,
+				flock: self.flock_locks,
+				local: if self.posix_locks && self.flock_locks {
+					None
+				} else {
+					Some(self.local_locks.get(fuse_nid))
+				},
+				posix_used: AtomicBool::new(false),
+				flock_used: AtomicBool::new(false),
+			},
+		}))
 	}
 
 	fn unlink(&self, path: &str) -> core::result::Result<(), FileError> {
-		let (cmd, rsp) = create_unlink(path);
-		let rsp = get_filesystem_driver()
-			.ok_or(FileError::ENOSYS())?
-			.lock()
-			.send_command(cmd, Some(rsp));
+		let (parent, name) = self.lookup_parent(path)?;
+		let (cmd, rsp) = create_unlink(parent.nid, name)?;
+		let rsp = send_request(self.dev, cmd, rsp)?;
 		trace!("unlink answer {:?}", rsp);
 
 		Ok(())
 	}
-}
 
-impl Fuse {
-	pub fn new() -> Self {
-		Self {}
+	fn opendir(&self, path: &str) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		let node = self.lookup(path)?;
+
+		let (cmd, rsp) = create_opendir(node.nid);
+		let rsp = send_request(self.dev, cmd, rsp)?;
+		trace!("Opendir answer {:?}", rsp);
+
+		Ok(Box::new(FuseDir {
+			dev: self.dev,
+			fuse_nid: node.keep(),
+			fuse_fh: rsp.rsp.fh,
+			offset: AtomicU64::new(0),
+		}))
 	}
 
-	pub fn send_init(&self) {
-		let (cmd, rsp) = create_init();
-		let rsp = get_filesystem_driver()
-			.unwrap()
-			.lock()
-			.send_command(cmd, Some(rsp));
-		trace!("fuse init answer: {:?}", rsp);
+	fn mkdir(&self, path: &str, mode: u32) -> Result<(), FileError> {
+		let (parent, name) = self.lookup_parent(path)?;
+		let (cmd, rsp) = create_mkdir(parent.nid, name, mode)?;
+		let rsp = send_request(self.dev, cmd, rsp)?;
+		trace!("mkdir answer {:?}", rsp);
+		forget(self.dev, rsp.rsp.nodeid);
+
+		Ok(())
+	}
+
+	fn rmdir(&self, path: &str) -> Result<(), FileError> {
+		let (parent, name) = self.lookup_parent(path)?;
+		let (cmd, rsp) = create_rmdir(parent.nid, name)?;
+		let rsp = send_request(self.dev, cmd, rsp)?;
+		trace!("rmdir answer {:?}", rsp);
+
+		Ok(())
+	}
+
+	fn lstat(&self, path: &str) -> Result<FileAttr, FileError> {
+		getattr(self.dev, self.lookup(path)?.nid, None)
+	}
+
+	fn rename(&self, oldpath: &str, newpath: &str) -> Result<(), FileError> {
+		let (oldparent, oldname) = self.lookup_parent(oldpath)?;
+		let (newparent, newname) = self.lookup_parent(newpath)?;
+		let (cmd, rsp) = create_rename(oldparent.nid, oldname, newparent.nid, newname);
+		let rsp = send_request(self.dev, cmd, rsp)?;
+		trace!("rename answer {:?}", rsp);
+
+		Ok(())
+	}
+
+	fn link(&self, oldpath: &str, newpath: &str) -> Result<(), FileError> {
+		let node = self.lookup(oldpath)?;
+		let (newparent, newname) = self.lookup_parent(newpath)?;
+		let (cmd, rsp) = create_link(node.nid, newparent.nid, newname);
+		let rsp = send_request(self.dev, cmd, rsp)?;
+		trace!("link answer {:?}", rsp);
+		forget(self.dev, rsp.rsp.nodeid);
+
+		Ok(())
 	}
 
-	pub fn lookup(&self, name: &str) -> Option<u64> {
-		let (cmd, rsp) = create_lookup(name);
-		let rsp = get_filesystem_driver()
-			.unwrap()
-			.lock()
-			.send_command(cmd, Some(rsp));
-		Some(rsp.unwrap().rsp.nodeid)
+	fn symlink(&self, target: &str, linkpath: &str) -> Result<(), FileError> {
+		let (parent, name) = self.lookup_parent(linkpath)?;
+		let (cmd, rsp) = create_symlink(parent.nid, name, target);
+		let rsp = send_request(self.dev, cmd, rsp)?;
+		trace!("symlink answer {:?}", rsp);
+		forget(self.dev, rsp.rsp.nodeid);
+
+		Ok(())
+	}
+
+	fn readlink(&self, path: &str) -> Result<String, FileError> {
+		let node = self.lookup(path)?;
+		let (cmd, rsp) = create_readlink(node.nid);
+		let rsp = send_request(self.dev, cmd, rsp)?;
+		trace!("readlink answer {:?}", rsp);
+
+		// the response consists of the bare link target, without a terminating NUL
+		let mut target = rsp.extra_buffer.ok_or(FileError::EIO())?;
+		target.truncate(payload_len(&rsp.header));
+		String::from_utf8(target).map_err(|_| FileError::EINVAL())
 	}
 }
 
-impl Default for Fuse {
-	fn default() -> Self {
-		Self::new()
+/// Sends a request to the virtio-fs device and waits for the reply.
+/// A failed request is reported by the FUSE server as negated errno, which is translated into a `FileError`.
+fn send_request<S, T>(dev: &FuseDevice, cmd: Cmd<S>, rsp: Rsp<T>) -> Result<Rsp<T>, FileError>
+where
+	S: FuseIn + fmt::Debug,
+	T: FuseOut + fmt::Debug,
+{
+	let rsp = dev.send_command(cmd, Some(rsp)).ok_or(FileError::EIO())?;
+	if rsp.header.error < 0 {
+		return Err(FileError::from_errno(-rsp.header.error));
 	}
+
+	Ok(rsp)
 }
 
-struct FuseFile {
-	fuse_nid: Option<u64>,
-	fuse_fh: Option<u64>,
-	offset: usize,
+/// Like `send_request`, but transfers the payloads `data_out` and `data_in` directly from and to
+/// the given buffers.
+fn send_request_with_data<S, T>(
+	dev: &FuseDevice,
+	cmd: Cmd<S>,
+	data_out: &[u8],
+	rsp: Rsp<T>,
+	data_in: &mut [u8],
+) -> Result<Rsp<T>, FileError>
+where
+	S: FuseIn + fmt::Debug,
+	T: FuseOut + fmt::Debug,
+{
+	let rsp = dev
+		.send_command_with_data(cmd, data_out, rsp, data_in)
+		.ok_or(FileError::EIO())?;
+	if rsp.header.error < 0 {
+		return Err(FileError::from_errno(-rsp.header.error));
+	}
+
+	Ok(rsp)
 }
 
-impl PosixFile for FuseFile {
-	fn close(&mut self) -> Result<(), FileError> {
-		let (cmd, rsp) = create_release(self.fuse_nid.unwrap(), self.fuse_fh.unwrap());
-		get_filesystem_driver()
-			.ok_or(FileError::ENOSYS())?
-			.lock()
-			.send_command(cmd, Some(rsp));
+/// Tells the server, that we dropped one reference to node `nid`, which we obtained by a lookup.
+/// The root is known without a lookup, so it is never forgotten.
+fn forget(dev: &FuseDevice, nid: u64) {
+	if nid != FUSE_ROOT_ID {
+		dev.send_oneway(create_forget(nid, 1));
+	}
+}
 
-		Ok(())
+/// A node, which has been looked up. The server counts the lookups of each node,
+/// so the node is forgotten, when it is dropped, unless an open file keeps it.
+struct Node {
+	dev: &'static FuseDevice,
+	nid: u64,
+}
+
+impl Node {
+	/// Returns the node id and keeps the lookup. It is forgotten, when the open file is closed.
+	fn keep(self) -> u64 {
+		let nid = self.nid;
+		core::mem::forget(self);
+		nid
 	}
+}
+
+impl Drop for Node {
+	fn drop(&mut self) {
+		forget(self.dev, self.nid);
+	}
+}
 
-	fn read(&mut self, len: u32) -> Result<Vec<u8>, FileError> {
-		let mut len = len;
-		if len as usize > MAX_READ_LEN {
-			debug!("Reading longer than max_read_len: {}", len);
-			len = MAX_READ_LEN as u32;
+/// Returns the number of bytes, which the reply contains in addition to the header.
+/// A bogus length is treated as an empty reply.
+fn payload_len(header: &fuse_out_header) -> usize {
+	(header.len as usize).saturating_sub(::core::mem::size_of::<fuse_out_header>())
+}
+
+/// Requests the attributes of node `nid`. If the node is opened, its file handle `fh` should be passed,
+/// since the filesystem may not be able to access a file by node id anymore (e.g. after unlinking it).
+fn getattr(dev: &FuseDevice, nid: u64, fh: Option<u64>) -> Result<FileAttr, FileError> {
+	let (cmd, rsp) = create_getattr(nid, fh);
+	let rsp = send_request(dev, cmd, rsp)?;
+	trace!("getattr answer {:?}", rsp);
+
+	Ok(FileAttr::from(&rsp.rsp.attr))
+}
+
+impl Fuse {
+	pub fn new(dev: &'static FuseDevice) -> Self {
+		Self {
+			dev,
+			max_read: FUSE_DEFAULT_MAX_PAGES_PER_REQ * BasePageSize::SIZE,
+			max_write: FUSE_DEFAULT_MAX_PAGES_PER_REQ * BasePageSize::SIZE,
+			mount: pagecache::new_mount_id(),
+			dax: None,
+			posix_locks: false,
+			flock_locks: false,
+			local_locks: LockTables::new(),
 		}
-		if let Some(fh) = self.fuse_fh {
-			let (cmd, rsp) = create_read(fh, len, self.offset as u64);
-			let rsp = get_filesystem_driver()
-				.ok_or(FileError::ENOSYS())?
-				.lock()
-				.send_command(cmd, Some(rsp));
-			let rsp = rsp.unwrap();
-			let len = rsp.header.len as usize - ::core::mem::size_of::<fuse_out_header>();
-			self.offset += len;
-			// TODO: do this zerocopy
-			let mut vec = rsp.extra_buffer.unwrap();
-			vec.truncate(len);
-			trace!("LEN: {}, VEC: {:?}", len, vec);
-			Ok(vec)
-		} else {
-			warn!("File not open, cannot read!");
-			Err(FileError::ENOENT())
+	}
+
+	/// Reads regular files through the DAX window at `addr`, which is `len` bytes long.
+	/// It has to be enabled before the session is started.
+	pub fn enable_dax(&mut self, addr: VirtAddr, len: usize) {
+		let slots = len / DAX_CHUNK_SIZE;
+		if slots == 0 {
+			warn!("DAX window of {} bytes is too small", len);
+			return;
 		}
+
+		info!("Using DAX window at {:#x} with {} bytes", addr, len);
+		self.dax = Some(Arc::new(DaxWindow::new(addr, slots)));
 	}
 
-	fn write(&mut self, buf: &[u8]) -> Result<u64, FileError> {
-		debug!("fuse write!");
-		let mut len = buf.len();
-		if len as usize > MAX_WRITE_LEN {
-			debug!(
-				"Writing longer than max_write_len: {} > {}",
-				buf.len(),
-				MAX_WRITE_LEN
+	/// Starts the FUSE session and negotiates the maximum size of reads and writes.
+	/// Each page of a request's payload needs a descriptor, so requests are also limited by the virtqueue.
+	pub fn send_init(&mut self) -> Result<(), FileError> {
+		let mut flags = FUSE_BIG_WRITES | FUSE_MAX_PAGES | FUSE_POSIX_LOCKS | FUSE_FLOCK_LOCKS;
+		if self.dax.is_some() {
+			flags |= FUSE_MAP_ALIGNMENT;
+		}
+		let (cmd, rsp) = create_init((FUSE_MAX_MAX_PAGES * BasePageSize::SIZE) as u32, flags);
+		let rsp = send_request(self.dev, cmd, rsp)?;
+		trace!("fuse init answer: {:?}", rsp);
+
+		// mappings have to be aligned to 2^map_alignment bytes
+		if self.dax.is_some()
+			&& rsp.rsp.flags & FUSE_MAP_ALIGNMENT != 0
+			&& 1usize
+				.checked_shl(rsp.rsp.map_alignment.into())
+				.map_or(true, |align| align > DAX_CHUNK_SIZE)
+		{
+			warn!(
+				"DAX is not supported with a mapping alignment of 2^{} bytes",
+				rsp.rsp.map_alignment
 			);
-			len = MAX_WRITE_LEN;
+			self.dax = None;
 		}
-		if let Some(fh) = self.fuse_fh {
-			let (cmd, rsp) = create_write(fh, &buf[..len], self.offset as u64);
-			let rsp = get_filesystem_driver()
-				.ok_or(FileError::ENOSYS())?
-				.lock()
-				.send_command(cmd, Some(rsp));
-			trace!("write response: {:?}", rsp);
-			let rsp = rsp.unwrap();
-
-			let len = rsp.rsp.size as usize;
-			self.offset += len;
-			debug!("Written {} bytes", len);
-			Ok(len as u64)
+
+		let max_pages = if rsp.rsp.flags & FUSE_MAX_PAGES != 0 {
+			usize::from(rsp.rsp.max_pages).clamp(1, FUSE_MAX_MAX_PAGES)
 		} else {
-			warn!("File not open, cannot read!");
-			Err(FileError::ENOENT())
+			FUSE_DEFAULT_MAX_PAGES_PER_REQ
+		};
+		let max_segments = self.dev.max_segments();
+		// the payload may start in the middle of a page and therefore touch one page more
+		let pages = max_pages.min(max_segments.saturating_sub(1)).max(1);
+		self.max_read = pages * BasePageSize::SIZE;
+		// the server may lower the proposed readahead, which we treat as its preferred read size
+		if rsp.rsp.max_readahead != 0 {
+			self.max_read = self.max_read.min(align_up!(
+				rsp.rsp.max_readahead as usize,
+				BasePageSize::SIZE
+			));
 		}
+		self.max_write = (rsp.rsp.max_write as usize).clamp(1, self.max_read);
+		self.posix_locks = rsp.rsp.flags & FUSE_POSIX_LOCKS != 0;
+		self.flock_locks = rsp.rsp.flags & FUSE_FLOCK_LOCKS != 0;
+		info!(
+			"FUSE session established, max_read {} bytes, max_write {} bytes",
+			self.max_read, self.max_write
+		);
+
+		Ok(())
 	}
 
-	fn lseek(&mut self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
-		debug!("fuse lseek");
+	/// Resolves a path relative to the root of the FUSE filesystem to its node.
+	/// FUSE_LOOKUP only resolves a single name inside a directory, so we walk the path component-wise.
+	/// The server counts the lookups of each node, so the intermediate directories are forgotten again.
+	fn lookup(&self, path: &str) -> Result<Node, FileError> {
+		let root = Node {
+			dev: self.dev,
+			nid: FUSE_ROOT_ID,
+		};
+		path.split('/')
+			.filter(|name| !name.is_empty())
+			.try_fold(root, |parent, name| {
+				Ok(Node {
+					dev: self.dev,
+					nid: self.lookup_name(parent.nid, name)?,
+				})
+			})
+	}
 
-		match whence {
-			SeekWhence::Set => self.offset = offset as usize,
-			SeekWhence::Cur => self.offset = (self.offset as isize + offset) as usize,
-			SeekWhence::End => unimplemented!("Cant seek from end yet!"),
-		}
+	/// Looks up `name` in the directory with node id `parent`.
+	fn lookup_name(&self, parent: u64, name: &str) -> Result<u64, FileError> {
+		let (cmd, rsp) = create_lookup(parent, name)?;
+		let rsp = send_request(self.dev, cmd, rsp).map_err(|err| {
+			debug!("Fuse lookup of {} failed with {:?}", name, err);
+			err
+		})?;
+		Ok(rsp.rsp.nodeid)
+	}
 
-		Ok(self.offset)
+	/// Splits `path` into the node of its parent directory and the name of the last component.
+	fn lookup_parent<'a>(&self, path: &'a str) -> Result<(Node, &'a str), FileError> {
+		match path.rfind('/') {
+			Some(idx) => Ok((self.lookup(&path[..idx])?, &path[idx + 1..])),
+			None => Ok((self.lookup("")?, path)),
+		}
 	}
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug)]
-#[allow(non_camel_case_types)]
-pub enum Opcode {
-	FUSE_LOOKUP = 1,
-	FUSE_FORGET = 2, // no reply
-	FUSE_GETATTR = 3,
-	FUSE_SETATTR = 4,
-	FUSE_READLINK = 5,
-	FUSE_SYMLINK = 6,
-	FUSE_MKNOD = 8,
-	FUSE_MKDIR = 9,
-	FUSE_UNLINK = 10,
-	FUSE_RMDIR = 11,
-	FUSE_RENAME = 12,
-	FUSE_LINK = 13,
-	FUSE_OPEN = 14,
-	FUSE_READ = 15,
-	FUSE_WRITE = 16,
-	FUSE_STATFS = 17,
-	FUSE_RELEASE = 18,
-	FUSE_FSYNC = 20,
-	FUSE_SETXATTR = 21,
-	FUSE_GETXATTR = 22,
-	FUSE_LISTXATTR = 23,
-	FUSE_REMOVEXATTR = 24,
-	FUSE_FLUSH = 25,
-	FUSE_INIT = 26,
-	FUSE_OPENDIR = 27,
-	FUSE_READDIR = 28,
-	FUSE_RELEASEDIR = 29,
-	FUSE_FSYNCDIR = 30,
-	FUSE_GETLK = 31,
-	FUSE_SETLK = 32,
-	FUSE_SETLKW = 33,
-	FUSE_ACCESS = 34,
-	FUSE_CREATE = 35,
-	FUSE_INTERRUPT = 36,
-	FUSE_BMAP = 37,
-	FUSE_DESTROY = 38,
-	FUSE_IOCTL = 39,
-	FUSE_POLL = 40,
-	FUSE_NOTIFY_REPLY = 41,
-	FUSE_BATCH_FORGET = 42,
-	FUSE_FALLOCATE = 43,
+/// Window, into which the device maps file contents on request (DAX). The window is divided into
+/// slots of `DAX_CHUNK_SIZE` bytes, each of them holding a chunk of a file. Once a chunk is mapped,
+/// it is read without any request. If all slots are in use, the least recently used one is reused.
+struct DaxWindow {
+	addr: usize,
+	slots: Spinlock<DaxSlots>,
+}
 
-	FUSE_SETVOLNAME = 61,
-	FUSE_GETXTIMES = 62,
-	FUSE_EXCHANGE = 63,
+struct DaxSlots {
+	/// Mapped chunks, keyed by node id and index of the chunk in the file
+	chunks: BTreeMap<(u64, u64), DaxChunk>,
+	free: Vec<usize>,
+	clock: u64,
+	/// Nodes with open files. The chunks of a node are unmapped with its last file.
+	open: BTreeMap<u64, DaxNode>,
+}
 
-	CUSE_INIT = 4096,
+struct DaxNode {
+	files: usize,
+	/// Size of the file, as far as known. The host does not tolerate accesses of mapped
+	/// chunks beyond the end of the file, so reads must not exceed it.
+	size: u64,
 }
 
-// From https://stackoverflow.com/questions/28127165/how-to-convert-struct-to-u8
-/*unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-	::core::slice::from_raw_parts(
-		(p as *const T) as *const u8,
-		::core::mem::size_of::<T>(),
-	)
+struct DaxChunk {
+	slot: usize,
+	/// The server has mapped the chunk
+	mapped: bool,
+	/// Number of tasks reading the chunk. Slots in use are not reused.
+	users: usize,
+	last_use: u64,
 }
-unsafe fn any_as_u8_slice_mut<T: Sized>(p: &mut T) -> &mut [u8] {
-	::core::slice::from_raw_parts_mut(
-		(p as *mut T) as *mut u8,
-		::core::mem::size_of::<T>(),
-	)
-}*/
 
-/// Marker trait, which signals that a struct is a valid Fuse command.
-/// Struct has to be repr(C)!
-pub unsafe trait FuseIn {}
-/// Marker trait, which signals that a struct is a valid Fuse response.
-/// Struct has to be repr(C)!
-pub unsafe trait FuseOut {}
+impl DaxWindow {
+	fn new(addr: VirtAddr, slots: usize) -> Self {
+		Self {
+			addr: addr.as_usize(),
+			slots: Spinlock::new(DaxSlots {
+				chunks: BTreeMap::new(),
+				free: (0..slots).rev().collect(),
+				clock: 0,
+				open: BTreeMap::new(),
+			}),
+		}
+	}
 
-#[repr(C)]
-#[derive(Debug)]
-pub struct Cmd<T: FuseIn + core::fmt::Debug> {
-	header: fuse_in_header,
-	cmd: T,
-	extra_buffer: Option<Vec<u8>>, // eg for writes. allows zero-copy and avoids rust size_of operations (which always add alignment padding)
-}
+	/// Returns the address of `slot`.
+	fn slot_addr(&self, slot: usize) -> usize {
+		self.addr + slot * DAX_CHUNK_SIZE
+	}
 
-#[repr(C)]
-#[derive(Debug)]
-pub struct Rsp<T: FuseOut + core::fmt::Debug> {
-	header: fuse_out_header,
-	rsp: T,
-	extra_buffer: Option<Vec<u8>>, // eg for reads. allows zero-copy and avoids rust size_of operations (which always add alignment padding)
-}
+	fn open(&self, nid: u64, size: u64) {
+		let mut slots = self.slots.lock();
+		let node = slots.open.entry(nid).or_insert(DaxNode { files: 0, size });
+		node.files += 1;
+		node.size = size;
+	}
 
-// TODO: use from/into? But these require consuming the command, so we need some better memory model to avoid deallocation
-impl<T> Cmd<T>
-where
-	T: FuseIn + core::fmt::Debug,
-{
-	pub fn to_u8buf(&self) -> Vec<&[u8]> {
-		let rawcmd = unsafe {
-			::core::slice::from_raw_parts(
-				(&self.header as *const fuse_in_header) as *const u8,
-				::core::mem::size_of::<T>() + ::core::mem::size_of::<fuse_in_header>(),
-			)
+	fn size(&self, nid: u64) -> u64 {
+		self.slots.lock().open.get(&nid).map_or(0, |node| node.size)
+	}
+
+	/// Updates the size of the file. If `truncate` is false, the file does not shrink.
+	fn set_size(&self, nid: u64, size: u64, truncate: bool) {
+		if let Some(node) = self.slots.lock().open.get_mut(&nid) {
+			node.size = if truncate { size } else { node.size.max(size) };
+		}
+	}
+
+	/// Returns the slots of node `nid`, which have to be unmapped, if its last file is closed.
+	/// The slots are reused, once the caller has unmapped them.
+	fn close(&self, nid: u64) -> Vec<usize> {
+		let mut slots = self.slots.lock();
+		let node = slots.open.get_mut(&nid).expect("DAX file is not open");
+		node.files -= 1;
+		if node.files > 0 {
+			return Vec::new();
+		}
+		slots.open.remove(&nid);
+
+		let keys: Vec<(u64, u64)> = slots
+			.chunks
+			.range((nid, 0)..=(nid, u64::MAX))
+			.filter(|(_, chunk)| chunk.users == 0)
+			.map(|(key, _)| *key)
+			.collect();
+		keys.iter()
+			.filter_map(|key| slots.chunks.remove(key))
+			.map(|chunk| chunk.slot)
+			.collect()
+	}
+
+	/// Returns unmapped slots to the window.
+	fn free(&self, unmapped: &[usize]) {
+		self.slots.lock().free.extend_from_slice(unmapped);
+	}
+
+	/// Reserves the slot of chunk `index` of node `nid` and returns it together with the information,
+	/// whether the chunk is already mapped. Returns `None`, if all slots are in use.
+	fn get(&self, nid: u64, index: u64) -> Option<(usize, bool)> {
+		let mut slots = self.slots.lock();
+		slots.clock += 1;
+		let clock = slots.clock;
+
+		if let Some(chunk) = slots.chunks.get_mut(&(nid, index)) {
+			chunk.users += 1;
+			chunk.last_use = clock;
+			return Some((chunk.slot, chunk.mapped));
+		}
+
+		// Mapping a chunk replaces the previous mapping of the slot, so it is not unmapped first.
+		let slot = match slots.free.pop() {
+			Some(slot) => slot,
+			None => {
+				let victim = slots
+					.chunks
+					.iter()
+					.filter(|(_, chunk)| chunk.users == 0)
+					.min_by_key(|(_, chunk)| chunk.last_use)
+					.map(|(key, _)| *key)?;
+				slots.chunks.remove(&victim)?.slot
+			}
 		};
-		if let Some(extra) = &self.extra_buffer {
-			vec![rawcmd, extra]
-		} else {
-			vec![rawcmd]
+		slots.chunks.insert(
+			(nid, index),
+			DaxChunk {
+				slot,
+				mapped: false,
+				users: 1,
+				last_use: clock,
+			},
+		);
+
+		Some((slot, false))
+	}
+
+	/// Releases a slot, which has been reserved by `get`. `mapped` tells, whether the chunk
+	/// has been mapped successfully.
+	fn put(&self, nid: u64, index: u64, mapped: bool) {
+		let mut slots = self.slots.lock();
+		let chunk = slots
+			.chunks
+			.get_mut(&(nid, index))
+			.expect("DAX chunk is not reserved");
+		chunk.users -= 1;
+		chunk.mapped |= mapped;
+
+		if !chunk.mapped && chunk.users == 0 {
+			let slot = chunk.slot;
+			slots.chunks.remove(&(nid, index));
+			slots.free.push(slot);
 		}
 	}
 }
-impl<T> Rsp<T>
-where
-	T: FuseOut + core::fmt::Debug,
-{
-	pub fn to_u8buf_mut(&mut self) -> Vec<&mut [u8]> {
-		let rawrsp = unsafe {
-			::core::slice::from_raw_parts_mut(
-				(&mut self.header as *mut fuse_out_header) as *mut u8,
-				::core::mem::size_of::<T>() + ::core::mem::size_of::<fuse_out_header>(),
-			)
+
+/// Writes to the server through the file handle `fh` of node `nid` and returns the number of written bytes.
+/// Large writes are split into several requests.
+fn write(
+	dev: &FuseDevice,
+	nid: u64,
+	fh: u64,
+	max_write: usize,
+	buf: &[u8],
+	offset: u64,
+) -> Result<usize, FileError> {
+	let mut total = 0;
+	for chunk in buf.chunks(max_write) {
+		let (cmd, rsp) = create_write(nid, fh, chunk.len() as u32, offset + total as u64);
+		let len = match send_request_with_data(dev, cmd, chunk, rsp, &mut []) {
+			Ok(rsp) => (rsp.rsp.size as usize).min(chunk.len()),
+			Err(_) if total > 0 => break,
+			Err(err) => return Err(err),
 		};
-		if let Some(extra) = &mut self.extra_buffer {
-			vec![rawrsp, extra]
-		} else {
-			vec![rawrsp]
+		total += len;
+		if len < chunk.len() {
+			break;
 		}
 	}
+
+	debug!("Written {} bytes at offset {}", total, offset);
+	Ok(total)
 }
 
-pub fn create_in_header<T>(opcode: Opcode) -> fuse_in_header
-where
-	T: FuseIn,
-{
-	fuse_in_header {
-		len: (core::mem::size_of::<T>() + core::mem::size_of::<T>()) as u32,
-		opcode: opcode as u32,
-		unique: 1,
-		nodeid: 0,
-		uid: 0,
-		pid: 0,
-		gid: 0,
-		padding: 0,
+/// Writes the cached dirty pages of a file back through one of its file handles
+struct FuseWriteBack {
+	dev: &'static FuseDevice,
+	mount: u64,
+	fuse_nid: u64,
+	fuse_fh: u64,
+	max_write: usize,
+}
+
+impl WriteBack for FuseWriteBack {
+	/// Writes the dirty pages of the file back to the server. Contiguous pages are combined
+	/// into a single request. Pages, which could not be written, are kept dirty.
+	fn write_back(&self) -> Result<(), FileError> {
+		let dirty = PAGE_CACHE.lock().take_dirty(self.mount, self.fuse_nid);
+		let mut pages = dirty.iter().peekable();
+		while let Some((first, data)) = pages.next() {
+			let mut run = data.clone();
+			let mut next = first + 1;
+			while let Some((index, data)) = pages.peek() {
+				// only full pages are followed by further pages
+				if *index != next
+					|| run.len() < (next - first) as usize * PAGE_SIZE
+					|| run.len() + data.len() > self.max_write
+				{
+					break;
+				}
+				run.extend_from_slice(data);
+				next += 1;
+				pages.next();
+			}
+
+			let result = match write(
+				self.dev,
+				self.fuse_nid,
+				self.fuse_fh,
+				self.max_write,
+				&run,
+				first * PAGE_SIZE as u64,
+			) {
+				Ok(len) if len == run.len() => Ok(()),
+				Ok(_) => Err(FileError::EIO()),
+				Err(err) => Err(err),
+			};
+			if let Err(err) = result {
+				let mut cache = PAGE_CACHE.lock();
+				for (index, data) in dirty.iter().filter(|(index, _)| *index >= *first) {
+					let key = PageKey {
+						mount: self.mount,
+						ino: self.fuse_nid,
+						index: *index,
+					};
+					cache.restore_dirty(key, data);
+				}
+				return Err(err);
+			}
+		}
+
+		Ok(())
 	}
 }
 
-pub fn create_init() -> (Cmd<fuse_init_in>, Rsp<fuse_init_out>) {
+struct FuseFile {
+	dev: &'static FuseDevice,
+	/// Mount of the file, which is part of the keys of its cached pages
+	mount: u64,
+	fuse_nid: u64,
+	fuse_fh: u64,
+	/// Writes always go to the end of the file (O_APPEND)
+	append: bool,
+	/// Transfers bypass the caches of the host (O_DIRECT)
+	direct: bool,
+	/// Transfers go through the page cache. This is not the case for O_DIRECT,
+	/// if the server requests direct I/O (FOPEN_DIRECT_IO) and with DAX.
+	cached: bool,
+	/// Reads go through the DAX window of the mount
+	dax: Option<Arc<DaxWindow>>,
+	/// Dirty pages can be written back through this file handle
+	writable: bool,
+	/// Writes back the dirty pages of the file, if it is cached and writable.
+	/// It is shared with the page cache, which writes back other files as well.
+	writeback: Option<Arc<dyn WriteBack>>,
+	/// Maximum payload of a single FUSE_READ
+	max_read: usize,
+	/// Maximum payload of a single FUSE_WRITE
+	max_write: usize,
+	/// The offset is shared by all duplicates of a file descriptor.
+	/// It is not locked during requests, since these may take a while.
+	offset: AtomicUsize,
+	/// Index of the page, which a sequential read accesses next
+	ra_next: AtomicU64,
+	/// Number of pages, which have been read ahead by the last sequential read.
+	/// The window doubles with every sequential read up to `max_read`.
+	ra_pages: AtomicUsize,
+	locks: FuseLocks,
+}
+
+/// Advisory locks of a file. Locks are kept by the server, if it supports them.
+/// Otherwise, they are only known to this kernel, like on Linux.
+struct FuseLocks {
+	/// The server keeps record locks
+	posix: bool,
+	/// The server keeps whole-file locks
+	flock: bool,
+	/// Locks of the node, which the server does not keep
+	local: Option<Arc<LockTable>>,
+	/// Record locks have been placed at the server through this file
+	posix_used: AtomicBool,
+	/// A whole-file lock has been placed at the server through this file
+	flock_used: AtomicBool,
+}
+
+impl FuseFile {
+	/// Files opened with O_DIRECT are accessed without the host's page cache, which
+	/// requires the same alignment of buffers, lengths and offsets as on Linux.
+	fn check_direct(&self, ptr: *const u8, len: usize, offset: u64) -> Result<(), FileError> {
+		if self.direct
+			&& (ptr as usize % DIRECT_IO_ALIGN != 0
+				|| len % DIRECT_IO_ALIGN != 0
+				|| offset % DIRECT_IO_ALIGN as u64 != 0)
+		{
+			return Err(FileError::EINVAL());
+		}
+
+		Ok(())
+	}
+
+	fn page_key(&self, index: u64) -> PageKey {
+		PageKey {
+			mount: self.mount,
+			ino: self.fuse_nid,
+			index,
+		}
+	}
+
+	/// Reads from the server without caching the data.
+	fn read_direct(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		// Large reads are split into several requests, each of them reads directly into `buf`.
+		let mut total = 0;
+		for chunk in buf.chunks_mut(self.max_read) {
+			let (cmd, rsp) = create_read(
+				self.fuse_nid,
+				self.fuse_fh,
+				chunk.len() as u32,
+				offset + total as u64,
+			);
+			let len = match send_request_with_data(self.dev, cmd, &[], rsp, chunk) {
+				Ok(rsp) => payload_len(&rsp.header).min(chunk.len()),
+				// report the data, which has already been read
+				Err(_) if total > 0 => break,
+				Err(err) => return Err(err),
+			};
+			total += len;
+			// a short read means the end of the file
+			if len < chunk.len() {
+				break;
+			}
+		}
+
+		trace!("Read {} bytes at offset {}", total, offset);
+		Ok(total)
+	}
+
+	/// Writes to the server without caching the data.
+	fn write_direct(&self, buf: &[u8], offset: u64) -> Result<usize, FileError> {
+		write(
+			self.dev,
+			self.fuse_nid,
+			self.fuse_fh,
+			self.max_write,
+			buf,
+			offset,
+		)
+	}
+
+	/// Returns the number of pages, which a read of `wanted` pages starting at page `index`
+	/// should fetch. Sequential reads double the window up to the maximum size of a request.
+	fn readahead_pages(&self, index: u64, wanted: usize) -> usize {
+		let max_pages = (self.max_read / PAGE_SIZE).max(1);
+		let pages = if index == self.ra_next.load(Ordering::Relaxed) {
+			(self.ra_pages.load(Ordering::Relaxed) * 2).max(wanted)
+		} else {
+			wanted
+		}
+		.clamp(1, max_pages);
+		self.ra_pages.store(pages, Ordering::Relaxed);
+
+		pages
+	}
+
+	/// Reads up to `pages` pages starting at page `index` by a single request and caches them.
+	/// Already cached pages are not read again, since they may have been modified.
+	/// Returns the content of page `index`, which is shorter than a page at the end of the file.
+	fn fill(&self, index: u64, pages: usize) -> Result<Vec<u8>, FileError> {
+		let count = {
+			let cache = PAGE_CACHE.lock();
+			(1..pages)
+				.find(|i| cache.contains(self.page_key(index + *i as u64)))
+				.unwrap_or(pages)
+		};
+
+		let mut data = vec![0; count * PAGE_SIZE];
+		let len = self.read_direct(&mut data, index * PAGE_SIZE as u64)?;
+
+		let mut cache = PAGE_CACHE.lock();
+		for i in 0..count {
+			let start = i * PAGE_SIZE;
+			let end = len.min(start + PAGE_SIZE);
+			if start > len {
+				break;
+			}
+			// a short (or empty) page marks the end of the file
+			cache.insert(self.page_key(index + i as u64), &data[start..end]);
+			if end - start < PAGE_SIZE {
+				break;
+			}
+		}
+		drop(cache);
+
+		data.truncate(len.min(PAGE_SIZE));
+		Ok(data)
+	}
+
+	/// Copies the file contents from the DAX window. Chunks, which are not mapped yet,
+	/// are mapped by the server first. Writes are sent as requests, which update
+	/// the mapped contents as well.
+	fn dax_pread(
+		&self,
+		window: &DaxWindow,
+		buf: &mut [u8],
+		offset: u64,
+	) -> Result<usize, FileError> {
+		let mut end = offset + buf.len() as u64;
+		// The file may have grown meanwhile, e.g. by other clients.
+		if end > window.size(self.fuse_nid) {
+			let size = getattr(self.dev, self.fuse_nid, Some(self.fuse_fh))?.size as u64;
+			window.set_size(self.fuse_nid, size, true);
+			end = end.min(size);
+		}
+
+		let mut position = offset;
+		while position < end {
+			let index = position / DAX_CHUNK_SIZE as u64;
+			let in_chunk = (position % DAX_CHUNK_SIZE as u64) as usize;
+			let len = (end - position).min((DAX_CHUNK_SIZE - in_chunk) as u64) as usize;
+			let dest = &mut buf[(position - offset) as usize..][..len];
+
+			let (slot, mapped) = match window.get(self.fuse_nid, index) {
+				Some(slot) => slot,
+				// all slots are being read by other tasks
+				None => {
+					let read = self.read_direct(dest, position)?;
+					position += read as u64;
+					if read < len {
+						break;
+					}
+					continue;
+				}
+			};
+
+			if !mapped {
+				let (cmd, rsp) = create_setupmapping(
+					self.fuse_nid,
+					self.fuse_fh,
+					index * DAX_CHUNK_SIZE as u64,
+					DAX_CHUNK_SIZE as u64,
+					FUSE_SETUPMAPPING_FLAG_READ,
+					(slot * DAX_CHUNK_SIZE) as u64,
+				);
+				if let Err(err) = send_request(self.dev, cmd, rsp) {
+					window.put(self.fuse_nid, index, false);
+					if position > offset {
+						break;
+					}
+					return Err(err);
+				}
+			}
+
+			unsafe {
+				ptr::copy_nonoverlapping(
+					(window.slot_addr(slot) + in_chunk) as *const u8,
+					dest.as_mut_ptr(),
+					len,
+				);
+			}
+			window.put(self.fuse_nid, index, true);
+			position += len as u64;
+		}
+
+		trace!(
+			"Read {} bytes at offset {} from the DAX window",
+			position - offset,
+			offset
+		);
+		Ok((position - offset) as usize)
+	}
+
+	/// Unmaps the chunks of the file from the DAX window, if no other file of the node is open.
+	fn dax_close(&self, window: &DaxWindow) {
+		let slots = window.close(self.fuse_nid);
+		for batch in slots.chunks(FUSE_REMOVEMAPPING_MAX_ENTRY) {
+			let ranges: Vec<(u64, u64)> = batch
+				.iter()
+				.map(|slot| ((slot * DAX_CHUNK_SIZE) as u64, DAX_CHUNK_SIZE as u64))
+				.collect();
+			let (cmd, rsp) = create_removemapping(self.fuse_nid, &ranges);
+			// the slots are reused anyway, since a new mapping replaces the old one
+			if let Err(err) = send_request(self.dev, cmd, rsp) {
+				debug!("Unable to remove DAX mappings: {:?}", err);
+			}
+		}
+		window.free(&slots);
+	}
+
+	fn cached_pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		let mut total = 0;
+		while total < buf.len() {
+			let position = offset + total as u64;
+			let index = position / PAGE_SIZE as u64;
+			let in_page = (position % PAGE_SIZE as u64) as usize;
+			let remaining = buf.len() - total;
+			let chunk = &mut buf[total..total + remaining.min(PAGE_SIZE - in_page)];
+
+			let cached = PAGE_CACHE.lock().read(self.page_key(index), in_page, chunk);
+			let len = match cached {
+				Some(len) => len,
+				None => {
+					let wanted = align_up!(in_page + remaining, PAGE_SIZE) / PAGE_SIZE;
+					let page = match self.fill(index, self.readahead_pages(index, wanted)) {
+						Ok(page) => page,
+						Err(_) if total > 0 => break,
+						Err(err) => return Err(err),
+					};
+					let len = chunk.len().min(page.len().saturating_sub(in_page));
+					chunk[..len].copy_from_slice(&page[in_page..in_page + len]);
+					len
+				}
+			};
+			self.ra_next.store(index + 1, Ordering::Relaxed);
+
+			total += len;
+			// a short page means the end of the file
+			if len < chunk.len() {
+				break;
+			}
+		}
+
+		trace!(
+			"Read {} bytes at offset {} from the page cache",
+			total,
+			offset
+		);
+		Ok(total)
+	}
+
+	fn cached_pwrite(&self, buf: &[u8], offset: u64) -> Result<usize, FileError> {
+		if let Some(writeback) = &self.writeback {
+			PAGE_CACHE
+				.lock()
+				.set_writer(self.mount, self.fuse_nid, writeback);
+		}
+
+		let mut total = 0;
+		while total < buf.len() {
+			let position = offset + total as u64;
+			let index = position / PAGE_SIZE as u64;
+			let in_page = (position % PAGE_SIZE as u64) as usize;
+			let chunk = &buf[total..total + (buf.len() - total).min(PAGE_SIZE - in_page)];
+			let key = self.page_key(index);
+
+			// The rest of a partially written page has to be read first.
+			let cached = if chunk.len() == PAGE_SIZE {
+				PAGE_CACHE.lock().insert_dirty(key, 0, chunk);
+				true
+			} else {
+				PAGE_CACHE.lock().write(key, in_page, chunk)
+					|| (self.fill(index, 1).is_ok() && PAGE_CACHE.lock().write(key, in_page, chunk))
+			};
+
+			if cached {
+				total += chunk.len();
+				continue;
+			}
+
+			// The page cannot be read (e.g. with O_WRONLY), so we write through.
+			let len = match self.write_direct(chunk, position) {
+				Ok(len) => len,
+				Err(_) if total > 0 => break,
+				Err(err) => return Err(err),
+			};
+			// the file may have grown, which invalidates cached ends of the file
+			PAGE_CACHE.lock().invalidate(self.mount, self.fuse_nid);
+			total += len;
+			if len < chunk.len() {
+				break;
+			}
+		}
+
+		pagecache::write_back_excess();
+
+		debug!(
+			"Written {} bytes at offset {} to the page cache",
+			total, offset
+		);
+		Ok(total)
+	}
+
+	/// Writes the dirty pages of the file back to the server.
+	fn flush(&self) -> Result<(), FileError> {
+		match &self.writeback {
+			Some(writeback) => writeback.write_back(),
+			None => Ok(()),
+		}
+	}
+
+	/// Returns the local lock table, if the server does not keep record locks.
+	fn local_records(&self) -> Option<&LockTable> {
+		self.locks.local.as_deref().filter(|_| !self.locks.posix)
+	}
+
+	/// Returns the local lock table, if the server does not keep whole-file locks.
+	fn local_flocks(&self) -> Option<&LockTable> {
+		self.locks.local.as_deref().filter(|_| !self.locks.flock)
+	}
+
+	/// Places `lock` at the server. Whole-file locks are marked by FUSE_LK_FLOCK in `lk_flags`.
+	fn send_setlk(&self, lock: &FileLock, lk_flags: u32, wait: bool) -> Result<(), FileError> {
+		let opcode = if wait {
+			Opcode::FUSE_SETLKW
+		} else {
+			Opcode::FUSE_SETLK
+		};
+		let (cmd, rsp) = create_setlk(
+			opcode,
+			self.fuse_nid,
+			self.fuse_fh,
+			lock.owner,
+			lock.into(),
+			lk_flags,
+		);
+		send_request(self.dev, cmd, rsp)?;
+
+		Ok(())
+	}
+}
+
+impl PosixFile for FuseFile {
+	fn close(&self) -> Result<(), FileError> {
+		let flushed = self.flush();
+		if let Some(window) = &self.dax {
+			self.dax_close(window);
+		}
+
+		// The locks of the open file are removed, the server releases its whole-file lock together with the handle.
+		let owner = self.lock_owner();
+		if let Some(local) = &self.locks.local {
+			local.release(owner);
+		}
+		if self.locks.posix_used.load(Ordering::Relaxed) {
+			let _ = self.send_setlk(&FileLock::whole_file(LockType::Unlock, owner, 0), 0, false);
+		}
+		let flock_owner = Some(owner).filter(|_| self.locks.flock_used.load(Ordering::Relaxed));
+		let (cmd, rsp) = create_release(self.fuse_nid, self.fuse_fh, flock_owner);
+		let released = send_request(self.dev, cmd, rsp);
+		// the lookup of the node has been kept for the open file
+		forget(self.dev, self.fuse_nid);
+		released?;
+
+		flushed
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		let len = self.pread(buf, self.offset.load(Ordering::Relaxed) as u64)?;
+		self.offset.fetch_add(len, Ordering::Relaxed);
+		Ok(len)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		if !self.writable {
+			return Err(FileError::EBADF());
+		}
+		if self.append {
+			self.offset
+				.store(self.fstat()?.size as usize, Ordering::Relaxed);
+		}
+		let len = self.pwrite(buf, self.offset.load(Ordering::Relaxed) as u64)?;
+		self.offset.fetch_add(len as usize, Ordering::Relaxed);
+		Ok(len)
+	}
+
+	fn pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		self.check_direct(buf.as_ptr(), buf.len(), offset)?;
+
+		if let Some(window) = &self.dax {
+			self.dax_pread(window, buf, offset)
+		} else if self.cached {
+			self.cached_pread(buf, offset)
+		} else {
+			self.read_direct(buf, offset)
+		}
+	}
+
+	fn pwrite(&self, buf: &[u8], offset: u64) -> Result<u64, FileError> {
+		// The page cache is shared by all handles of the file, so the access mode has to be checked before writing to it.
+		if !self.writable {
+			return Err(FileError::EBADF());
+		}
+		self.check_direct(buf.as_ptr(), buf.len(), offset)?;
+
+		let len = if self.cached {
+			self.cached_pwrite(buf, offset)?
+		} else {
+			let len = self.write_direct(buf, offset)?;
+			// pages, which have been cached through other file handles, are outdated now
+			PAGE_CACHE.lock().invalidate(self.mount, self.fuse_nid);
+			if let Some(window) = &self.dax {
+				window.set_size(self.fuse_nid, offset + len as u64, false);
+			}
+			len
+		};
+
+		Ok(len as u64)
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		debug!("fuse lseek");
+
+		let position = self.offset.load(Ordering::Relaxed);
+		let new = match whence {
+			SeekWhence::Set | SeekWhence::Cur => seek_position(position, offset, whence, 0)?,
+			SeekWhence::End => {
+				seek_position(position, offset, whence, self.fstat()?.size as usize)?
+			}
+			SeekWhence::Data | SeekWhence::Hole => {
+				if offset < 0 {
+					return Err(FileError::ENXIO());
+				}
+				let fuse_whence = match whence {
+					SeekWhence::Data => SEEK_DATA,
+					_ => SEEK_HOLE,
+				};
+				// the server has to know about cached modifications to find data and holes
+				self.flush()?;
+				let (cmd, rsp) =
+					create_lseek(self.fuse_nid, self.fuse_fh, offset as u64, fuse_whence);
+				match send_request(self.dev, cmd, rsp) {
+					Ok(rsp) => rsp.rsp.offset as usize,
+					// FUSE_LSEEK is optional, so treat the file as not sparse if the server does not know it
+					Err(FileError::ENOSYS()) => {
+						seek_position(position, offset, whence, self.fstat()?.size as usize)?
+					}
+					Err(err) => return Err(err),
+				}
+			}
+		};
+
+		self.offset.store(new, Ordering::Relaxed);
+		Ok(new)
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		// the size of the file includes cached writes
+		self.flush()?;
+		getattr(self.dev, self.fuse_nid, Some(self.fuse_fh))
+	}
+
+	fn ftruncate(&self, len: u64) -> Result<(), FileError> {
+		self.flush()?;
+		let (cmd, rsp) = create_truncate(self.fuse_nid, self.fuse_fh, len);
+		let rsp = send_request(self.dev, cmd, rsp);
+		PAGE_CACHE.lock().invalidate(self.mount, self.fuse_nid);
+		trace!("truncate answer {:?}", rsp?);
+		if let Some(window) = &self.dax {
+			window.set_size(self.fuse_nid, len, true);
+		}
+
+		Ok(())
+	}
+
+	fn fsync(&self, datasync: bool) -> Result<(), FileError> {
+		self.flush()?;
+		let (cmd, rsp) = create_fsync(Opcode::FUSE_FSYNC, self.fuse_nid, self.fuse_fh, datasync);
+		send_request(self.dev, cmd, rsp)?;
+
+		Ok(())
+	}
+
+	fn fallocate(&self, mode: u32, offset: u64, len: u64) -> Result<(), FileError> {
+		self.flush()?;
+		let (cmd, rsp) = create_fallocate(self.fuse_nid, self.fuse_fh, mode, offset, len);
+		let rsp = send_request(self.dev, cmd, rsp);
+		PAGE_CACHE.lock().invalidate(self.mount, self.fuse_nid);
+		rsp?;
+
+		Ok(())
+	}
+
+	fn getlk(&self, lock: &FileLock) -> Result<Option<FileLock>, FileError> {
+		if let Some(local) = self.local_records() {
+			return Ok(local.get(lock));
+		}
+
+		let (cmd, rsp) = create_getlk(self.fuse_nid, self.fuse_fh, lock.owner, lock.into());
+		let lk = send_request(self.dev, cmd, rsp)?.rsp.lk;
+		let typ = match lk.typ {
+			F_RDLCK => LockType::Read,
+			F_WRLCK => LockType::Write,
+			_ => return Ok(None),
+		};
+
+		// the owner of the conflicting lock is unknown, the server only reports its pid
+		Ok(Some(FileLock {
+			typ,
+			start: lk.start,
+			end: lk.end.min(OFFSET_MAX),
+			owner: PROCESS_LOCK_OWNER,
+			pid: lk.pid,
+		}))
+	}
+
+	fn setlk(&self, lock: &FileLock, wait: bool) -> Result<(), FileError> {
+		lock::check_access(lock, true, self.writable)?;
+		if let Some(local) = self.local_records() {
+			return local.set(lock, wait);
+		}
+
+		self.send_setlk(lock, 0, wait)?;
+		if lock.typ != LockType::Unlock {
+			self.locks.posix_used.store(true, Ordering::Relaxed);
+		}
+
+		Ok(())
+	}
+
+	fn flock(&self, typ: LockType, wait: bool) -> Result<(), FileError> {
+		let owner = self.lock_owner();
+		if let Some(local) = self.local_flocks() {
+			return local.flock(typ, owner, wait);
+		}
+
+		self.send_setlk(&FileLock::whole_file(typ, owner, 0), FUSE_LK_FLOCK, wait)?;
+		if typ != LockType::Unlock {
+			self.locks.flock_used.store(true, Ordering::Relaxed);
+		}
+
+		Ok(())
+	}
+
+	fn release_locks(&self, owner: LockOwner) {
+		if let Some(local) = self.local_records() {
+			local.release(owner);
+		} else if self.locks.posix_used.load(Ordering::Relaxed) {
+			let _ = self.send_setlk(&FileLock::whole_file(LockType::Unlock, owner, 0), 0, false);
+		}
+	}
+}
+
+struct FuseDir {
+	dev: &'static FuseDevice,
+	fuse_nid: u64,
+	fuse_fh: u64,
+	offset: AtomicU64,
+}
+
+impl PosixFile for FuseDir {
+	fn close(&self) -> Result<(), FileError> {
+		let (cmd, rsp) = create_releasedir(self.fuse_nid, self.fuse_fh);
+		let released = send_request(self.dev, cmd, rsp);
+		// the lookup of the node has been kept for the open directory
+		forget(self.dev, self.fuse_nid);
+		released?;
+
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn pread(&self, _buf: &mut [u8], _offset: u64) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		// The offset of a directory is an opaque cookie, so only absolute positioning
+		// and querying the current position make sense.
+		match whence {
+			SeekWhence::Set => self.offset.store(offset as u64, Ordering::Relaxed),
+			SeekWhence::Cur if offset == 0 => {}
+			_ => return Err(FileError::EINVAL()),
+		}
+
+		Ok(self.offset.load(Ordering::Relaxed) as usize)
+	}
+
+	fn fsync(&self, datasync: bool) -> Result<(), FileError> {
+		let (cmd, rsp) = create_fsync(Opcode::FUSE_FSYNCDIR, self.fuse_nid, self.fuse_fh, datasync);
+		send_request(self.dev, cmd, rsp)?;
+
+		Ok(())
+	}
+
+	fn readdir(&self) -> Result<Vec<DirEntry>, FileError> {
+		let (cmd, rsp) = create_readdirplus(
+			self.fuse_nid,
+			self.fuse_fh,
+			MAX_READDIR_LEN as u32,
+			self.offset.load(Ordering::Relaxed),
+		);
+		let rsp = send_request(self.dev, cmd, rsp)?;
+
+		let buf = rsp.extra_buffer.as_deref().ok_or(FileError::EIO())?;
+		let len = payload_len(&rsp.header).min(buf.len());
+		let (entries, nids) = parse_direntplus(&buf[..len]);
+		// each returned node counts as looked up, but we only need its name
+		for nid in nids {
+			forget(self.dev, nid);
+		}
+		if let Some(last) = entries.last() {
+			self.offset.store(last.offset, Ordering::Relaxed);
+		}
+
+		Ok(entries)
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		getattr(self.dev, self.fuse_nid, Some(self.fuse_fh))
+	}
+}
+
+/// Splits the reply of FUSE_READDIRPLUS into directory entries.
+/// Each entry consists of a fuse_direntplus followed by the name, padded to 8 bytes.
+///
+/// Also returns the node ids, whose lookup count the server has increased. Like Linux, we assume
+/// that the entries "." and ".." and entries without node id are not counted.
+fn parse_direntplus(buf: &[u8]) -> (Vec<DirEntry>, Vec<u64>) {
+	let header_len = ::core::mem::size_of::<fuse_direntplus>();
+	let mut entries = Vec::new();
+	let mut nids = Vec::new();
+	let mut pos = 0;
+
+	while pos + header_len <= buf.len() {
+		let direntplus =
+			unsafe { ptr::read_unaligned(buf[pos..].as_ptr() as *const fuse_direntplus) };
+		let namelen = direntplus.dirent.namelen as usize;
+		if pos + header_len + namelen > buf.len() {
+			warn!("FUSE: Truncated directory entry!");
+			break;
+		}
+
+		let name = &buf[pos + header_len..pos + header_len + namelen];
+		if direntplus.entry_out.nodeid != 0 && name != b"." && name != b".." {
+			nids.push(direntplus.entry_out.nodeid);
+		}
+		entries.push(DirEntry {
+			ino: direntplus.dirent.ino,
+			offset: direntplus.dirent.off,
+			file_type: FileType::from_mode(direntplus.dirent.typ << 12),
+			name: String::from_utf8_lossy(name).into_owned(),
+		});
+
+		pos += align_up!(header_len + namelen, 8);
+	}
+
+	(entries, nids)
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+#[allow(non_camel_case_types)]
+pub enum Opcode {
+	FUSE_LOOKUP = 1,
+	FUSE_FORGET = 2, // no reply
+	FUSE_GETATTR = 3,
+	FUSE_SETATTR = 4,
+	FUSE_READLINK = 5,
+	FUSE_SYMLINK = 6,
+	FUSE_MKNOD = 8,
+	FUSE_MKDIR = 9,
+	FUSE_UNLINK = 10,
+	FUSE_RMDIR = 11,
+	FUSE_RENAME = 12,
+	FUSE_LINK = 13,
+	FUSE_OPEN = 14,
+	FUSE_READ = 15,
+	FUSE_WRITE = 16,
+	FUSE_STATFS = 17,
+	FUSE_RELEASE = 18,
+	FUSE_FSYNC = 20,
+	FUSE_SETXATTR = 21,
+	FUSE_GETXATTR = 22,
+	FUSE_LISTXATTR = 23,
+	FUSE_REMOVEXATTR = 24,
+	FUSE_FLUSH = 25,
+	FUSE_INIT = 26,
+	FUSE_OPENDIR = 27,
+	FUSE_READDIR = 28,
+	FUSE_RELEASEDIR = 29,
+	FUSE_FSYNCDIR = 30,
+	FUSE_GETLK = 31,
+	FUSE_SETLK = 32,
+	FUSE_SETLKW = 33,
+	FUSE_ACCESS = 34,
+	FUSE_CREATE = 35,
+	FUSE_INTERRUPT = 36,
+	FUSE_BMAP = 37,
+	FUSE_DESTROY = 38,
+	FUSE_IOCTL = 39,
+	FUSE_POLL = 40,
+	FUSE_NOTIFY_REPLY = 41,
+	FUSE_BATCH_FORGET = 42,
+	FUSE_FALLOCATE = 43,
+	FUSE_READDIRPLUS = 44,
+	FUSE_RENAME2 = 45,
+	FUSE_LSEEK = 46,
+	FUSE_COPY_FILE_RANGE = 47,
+	FUSE_SETUPMAPPING = 48,
+	FUSE_REMOVEMAPPING = 49,
+
+	FUSE_SETVOLNAME = 61,
+	FUSE_GETXTIMES = 62,
+	FUSE_EXCHANGE = 63,
+
+	CUSE_INIT = 4096,
+}
+
+// From https://stackoverflow.com/questions/28127165/how-to-convert-struct-to-u8
+/*unsafe fn any_as_u8_slice<T: Sized>(p: &T) -> &[u8] {
+	::core::slice::from_raw_parts(
+		(p as *const T) as *const u8,
+		::core::mem::size_of::<T>(),
+	)
+}
+unsafe fn any_as_u8_slice_mut<T: Sized>(p: &mut T) -> &mut [u8] {
+	::core::slice::from_raw_parts_mut(
+		(p as *mut T) as *mut u8,
+		::core::mem::size_of::<T>(),
+	)
+}*/
+
+/// Marker trait, which signals that a struct is a valid Fuse command.
+/// Struct has to be repr(C)!
+pub unsafe trait FuseIn {}
+/// Marker trait, which signals that a struct is a valid Fuse response.
+/// Struct has to be repr(C)!
+pub unsafe trait FuseOut {}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct Cmd<T: FuseIn + core::fmt::Debug> {
+	header: fuse_in_header,
+	cmd: T,
+	extra_buffer: Option<Vec<u8>>, // eg for writes. allows zero-copy and avoids rust size_of operations (which always add alignment padding)
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct Rsp<T: FuseOut + core::fmt::Debug> {
+	header: fuse_out_header,
+	rsp: T,
+	extra_buffer: Option<Vec<u8>>, // eg for reads. allows zero-copy and avoids rust size_of operations (which always add alignment padding)
+}
+
+// TODO: use from/into? But these require consuming the command, so we need some better memory model to avoid deallocation
+impl<T> Cmd<T>
+where
+	T: FuseIn + core::fmt::Debug,
+{
+	/// Returns the id, which identifies the request and its reply.
+	pub fn unique(&self) -> u64 {
+		self.header.unique
+	}
+
+	/// Returns true, if the server may delay its reply indefinitely, e.g. until a lock is released.
+	pub fn may_block(&self) -> bool {
+		self.header.opcode == Opcode::FUSE_SETLKW as u32
+	}
+
+	pub fn to_u8buf(&self) -> Vec<&[u8]> {
+		let rawcmd = unsafe {
+			::core::slice::from_raw_parts(
+				(&self.header as *const fuse_in_header) as *const u8,
+				::core::mem::size_of::<T>() + ::core::mem::size_of::<fuse_in_header>(),
+			)
+		};
+		if let Some(extra) = &self.extra_buffer {
+			vec![rawcmd, extra]
+		} else {
+			vec![rawcmd]
+		}
+	}
+}
+impl<T> Rsp<T>
+where
+	T: FuseOut + core::fmt::Debug,
+{
+	pub fn to_u8buf_mut(&mut self) -> Vec<&mut [u8]> {
+		let rawrsp = unsafe {
+			::core::slice::from_raw_parts_mut(
+				(&mut self.header as *mut fuse_out_header) as *mut u8,
+				::core::mem::size_of::<T>() + ::core::mem::size_of::<fuse_out_header>(),
+			)
+		};
+		if let Some(extra) = &mut self.extra_buffer {
+			vec![rawrsp, extra]
+		} else {
+			vec![rawrsp]
+		}
+	}
+}
+
+/// Id of the next request. Several requests may be in flight, so each one needs its own id.
+static NEXT_UNIQUE: AtomicU64 = AtomicU64::new(1);
+
+pub fn create_in_header<T>(opcode: Opcode) -> fuse_in_header
+where
+	T: FuseIn,
+{
+	fuse_in_header {
+		len: (core::mem::size_of::<fuse_in_header>() + core::mem::size_of::<T>()) as u32,
+		opcode: opcode as u32,
+		unique: NEXT_UNIQUE.fetch_add(1, Ordering::Relaxed),
+		nodeid: 0,
+		uid: 0,
+		pid: 0,
+		gid: 0,
+		padding: 0,
+	}
+}
+
+pub fn create_init(max_readahead: u32, flags: u32) -> (Cmd<fuse_init_in>, Rsp<fuse_init_out>) {
 	let cmd = fuse_init_in {
 		major: 7,
 		minor: 31,
-		max_readahead: 0,
-		flags: 0,
+		max_readahead,
+		flags,
+	};
+	let cmdhdr = create_in_header::<fuse_init_in>(Opcode::FUSE_INIT);
+	let rsp: fuse_init_out = Default::default();
+	let rsphdr: fuse_out_header = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+pub fn create_lookup(
+	parent: u64,
+	name: &str,
+) -> Result<(Cmd<fuse_lookup_in>, Rsp<fuse_entry_out>), FileError> {
+	let cmd = fuse_lookup_in::try_from(name)?;
+	let mut cmdhdr = create_in_header::<fuse_lookup_in>(Opcode::FUSE_LOOKUP);
+	cmdhdr.nodeid = parent;
+	let rsp: fuse_entry_out = Default::default();
+	let rsphdr: fuse_out_header = Default::default();
+	Ok((
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	))
+}
+
+pub fn create_forget(nid: u64, nlookup: u64) -> Cmd<fuse_forget_in> {
+	let cmd = fuse_forget_in { nlookup };
+	let mut cmdhdr = create_in_header::<fuse_forget_in>(Opcode::FUSE_FORGET);
+	cmdhdr.nodeid = nid;
+	Cmd {
+		cmd,
+		header: cmdhdr,
+		extra_buffer: None,
+	}
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_forget_in {
+	pub nlookup: u64,
+}
+unsafe impl FuseIn for fuse_forget_in {}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_in_header {
+	pub len: u32,
+	pub opcode: u32,
+	pub unique: u64,
+	pub nodeid: u64,
+	pub uid: u32,
+	pub gid: u32,
+	pub pid: u32,
+	pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct fuse_out_header {
+	pub len: u32,
+	pub error: i32,
+	pub unique: u64,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct fuse_init_in {
+	pub major: u32,
+	pub minor: u32,
+	pub max_readahead: u32,
+	pub flags: u32,
+}
+unsafe impl FuseIn for fuse_init_in {}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct fuse_init_out {
+	pub major: u32,
+	pub minor: u32,
+	pub max_readahead: u32,
+	pub flags: u32,
+	pub max_background: u16,
+	pub congestion_threshold: u16,
+	pub max_write: u32,
+	pub time_gran: u32,
+	pub max_pages: u16,
+	pub map_alignment: u16,
+	pub unused: [u32; 8],
+}
+unsafe impl FuseOut for fuse_init_out {}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_read_in {
+	pub fh: u64,
+	pub offset: u64,
+	pub size: u32,
+	pub read_flags: u32,
+	pub lock_owner: u64,
+	pub flags: u32,
+	pub padding: u32,
+}
+unsafe impl FuseIn for fuse_read_in {}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_read_out {}
+unsafe impl FuseOut for fuse_read_out {}
+
+/// Creates a request to read `size` bytes at `offset`. The data is received into the buffer
+/// passed to `send_request_with_data`.
+pub fn create_read(
+	nid: u64,
+	fh: u64,
+	size: u32,
+	offset: u64,
+) -> (Cmd<fuse_read_in>, Rsp<fuse_read_out>) {
+	let cmd = fuse_read_in {
+		fh,
+		offset,
+		size,
+		..Default::default()
+	};
+	let mut cmdhdr = create_in_header::<fuse_read_in>(Opcode::FUSE_READ);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_write_in {
+	pub fh: u64,
+	pub offset: u64,
+	pub size: u32,
+	pub write_flags: u32,
+	pub lock_owner: u64,
+	pub flags: u32,
+	pub padding: u32,
+}
+unsafe impl FuseIn for fuse_write_in {}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_write_out {
+	pub size: u32,
+	pub padding: u32,
+}
+unsafe impl FuseOut for fuse_write_out {}
+
+/// Creates a request to write `size` bytes at `offset`. The data is sent from the buffer
+/// passed to `send_request_with_data`.
+pub fn create_write(
+	nid: u64,
+	fh: u64,
+	size: u32,
+	offset: u64,
+) -> (Cmd<fuse_write_in>, Rsp<fuse_write_out>) {
+	let cmd = fuse_write_in {
+		fh,
+		offset,
+		size,
+		..Default::default()
 	};
-	let cmdhdr = create_in_header::<fuse_init_in>(Opcode::FUSE_INIT);
-	let rsp: fuse_init_out = Default::default();
+	let mut cmdhdr = create_in_header::<fuse_write_in>(Opcode::FUSE_WRITE);
+	cmdhdr.nodeid = nid;
+	// the data follows the command
+	cmdhdr.len += size;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_open_in {
+	pub flags: u32,
+	pub unused: u32,
+}
+unsafe impl FuseIn for fuse_open_in {}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_open_out {
+	pub fh: u64,
+	pub open_flags: u32,
+	pub padding: u32,
+}
+unsafe impl FuseOut for fuse_open_out {}
+
+pub fn create_open(nid: u64, flags: u32) -> (Cmd<fuse_open_in>, Rsp<fuse_open_out>) {
+	let cmd = fuse_open_in {
+		flags,
+		..Default::default()
+	};
+	let mut cmdhdr = create_in_header::<fuse_open_in>(Opcode::FUSE_OPEN);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_release_in {
+	pub fh: u64,
+	pub flags: u32,
+	pub release_flags: u32,
+	pub lock_owner: u64,
+}
+unsafe impl FuseIn for fuse_release_in {}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_release_out {}
+unsafe impl FuseOut for fuse_release_out {}
+
+/// Closes the file handle `fh`. If `flock_owner` is given, the server removes its whole-file lock.
+pub fn create_release(
+	nid: u64,
+	fh: u64,
+	flock_owner: Option<u64>,
+) -> (Cmd<fuse_release_in>, Rsp<fuse_release_out>) {
+	let mut cmd: fuse_release_in = Default::default();
+	let mut cmdhdr = create_in_header::<fuse_release_in>(Opcode::FUSE_RELEASE);
+	cmdhdr.nodeid = nid;
+	cmd.fh = fh;
+	if let Some(owner) = flock_owner {
+		cmd.release_flags = FUSE_RELEASE_FLOCK_UNLOCK;
+		cmd.lock_owner = owner;
+	}
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+/// Copies the name `s` into the NUL-terminated buffer `u8buf`.
+/// Fails with ENAMETOOLONG, if the name and its terminating NUL do not fit into the buffer.
+fn str_into_u8buf(s: &str, u8buf: &mut [u8]) -> Result<(), FileError> {
+	let bytes = s.as_bytes();
+	if bytes.len() >= u8buf.len() {
+		return Err(FileError::ENAMETOOLONG());
+	}
+	u8buf[..bytes.len()].copy_from_slice(bytes);
+
+	Ok(())
+}
+
+/// Size of the name buffers, which allows names of up to NAME_MAX (255) bytes
+const MAX_PATH_LEN: usize = 256;
+fn str_to_path(s: &str) -> Result<[u8; MAX_PATH_LEN], FileError> {
+	let mut buf = [0; MAX_PATH_LEN];
+	str_into_u8buf(s, &mut buf)?;
+	Ok(buf)
+}
+
+#[repr(C)]
+pub struct fuse_lookup_in {
+	pub name: [u8; MAX_PATH_LEN],
+}
+unsafe impl FuseIn for fuse_lookup_in {}
+
+impl TryFrom<&str> for fuse_lookup_in {
+	type Error = FileError;
+
+	fn try_from(name: &str) -> Result<Self, FileError> {
+		Ok(Self {
+			name: str_to_path(name)?,
+		})
+	}
+}
+
+impl fmt::Debug for fuse_lookup_in {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "fuse_lookup_in {{ {:?} }}", &self.name[..])
+	}
+}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_entry_out {
+	pub nodeid: u64,
+	pub generation: u64,
+	pub entry_valid: u64,
+	pub attr_valid: u64,
+	pub entry_valid_nsec: u32,
+	pub attr_valid_nsec: u32,
+	pub attr: fuse_attr,
+}
+unsafe impl FuseOut for fuse_entry_out {}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_attr {
+	pub ino: u64,
+	pub size: u64,
+	pub blocks: u64,
+	pub atime: u64,
+	pub mtime: u64,
+	pub ctime: u64,
+	pub atimensec: u32,
+	pub mtimensec: u32,
+	pub ctimensec: u32,
+	pub mode: u32,
+	pub nlink: u32,
+	pub uid: u32,
+	pub gid: u32,
+	pub rdev: u32,
+	pub blksize: u32,
+	pub padding: u32,
+}
+
+impl From<&fuse_attr> for FileAttr {
+	fn from(attr: &fuse_attr) -> Self {
+		Self {
+			ino: attr.ino,
+			mode: attr.mode,
+			nlink: attr.nlink.into(),
+			uid: attr.uid,
+			gid: attr.gid,
+			rdev: attr.rdev.into(),
+			size: attr.size,
+			blksize: attr.blksize.into(),
+			blocks: attr.blocks,
+			atime: Timespec {
+				sec: attr.atime as i64,
+				nsec: attr.atimensec.into(),
+			},
+			mtime: Timespec {
+				sec: attr.mtime as i64,
+				nsec: attr.mtimensec.into(),
+			},
+			ctime: Timespec {
+				sec: attr.ctime as i64,
+				nsec: attr.ctimensec.into(),
+			},
+		}
+	}
+}
+
+/// Flag of `fuse_getattr_in`, which marks `fh` as valid
+const FUSE_GETATTR_FH: u32 = 1 << 0;
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_getattr_in {
+	pub getattr_flags: u32,
+	pub dummy: u32,
+	pub fh: u64,
+}
+unsafe impl FuseIn for fuse_getattr_in {}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_attr_out {
+	pub attr_valid: u64,
+	pub attr_valid_nsec: u32,
+	pub dummy: u32,
+	pub attr: fuse_attr,
+}
+unsafe impl FuseOut for fuse_attr_out {}
+
+pub fn create_getattr(nid: u64, fh: Option<u64>) -> (Cmd<fuse_getattr_in>, Rsp<fuse_attr_out>) {
+	let cmd = match fh {
+		Some(fh) => fuse_getattr_in {
+			getattr_flags: FUSE_GETATTR_FH,
+			fh,
+			..Default::default()
+		},
+		None => Default::default(),
+	};
+	let mut cmdhdr = create_in_header::<fuse_getattr_in>(Opcode::FUSE_GETATTR);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+#[repr(C)]
+pub struct fuse_unlink_in {
+	pub name: [u8; MAX_PATH_LEN],
+}
+unsafe impl FuseIn for fuse_unlink_in {}
+
+impl TryFrom<&str> for fuse_unlink_in {
+	type Error = FileError;
+
+	fn try_from(name: &str) -> Result<Self, FileError> {
+		Ok(Self {
+			name: str_to_path(name)?,
+		})
+	}
+}
+
+impl fmt::Debug for fuse_unlink_in {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "fuse_unlink_in {{ {:?} }}", &self.name[..])
+	}
+}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_unlink_out {}
+unsafe impl FuseOut for fuse_unlink_out {}
+
+pub fn create_unlink(
+	parent: u64,
+	name: &str,
+) -> Result<(Cmd<fuse_unlink_in>, Rsp<fuse_unlink_out>), FileError> {
+	let cmd = fuse_unlink_in::try_from(name)?;
+	let mut cmdhdr = create_in_header::<fuse_unlink_in>(Opcode::FUSE_UNLINK);
+	cmdhdr.nodeid = parent;
+	let rsp: fuse_unlink_out = Default::default();
 	let rsphdr: fuse_out_header = Default::default();
+	Ok((
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	))
+}
+
+#[repr(C)]
+pub struct fuse_create_in {
+	pub flags: u32,
+	pub mode: u32,
+	pub umask: u32,
+	pub padding: u32,
+	pub name: [u8; MAX_PATH_LEN],
+}
+unsafe impl FuseIn for fuse_create_in {}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct fuse_create_out {
+	pub entry: fuse_entry_out,
+	pub open: fuse_open_out,
+}
+unsafe impl FuseOut for fuse_create_out {}
+
+impl fuse_create_in {
+	fn new(name: &str, flags: u32, mode: u32) -> Result<Self, FileError> {
+		Ok(Self {
+			flags,
+			mode,
+			umask: 0,
+			padding: 0,
+			name: str_to_path(name)?,
+		})
+	}
+}
+
+impl fmt::Debug for fuse_create_in {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"fuse_create_in {{ flags: {}, mode: {}, umask: {}, name: {:?} ...}}",
+			self.flags,
+			self.mode,
+			self.umask,
+			&self.name[..10]
+		)
+	}
+}
+
+pub fn create_create(
+	parent: u64,
+	name: &str,
+	flags: u32,
+	mode: u32,
+) -> Result<(Cmd<fuse_create_in>, Rsp<fuse_create_out>), FileError> {
+	let cmd = fuse_create_in::new(name, flags, mode)?;
+	let mut cmdhdr = create_in_header::<fuse_create_in>(Opcode::FUSE_CREATE);
+	cmdhdr.nodeid = parent;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	Ok((
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	))
+}
+
+#[repr(C)]
+pub struct fuse_mkdir_in {
+	pub mode: u32,
+	pub umask: u32,
+	pub name: [u8; MAX_PATH_LEN],
+}
+unsafe impl FuseIn for fuse_mkdir_in {}
+
+impl fuse_mkdir_in {
+	fn new(name: &str, mode: u32) -> Result<Self, FileError> {
+		Ok(Self {
+			mode,
+			umask: 0,
+			name: str_to_path(name)?,
+		})
+	}
+}
+
+impl fmt::Debug for fuse_mkdir_in {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"fuse_mkdir_in {{ mode: {}, umask: {}, name: {:?} ...}}",
+			self.mode,
+			self.umask,
+			&self.name[..10]
+		)
+	}
+}
+
+pub fn create_mkdir(
+	parent: u64,
+	name: &str,
+	mode: u32,
+) -> Result<(Cmd<fuse_mkdir_in>, Rsp<fuse_entry_out>), FileError> {
+	let cmd = fuse_mkdir_in::new(name, mode)?;
+	let mut cmdhdr = create_in_header::<fuse_mkdir_in>(Opcode::FUSE_MKDIR);
+	cmdhdr.nodeid = parent;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	Ok((
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	))
+}
+
+pub fn create_rmdir(
+	parent: u64,
+	name: &str,
+) -> Result<(Cmd<fuse_unlink_in>, Rsp<fuse_unlink_out>), FileError> {
+	// FUSE_RMDIR has the same layout as FUSE_UNLINK
+	let cmd = fuse_unlink_in::try_from(name)?;
+	let mut cmdhdr = create_in_header::<fuse_unlink_in>(Opcode::FUSE_RMDIR);
+	cmdhdr.nodeid = parent;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	Ok((
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	))
+}
+
+pub fn create_opendir(nid: u64) -> (Cmd<fuse_open_in>, Rsp<fuse_open_out>) {
+	let cmd = Default::default();
+	let mut cmdhdr = create_in_header::<fuse_open_in>(Opcode::FUSE_OPENDIR);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
 	(
 		Cmd {
 			cmd,
@@ -363,12 +2288,15 @@ pub fn create_init() -> (Cmd<fuse_init_in>, Rsp<fuse_init_out>) {
 	)
 }
 
-pub fn create_lookup(name: &str) -> (Cmd<fuse_lookup_in>, Rsp<fuse_entry_out>) {
-	let cmd = name.into();
-	let mut cmdhdr = create_in_header::<fuse_lookup_in>(Opcode::FUSE_LOOKUP);
-	cmdhdr.nodeid = FUSE_ROOT_ID;
-	let rsp: fuse_entry_out = Default::default();
-	let rsphdr: fuse_out_header = Default::default();
+pub fn create_releasedir(nid: u64, fh: u64) -> (Cmd<fuse_release_in>, Rsp<fuse_release_out>) {
+	let cmd = fuse_release_in {
+		fh,
+		..Default::default()
+	};
+	let mut cmdhdr = create_in_header::<fuse_release_in>(Opcode::FUSE_RELEASEDIR);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
 	(
 		Cmd {
 			cmd,
@@ -384,94 +2312,249 @@ pub fn create_lookup(name: &str) -> (Cmd<fuse_lookup_in>, Rsp<fuse_entry_out>) {
 }
 
 #[repr(C)]
-#[derive(Debug)]
-pub struct fuse_in_header {
-	pub len: u32,
-	pub opcode: u32,
-	pub unique: u64,
-	pub nodeid: u64,
-	pub uid: u32,
-	pub gid: u32,
-	pub pid: u32,
-	pub padding: u32,
+#[derive(Debug, Default)]
+pub struct fuse_dirent {
+	pub ino: u64,
+	pub off: u64,
+	pub namelen: u32,
+	pub typ: u32,
+	// followed by the name, which is padded to 8 bytes
 }
 
 #[repr(C)]
 #[derive(Debug, Default)]
-pub struct fuse_out_header {
-	pub len: u32,
-	pub error: i32,
-	pub unique: u64,
+pub struct fuse_direntplus {
+	pub entry_out: fuse_entry_out,
+	pub dirent: fuse_dirent,
+}
+
+pub fn create_readdirplus(
+	nid: u64,
+	fh: u64,
+	size: u32,
+	offset: u64,
+) -> (Cmd<fuse_read_in>, Rsp<fuse_read_out>) {
+	let cmd = fuse_read_in {
+		fh,
+		offset,
+		size,
+		..Default::default()
+	};
+	let mut cmdhdr = create_in_header::<fuse_read_in>(Opcode::FUSE_READDIRPLUS);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: Some(vec![0; size as usize]),
+		},
+	)
+}
+
+/// Encodes `names` as consecutive NUL-terminated strings, as expected by commands with several names.
+fn names_to_buffer(names: &[&str]) -> Vec<u8> {
+	let mut buf = Vec::with_capacity(names.iter().map(|name| name.len() + 1).sum());
+	for name in names {
+		buf.extend_from_slice(name.as_bytes());
+		buf.push(0);
+	}
+	buf
 }
 
 #[repr(C)]
-#[derive(Debug)]
-pub struct fuse_init_in {
-	pub major: u32,
-	pub minor: u32,
-	pub max_readahead: u32,
+#[derive(Default, Debug)]
+pub struct fuse_rename2_in {
+	pub newdir: u64,
 	pub flags: u32,
+	pub padding: u32,
+	// followed by the old and the new name
 }
-unsafe impl FuseIn for fuse_init_in {}
+unsafe impl FuseIn for fuse_rename2_in {}
 
 #[repr(C)]
-#[derive(Debug, Default)]
-pub struct fuse_init_out {
-	pub major: u32,
-	pub minor: u32,
-	pub max_readahead: u32,
-	pub flags: u32,
-	pub max_background: u16,
-	pub congestion_threshold: u16,
-	pub max_write: u32,
-	pub time_gran: u32,
-	pub unused: [u32; 9],
+#[derive(Default, Debug)]
+pub struct fuse_rename_out {}
+unsafe impl FuseOut for fuse_rename_out {}
+
+pub fn create_rename(
+	oldparent: u64,
+	oldname: &str,
+	newparent: u64,
+	newname: &str,
+) -> (Cmd<fuse_rename2_in>, Rsp<fuse_rename_out>) {
+	let cmd = fuse_rename2_in {
+		newdir: newparent,
+		..Default::default()
+	};
+	let names = names_to_buffer(&[oldname, newname]);
+	let mut cmdhdr = create_in_header::<fuse_rename2_in>(Opcode::FUSE_RENAME2);
+	cmdhdr.nodeid = oldparent;
+	cmdhdr.len = (core::mem::size_of::<fuse_in_header>()
+		+ core::mem::size_of::<fuse_rename2_in>()
+		+ names.len()) as u32;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: Some(names),
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_link_in {
+	pub oldnodeid: u64,
+	// followed by the new name
+}
+unsafe impl FuseIn for fuse_link_in {}
+
+pub fn create_link(
+	nid: u64,
+	newparent: u64,
+	newname: &str,
+) -> (Cmd<fuse_link_in>, Rsp<fuse_entry_out>) {
+	let cmd = fuse_link_in { oldnodeid: nid };
+	let name = names_to_buffer(&[newname]);
+	let mut cmdhdr = create_in_header::<fuse_link_in>(Opcode::FUSE_LINK);
+	cmdhdr.nodeid = newparent;
+	cmdhdr.len = (core::mem::size_of::<fuse_in_header>()
+		+ core::mem::size_of::<fuse_link_in>()
+		+ name.len()) as u32;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: Some(name),
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_symlink_in {
+	// only consists of the name of the link and its target
+}
+unsafe impl FuseIn for fuse_symlink_in {}
+
+pub fn create_symlink(
+	parent: u64,
+	name: &str,
+	target: &str,
+) -> (Cmd<fuse_symlink_in>, Rsp<fuse_entry_out>) {
+	let cmd = Default::default();
+	let names = names_to_buffer(&[name, target]);
+	let mut cmdhdr = create_in_header::<fuse_symlink_in>(Opcode::FUSE_SYMLINK);
+	cmdhdr.nodeid = parent;
+	cmdhdr.len = (core::mem::size_of::<fuse_in_header>() + names.len()) as u32;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: Some(names),
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
+}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_readlink_in {}
+unsafe impl FuseIn for fuse_readlink_in {}
+
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_readlink_out {
+	// only consists of the link target
+}
+unsafe impl FuseOut for fuse_readlink_out {}
+
+pub fn create_readlink(nid: u64) -> (Cmd<fuse_readlink_in>, Rsp<fuse_readlink_out>) {
+	let cmd = Default::default();
+	let mut cmdhdr = create_in_header::<fuse_readlink_in>(Opcode::FUSE_READLINK);
+	cmdhdr.nodeid = nid;
+	cmdhdr.len = core::mem::size_of::<fuse_in_header>() as u32;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: Some(vec![0; MAX_READLINK_LEN]),
+		},
+	)
 }
-unsafe impl FuseOut for fuse_init_out {}
+
+/// Flags of `fuse_setattr_in`, which mark the fields to be changed
+const FATTR_SIZE: u32 = 1 << 3;
+const FATTR_FH: u32 = 1 << 6;
 
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_read_in {
+pub struct fuse_setattr_in {
+	pub valid: u32,
+	pub padding: u32,
 	pub fh: u64,
-	pub offset: u64,
-	pub size: u32,
-	pub read_flags: u32,
+	pub size: u64,
 	pub lock_owner: u64,
-	pub flags: u32,
-	pub padding: u32,
+	pub atime: u64,
+	pub mtime: u64,
+	pub ctime: u64,
+	pub atimensec: u32,
+	pub mtimensec: u32,
+	pub ctimensec: u32,
+	pub mode: u32,
+	pub unused4: u32,
+	pub uid: u32,
+	pub gid: u32,
+	pub unused5: u32,
 }
-unsafe impl FuseIn for fuse_read_in {}
-
-#[repr(C)]
-#[derive(Default, Debug)]
-pub struct fuse_read_out {}
-unsafe impl FuseOut for fuse_read_out {}
+unsafe impl FuseIn for fuse_setattr_in {}
 
-pub fn create_read(nid: u64, size: u32, offset: u64) -> (Cmd<fuse_read_in>, Rsp<fuse_read_out>) {
-	let cmd = fuse_read_in {
-		offset,
+/// Changes the size of the opened file `fh` to `size` bytes.
+pub fn create_truncate(nid: u64, fh: u64, size: u64) -> (Cmd<fuse_setattr_in>, Rsp<fuse_attr_out>) {
+	let cmd = fuse_setattr_in {
+		valid: FATTR_SIZE | FATTR_FH,
+		fh,
 		size,
 		..Default::default()
 	};
-	let mut cmdhdr = create_in_header::<fuse_read_in>(Opcode::FUSE_READ);
+	let mut cmdhdr = create_in_header::<fuse_setattr_in>(Opcode::FUSE_SETATTR);
 	cmdhdr.nodeid = nid;
 	let rsp = Default::default();
 	let rsphdr = Default::default();
-	// direct-io requires aligned memory.
-	// ugly hack from https://stackoverflow.com/questions/60180121/how-do-i-allocate-a-vecu8-that-is-aligned-to-the-size-of-the-cache-line
-	let mut aligned: Vec<AlignToPage> =
-		Vec::with_capacity(size as usize / ::core::mem::size_of::<AlignToPage>() + 1);
-	let ptr = aligned.as_mut_ptr();
-	let cap_units = aligned.capacity();
-	::core::mem::forget(aligned);
-	let readbuf = unsafe {
-		Vec::from_raw_parts(
-			ptr as *mut u8,
-			size as usize,
-			cap_units * ::core::mem::size_of::<AlignToPage>(),
-		)
-	};
-	// let readbuf = vec![0; size as usize];
 	(
 		Cmd {
 			cmd,
@@ -481,73 +2564,49 @@ pub fn create_read(nid: u64, size: u32, offset: u64) -> (Cmd<fuse_read_in>, Rsp<
 		Rsp {
 			rsp,
 			header: rsphdr,
-			extra_buffer: Some(readbuf),
+			extra_buffer: None,
 		},
 	)
 }
 
+/// Flag of `fuse_fsync_in`, which restricts the synchronization to the file content
+const FUSE_FSYNC_FDATASYNC: u32 = 1 << 0;
+
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_write_in {
+pub struct fuse_fsync_in {
 	pub fh: u64,
-	pub offset: u64,
-	pub size: u32,
-	pub write_flags: u32,
-	pub lock_owner: u64,
-	pub flags: u32,
+	pub fsync_flags: u32,
 	pub padding: u32,
 }
-unsafe impl FuseIn for fuse_write_in {}
+unsafe impl FuseIn for fuse_fsync_in {}
 
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_write_out {
-	pub size: u32,
-	pub padding: u32,
-}
-unsafe impl FuseOut for fuse_write_out {}
+pub struct fuse_fsync_out {}
+unsafe impl FuseOut for fuse_fsync_out {}
 
-#[repr(C, align(4096))]
-struct AlignToPage([u8; 4096]);
-// TODO: do write zerocopy? currently does buf.to_vec()
-// problem: i cannot create owned type, since this would deallocate memory on drop. But memory belongs to userspace!
-//          Using references, i have to be careful of lifetimes!
-pub fn create_write(
+/// Flushes the opened file (or directory, if `opcode` is FUSE_FSYNCDIR) `fh` to the storage of the host.
+pub fn create_fsync(
+	opcode: Opcode,
 	nid: u64,
-	buf: &[u8],
-	offset: u64,
-) -> (Cmd<fuse_write_in>, Rsp<fuse_write_out>) {
-	let cmd = fuse_write_in {
-		offset,
-		size: buf.len() as u32,
+	fh: u64,
+	datasync: bool,
+) -> (Cmd<fuse_fsync_in>, Rsp<fuse_fsync_out>) {
+	let cmd = fuse_fsync_in {
+		fh,
+		fsync_flags: if datasync { FUSE_FSYNC_FDATASYNC } else { 0 },
 		..Default::default()
 	};
-	let mut cmdhdr = create_in_header::<fuse_write_in>(Opcode::FUSE_WRITE);
+	let mut cmdhdr = create_in_header::<fuse_fsync_in>(opcode);
 	cmdhdr.nodeid = nid;
 	let rsp = Default::default();
 	let rsphdr = Default::default();
-
-	//direct-io requires aligned memory.
-	// ugly hack from https://stackoverflow.com/questions/60180121/how-do-i-allocate-a-vecu8-that-is-aligned-to-the-size-of-the-cache-line
-	let mut aligned: Vec<AlignToPage> =
-		Vec::with_capacity(buf.len() / ::core::mem::size_of::<AlignToPage>() + 1);
-	let ptr = aligned.as_mut_ptr();
-	let cap_units = aligned.capacity();
-	::core::mem::forget(aligned);
-	let mut writebuf = unsafe {
-		Vec::from_raw_parts(
-			ptr as *mut u8,
-			buf.len(),
-			cap_units * ::core::mem::size_of::<AlignToPage>(),
-		)
-	};
-	writebuf.clone_from_slice(buf);
-	// let writebuf = buf.to_vec();
 	(
 		Cmd {
 			cmd,
 			header: cmdhdr,
-			extra_buffer: Some(writebuf),
+			extra_buffer: None,
 		},
 		Rsp {
 			rsp,
@@ -559,27 +2618,35 @@ pub fn create_write(
 
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_open_in {
-	pub flags: u32,
-	pub unused: u32,
+pub struct fuse_fallocate_in {
+	pub fh: u64,
+	pub offset: u64,
+	pub length: u64,
+	pub mode: u32,
+	pub padding: u32,
 }
-unsafe impl FuseIn for fuse_open_in {}
+unsafe impl FuseIn for fuse_fallocate_in {}
 
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_open_out {
-	pub fh: u64,
-	pub open_flags: u32,
-	pub padding: u32,
-}
-unsafe impl FuseOut for fuse_open_out {}
+pub struct fuse_fallocate_out {}
+unsafe impl FuseOut for fuse_fallocate_out {}
 
-pub fn create_open(nid: u64, flags: u32) -> (Cmd<fuse_open_in>, Rsp<fuse_open_out>) {
-	let cmd = fuse_open_in {
-		flags,
+pub fn create_fallocate(
+	nid: u64,
+	fh: u64,
+	mode: u32,
+	offset: u64,
+	length: u64,
+) -> (Cmd<fuse_fallocate_in>, Rsp<fuse_fallocate_out>) {
+	let cmd = fuse_fallocate_in {
+		fh,
+		offset,
+		length,
+		mode,
 		..Default::default()
 	};
-	let mut cmdhdr = create_in_header::<fuse_open_in>(Opcode::FUSE_OPEN);
+	let mut cmdhdr = create_in_header::<fuse_fallocate_in>(Opcode::FUSE_FALLOCATE);
 	cmdhdr.nodeid = nid;
 	let rsp = Default::default();
 	let rsphdr = Default::default();
@@ -599,24 +2666,36 @@ pub fn create_open(nid: u64, flags: u32) -> (Cmd<fuse_open_in>, Rsp<fuse_open_ou
 
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_release_in {
+pub struct fuse_lseek_in {
 	pub fh: u64,
-	pub flags: u32,
-	pub release_flags: u32,
-	pub lock_owner: u64,
+	pub offset: u64,
+	pub whence: u32,
+	pub padding: u32,
 }
-unsafe impl FuseIn for fuse_release_in {}
+unsafe impl FuseIn for fuse_lseek_in {}
 
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_release_out {}
-unsafe impl FuseOut for fuse_release_out {}
+pub struct fuse_lseek_out {
+	pub offset: u64,
+}
+unsafe impl FuseOut for fuse_lseek_out {}
 
-pub fn create_release(nid: u64, fh: u64) -> (Cmd<fuse_release_in>, Rsp<fuse_release_out>) {
-	let mut cmd: fuse_release_in = Default::default();
-	let mut cmdhdr = create_in_header::<fuse_release_in>(Opcode::FUSE_RELEASE);
+/// Searches for data or a hole (depending on `whence`, SEEK_DATA or SEEK_HOLE) at or behind `offset`.
+pub fn create_lseek(
+	nid: u64,
+	fh: u64,
+	offset: u64,
+	whence: u32,
+) -> (Cmd<fuse_lseek_in>, Rsp<fuse_lseek_out>) {
+	let cmd = fuse_lseek_in {
+		fh,
+		offset,
+		whence,
+		..Default::default()
+	};
+	let mut cmdhdr = create_in_header::<fuse_lseek_in>(Opcode::FUSE_LSEEK);
 	cmdhdr.nodeid = nid;
-	cmd.fh = fh;
 	let rsp = Default::default();
 	let rsphdr = Default::default();
 	(
@@ -633,110 +2712,161 @@ pub fn create_release(nid: u64, fh: u64) -> (Cmd<fuse_release_in>, Rsp<fuse_rele
 	)
 }
 
-fn str_into_u8buf(s: &str, u8buf: &mut [u8]) {
-	// TODO: fix this hacky conversion..
-	for (i, c) in s.chars().enumerate() {
-		u8buf[i] = c as u8;
-		if i > u8buf.len() {
-			warn!("FUSE: Name too long!");
-			break;
-		}
-	}
-}
-
-// TODO: max path length?
-const MAX_PATH_LEN: usize = 256;
-fn str_to_path(s: &str) -> [u8; MAX_PATH_LEN] {
-	let mut buf = [0; MAX_PATH_LEN];
-	str_into_u8buf(s, &mut buf);
-	buf
-}
-
 #[repr(C)]
-pub struct fuse_lookup_in {
-	pub name: [u8; MAX_PATH_LEN],
+#[derive(Default, Debug, Clone, Copy)]
+pub struct fuse_file_lock {
+	pub start: u64,
+	/// Last byte of the lock, OFFSET_MAX extends the lock to the end of the file
+	pub end: u64,
+	pub typ: u32,
+	pub pid: u32,
 }
-unsafe impl FuseIn for fuse_lookup_in {}
 
-impl From<&str> for fuse_lookup_in {
-	fn from(name: &str) -> Self {
+impl From<&FileLock> for fuse_file_lock {
+	fn from(lock: &FileLock) -> Self {
 		Self {
-			name: str_to_path(name),
+			start: lock.start,
+			end: lock.end,
+			typ: match lock.typ {
+				LockType::Read => F_RDLCK,
+				LockType::Write => F_WRLCK,
+				LockType::Unlock => F_UNLCK,
+			},
+			pid: lock.pid,
 		}
 	}
 }
 
-impl fmt::Debug for fuse_lookup_in {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "fuse_lookup_in {{ {:?} }}", &self.name[..])
-	}
-}
-
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_entry_out {
-	pub nodeid: u64,
-	pub generation: u64,
-	pub entry_valid: u64,
-	pub attr_valid: u64,
-	pub entry_valid_nsec: u32,
-	pub attr_valid_nsec: u32,
-	pub attr: fuse_attr,
+pub struct fuse_lk_in {
+	pub fh: u64,
+	pub owner: u64,
+	pub lk: fuse_file_lock,
+	pub lk_flags: u32,
+	pub padding: u32,
 }
-unsafe impl FuseOut for fuse_entry_out {}
+unsafe impl FuseIn for fuse_lk_in {}
 
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_attr {
-	pub ino: u64,
-	pub size: u64,
-	pub blocks: u64,
-	pub atime: u64,
-	pub mtime: u64,
-	pub ctime: u64,
-	pub atimensec: u32,
-	pub mtimensec: u32,
-	pub ctimensec: u32,
-	pub mode: u32,
-	pub nlink: u32,
-	pub uid: u32,
-	pub gid: u32,
-	pub rdev: u32,
-	pub blksize: u32,
-	pub padding: u32,
+pub struct fuse_lk_out {
+	pub lk: fuse_file_lock,
 }
+unsafe impl FuseOut for fuse_lk_out {}
 
 #[repr(C)]
-pub struct fuse_unlink_in {
-	pub name: [u8; MAX_PATH_LEN],
+#[derive(Default, Debug)]
+pub struct fuse_setlk_out {}
+unsafe impl FuseOut for fuse_setlk_out {}
+
+/// Asks for a lock of another owner, which conflicts with `lk`.
+pub fn create_getlk(
+	nid: u64,
+	fh: u64,
+	owner: u64,
+	lk: fuse_file_lock,
+) -> (Cmd<fuse_lk_in>, Rsp<fuse_lk_out>) {
+	let cmd = fuse_lk_in {
+		fh,
+		owner,
+		lk,
+		..Default::default()
+	};
+	let mut cmdhdr = create_in_header::<fuse_lk_in>(Opcode::FUSE_GETLK);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
 }
-unsafe impl FuseIn for fuse_unlink_in {}
 
-impl From<&str> for fuse_unlink_in {
-	fn from(name: &str) -> Self {
-		Self {
-			name: str_to_path(name),
-		}
-	}
+/// Places or removes the lock `lk` of `owner`. With FUSE_SETLKW, the server replies
+/// once conflicting locks have been released.
+pub fn create_setlk(
+	opcode: Opcode,
+	nid: u64,
+	fh: u64,
+	owner: u64,
+	lk: fuse_file_lock,
+	lk_flags: u32,
+) -> (Cmd<fuse_lk_in>, Rsp<fuse_setlk_out>) {
+	let cmd = fuse_lk_in {
+		fh,
+		owner,
+		lk,
+		lk_flags,
+		..Default::default()
+	};
+	let mut cmdhdr = create_in_header::<fuse_lk_in>(opcode);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
+	(
+		Cmd {
+			cmd,
+			header: cmdhdr,
+			extra_buffer: None,
+		},
+		Rsp {
+			rsp,
+			header: rsphdr,
+			extra_buffer: None,
+		},
+	)
 }
 
-impl fmt::Debug for fuse_unlink_in {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "fuse_unlink_in {{ {:?} }}", &self.name[..])
-	}
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_setupmapping_in {
+	/// An already open handle
+	pub fh: u64,
+	/// Offset into the file to start the mapping
+	pub foffset: u64,
+	/// Length of mapping required
+	pub len: u64,
+	/// Flags, FUSE_SETUPMAPPING_FLAG_*
+	pub flags: u64,
+	/// Offset in the DAX window
+	pub moffset: u64,
 }
+unsafe impl FuseIn for fuse_setupmapping_in {}
 
 #[repr(C)]
 #[derive(Default, Debug)]
-pub struct fuse_unlink_out {}
-unsafe impl FuseOut for fuse_unlink_out {}
+pub struct fuse_setupmapping_out {}
+unsafe impl FuseOut for fuse_setupmapping_out {}
 
-pub fn create_unlink(name: &str) -> (Cmd<fuse_unlink_in>, Rsp<fuse_unlink_out>) {
-	let cmd = name.into();
-	let mut cmdhdr = create_in_header::<fuse_unlink_in>(Opcode::FUSE_UNLINK);
-	cmdhdr.nodeid = FUSE_ROOT_ID;
-	let rsp: fuse_unlink_out = Default::default();
-	let rsphdr: fuse_out_header = Default::default();
+/// Maps `len` bytes of the file at `foffset` into the DAX window at `moffset`.
+pub fn create_setupmapping(
+	nid: u64,
+	fh: u64,
+	foffset: u64,
+	len: u64,
+	flags: u64,
+	moffset: u64,
+) -> (Cmd<fuse_setupmapping_in>, Rsp<fuse_setupmapping_out>) {
+	let cmd = fuse_setupmapping_in {
+		fh,
+		foffset,
+		len,
+		flags,
+		moffset,
+	};
+	let mut cmdhdr = create_in_header::<fuse_setupmapping_in>(Opcode::FUSE_SETUPMAPPING);
+	cmdhdr.nodeid = nid;
+	let rsp = Default::default();
+	let rsphdr = Default::default();
 	(
 		Cmd {
 			cmd,
@@ -752,63 +2882,53 @@ pub fn create_unlink(name: &str) -> (Cmd<fuse_unlink_in>, Rsp<fuse_unlink_out>)
 }
 
 #[repr(C)]
-pub struct fuse_create_in {
-	pub flags: u32,
-	pub mode: u32,
-	pub umask: u32,
-	pub padding: u32,
-	pub name: [u8; MAX_PATH_LEN],
+#[derive(Default, Debug)]
+pub struct fuse_removemapping_in {
+	/// Number of `fuse_removemapping_one`, which follow
+	pub count: u32,
 }
-unsafe impl FuseIn for fuse_create_in {}
+unsafe impl FuseIn for fuse_removemapping_in {}
 
 #[repr(C)]
-#[derive(Debug, Default)]
-pub struct fuse_create_out {
-	pub entry: fuse_entry_out,
-	pub open: fuse_open_out,
+#[derive(Default, Debug)]
+pub struct fuse_removemapping_one {
+	/// Offset in the DAX window
+	pub moffset: u64,
+	/// Length of the mapping
+	pub len: u64,
 }
-unsafe impl FuseOut for fuse_create_out {}
 
-impl fuse_create_in {
-	fn new(name: &str, flags: u32, mode: u32) -> Self {
-		Self {
-			flags,
-			mode,
-			umask: 0,
-			padding: 0,
-			name: str_to_path(name),
-		}
-	}
-}
+#[repr(C)]
+#[derive(Default, Debug)]
+pub struct fuse_removemapping_out {}
+unsafe impl FuseOut for fuse_removemapping_out {}
 
-impl fmt::Debug for fuse_create_in {
-	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(
-			f,
-			"fuse_create_in {{ flags: {}, mode: {}, umask: {}, name: {:?} ...}}",
-			self.flags,
-			self.mode,
-			self.umask,
-			&self.name[..10]
-		)
+/// Removes the mappings of the DAX window, which are given by their offset and length.
+pub fn create_removemapping(
+	nid: u64,
+	ranges: &[(u64, u64)],
+) -> (Cmd<fuse_removemapping_in>, Rsp<fuse_removemapping_out>) {
+	let cmd = fuse_removemapping_in {
+		count: ranges.len() as u32,
+	};
+	let mut entries =
+		Vec::with_capacity(ranges.len() * core::mem::size_of::<fuse_removemapping_one>());
+	for (moffset, len) in ranges {
+		entries.extend_from_slice(&moffset.to_le_bytes());
+		entries.extend_from_slice(&len.to_le_bytes());
 	}
-}
-
-pub fn create_create(
-	path: &str,
-	flags: u32,
-	mode: u32,
-) -> (Cmd<fuse_create_in>, Rsp<fuse_create_out>) {
-	let cmd = fuse_create_in::new(path, flags, mode);
-	let mut cmdhdr = create_in_header::<fuse_create_in>(Opcode::FUSE_CREATE);
-	cmdhdr.nodeid = FUSE_ROOT_ID;
+	let mut cmdhdr = create_in_header::<fuse_removemapping_in>(Opcode::FUSE_REMOVEMAPPING);
+	cmdhdr.nodeid = nid;
+	cmdhdr.len = (core::mem::size_of::<fuse_in_header>()
+		+ core::mem::size_of::<fuse_removemapping_in>()
+		+ entries.len()) as u32;
 	let rsp = Default::default();
 	let rsphdr = Default::default();
 	(
 		Cmd {
 			cmd,
 			header: cmdhdr,
-			extra_buffer: None,
+			extra_buffer: Some(entries),
 		},
 		Rsp {
 			rsp,
@@ -2,7 +2,7 @@ use alloc::collections::BTreeMap;
 use core::convert::TryInto;
 #[cfg(feature = "newlib")]
 use core::slice;
-use core::{intrinsics, ptr};
+use core::{fmt, intrinsics, ptr};
 
 use x86::controlregs::{cr0, cr0_write, cr4, Cr0};
 
@@ -439,6 +439,26 @@ fn finish_processor_init() {
 	}
 }
 
+/// Writes the number of interrupts, which each core has handled, one line per core and interrupt.
+pub fn write_statistics(f: &mut dyn fmt::Write) -> fmt::Result {
+	writeln!(
+		f,
+		"{:>4} {:>4} {:<24} {:>12}",
+		"CORE", "IRQ", "NAME", "COUNT"
+	)?;
+	unsafe {
+		for (core_id, irq_statistics) in IRQ_COUNTERS.iter() {
+			for (i, counter) in irq_statistics.counters.iter().enumerate() {
+				if *counter > 0 {
+					let name = get_irq_name(i.try_into().unwrap()).unwrap_or("");
+					writeln!(f, "{:>4} {:>4} {:<24} {:>12}", core_id, i, name, counter)?;
+				}
+			}
+		}
+	}
+	Ok(())
+}
+
 pub fn print_statistics() {
 	info!("Number of interrupts");
 	unsafe {
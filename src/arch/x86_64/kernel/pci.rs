@@ -1,10 +1,11 @@
 use crate::arch::x86_64::kernel::pci_ids::{CLASSES, VENDORS};
 use crate::arch::x86_64::mm::{PhysAddr, VirtAddr};
 use crate::collections::irqsave;
+use crate::drivers::block::virtio_blk::{self, VirtioBlkDriver};
+use crate::drivers::fs::virtio_fs::{self, VirtioFsDriver};
 use crate::drivers::net::rtl8139::{self, RTL8139Driver};
 use crate::drivers::net::virtio_net::VirtioNetDriver;
 use crate::drivers::net::NetworkInterface;
-use crate::drivers::virtio::depr::virtio_fs::VirtioFsDriver;
 use crate::drivers::virtio::transport::pci as pci_virtio;
 use crate::drivers::virtio::transport::pci::VirtioDriver;
 use crate::synch::spinlock::SpinlockIrqSave;
@@ -45,7 +46,7 @@ pub const PCI_MULTIFUNCTION_MASK: u32 = 0x0080_0000;
 pub const PCI_CAP_ID_VNDR: u32 = 0x09;
 
 static mut PCI_ADAPTERS: Vec<PciAdapter> = Vec::new();
-static mut PCI_DRIVERS: Vec<PciDriver<'_>> = Vec::new();
+static mut PCI_DRIVERS: Vec<PciDriver> = Vec::new();
 
 /// Classes of PCI nodes.
 #[derive(Copy, Clone, Debug, FromPrimitive, ToPrimitive, PartialEq)]
@@ -117,13 +118,14 @@ pub struct MemoryBar {
 	pub prefetchable: bool,
 }
 
-pub enum PciDriver<'a> {
-	VirtioFs(SpinlockIrqSave<VirtioFsDriver<'a>>),
+pub enum PciDriver {
+	VirtioFs(SpinlockIrqSave<VirtioFsDriver>),
+	VirtioBlk(SpinlockIrqSave<VirtioBlkDriver>),
 	VirtioNet(SpinlockIrqSave<VirtioNetDriver>),
 	RTL8139Net(SpinlockIrqSave<RTL8139Driver>),
 }
 
-impl<'a> PciDriver<'a> {
+impl PciDriver {
 	fn get_network_driver(&self) -> Option<&SpinlockIrqSave<dyn NetworkInterface>> {
 		match self {
 			Self::VirtioNet(drv) => Some(drv),
@@ -132,14 +134,21 @@ impl<'a> PciDriver<'a> {
 		}
 	}
 
-	fn get_filesystem_driver(&self) -> Option<&SpinlockIrqSave<VirtioFsDriver<'a>>> {
+	fn get_filesystem_driver(&self) -> Option<&SpinlockIrqSave<VirtioFsDriver>> {
 		match self {
 			Self::VirtioFs(drv) => Some(drv),
 			_ => None,
 		}
 	}
+
+	fn get_block_driver(&self) -> Option<&SpinlockIrqSave<VirtioBlkDriver>> {
+		match self {
+			Self::VirtioBlk(drv) => Some(drv),
+			_ => None,
+		}
+	}
 }
-pub fn register_driver(drv: PciDriver<'static>) {
+pub fn register_driver(drv: PciDriver) {
 	unsafe {
 		PCI_DRIVERS.push(drv);
 	}
@@ -149,14 +158,20 @@ pub fn get_network_driver() -> Option<&'static SpinlockIrqSave<dyn NetworkInterf
 	unsafe { PCI_DRIVERS.iter().find_map(|drv| drv.get_network_driver()) }
 }
 
-pub fn get_filesystem_driver() -> Option<&'static SpinlockIrqSave<VirtioFsDriver<'static>>> {
+/// Returns the drivers of all virtio filesystem devices in the order of their discovery.
+pub fn get_filesystem_drivers() -> impl Iterator<Item = &'static SpinlockIrqSave<VirtioFsDriver>> {
 	unsafe {
 		PCI_DRIVERS
 			.iter()
-			.find_map(|drv| drv.get_filesystem_driver())
+			.filter_map(|drv| drv.get_filesystem_driver())
 	}
 }
 
+/// Returns the drivers of all virtio block devices in the order of their discovery.
+pub fn get_block_drivers() -> impl Iterator<Item = &'static SpinlockIrqSave<VirtioBlkDriver>> {
+	unsafe { PCI_DRIVERS.iter().filter_map(|drv| drv.get_block_driver()) }
+}
+
 /// Reads all bar registers of specified device and returns vector of PciBar's containing addresses and sizes.
 fn parse_bars(bus: u8, device: u8, vendor_id: u16, device_id: u16) -> Vec<PciBar> {
 	let mut bar_idxs = 0..6;
@@ -504,13 +519,22 @@ pub fn init_drivers() {
 				.filter(|x| x.vendor_id == 0x1AF4 && x.device_id >= 0x1000 && x.device_id <= 0x107F)
 		} {
 			info!(
-				"Found virtio network device with device id {:#x}",
+				"Found virtio device with device id {:#x}",
 				adapter.device_id
 			);
 
-			if let Ok(VirtioDriver::Network(drv)) = pci_virtio::init_device(adapter) {
-				nic_available = true;
-				register_driver(PciDriver::VirtioNet(SpinlockIrqSave::new(drv)))
+			match pci_virtio::init_device(adapter) {
+				Ok(VirtioDriver::Network(drv)) => {
+					nic_available = true;
+					register_driver(PciDriver::VirtioNet(SpinlockIrqSave::new(drv)))
+				}
+				Ok(VirtioDriver::FileSystem(drv)) => {
+					register_driver(PciDriver::VirtioFs(SpinlockIrqSave::new(drv)))
+				}
+				Ok(VirtioDriver::Block(drv)) => {
+					register_driver(PciDriver::VirtioBlk(SpinlockIrqSave::new(drv)))
+				}
+				Err(_) => {}
 			}
 		}
 
@@ -533,6 +557,13 @@ pub fn init_drivers() {
 			}
 		}
 	});
+
+	// The FUSE session is started with interrupts enabled, since its
+	// requests wait for the completion interrupt of the device.
+	virtio_fs::init_fs();
+
+	// The devices are registered after the discovery, since the drivers must not move anymore.
+	virtio_blk::init_block();
 }
 
 pub fn print_information() {
@@ -545,6 +576,14 @@ pub fn print_information() {
 	infofooter!();
 }
 
+/// Writes the detected PCI devices, one line per device.
+pub fn write_information(f: &mut dyn fmt::Write) -> fmt::Result {
+	for adapter in unsafe { PCI_ADAPTERS.iter() } {
+		writeln!(f, "{}", adapter)?;
+	}
+	Ok(())
+}
+
 /// A module containg PCI specifc errors
 ///
 /// Errors include...
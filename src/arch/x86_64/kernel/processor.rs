@@ -866,6 +866,25 @@ pub fn print_information() {
 	infofooter!();
 }
 
+/// Writes the information of `print_information` as `name: value` lines.
+pub fn write_information(f: &mut dyn fmt::Write) -> fmt::Result {
+	let cpuid = CpuId::new();
+
+	if let Some(brand_string) = cpuid.get_processor_brand_string() {
+		writeln!(f, "model name: {}", brand_string.as_str())?;
+	}
+	unsafe {
+		writeln!(f, "frequency: {}", CPU_FREQUENCY)?;
+	}
+	writeln!(f, "features: {}", CpuFeaturePrinter::new(&cpuid))?;
+	writeln!(
+		f,
+		"address sizes: {} bits physical, {} bits virtual",
+		get_physical_address_bits(),
+		get_linear_address_bits()
+	)
+}
+
 /*#[cfg(not(any(target_os = "hermit", target_os = "none")))]
 #[test]
 fn print_cpu_information() {
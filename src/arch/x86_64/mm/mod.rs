@@ -5,6 +5,9 @@ pub mod virtualmem;
 pub use self::paging::init_page_tables;
 use core::mem;
 use core::slice;
+use multiboot::information::Multiboot;
+
+use crate::arch::x86_64::kernel::get_mbinfo;
 
 pub use x86::bits64::paging::PAddr as PhysAddr;
 pub use x86::bits64::paging::VAddr as VirtAddr;
@@ -44,6 +47,23 @@ impl multiboot::information::MemoryManagement for MultibootMemory {
 
 static mut MEM: MultibootMemory = MultibootMemory::new();
 
+/// Returns the physical address range `[start, end)` of the first Multiboot module.
+/// Loaders use this module to pass an initial ramdisk to the kernel.
+pub fn get_initrd_region() -> Option<(PhysAddr, PhysAddr)> {
+	let mb_info = get_mbinfo();
+	if mb_info.is_zero() {
+		return None;
+	}
+
+	let mb = unsafe { Multiboot::from_ptr(mb_info.as_u64(), &mut MEM)? };
+	let module = mb.modules()?.next()?;
+	if module.end <= module.start {
+		return None;
+	}
+
+	Some((PhysAddr(module.start), PhysAddr(module.end)))
+}
+
 pub fn init() {
 	paging::init();
 	physicalmem::init();
@@ -768,6 +768,12 @@ pub fn init_page_tables() {
 				.expect("Could not first map address")
 				.base_address();
 			identity_map(PhysAddr(memory_map_address), PhysAddr(memory_map_address));
+
+			// Map the initial ramdisk, which is accessed in place by the initrd filesystem.
+			if let Some((start, end)) = super::get_initrd_region() {
+				info!("Found initial ramdisk at {:#x} - {:#x}", start, end);
+				identity_map(start, end - 1u64);
+			}
 		}
 
 		let cmdsize = environment::get_cmdsize();
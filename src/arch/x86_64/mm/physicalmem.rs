@@ -4,7 +4,7 @@ use multiboot::information::{MemoryType, Multiboot};
 
 use crate::arch::x86_64::kernel::{get_limit, get_mbinfo};
 use crate::arch::x86_64::mm::paging::{BasePageSize, PageSize};
-use crate::arch::x86_64::mm::MEM;
+use crate::arch::x86_64::mm::{get_initrd_region, MEM};
 use crate::arch::x86_64::mm::{PhysAddr, VirtAddr};
 use crate::mm;
 use crate::mm::freelist::{FreeList, FreeListEntry};
@@ -29,6 +29,14 @@ fn detect_from_multiboot_info() -> Result<(), ()> {
 	});
 	let mut found_ram = false;
 
+	// The initial ramdisk is used in place, so its pages must never be handed out.
+	let initrd = get_initrd_region().map(|(start, end)| {
+		(
+			align_down!(start.as_usize(), BasePageSize::SIZE),
+			align_up!(end.as_usize(), BasePageSize::SIZE),
+		)
+	});
+
 	for m in ram_regions {
 		found_ram = true;
 
@@ -37,13 +45,28 @@ fn detect_from_multiboot_info() -> Result<(), ()> {
 		} else {
 			VirtAddr(m.base_address())
 		};
-
-		let entry = FreeListEntry::new(
-			start_address.as_usize(),
-			(m.base_address() + m.length()) as usize,
-		);
+		let end_address = (m.base_address() + m.length()) as usize;
 		let _ = TOTAL_MEMORY.fetch_add((m.base_address() + m.length()) as usize, Ordering::SeqCst);
-		PHYSICAL_FREE_LIST.lock().list.push_back(entry);
+
+		match initrd {
+			Some((initrd_start, initrd_end))
+				if initrd_start < end_address && initrd_end > start_address.as_usize() =>
+			{
+				let mut list = PHYSICAL_FREE_LIST.lock();
+				if start_address.as_usize() < initrd_start {
+					list.list
+						.push_back(FreeListEntry::new(start_address.as_usize(), initrd_start));
+				}
+				if initrd_end < end_address {
+					list.list
+						.push_back(FreeListEntry::new(initrd_end, end_address));
+				}
+			}
+			_ => {
+				let entry = FreeListEntry::new(start_address.as_usize(), end_address);
+				PHYSICAL_FREE_LIST.lock().list.push_back(entry);
+			}
+		}
 	}
 
 	assert!(
@@ -159,6 +182,11 @@ pub fn deallocate(physical_address: PhysAddr, size: usize) {
 		.deallocate(physical_address.as_usize(), size);
 }
 
+/// Returns the number of free bytes of physical memory.
+pub fn free_memory_size() -> usize {
+	PHYSICAL_FREE_LIST.lock().free_size()
+}
+
 pub fn print_information() {
 	PHYSICAL_FREE_LIST
 		.lock()
@@ -1,7 +1,7 @@
 use core::{alloc::AllocError, convert::TryInto};
 
 use crate::arch::x86_64::mm::paging::{BasePageSize, PageSize};
-use crate::arch::x86_64::mm::VirtAddr;
+use crate::arch::x86_64::mm::{get_initrd_region, VirtAddr};
 use crate::mm;
 use crate::mm::freelist::{FreeList, FreeListEntry};
 use crate::synch::spinlock::*;
@@ -9,11 +9,29 @@ use crate::synch::spinlock::*;
 static KERNEL_FREE_LIST: SpinlockIrqSave<FreeList> = SpinlockIrqSave::new(FreeList::new());
 
 pub fn init() {
-	let entry = FreeListEntry::new(
-		mm::kernel_end_address().as_usize(),
-		kernel_heap_end().as_usize(),
-	);
-	KERNEL_FREE_LIST.lock().list.push_back(entry);
+	let start = mm::kernel_end_address().as_usize();
+	let end = kernel_heap_end().as_usize();
+
+	// The initial ramdisk is identity mapped and accessed in place, so its addresses must never be handed out.
+	let initrd = get_initrd_region().map(|(initrd_start, initrd_end)| {
+		(
+			align_down!(initrd_start.as_usize(), BasePageSize::SIZE),
+			align_up!(initrd_end.as_usize(), BasePageSize::SIZE),
+		)
+	});
+
+	let mut list = KERNEL_FREE_LIST.lock();
+	match initrd {
+		Some((initrd_start, initrd_end)) if initrd_start < end && initrd_end > start => {
+			if start < initrd_start {
+				list.list.push_back(FreeListEntry::new(start, initrd_start));
+			}
+			if initrd_end < end {
+				list.list.push_back(FreeListEntry::new(initrd_end, end));
+			}
+		}
+		_ => list.list.push_back(FreeListEntry::new(start, end)),
+	}
 }
 
 pub fn allocate(size: usize) -> Result<VirtAddr, AllocError> {
@@ -132,6 +150,11 @@ pub fn deallocate(virtual_address: VirtAddr, size: usize) {
 	);
 }*/
 
+/// Returns the number of free bytes of the virtual address space of the kernel.
+pub fn free_size() -> usize {
+	KERNEL_FREE_LIST.lock().free_size()
+}
+
 pub fn print_information() {
 	KERNEL_FREE_LIST
 		.lock()
@@ -0,0 +1,247 @@
+//! A module containing block device drivers and their common interface.
+//!
+//! Requests to a block device are completed asynchronously. `BlockDevice::submit` passes a request
+//! to the device and returns a `BlockCompletion`, which the caller may wait for at any later time.
+//! The buffers of a request are owned by the request, so they stay valid until the device
+//! has finished it, even if the caller drops the completion.
+#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+pub mod virtio_blk;
+
+#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+use crate::arch::kernel::pci;
+use crate::synch::semaphore::Semaphore;
+use crate::synch::spinlock::SpinlockIrqSave;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockError {
+	/// The device failed to perform the request
+	IoError,
+	/// The device does not support the operation
+	Unsupported,
+	/// The request is out of the range of the device or its buffer does not match whole sectors
+	InvalidRequest,
+	/// The request modifies a read-only device
+	ReadOnly,
+}
+
+/// A request to a block device. Positions and lengths are given in sectors of the device.
+#[derive(Debug)]
+pub enum BlockRequest {
+	/// Reads `buffer.len()` bytes starting at `sector` into `buffer`
+	Read { sector: u64, buffer: Vec<u8> },
+	/// Writes `buffer` starting at `sector`
+	Write { sector: u64, buffer: Vec<u8> },
+	/// Writes the volatile cache of the device back to the medium
+	Flush,
+	/// Informs the device, that the content of the sectors is not needed anymore
+	Discard { sector: u64, count: u64 },
+	/// Sets the sectors to zero. With `unmap`, the device may discard them in the process.
+	WriteZeroes {
+		sector: u64,
+		count: u64,
+		unmap: bool,
+	},
+}
+
+struct CompletionState {
+	done: Semaphore,
+	/// Result of the request, which contains the buffer of reads and writes
+	result: SpinlockIrqSave<Option<Result<Vec<u8>, BlockError>>>,
+}
+
+/// Handle of a submitted request, which is used to wait for its completion
+pub struct BlockCompletion {
+	state: Arc<CompletionState>,
+}
+
+/// Counterpart of a `BlockCompletion`, which the driver uses to complete the request
+pub struct BlockCompleter {
+	state: Arc<CompletionState>,
+}
+
+/// Creates the completion of a new request. The driver keeps the completer and the
+/// submitter receives the completion.
+pub fn completion() -> (BlockCompletion, BlockCompleter) {
+	let state = Arc::new(CompletionState {
+		done: Semaphore::new(0),
+		result: SpinlockIrqSave::new(None),
+	});
+
+	(
+		BlockCompletion {
+			state: state.clone(),
+		},
+		BlockCompleter { state },
+	)
+}
+
+impl BlockCompletion {
+	/// Returns true, if the device has finished the request.
+	pub fn is_complete(&self) -> bool {
+		self.state.result.lock().is_some()
+	}
+
+	/// Blocks the current task until the device has finished the request.
+	/// Returns the buffer of reads and writes or an empty buffer for the other requests.
+	pub fn wait(self) -> Result<Vec<u8>, BlockError> {
+		if !self.state.done.try_acquire() {
+			self.state.done.acquire(None);
+		}
+		self.state.result.lock().take().unwrap()
+	}
+}
+
+impl BlockCompleter {
+	/// Stores the result of the request and wakes up the waiting task.
+	/// May be called in interrupt context.
+	pub fn complete(self, result: Result<Vec<u8>, BlockError>) {
+		*self.state.result.lock() = Some(result);
+		self.state.done.release();
+	}
+}
+
+/// Common interface of block devices
+pub trait BlockDevice {
+	/// Size of a sector in bytes
+	fn sector_size(&self) -> usize;
+
+	/// Number of sectors of the device
+	fn capacity(&self) -> u64;
+
+	fn is_read_only(&self) -> bool;
+
+	/// Maximum number of sectors, which a single read or write may transfer
+	fn max_transfer_sectors(&self) -> u64;
+
+	/// Passes `request` to the device. Fails, if the request is invalid for the device.
+	/// Blocks the current task, if the device cannot accept further requests at the moment.
+	fn submit(&self, request: BlockRequest) -> Result<BlockCompletion, BlockError>;
+
+	/// Reads whole sectors starting at `sector` into `buf` and blocks until they have been read.
+	fn read_sectors(&self, sector: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+		let chunk_size = max_transfer_bytes(self)?;
+		for (i, chunk) in buf.chunks_mut(chunk_size).enumerate() {
+			let request = BlockRequest::Read {
+				sector: sector + i as u64 * self.max_transfer_sectors(),
+				buffer: vec![0; chunk.len()],
+			};
+			let data = self.submit(request)?.wait()?;
+			chunk.copy_from_slice(&data);
+		}
+		Ok(())
+	}
+
+	/// Writes whole sectors starting at `sector` from `buf` and blocks until they have been written.
+	/// The data may remain in the volatile cache of the device until it is flushed.
+	fn write_sectors(&self, sector: u64, buf: &[u8]) -> Result<(), BlockError> {
+		let chunk_size = max_transfer_bytes(self)?;
+		for (i, chunk) in buf.chunks(chunk_size).enumerate() {
+			let request = BlockRequest::Write {
+				sector: sector + i as u64 * self.max_transfer_sectors(),
+				buffer: chunk.to_vec(),
+			};
+			self.submit(request)?.wait()?;
+		}
+		Ok(())
+	}
+
+	/// Writes the volatile cache of the device back and blocks until it has been written.
+	fn flush(&self) -> Result<(), BlockError> {
+		self.submit(BlockRequest::Flush)?.wait().map(|_| ())
+	}
+}
+
+/// Returns the number of bytes, which a single read or write of `device` may transfer.
+fn max_transfer_bytes<D: BlockDevice + ?Sized>(device: &D) -> Result<usize, BlockError> {
+	usize::try_from(device.max_transfer_sectors())
+		.ok()
+		.and_then(|sectors| sectors.checked_mul(device.sector_size()))
+		.filter(|bytes| *bytes > 0)
+		.ok_or(BlockError::Unsupported)
+}
+
+/// Lets every virtio block device check, whether it has raised the interrupt.
+/// Returns true, if a waiting task has been woken up.
+#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+pub fn handle_interrupts() -> bool {
+	pci::get_block_drivers().fold(false, |woken, driver| {
+		driver.lock().handle_interrupt() || woken
+	})
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A device in memory, which completes all requests at once
+	struct RamDisk {
+		data: SpinlockIrqSave<Vec<u8>>,
+	}
+
+	impl BlockDevice for RamDisk {
+		fn sector_size(&self) -> usize {
+			4
+		}
+
+		fn capacity(&self) -> u64 {
+			(self.data.lock().len() / 4) as u64
+		}
+
+		fn is_read_only(&self) -> bool {
+			false
+		}
+
+		fn max_transfer_sectors(&self) -> u64 {
+			2
+		}
+
+		fn submit(&self, request: BlockRequest) -> Result<BlockCompletion, BlockError> {
+			let (completion, completer) = completion();
+			let mut data = self.data.lock();
+			let result = match request {
+				BlockRequest::Read { sector, mut buffer } => {
+					let start = sector as usize * 4;
+					buffer.copy_from_slice(&data[start..start + buffer.len()]);
+					Ok(buffer)
+				}
+				BlockRequest::Write { sector, buffer } => {
+					let start = sector as usize * 4;
+					data[start..start + buffer.len()].copy_from_slice(&buffer);
+					Ok(buffer)
+				}
+				BlockRequest::Flush => Ok(Vec::new()),
+				_ => Err(BlockError::Unsupported),
+			};
+			completer.complete(result);
+			Ok(completion)
+		}
+	}
+
+	#[test]
+	fn test_chunked_transfer() {
+		let disk = RamDisk {
+			data: SpinlockIrqSave::new(vec![0; 32]),
+		};
+		let data: Vec<u8> = (0..20).collect();
+		disk.write_sectors(1, &data).unwrap();
+
+		let mut buf = [0u8; 24];
+		disk.read_sectors(0, &mut buf).unwrap();
+		assert_eq!(&buf[..4], &[0; 4]);
+		assert_eq!(&buf[4..], &data[..]);
+		assert_eq!(disk.flush(), Ok(()));
+	}
+
+	#[test]
+	fn test_completion() {
+		let (completion, completer) = completion();
+		assert!(!completion.is_complete());
+		completer.complete(Err(BlockError::IoError));
+		assert!(completion.is_complete());
+		assert_eq!(completion.wait(), Err(BlockError::IoError));
+	}
+}
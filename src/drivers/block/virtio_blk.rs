@@ -0,0 +1,753 @@
+//! A module containing a virtio block device driver.
+//!
+//! The driver passes read, write, flush, discard and write-zeroes requests to the device and
+//! is specified in Virtio specification v1.2. - 5.2
+
+use crate::arch::kernel::pci::{get_block_drivers, PciAdapter};
+use crate::arch::kernel::percore::core_id;
+use crate::arch::mm::paging::{BasePageSize, PageSize};
+use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::drivers::block::{
+	self, BlockCompleter, BlockCompletion, BlockDevice, BlockError, BlockRequest,
+};
+use crate::synch::semaphore::Semaphore;
+use crate::synch::spinlock::SpinlockIrqSave;
+use crate::syscalls::fs;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::mem::size_of;
+use core::result::Result;
+use core::{ptr, slice};
+
+use crate::drivers::virtio::env::memory::MemLen;
+use crate::drivers::virtio::error::VirtioError;
+use crate::drivers::virtio::features::Features;
+use crate::drivers::virtio::transport::pci;
+use crate::drivers::virtio::transport::pci::{ComCfg, IsrStatus, NotifCfg, PciCap, UniCapsColl};
+use crate::drivers::virtio::virtqueue::error::VirtqError;
+use crate::drivers::virtio::virtqueue::{AsSliceU8, Transfer, Virtq, VqIndex, VqSize, VqType};
+
+use self::error::VirtioBlkError;
+
+/// Maximum number of request queues used by the driver
+const MAX_NUM_REQ_VQ: u16 = 8;
+
+/// Maximum number of bytes, which a single read or write transfers
+const MAX_TRANSFER_SIZE: usize = 1024 * 1024;
+
+/// The sectors of requests are always counted in units of 512 bytes, regardless of the block size.
+/// See Virtio specification v1.2. - 5.2.6
+const VIRTIO_BLK_SECTOR_SIZE: usize = 512;
+
+/// Feature bits of block devices.
+/// See Virtio specification v1.2. - 5.2.3
+const VIRTIO_BLK_F_SEG_MAX: u64 = 1 << 2;
+const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+const VIRTIO_BLK_F_BLK_SIZE: u64 = 1 << 6;
+const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+const VIRTIO_BLK_F_MQ: u64 = 1 << 12;
+const VIRTIO_BLK_F_DISCARD: u64 = 1 << 13;
+const VIRTIO_BLK_F_WRITE_ZEROES: u64 = 1 << 14;
+
+/// Request types.
+/// See Virtio specification v1.2. - 5.2.6
+const VIRTIO_BLK_T_IN: u32 = 0;
+const VIRTIO_BLK_T_OUT: u32 = 1;
+const VIRTIO_BLK_T_FLUSH: u32 = 4;
+const VIRTIO_BLK_T_DISCARD: u32 = 11;
+const VIRTIO_BLK_T_WRITE_ZEROES: u32 = 13;
+
+/// Values of the status byte of a finished request
+const VIRTIO_BLK_S_OK: u8 = 0;
+const VIRTIO_BLK_S_UNSUPP: u8 = 2;
+
+const VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP: u32 = 1;
+
+/// A wrapper struct for the raw configuration structure.
+/// Handling the right access to fields, as some are read-only
+/// for the driver.
+struct BlkDevCfg {
+	raw: &'static BlkDevCfgRaw,
+	/// Limits of discard and write-zeroes requests, if the device config contains them
+	discard: Option<&'static BlkDiscardCfgRaw>,
+	dev_id: u16,
+	features: u64,
+}
+
+/// Device configuration of a virtio block device up to the number of queues.
+/// See Virtio specification v1.2. - 5.2.4
+///
+/// The structure is only aligned to 4 bytes, since the device config may end directly behind it.
+#[allow(dead_code)]
+#[repr(C, packed(4))]
+struct BlkDevCfgRaw {
+	/// Size of the device in 512-byte sectors
+	capacity: u64,
+	size_max: u32,
+	seg_max: u32,
+	cylinders: u16,
+	heads: u8,
+	sectors: u8,
+	/// Size of a logical block, which is the unit of reads and writes
+	blk_size: u32,
+	physical_block_exp: u8,
+	alignment_offset: u8,
+	min_io_size: u16,
+	opt_io_size: u32,
+	writeback: u8,
+	unused0: u8,
+	num_queues: u16,
+}
+
+/// Continuation of the device configuration, which the device only provides,
+/// if it offers discard or write-zeroes requests.
+#[allow(dead_code)]
+#[repr(C)]
+struct BlkDiscardCfgRaw {
+	max_discard_sectors: u32,
+	max_discard_seg: u32,
+	discard_sector_alignment: u32,
+	max_write_zeroes_sectors: u32,
+	max_write_zeroes_seg: u32,
+	write_zeroes_may_unmap: u8,
+	unused1: [u8; 3],
+}
+
+/// Header, which precedes the data of each request
+#[allow(dead_code)]
+#[repr(C)]
+struct BlkReqHeader {
+	typ: u32,
+	reserved: u32,
+	/// First 512-byte sector of the request
+	sector: u64,
+}
+
+impl AsSliceU8 for BlkReqHeader {}
+
+/// Range of a discard or write-zeroes request
+#[allow(dead_code)]
+#[repr(C)]
+struct BlkDiscardWriteZeroes {
+	sector: u64,
+	num_sectors: u32,
+	flags: u32,
+}
+
+impl AsSliceU8 for BlkDiscardWriteZeroes {}
+
+/// Memory areas of a request, which the device accesses
+struct Request {
+	header: Box<BlkReqHeader>,
+	segment: Option<Box<BlkDiscardWriteZeroes>>,
+	data: Vec<u8>,
+	/// The device writes into `data` instead of reading from it
+	data_in: bool,
+	status: Box<u8>,
+}
+
+/// A request, which has been passed to the device. It owns the memory areas of the transfer.
+struct PendingRequest {
+	transfer: Transfer,
+	request: Request,
+	completer: BlockCompleter,
+}
+
+/// Virtio block device driver struct.
+///
+/// Struct allows to control devices virtqueues as also
+/// the device itself.
+pub struct VirtioBlkDriver {
+	dev_cfg: BlkDevCfg,
+	com_cfg: ComCfg,
+	isr_stat: IsrStatus,
+	notif_cfg: NotifCfg,
+
+	/// Request queues. Each core uses one of them.
+	vqs: Vec<Rc<Virtq>>,
+
+	/// Requests in flight, keyed by an identifier assigned by the driver
+	pending: BTreeMap<u64, PendingRequest>,
+	next_id: u64,
+	/// Number of tasks, which wait for descriptors to become available
+	starved: usize,
+	/// Released for a starved task, whenever a finished request frees its descriptors
+	freed: Arc<Semaphore>,
+}
+
+// The queues are reference counted by `Rc`. The driver and the transfers referring to its
+// queues are only accessed under the lock of the driver, so it may be shared between cores.
+unsafe impl Send for VirtioBlkDriver {}
+
+// Public interface for virtio block device driver.
+impl VirtioBlkDriver {
+	/// Processes an interrupt of the device. Finished requests are completed here,
+	/// which wakes up the tasks waiting for them.
+	///
+	/// Returns true, if a request has been completed.
+	pub fn handle_interrupt(&mut self) -> bool {
+		if !self.isr_stat.is_interrupt() {
+			return false;
+		}
+
+		for vq in &self.vqs {
+			vq.poll();
+		}
+
+		let finished: Vec<u64> = self
+			.pending
+			.iter()
+			.filter(|(_, req)| req.transfer.poll())
+			.map(|(id, _)| *id)
+			.collect();
+		for id in &finished {
+			let req = self.pending.remove(id).unwrap();
+			req.transfer.close();
+
+			// the status has been written by the device
+			let result = match unsafe { ptr::read_volatile(&*req.request.status) } {
+				VIRTIO_BLK_S_OK => Ok(req.request.data),
+				VIRTIO_BLK_S_UNSUPP => Err(BlockError::Unsupported),
+				_ => Err(BlockError::IoError),
+			};
+			req.completer.complete(result);
+
+			if self.starved > 0 {
+				self.starved -= 1;
+				self.freed.release();
+			}
+		}
+
+		!finished.is_empty()
+	}
+
+	/// Size of a logical block, which is the sector size of the device
+	pub fn sector_size(&self) -> usize {
+		let blk_size = self.dev_cfg.raw.blk_size as usize;
+		if self.dev_cfg.features & VIRTIO_BLK_F_BLK_SIZE != 0
+			&& blk_size >= VIRTIO_BLK_SECTOR_SIZE
+			&& blk_size.is_power_of_two()
+		{
+			blk_size
+		} else {
+			VIRTIO_BLK_SECTOR_SIZE
+		}
+	}
+
+	/// Number of sectors of the device. The capacity may change at runtime.
+	pub fn capacity(&self) -> u64 {
+		let capacity = self.dev_cfg.raw.capacity;
+		capacity / self.sectors_per_block()
+	}
+
+	pub fn is_read_only(&self) -> bool {
+		self.dev_cfg.features & VIRTIO_BLK_F_RO != 0
+	}
+
+	/// Maximum number of sectors, which a single read or write may transfer
+	pub fn max_transfer_sectors(&self) -> u64 {
+		// The header and the status need up to two descriptors each, if they cross a page boundary.
+		// The data may need a descriptor per page and an additional one, if it does not start at a page boundary.
+		let mut segments = self
+			.vqs
+			.first()
+			.map_or(0, |vq| usize::from(u16::from(vq.size())).saturating_sub(4));
+		if self.dev_cfg.features & VIRTIO_BLK_F_SEG_MAX != 0 {
+			let seg_max = self.dev_cfg.raw.seg_max as usize;
+			segments = segments.min(seg_max);
+		}
+
+		let bytes = (segments.saturating_sub(1) * BasePageSize::SIZE).min(MAX_TRANSFER_SIZE);
+		(bytes / self.sector_size()) as u64
+	}
+}
+
+// Private funtctions for Virtio block device driver
+impl VirtioBlkDriver {
+	fn map_cfg(cap: &PciCap) -> Option<BlkDevCfg> {
+		let dev_cfg: &'static BlkDevCfgRaw = match pci::map_dev_cfg::<BlkDevCfgRaw>(cap) {
+			Some(cfg) => cfg,
+			None => return None,
+		};
+
+		// The device config only contains the limits of discard and write-zeroes requests,
+		// if the device offers them.
+		let discard = if cap.len()
+			>= MemLen::from(size_of::<BlkDevCfgRaw>() + size_of::<BlkDiscardCfgRaw>())
+		{
+			Some(unsafe { &*((dev_cfg as *const BlkDevCfgRaw).add(1) as *const BlkDiscardCfgRaw) })
+		} else {
+			None
+		};
+
+		Some(BlkDevCfg {
+			raw: dev_cfg,
+			discard,
+			dev_id: cap.dev_id(),
+			features: 0,
+		})
+	}
+
+	/// Instanciates a new (VirtioBlkDriver)[VirtioBlkDriver] struct, by checking the available
+	/// configuration structures and moving them into the struct.
+	fn new(mut caps_coll: UniCapsColl, adapter: &PciAdapter) -> Result<Self, VirtioBlkError> {
+		let com_cfg = match caps_coll.get_com_cfg() {
+			Some(com_cfg) => com_cfg,
+			None => {
+				error!("No common config. Aborting!");
+				return Err(VirtioBlkError::NoComCfg(adapter.device_id));
+			}
+		};
+
+		let isr_stat = match caps_coll.get_isr_cfg() {
+			Some(isr_stat) => isr_stat,
+			None => {
+				error!("No ISR status config. Aborting!");
+				return Err(VirtioBlkError::NoIsrCfg(adapter.device_id));
+			}
+		};
+
+		let notif_cfg = match caps_coll.get_notif_cfg() {
+			Some(notif_cfg) => notif_cfg,
+			None => {
+				error!("No notif config. Aborting!");
+				return Err(VirtioBlkError::NoNotifCfg(adapter.device_id));
+			}
+		};
+
+		let dev_cfg = loop {
+			match caps_coll.get_dev_cfg() {
+				Some(cfg) => {
+					if let Some(dev_cfg) = VirtioBlkDriver::map_cfg(&cfg) {
+						break dev_cfg;
+					}
+				}
+				None => {
+					error!("No dev config. Aborting!");
+					return Err(VirtioBlkError::NoDevCfg(adapter.device_id));
+				}
+			}
+		};
+
+		Ok(VirtioBlkDriver {
+			dev_cfg,
+			com_cfg,
+			isr_stat,
+			notif_cfg,
+
+			vqs: Vec::new(),
+
+			pending: BTreeMap::new(),
+			next_id: 0,
+			starved: 0,
+			freed: Arc::new(Semaphore::new(0)),
+		})
+	}
+
+	/// Initiallizes the device in adherence to specificaton.
+	///
+	/// See Virtio specification v1.2. - 3.1.1.
+	///                      and v1.2. - 5.2.5
+	fn init_dev(&mut self) -> Result<(), VirtioBlkError> {
+		// Reset
+		self.com_cfg.reset_dev();
+
+		// Indiacte device, that OS noticed it
+		self.com_cfg.ack_dev();
+
+		// Indicate device, that driver is able to handle it
+		self.com_cfg.set_drv();
+
+		// All block features are optional. Packed virtqueues are used, if the device offers them.
+		let min_feats = u64::from(Features::VIRTIO_F_VERSION_1);
+		let wanted_feats = Features::VIRTIO_F_VERSION_1
+			| Features::VIRTIO_F_RING_PACKED
+			| VIRTIO_BLK_F_SEG_MAX
+			| VIRTIO_BLK_F_RO
+			| VIRTIO_BLK_F_BLK_SIZE
+			| VIRTIO_BLK_F_FLUSH
+			| VIRTIO_BLK_F_MQ
+			| VIRTIO_BLK_F_DISCARD
+			| VIRTIO_BLK_F_WRITE_ZEROES;
+
+		let dev_feats = self.com_cfg.dev_features();
+		if dev_feats & min_feats != min_feats {
+			error!(
+				"Device features {:#x} do not satisfy minimal features needed. Aborting!",
+				dev_feats
+			);
+			return Err(VirtioBlkError::FailFeatureNeg(self.dev_cfg.dev_id));
+		}
+		let feats = dev_feats & wanted_feats;
+		self.com_cfg.set_drv_features(feats);
+
+		// Indicates the device, that the current feature set is final for the driver
+		// and will not be changed.
+		self.com_cfg.features_ok();
+
+		// Checks if the device has accepted final set. This finishes feature negotiation.
+		if self.com_cfg.check_features() {
+			info!(
+				"Features have been negotiated between virtio block device {:x} and driver: {:#x}",
+				self.dev_cfg.dev_id, feats
+			);
+			self.dev_cfg.features = feats;
+		} else {
+			return Err(VirtioBlkError::FailFeatureNeg(self.dev_cfg.dev_id));
+		}
+
+		if self.dev_cfg.discard.is_none() {
+			// the limits of the requests are unknown, so they are not used
+			self.dev_cfg.features &= !(VIRTIO_BLK_F_DISCARD | VIRTIO_BLK_F_WRITE_ZEROES);
+		}
+
+		self.virtqueue_init();
+
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		Ok(())
+	}
+
+	/// Creates one request queue or, with VIRTIO_BLK_F_MQ, up to `MAX_NUM_REQ_VQ` request queues.
+	///
+	/// See Virtio specification v1.2. - 5.2.2
+	fn virtqueue_init(&mut self) {
+		let num_vqs = if self.dev_cfg.features & VIRTIO_BLK_F_MQ != 0 {
+			let num_queues = self.dev_cfg.raw.num_queues;
+			num_queues.max(1).min(MAX_NUM_REQ_VQ)
+		} else {
+			1
+		};
+
+		let vq_type = if self.dev_cfg.features & Features::VIRTIO_F_RING_PACKED != 0 {
+			VqType::Packed
+		} else {
+			VqType::Split
+		};
+
+		// Interrupts for finished requests are wanted on all queues
+		for i in 0..num_vqs {
+			let vq = Rc::new(Virtq::new(
+				&mut self.com_cfg,
+				&self.notif_cfg,
+				VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
+				vq_type,
+				VqIndex::from(i),
+				self.dev_cfg.features,
+			));
+			vq.enable_notifs();
+			self.vqs.push(vq);
+		}
+
+		info!(
+			"Virtio block device {:x} uses {} request queue(s)",
+			self.dev_cfg.dev_id, num_vqs
+		);
+	}
+
+	/// Number of 512-byte sectors per logical block
+	fn sectors_per_block(&self) -> u64 {
+		(self.sector_size() / VIRTIO_BLK_SECTOR_SIZE) as u64
+	}
+
+	/// Checks that the sectors `sector..sector + count` exist and converts `sector` into 512-byte sectors.
+	fn device_sector(&self, sector: u64, count: u64) -> Result<u64, BlockError> {
+		match sector.checked_add(count) {
+			Some(end) if count > 0 && end <= self.capacity() => {
+				Ok(sector * self.sectors_per_block())
+			}
+			_ => Err(BlockError::InvalidRequest),
+		}
+	}
+
+	/// Checks a discard or write-zeroes request of `count` sectors against `limit` (in 512-byte sectors)
+	/// and converts the count into 512-byte sectors.
+	fn device_count(&self, count: u64, limit: u32) -> Result<u32, BlockError> {
+		count
+			.checked_mul(self.sectors_per_block())
+			.and_then(|count| u32::try_from(count).ok())
+			.filter(|count| *count <= limit)
+			.ok_or(BlockError::InvalidRequest)
+	}
+
+	/// Validates `request` and creates the memory areas, which are passed to the device.
+	/// Returns `None`, if the request has nothing to do.
+	fn prepare(&self, request: BlockRequest) -> Result<Option<Request>, BlockError> {
+		let (typ, sector, segment, data, data_in) = match request {
+			BlockRequest::Read { ref buffer, .. } | BlockRequest::Write { ref buffer, .. }
+				if buffer.len() % self.sector_size() != 0
+					|| buffer.len() as u64
+						> self.max_transfer_sectors() * self.sector_size() as u64 =>
+			{
+				return Err(BlockError::InvalidRequest);
+			}
+			BlockRequest::Read { sector, buffer } => {
+				let count = (buffer.len() / self.sector_size()) as u64;
+				(
+					VIRTIO_BLK_T_IN,
+					self.device_sector(sector, count)?,
+					None,
+					buffer,
+					true,
+				)
+			}
+			BlockRequest::Write { .. }
+			| BlockRequest::Discard { .. }
+			| BlockRequest::WriteZeroes { .. }
+				if self.is_read_only() =>
+			{
+				return Err(BlockError::ReadOnly);
+			}
+			BlockRequest::Write { sector, buffer } => {
+				let count = (buffer.len() / self.sector_size()) as u64;
+				(
+					VIRTIO_BLK_T_OUT,
+					self.device_sector(sector, count)?,
+					None,
+					buffer,
+					false,
+				)
+			}
+			BlockRequest::Flush => {
+				// without a volatile cache, written data is already on the medium
+				if self.dev_cfg.features & VIRTIO_BLK_F_FLUSH == 0 {
+					return Ok(None);
+				}
+				(VIRTIO_BLK_T_FLUSH, 0, None, Vec::new(), false)
+			}
+			BlockRequest::Discard { sector, count } => {
+				let limits = match self.dev_cfg.discard {
+					Some(limits) if self.dev_cfg.features & VIRTIO_BLK_F_DISCARD != 0 => limits,
+					_ => return Err(BlockError::Unsupported),
+				};
+				let segment = BlkDiscardWriteZeroes {
+					sector: self.device_sector(sector, count)?,
+					num_sectors: self.device_count(count, limits.max_discard_sectors)?,
+					flags: 0,
+				};
+				(VIRTIO_BLK_T_DISCARD, 0, Some(segment), Vec::new(), false)
+			}
+			BlockRequest::WriteZeroes {
+				sector,
+				count,
+				unmap,
+			} => {
+				let limits = match self.dev_cfg.discard {
+					Some(limits) if self.dev_cfg.features & VIRTIO_BLK_F_WRITE_ZEROES != 0 => {
+						limits
+					}
+					_ => return Err(BlockError::Unsupported),
+				};
+				let segment = BlkDiscardWriteZeroes {
+					sector: self.device_sector(sector, count)?,
+					num_sectors: self.device_count(count, limits.max_write_zeroes_sectors)?,
+					flags: if unmap {
+						VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP
+					} else {
+						0
+					},
+				};
+				(
+					VIRTIO_BLK_T_WRITE_ZEROES,
+					0,
+					Some(segment),
+					Vec::new(),
+					false,
+				)
+			}
+		};
+
+		Ok(Some(Request {
+			header: Box::new(BlkReqHeader {
+				typ,
+				reserved: 0,
+				sector,
+			}),
+			segment: segment.map(Box::new),
+			data,
+			data_in,
+			status: Box::new(u8::MAX),
+		}))
+	}
+
+	/// Places `request` into the request queue of the current core.
+	fn dispatch(&self, request: &mut Request) -> Result<Transfer, VirtqError> {
+		// the device has at least one request queue after its initialization
+		let vq = &self.vqs[core_id() as usize % self.vqs.len()];
+
+		let header = request.header.as_slice_u8();
+		let status = slice::from_mut(&mut *request.status);
+		let tkn = if request.data_in {
+			vq.prep_transfer_from_slices(
+				Rc::clone(vq),
+				&[header],
+				&[&mut request.data[..], status],
+			)?
+		} else {
+			let segment = request
+				.segment
+				.as_ref()
+				.map_or(&[][..], |segment| segment.as_slice_u8());
+			vq.prep_transfer_from_slices(
+				Rc::clone(vq),
+				&[header, segment, &request.data],
+				&[status],
+			)?
+		};
+
+		Ok(tkn.dispatch(false))
+	}
+}
+
+impl BlockDevice for SpinlockIrqSave<VirtioBlkDriver> {
+	fn sector_size(&self) -> usize {
+		self.lock().sector_size()
+	}
+
+	fn capacity(&self) -> u64 {
+		self.lock().capacity()
+	}
+
+	fn is_read_only(&self) -> bool {
+		self.lock().is_read_only()
+	}
+
+	fn max_transfer_sectors(&self) -> u64 {
+		self.lock().max_transfer_sectors()
+	}
+
+	/// The driver is only locked while its queues are accessed, so that other tasks and the
+	/// interrupt of the device can access it meanwhile.
+	fn submit(&self, request: BlockRequest) -> Result<BlockCompletion, BlockError> {
+		let (completion, completer) = block::completion();
+		let mut request = match self.lock().prepare(request)? {
+			Some(request) => request,
+			None => {
+				completer.complete(Ok(Vec::new()));
+				return Ok(completion);
+			}
+		};
+
+		loop {
+			let mut guard = self.lock();
+			match guard.dispatch(&mut request) {
+				Ok(transfer) => {
+					let id = guard.next_id;
+					guard.next_id += 1;
+					let req = PendingRequest {
+						transfer,
+						request,
+						completer,
+					};
+					guard.pending.insert(id, req);
+					return Ok(completion);
+				}
+				Err(VirtqError::NoDescrAvail) if guard.pending.is_empty() => {
+					// Without requests in flight, no descriptors will be freed. Thus, the request
+					// is too large for the queue. Other starved tasks may have been waiting for
+					// the same descriptors, so one of them retries.
+					if guard.starved > 0 {
+						guard.starved -= 1;
+						guard.freed.release();
+					}
+					error!("Block request does not fit into the queue of the device");
+					return Err(BlockError::IoError);
+				}
+				Err(VirtqError::NoDescrAvail) => {
+					// wait until another request has finished and try again
+					guard.starved += 1;
+					let freed = Arc::clone(&guard.freed);
+					drop(guard);
+					freed.acquire(None);
+				}
+				Err(err) => {
+					error!("Unable to pass block request to the device: {:?}", err);
+					return Err(BlockError::IoError);
+				}
+			}
+		}
+	}
+}
+
+// Public interface for virtio block device driver.
+impl VirtioBlkDriver {
+	/// Initializes virtio block device by mapping configuration layout to
+	/// respective structs (configuration structs are:
+	/// [ComCfg](structs.comcfg.html), [NotifCfg](structs.notifcfg.html)
+	/// [IsrStatus](structs.isrstatus.html), [PciCfg](structs.pcicfg.html)).
+	///
+	/// Returns a driver instance of
+	/// [VirtioBlkDriver](structs.virtioblkdriver.html) or an [VirtioError](enums.virtioerror.html).
+	pub fn init(adapter: &PciAdapter) -> Result<VirtioBlkDriver, VirtioError> {
+		let mut drv = match pci::map_caps(adapter) {
+			Ok(caps) => match VirtioBlkDriver::new(caps, adapter) {
+				Ok(driver) => driver,
+				Err(vblk_err) => {
+					error!("Initializing new block device driver failed. Aborting!");
+					return Err(VirtioError::BlkDriver(vblk_err));
+				}
+			},
+			Err(pci_error) => {
+				error!("Mapping capabilites failed. Aborting!");
+				return Err(VirtioError::FromPci(pci_error));
+			}
+		};
+
+		match drv.init_dev() {
+			Ok(_) => info!(
+				"Block device with id {:x}, has been initialized by driver!",
+				drv.dev_cfg.dev_id
+			),
+			Err(vblk_err) => {
+				drv.com_cfg.set_failed();
+				return Err(VirtioError::BlkDriver(vblk_err));
+			}
+		}
+
+		Ok(drv)
+	}
+}
+
+/// Makes each virtio block device accessible as `/dev/vda`, `/dev/vdb`, ... in the order of their discovery.
+pub fn init_block() {
+	for (i, drv) in get_block_drivers().enumerate() {
+		let name = match u8::try_from(i).ok().filter(|i| *i < 26) {
+			Some(i) => format!("vd{}", char::from(b'a' + i)),
+			None => {
+				warn!("Too many virtio block devices, ignoring the remaining ones");
+				break;
+			}
+		};
+
+		let (capacity, sector_size) = {
+			let guard = drv.lock();
+			(guard.capacity(), guard.sector_size())
+		};
+		info!(
+			"Virtio block device /dev/{}: {} sectors of {} bytes",
+			name, capacity, sector_size
+		);
+		if let Err(err) = fs::register_block_device(&name, drv) {
+			error!("Registering /dev/{} failed: {:?}", name, err);
+		}
+	}
+}
+
+pub mod error {
+	/// Block device drivers error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum VirtioBlkError {
+		NoDevCfg(u16),
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		FailFeatureNeg(u16),
+	}
+}
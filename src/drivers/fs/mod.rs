@@ -0,0 +1,15 @@
+//! A module containing filesystem drivers.
+//!
+//! Requests to a filesystem device are completed asynchronously. The requesting task
+//! sleeps until the device signals the completion by an interrupt.
+pub mod virtio_fs;
+
+use crate::arch::kernel::pci;
+
+/// Lets every filesystem device check, whether it has raised the interrupt.
+/// Returns true, if a waiting task has been woken up.
+pub fn handle_interrupts() -> bool {
+	pci::get_filesystem_drivers().fold(false, |woken, driver| {
+		driver.lock().handle_interrupt() || woken
+	})
+}
@@ -0,0 +1,694 @@
+//! A module containing a virtio filesystem driver.
+//!
+//! The driver passes FUSE requests to the device and is specified in
+//! Virtio specification v1.2. - 5.11
+
+use crate::arch::kernel::pci::{get_filesystem_drivers, PciAdapter};
+use crate::arch::kernel::percore::core_id;
+use crate::arch::kernel::processor::get_timer_ticks;
+use crate::arch::mm::{paging, VirtAddr};
+use crate::arch::x86_64::kernel::fuse::{self, FuseIn, FuseInterface, FuseOut};
+use crate::config::VIRTIO_MAX_QUEUE_SIZE;
+use crate::environment;
+use crate::synch::semaphore::Semaphore;
+use crate::synch::spinlock::SpinlockIrqSave;
+use crate::syscalls::fs;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::result::Result;
+use core::str;
+
+use crate::drivers::virtio::device;
+use crate::drivers::virtio::error::VirtioError;
+use crate::drivers::virtio::features::Features;
+use crate::drivers::virtio::transport::pci;
+use crate::drivers::virtio::transport::pci::{
+	ComCfg, IsrStatus, NotifCfg, PciCap, ShMemCfg, UniCapsColl,
+};
+use crate::drivers::virtio::virtqueue::error::VirtqError;
+use crate::drivers::virtio::virtqueue::{Transfer, Virtq, VqIndex, VqSize, VqType};
+
+use self::error::VirtioFsError;
+
+/// Maximum number of request queues used by the driver
+const MAX_NUM_REQ_VQ: u32 = 8;
+
+/// Index of the high-priority queue. The request queues follow it.
+const HIPRIO_VQ_IDX: u16 = 0;
+
+/// Id of the shared memory region, which serves as DAX window.
+/// See Virtio specification v1.2. - 5.11.6.3
+const VIRTIO_FS_SHMCAP_ID_CACHE: u8 = 0;
+
+/// Time in milliseconds, after which a request, which the device has not processed, fails
+const REQUEST_TIMEOUT: u64 = 60_000;
+/// Interval in milliseconds, in which waiting tasks check whether the device has failed
+const FAILURE_CHECK_INTERVAL: u64 = 1000;
+
+/// A request, which has been passed to the device
+struct PendingRequest {
+	transfer: Transfer,
+	/// Released, when the device has processed the request
+	done: Arc<Semaphore>,
+	/// The waiting task has already been woken up
+	signaled: bool,
+}
+
+/// A wrapper struct for the raw configuration structure.
+/// Handling the right access to fields, as some are read-only
+/// for the driver.
+struct FsDevCfg {
+	raw: &'static FsDevCfgRaw,
+	dev_id: u16,
+	features: u64,
+}
+
+/// Device configuration of a virtio filesystem device.
+/// See Virtio specification v1.2. - 5.11.4
+#[repr(C)]
+struct FsDevCfgRaw {
+	/// Name of the filesystem (UTF-8, not NUL-terminated, padded with NULs)
+	tag: [u8; 36],
+	/// Number of request queues
+	num_request_queues: u32,
+}
+
+impl FsDevCfgRaw {
+	/// Returns the tag of the filesystem or `None`, if it is not valid UTF-8.
+	fn tag(&self) -> Option<&str> {
+		let nul_position = self.tag.iter().position(|&b| b == 0);
+		str::from_utf8(&self.tag[..nul_position.unwrap_or(self.tag.len())]).ok()
+	}
+}
+
+impl fmt::Debug for FsDevCfgRaw {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("FsDevCfgRaw")
+			.field("tag", &self.tag())
+			.field("num_request_queues", &self.num_request_queues)
+			.finish()
+	}
+}
+
+/// Virtio filesystem driver struct.
+///
+/// Struct allows to control devices virtqueues as also
+/// the device itself.
+pub struct VirtioFsDriver {
+	dev_cfg: FsDevCfg,
+	com_cfg: ComCfg,
+	isr_stat: IsrStatus,
+	notif_cfg: NotifCfg,
+	/// Shared memory region, into which the device maps file contents on request (DAX window)
+	dax_window: Option<ShMemCfg>,
+
+	/// Queue for requests, which must not wait behind regular requests (e.g. FUSE_FORGET)
+	hiprio_vq: Option<Rc<Virtq>>,
+	/// Queues for regular requests. Each core uses one of them.
+	req_vqs: Vec<Rc<Virtq>>,
+
+	/// Requests in flight, keyed by the `unique` of their FUSE header
+	pending: BTreeMap<u64, PendingRequest>,
+	/// Number of tasks, which wait for descriptors to become available
+	starved: usize,
+	/// Released for a starved task, whenever a finished request frees its descriptors
+	freed: Arc<Semaphore>,
+	/// The device has been reset after a failure and accepts no more requests
+	failed: bool,
+}
+
+// SAFETY: The queues are reference counted by `Rc`, which is not thread-safe. The driver and
+// the transfers referring to its queues are only accessed under the lock of the driver, which
+// serializes all updates of the reference counts, so it may be moved between cores.
+unsafe impl Send for VirtioFsDriver {}
+
+// Public interface for virtio filesystem driver.
+impl VirtioFsDriver {
+	/// Returns the tag of the filesystem, under which it is mounted.
+	pub fn tag(&self) -> &'static str {
+		// the tag has been validated during initialization
+		self.dev_cfg.raw.tag().unwrap_or_default()
+	}
+
+	/// Maps the DAX window, if the device provides one, and returns its address and length.
+	///
+	/// The BARs of the device are mapped uncached, which is required for its registers.
+	/// The window behaves like normal memory, so it is mapped a second time with caching enabled.
+	pub fn map_dax_window(&self) -> Option<(VirtAddr, usize)> {
+		let window = self.dax_window.as_ref()?;
+		let len = usize::from(window.len());
+		let phys = paging::virtual_to_physical(VirtAddr(usize::from(window.addr()) as u64));
+
+		Some((crate::mm::map(phys, len, true, true, false), len))
+	}
+
+	/// Processes an interrupt of the device. The tasks waiting for finished requests
+	/// are woken up here and collect their replies on their own.
+	///
+	/// Returns true, if a task has been woken up.
+	pub fn handle_interrupt(&mut self) -> bool {
+		if !self.isr_stat.is_interrupt() {
+			return false;
+		}
+
+		self.poll();
+		let mut woken = false;
+		for req in self.pending.values_mut() {
+			if !req.signaled && req.transfer.poll() {
+				req.signaled = true;
+				req.done.release();
+				woken = true;
+			}
+		}
+		woken
+	}
+
+	/// Maximum number of buffer elements, which a single request may consist of
+	pub fn max_segments(&self) -> usize {
+		// headers may straddle a page boundary and need two descriptors each
+		self.req_vqs
+			.first()
+			.map_or(0, |vq| usize::from(u16::from(vq.size())).saturating_sub(4))
+	}
+}
+
+// Private funtctions for Virtio filesystem driver
+impl VirtioFsDriver {
+	fn map_cfg(cap: &PciCap) -> Option<FsDevCfg> {
+		let dev_cfg: &'static FsDevCfgRaw = match pci::map_dev_cfg::<FsDevCfgRaw>(cap) {
+			Some(cfg) => cfg,
+			None => return None,
+		};
+
+		Some(FsDevCfg {
+			raw: dev_cfg,
+			dev_id: cap.dev_id(),
+			features: 0,
+		})
+	}
+
+	/// Instanciates a new (VirtioFsDriver)[VirtioFsDriver] struct, by checking the available
+	/// configuration structures and moving them into the struct.
+	fn new(mut caps_coll: UniCapsColl, adapter: &PciAdapter) -> Result<Self, VirtioFsError> {
+		let com_cfg = match caps_coll.get_com_cfg() {
+			Some(com_cfg) => com_cfg,
+			None => {
+				error!("No common config. Aborting!");
+				return Err(VirtioFsError::NoComCfg(adapter.device_id));
+			}
+		};
+
+		let isr_stat = match caps_coll.get_isr_cfg() {
+			Some(isr_stat) => isr_stat,
+			None => {
+				error!("No ISR status config. Aborting!");
+				return Err(VirtioFsError::NoIsrCfg(adapter.device_id));
+			}
+		};
+
+		let notif_cfg = match caps_coll.get_notif_cfg() {
+			Some(notif_cfg) => notif_cfg,
+			None => {
+				error!("No notif config. Aborting!");
+				return Err(VirtioFsError::NoNotifCfg(adapter.device_id));
+			}
+		};
+
+		let dev_cfg = loop {
+			match caps_coll.get_dev_cfg() {
+				Some(cfg) => {
+					if let Some(dev_cfg) = VirtioFsDriver::map_cfg(&cfg) {
+						break dev_cfg;
+					}
+				}
+				None => {
+					error!("No dev config. Aborting!");
+					return Err(VirtioFsError::NoDevCfg(adapter.device_id));
+				}
+			}
+		};
+
+		let dax_window = caps_coll.get_sh_mem_cfg(VIRTIO_FS_SHMCAP_ID_CACHE);
+
+		Ok(VirtioFsDriver {
+			dev_cfg,
+			com_cfg,
+			isr_stat,
+			notif_cfg,
+			dax_window,
+
+			hiprio_vq: None,
+			req_vqs: Vec::new(),
+
+			pending: BTreeMap::new(),
+			starved: 0,
+			freed: Arc::new(Semaphore::new(0)),
+			failed: false,
+		})
+	}
+
+	/// Initiallizes the device in adherence to specificaton.
+	///
+	/// See Virtio specification v1.2. - 3.1.1.
+	///                      and v1.2. - 5.11.5
+	fn init_dev(&mut self) -> Result<(), VirtioFsError> {
+		// Reset
+		self.com_cfg.reset_dev();
+
+		// Indiacte device, that OS noticed it
+		self.com_cfg.ack_dev();
+
+		// Indicate device, that driver is able to handle it
+		self.com_cfg.set_drv();
+
+		// There are no filesystem specific features. Packed virtqueues are
+		// used, if the device offers them.
+		let min_feats = u64::from(Features::VIRTIO_F_VERSION_1);
+		let wanted_feats = Features::VIRTIO_F_VERSION_1 | Features::VIRTIO_F_RING_PACKED;
+
+		let dev_feats = self.com_cfg.dev_features();
+		if dev_feats & min_feats != min_feats {
+			error!(
+				"Device features {:#x} do not satisfy minimal features needed. Aborting!",
+				dev_feats
+			);
+			return Err(VirtioFsError::FailFeatureNeg(self.dev_cfg.dev_id));
+		}
+		let feats = dev_feats & wanted_feats;
+		self.com_cfg.set_drv_features(feats);
+
+		// Indicates the device, that the current feature set is final for the driver
+		// and will not be changed.
+		self.com_cfg.features_ok();
+
+		// Checks if the device has accepted final set. This finishes feature negotiation.
+		if self.com_cfg.check_features() {
+			info!(
+				"Features have been negotiated between virtio filesystem device {:x} and driver: {:#x}",
+				self.dev_cfg.dev_id, feats
+			);
+			self.dev_cfg.features = feats;
+		} else {
+			return Err(VirtioFsError::FailFeatureNeg(self.dev_cfg.dev_id));
+		}
+
+		if self.dev_cfg.raw.tag().map_or(true, str::is_empty) {
+			error!(
+				"Filesystem tag of device {:x} is invalid!",
+				self.dev_cfg.dev_id
+			);
+			return Err(VirtioFsError::InvalidTag(self.dev_cfg.dev_id));
+		}
+
+		self.virtqueue_init()?;
+
+		// At this point the device is "live"
+		self.com_cfg.drv_ok();
+
+		Ok(())
+	}
+
+	/// Creates the high-priority queue and up to `MAX_NUM_REQ_VQ` request queues.
+	///
+	/// See Virtio specification v1.2. - 5.11.2
+	fn virtqueue_init(&mut self) -> Result<(), VirtioFsError> {
+		let num_req_vqs = self.dev_cfg.raw.num_request_queues.min(MAX_NUM_REQ_VQ);
+		if num_req_vqs == 0 {
+			error!(
+				"Device {:x} does not offer any request queue. Aborting!",
+				self.dev_cfg.dev_id
+			);
+			return Err(VirtioFsError::NoReqQueue(self.dev_cfg.dev_id));
+		}
+
+		// Interrupts for finished requests are wanted on all queues
+		let hiprio_vq = self.create_vq(HIPRIO_VQ_IDX);
+		hiprio_vq.enable_notifs();
+		self.hiprio_vq = Some(hiprio_vq);
+
+		for i in 1..=num_req_vqs {
+			let vq = self.create_vq(i as u16);
+			vq.enable_notifs();
+			self.req_vqs.push(vq);
+		}
+
+		info!(
+			"Virtio filesystem device {:x} uses {} request queue(s)",
+			self.dev_cfg.dev_id, num_req_vqs
+		);
+
+		Ok(())
+	}
+
+	fn create_vq(&mut self, index: u16) -> Rc<Virtq> {
+		let vq_type = if self.dev_cfg.features & Features::VIRTIO_F_RING_PACKED != 0 {
+			VqType::Packed
+		} else {
+			VqType::Split
+		};
+
+		Rc::new(Virtq::new(
+			&mut self.com_cfg,
+			&self.notif_cfg,
+			VqSize::from(VIRTIO_MAX_QUEUE_SIZE),
+			vq_type,
+			VqIndex::from(index),
+			self.dev_cfg.features,
+		))
+	}
+
+	/// Places a request, consisting of the device-readable `send` and the device-writable `recv`
+	/// slices, into the high-priority queue or the request queue of the current core.
+	fn dispatch(
+		&self,
+		hiprio: bool,
+		send: &[&[u8]],
+		recv: &[&mut [u8]],
+	) -> Result<Transfer, VirtqError> {
+		let vq = if hiprio {
+			self.hiprio_vq.as_ref()
+		} else if self.req_vqs.is_empty() {
+			None
+		} else {
+			self.req_vqs.get(core_id() as usize % self.req_vqs.len())
+		}
+		.ok_or(VirtqError::General)?;
+
+		let tkn = vq.prep_transfer_from_slices(Rc::clone(vq), send, recv)?;
+		Ok(tkn.dispatch(false))
+	}
+
+	/// Updates the state of the dispatched requests.
+	fn poll(&self) {
+		if let Some(vq) = &self.hiprio_vq {
+			vq.poll();
+		}
+		for vq in &self.req_vqs {
+			vq.poll();
+		}
+	}
+
+	/// Removes the finished request `unique` and releases its descriptors.
+	fn finish(&mut self, unique: u64) {
+		if let Some(req) = self.pending.remove(&unique) {
+			req.transfer.close();
+		}
+
+		if self.starved > 0 {
+			self.starved -= 1;
+			self.freed.release();
+		}
+	}
+
+	/// Returns true, if the device has signaled an error, from which it only recovers by a reset.
+	fn needs_reset(&self) -> bool {
+		self.com_cfg.dev_status() & u8::from(device::Status::DEVICE_NEEDS_RESET) != 0
+	}
+
+	/// Resets the device, which does not process requests anymore, and wakes up all waiting
+	/// tasks. After the reset, the device no longer accesses the buffers of the requests in flight,
+	/// so their tasks may return.
+	fn fail(&mut self) {
+		if self.failed {
+			return;
+		}
+
+		error!(
+			"Virtio filesystem device {:x} does not respond and is reset",
+			self.dev_cfg.dev_id
+		);
+		self.com_cfg.reset_dev();
+		self.failed = true;
+
+		for req in self.pending.values_mut() {
+			if !req.signaled {
+				req.signaled = true;
+				req.done.release();
+			}
+		}
+		for _ in 0..self.starved {
+			self.freed.release();
+		}
+		self.starved = 0;
+	}
+
+	/// Passes the request `unique` to the device and blocks the current task until the device
+	/// has processed it. Any number of requests may be in flight at the same time.
+	///
+	/// The driver is only locked while its queues are accessed, so that other tasks and the
+	/// interrupt of the device can access it meanwhile. Transfers hold references to their
+	/// queue, hence they are only touched under the lock.
+	///
+	/// If the device has not processed the request within `timeout` milliseconds or signals an
+	/// error, the device is reset and all requests fail. Without a timeout, the request waits
+	/// until the device processes it or fails, e.g. for locks, which the server grants later.
+	fn transfer_blocking(
+		driver: &SpinlockIrqSave<VirtioFsDriver>,
+		unique: u64,
+		hiprio: bool,
+		timeout: Option<u64>,
+		send: &[&[u8]],
+		recv: &[&mut [u8]],
+	) -> Result<(), VirtqError> {
+		let done = Arc::new(Semaphore::new(0));
+
+		loop {
+			let mut guard = driver.lock();
+			if guard.failed {
+				return Err(VirtqError::General);
+			}
+			match guard.dispatch(hiprio, send, recv) {
+				Ok(transfer) => {
+					let req = PendingRequest {
+						transfer,
+						done: Arc::clone(&done),
+						signaled: false,
+					};
+					guard.pending.insert(unique, req);
+					break;
+				}
+				Err(VirtqError::NoDescrAvail) => {
+					// Without requests in flight, no descriptors will be freed.
+					// Thus, the request is too large for the queue. Other starved tasks may
+					// have been waiting for the same descriptors, so one of them retries.
+					if guard.pending.is_empty() {
+						if guard.starved > 0 {
+							guard.starved -= 1;
+							guard.freed.release();
+						}
+						return Err(VirtqError::NoDescrAvail);
+					}
+					// wait until another request has finished and try again
+					guard.starved += 1;
+					let freed = Arc::clone(&guard.freed);
+					drop(guard);
+					freed.acquire(None);
+				}
+				Err(err) => return Err(err),
+			}
+		}
+
+		let deadline = timeout.map(|ms| get_timer_ticks() + ms * 1000);
+		loop {
+			let signaled = done.acquire(Some(FAILURE_CHECK_INTERVAL));
+			let mut guard = driver.lock();
+			if !signaled {
+				// the interrupt may have been lost
+				guard.poll();
+			}
+			if guard
+				.pending
+				.get(&unique)
+				.map_or(false, |req| req.transfer.poll())
+			{
+				guard.finish(unique);
+				return Ok(());
+			}
+
+			let expired = deadline.map_or(false, |deadline| deadline < get_timer_ticks());
+			if guard.failed || guard.needs_reset() || expired {
+				guard.fail();
+				guard.finish(unique);
+				return Err(VirtqError::General);
+			}
+		}
+	}
+}
+
+impl FuseInterface for SpinlockIrqSave<VirtioFsDriver> {
+	fn send_command<S, T>(
+		&self,
+		cmd: fuse::Cmd<S>,
+		rsp: Option<fuse::Rsp<T>>,
+	) -> Option<fuse::Rsp<T>>
+	where
+		S: FuseIn + fmt::Debug,
+		T: FuseOut + fmt::Debug,
+	{
+		self.send_command_with_data(cmd, &[], rsp?, &mut [])
+	}
+
+	fn send_command_with_data<S, T>(
+		&self,
+		cmd: fuse::Cmd<S>,
+		data_out: &[u8],
+		mut rsp: fuse::Rsp<T>,
+		data_in: &mut [u8],
+	) -> Option<fuse::Rsp<T>>
+	where
+		S: FuseIn + fmt::Debug,
+		T: FuseOut + fmt::Debug,
+	{
+		// The data is passed to the device in place, the request only adds its headers in front of it.
+		trace!(
+			"Sending Fuse Command: {:?} with {} bytes out, {} bytes in",
+			cmd,
+			data_out.len(),
+			data_in.len()
+		);
+		let mut send = cmd.to_u8buf();
+		send.push(data_out);
+		let mut recv = rsp.to_u8buf_mut();
+		recv.push(data_in);
+
+		let timeout = if cmd.may_block() {
+			None
+		} else {
+			Some(REQUEST_TIMEOUT)
+		};
+		if let Err(err) =
+			VirtioFsDriver::transfer_blocking(self, cmd.unique(), false, timeout, &send, &recv)
+		{
+			error!("Unable to pass FUSE request to the device: {:?}", err);
+			return None;
+		}
+
+		drop(recv);
+		trace!("Got Fuse Reply: {:?}", rsp);
+		Some(rsp)
+	}
+
+	fn send_oneway<S>(&self, cmd: fuse::Cmd<S>)
+	where
+		S: FuseIn + fmt::Debug,
+	{
+		trace!("Sending Fuse Command without reply: {:?}", cmd);
+		if let Err(err) = VirtioFsDriver::transfer_blocking(
+			self,
+			cmd.unique(),
+			true,
+			Some(REQUEST_TIMEOUT),
+			&cmd.to_u8buf(),
+			&[],
+		) {
+			error!("Unable to pass FUSE request to the device: {:?}", err);
+		}
+	}
+
+	fn max_segments(&self) -> usize {
+		self.lock().max_segments()
+	}
+}
+
+// Public interface for virtio filesystem driver.
+impl VirtioFsDriver {
+	/// Initializes virtio filesystem device by mapping configuration layout to
+	/// respective structs (configuration structs are:
+	/// [ComCfg](structs.comcfg.html), [NotifCfg](structs.notifcfg.html)
+	/// [IsrStatus](structs.isrstatus.html), [PciCfg](structs.pcicfg.html)
+	/// [ShMemCfg](structs.ShMemCfg)).
+	///
+	/// Returns a driver instance of
+	/// [VirtioFsDriver](structs.virtiofsdriver.html) or an [VirtioError](enums.virtioerror.html).
+	pub fn init(adapter: &PciAdapter) -> Result<VirtioFsDriver, VirtioError> {
+		let mut drv = match pci::map_caps(adapter) {
+			Ok(caps) => match VirtioFsDriver::new(caps, adapter) {
+				Ok(driver) => driver,
+				Err(vfs_err) => {
+					error!("Initializing new filesystem driver failed. Aborting!");
+					return Err(VirtioError::FsDriver(vfs_err));
+				}
+			},
+			Err(pci_error) => {
+				error!("Mapping capabilites failed. Aborting!");
+				return Err(VirtioError::FromPci(pci_error));
+			}
+		};
+
+		match drv.init_dev() {
+			Ok(_) => info!(
+				"Filesystem device with id {:x}, has been initialized by driver!",
+				drv.dev_cfg.dev_id
+			),
+			Err(vfs_err) => {
+				drv.com_cfg.set_failed();
+				return Err(VirtioError::FsDriver(vfs_err));
+			}
+		}
+
+		Ok(drv)
+	}
+}
+
+/// Starts a FUSE session with each registered virtio filesystem device and mounts it.
+/// The mount point is taken from the `-mount <tag>=<path>[,ro]` command line option and defaults to `/<tag>`.
+///
+/// The requests wait for the interrupt of the device, so interrupts have to be enabled.
+pub fn init_fs() {
+	for drv in get_filesystem_drivers() {
+		let tag = drv.lock().tag();
+		let (path, readonly, dax) = match environment::get_command_line_mount(tag) {
+			Some(mount) => (mount.path.clone(), mount.readonly, mount.dax),
+			None => (format!("/{}", tag), false, false),
+		};
+
+		let mut fuse = fuse::Fuse::new(drv);
+		if dax {
+			match drv.lock().map_dax_window() {
+				Some((addr, len)) => fuse.enable_dax(addr, len),
+				None => warn!("virtio-fs {} does not provide a DAX window", tag),
+			}
+		}
+
+		// send FUSE_INIT to create session
+		if let Err(err) = fuse.send_init() {
+			error!(
+				"Unable to initialize the FUSE session of {}: {:?}",
+				tag, err
+			);
+			continue;
+		}
+
+		info!(
+			"Mounting virtio-fs {} at {}{}",
+			tag,
+			path,
+			if readonly { " (read-only)" } else { "" }
+		);
+		if let Err(err) = fs::FILESYSTEM.lock().mount(&path, Box::new(fuse), readonly) {
+			error!("Mounting virtio-fs {} at {} failed: {:?}", tag, path, err);
+		}
+	}
+}
+
+pub mod error {
+	/// Filesystem drivers error enum.
+	#[derive(Debug, Copy, Clone)]
+	pub enum VirtioFsError {
+		NoDevCfg(u16),
+		NoComCfg(u16),
+		NoIsrCfg(u16),
+		NoNotifCfg(u16),
+		FailFeatureNeg(u16),
+		/// The device does not name the filesystem
+		InvalidTag(u16),
+		/// The device does not offer any request queue
+		NoReqQueue(u16),
+	}
+}
@@ -5,6 +5,11 @@
 // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
 // UNCOMMENTED FOR CORRECT USE STATEMENT; IS THIS CORRECT?
 // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
+pub mod block;
+
+#[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
+pub mod fs;
+
 #[cfg(all(feature = "pci", not(target_arch = "aarch64")))]
 pub mod net;
 
@@ -79,23 +79,24 @@ pub fn netwakeup() {
 	NET_SEM.release();
 }
 
+/// Lets the network device check, whether it has raised the interrupt.
+/// Returns true, if a waiting task has been woken up.
+pub fn handle_interrupt() -> bool {
+	#[cfg(feature = "pci")]
+	if let Some(driver) = pci::get_network_driver() {
+		return driver.lock().handle_interrupt();
+	}
+
+	debug!("Unable to handle interrupt!");
+	false
+}
+
 #[cfg(target_arch = "x86_64")]
 pub extern "x86-interrupt" fn network_irqhandler(_stack_frame: ExceptionStackFrame) {
 	debug!("Receive network interrupt");
 	apic::eoi();
 
-	#[cfg(feature = "pci")]
-	let check_scheduler = match pci::get_network_driver() {
-		Some(driver) => driver.lock().handle_interrupt(),
-		_ => {
-			debug!("Unable to handle interrupt!");
-			false
-		}
-	};
-	#[cfg(not(feature = "pci"))]
-	let check_scheduler = false;
-
-	if check_scheduler {
+	if handle_interrupt() {
 		core_scheduler().scheduler();
 	}
 }
@@ -5,7 +5,6 @@
 #[cfg(not(feature = "newlib"))]
 use super::netwakeup;
 use crate::arch::kernel::pci::PciAdapter;
-use crate::arch::kernel::percore::increment_irq_counter;
 use crate::config::VIRTIO_MAX_QUEUE_SIZE;
 use crate::drivers::net::NetworkInterface;
 
@@ -481,7 +480,6 @@ pub struct VirtioNetDriver {
 	send_vqs: TxQueues,
 
 	num_vqs: u16,
-	irq: u8,
 }
 
 impl NetworkInterface for VirtioNetDriver {
@@ -635,8 +633,6 @@ impl NetworkInterface for VirtioNetDriver {
 	}
 
 	fn handle_interrupt(&mut self) -> bool {
-		increment_irq_counter((32 + self.irq).into());
-
 		if self.isr_stat.is_interrupt() {
 			// handle incoming packets
 			#[cfg(not(feature = "newlib"))]
@@ -823,7 +819,6 @@ impl VirtioNetDriver {
 				is_multi: false,
 			},
 			num_vqs: 0,
-			irq: adapter.irq,
 		})
 	}
 
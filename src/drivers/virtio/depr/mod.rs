@@ -1,3 +0,0 @@
-pub mod virtio;
-pub mod virtio_fs;
-pub mod virtio_net;
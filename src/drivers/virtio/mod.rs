@@ -1,13 +1,14 @@
 //! A module containing virtios core infrastructure for hermit-rs.
 //!
 //! The module contains virtios transport mechanisms, virtqueues and virtio specific errors
-pub mod depr;
 pub mod env;
 pub mod transport;
 pub mod virtqueue;
 
 pub mod error {
 	use crate::arch::x86_64::kernel::pci::error::PciError;
+	use crate::drivers::block::virtio_blk::error::VirtioBlkError;
+	use crate::drivers::fs::virtio_fs::error::VirtioFsError;
 	use crate::drivers::net::virtio_net::error::VirtioNetError;
 	use core::fmt;
 
@@ -16,6 +17,8 @@ pub mod error {
 		FromPci(PciError),
 		DevNotSupported(u16),
 		NetDriver(VirtioNetError),
+		FsDriver(VirtioFsError),
+		BlkDriver(VirtioBlkError),
 		Unknown,
 	}
 
@@ -43,6 +46,22 @@ pub mod error {
                     VirtioNetError::IncompFeatsSet(drv_feats, dev_feats) => write!(f, "Feature set: {:x} , is incompatible with the device features: {:x}", u64::from(*drv_feats), u64::from(*dev_feats)),
                     VirtioNetError::ProcessOngoing => write!(f, "Driver performed an unsuitable operation upon an ongoging transfer."),
                 },
+                VirtioError::FsDriver(fs_error) => match fs_error {
+                    VirtioFsError::NoDevCfg(id) => write!(f, "Filesystem driver failed, for device {:x}, due to a missing or malformed device config!", id),
+                    VirtioFsError::NoComCfg(id) => write!(f, "Filesystem driver failed, for device {:x}, due to a missing or malformed common config!", id),
+                    VirtioFsError::NoIsrCfg(id) => write!(f, "Filesystem driver failed, for device {:x}, due to a missing or malformed ISR status config!", id),
+                    VirtioFsError::NoNotifCfg(id) => write!(f, "Filesystem driver failed, for device {:x}, due to a missing or malformed notification config!", id),
+                    VirtioFsError::FailFeatureNeg(id) => write!(f, "Filesystem driver failed, for device {:x}, device did not acknowledge negotiated feature set!", id),
+                    VirtioFsError::InvalidTag(id) => write!(f, "Filesystem driver failed, for device {:x}, the filesystem tag is missing or not valid UTF-8!", id),
+                    VirtioFsError::NoReqQueue(id) => write!(f, "Filesystem driver failed, for device {:x}, the device does not offer any request queue!", id),
+                },
+                VirtioError::BlkDriver(blk_error) => match blk_error {
+                    VirtioBlkError::NoDevCfg(id) => write!(f, "Block driver failed, for device {:x}, due to a missing or malformed device config!", id),
+                    VirtioBlkError::NoComCfg(id) => write!(f, "Block driver failed, for device {:x}, due to a missing or malformed common config!", id),
+                    VirtioBlkError::NoIsrCfg(id) => write!(f, "Block driver failed, for device {:x}, due to a missing or malformed ISR status config!", id),
+                    VirtioBlkError::NoNotifCfg(id) => write!(f, "Block driver failed, for device {:x}, due to a missing or malformed notification config!", id),
+                    VirtioBlkError::FailFeatureNeg(id) => write!(f, "Block driver failed, for device {:x}, device did not acknowledge negotiated feature set!", id),
+                },
             }
 		}
 	}
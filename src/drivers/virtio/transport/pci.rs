@@ -3,26 +3,27 @@
 //! The module contains ...
 #![allow(dead_code)]
 
-use crate::arch::kernel::pci as kernel_pci;
 use crate::arch::kernel::pci::error::PciError;
-use crate::arch::kernel::pci::{PciAdapter, PciDriver};
+use crate::arch::kernel::pci::PciAdapter;
 use crate::arch::mm::PhysAddr;
-use crate::synch::spinlock::SpinlockIrqSave;
 use alloc::vec::Vec;
 use core::convert::TryInto;
 use core::mem;
 use core::result::Result;
 
+use crate::drivers::block::virtio_blk::VirtioBlkDriver;
 use crate::drivers::error::DriverError;
+use crate::drivers::fs::virtio_fs::VirtioFsDriver;
 use crate::drivers::net::virtio_net::VirtioNetDriver;
 use crate::drivers::virtio::device;
 use crate::drivers::virtio::env;
 use crate::drivers::virtio::env::memory::{MemLen, MemOff, VirtMemAddr};
 use crate::drivers::virtio::error::VirtioError;
 
+use crate::arch::x86_64::kernel::apic;
 use crate::arch::x86_64::kernel::irq::*;
-use crate::drivers::net::network_irqhandler;
-use crate::drivers::virtio::depr::virtio_fs;
+use crate::arch::x86_64::kernel::percore::*;
+use crate::drivers::{block, fs, net};
 
 /// Virtio device ID's
 /// See Virtio specification v1.1. - 5
@@ -44,6 +45,7 @@ pub enum DevId {
 	VIRTIO_TRANS_DEV_ID_ENTROPY = 0x1005,
 	VIRTIO_TRANS_DEV_ID_9P = 0x1009,
 	VIRTIO_DEV_ID_NET = 0x1041,
+	VIRTIO_DEV_ID_BLK = 0x1042,
 	VIRTIO_DEV_ID_FS = 0x105A,
 }
 
@@ -58,6 +60,7 @@ impl From<DevId> for u16 {
 			DevId::VIRTIO_TRANS_DEV_ID_ENTROPY => 0x1005,
 			DevId::VIRTIO_TRANS_DEV_ID_9P => 0x1009,
 			DevId::VIRTIO_DEV_ID_NET => 0x1041,
+			DevId::VIRTIO_DEV_ID_BLK => 0x1042,
 			DevId::VIRTIO_DEV_ID_FS => 0x105A,
 			DevId::INVALID => 0x0,
 		}
@@ -75,6 +78,7 @@ impl From<u16> for DevId {
 			0x1005 => DevId::VIRTIO_TRANS_DEV_ID_ENTROPY,
 			0x1009 => DevId::VIRTIO_TRANS_DEV_ID_9P,
 			0x1041 => DevId::VIRTIO_DEV_ID_NET,
+			0x1042 => DevId::VIRTIO_DEV_ID_BLK,
 			0x105A => DevId::VIRTIO_DEV_ID_FS,
 			_ => DevId::INVALID,
 		}
@@ -374,6 +378,17 @@ impl UniCapsColl {
 	pub fn get_notif_cfg(&mut self) -> Option<NotifCfg> {
 		self.notif_cfg_list.pop()
 	}
+
+	/// Returns the shared memory region with the given id, if the device provides it.
+	///
+	/// INFO: This function removes the Capability and returns ownership.
+	pub fn get_sh_mem_cfg(&mut self, id: u8) -> Option<ShMemCfg> {
+		let index = self
+			.sh_mem_cfg_list
+			.iter()
+			.position(|sh_mem| sh_mem.id == id)?;
+		Some(self.sh_mem_cfg_list.remove(index))
+	}
 }
 
 /// Wraps a [ComCfgRaw](structs.comcfgraw.html) in order to preserve
@@ -863,7 +878,6 @@ impl PciCfgAlt {
 pub struct ShMemCfg {
 	mem_addr: VirtMemAddr,
 	length: MemLen,
-	sh_mem: ShMem,
 	/// Shared memory regions are identified via an ID
 	/// See Virtio specification v1.1. - 4.1.4.7
 	id: u8,
@@ -871,99 +885,59 @@ pub struct ShMemCfg {
 
 impl ShMemCfg {
 	fn new(cap: &PciCap) -> Option<Self> {
-		if cap.bar.length < u64::from(cap.length + cap.offset) {
-			error!("Shared memory config of with id {} of device {:x}, does not fit into memory specified by bar {:x}!", 
-                cap.id,
-                cap.origin.dev_id,
-                 cap.bar.index
-            );
-			return None;
-		}
-
-		// Read the PciCap64 fields after the PciCap structure to get the right offset and length
-
-		// Assumes the cap_len is a multiple of 8
-		// This read MIGHT be slow, as it does NOT ensure 32 bit alignment.
+		// Read the PciCap64 fields after the PciCap structure to get the right offset and length.
+		// The cap_len field covers these fields, so they do not start at cap_len.
 		let offset_high = env::pci::read_cfg_no_adapter(
 			cap.origin.bus,
-			cap.origin.bus,
-			cap.origin.cfg_ptr + u32::from(cap.origin.cap_struct.cap_len),
+			cap.origin.dev,
+			cap.origin.cfg_ptr + mem::size_of::<PciCapRaw>() as u32,
 		);
 
 		// Create 64 bit offset from high and low 32 bit values
-		let offset =
-			MemOff::from((u64::from(offset_high) << 32) ^ u64::from(cap.origin.cap_struct.offset));
+		let offset = (u64::from(offset_high) << 32) ^ u64::from(cap.origin.cap_struct.offset);
 
-		// Assumes the cap_len is a multiple of 8
-		// This read MIGHT be slow, as it does NOT ensure 32 bit alignment.
 		let length_high = env::pci::read_cfg_no_adapter(
 			cap.origin.bus,
-			cap.origin.bus,
-			cap.origin.cfg_ptr + u32::from(cap.origin.cap_struct.cap_len + 4),
+			cap.origin.dev,
+			cap.origin.cfg_ptr + mem::size_of::<PciCapRaw>() as u32 + 4,
 		);
 
 		// Create 64 bit length from high and low 32 bit values
-		let length =
-			MemLen::from((u64::from(length_high) << 32) ^ u64::from(cap.origin.cap_struct.length));
-
-		let virt_addr_raw = cap.bar.mem_addr + offset;
-		let raw_ptr = usize::from(virt_addr_raw) as *mut u8;
+		let length = (u64::from(length_high) << 32) ^ u64::from(cap.origin.cap_struct.length);
 
-		// Zero initialize shared memory area
-		unsafe {
-			for i in 0..usize::from(length) {
-				*(raw_ptr.add(i)) = 0;
-			}
-		};
-
-		// Currently in place in order to ensure a safe cast below
-		// "len: cap.bar.length as usize"
-		// In order to remove this assert a safe conversion from
-		// kernel PciBar struct into usize must be made
-		assert!(mem::size_of::<usize>() == 8);
+		if cap.bar.length < offset + length {
+			error!("Shared memory config of with id {} of device {:x}, does not fit into memory specified by bar {:x}!",
+				cap.id,
+				cap.origin.dev_id,
+				cap.bar.index
+			);
+			return None;
+		}
 
+		// The content of the region is defined by the device (e.g. a file mapped by the host),
+		// so it must neither be initialized nor cleared by the driver.
 		Some(ShMemCfg {
-			mem_addr: virt_addr_raw,
-			length: cap.length,
-			sh_mem: ShMem {
-				ptr: raw_ptr,
-				len: cap.bar.length as usize,
-			},
+			mem_addr: cap.bar.mem_addr + MemOff::from(offset),
+			length: MemLen::from(length),
 			id: cap.id,
 		})
 	}
 }
 
-/// Defines a shared memory locate at location ptr with a length of len.
-/// The shared memories Drop implementation does not dealloc the memory
-/// behind the pointer but sets it to zero, to prevent leakage of data.
-struct ShMem {
-	ptr: *mut u8,
-	len: usize,
-}
-
-impl core::ops::Deref for ShMem {
-	type Target = [u8];
-
-	fn deref(&self) -> &[u8] {
-		unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+// Public interface of ShMemCfg
+impl ShMemCfg {
+	/// Returns the id, which defines the purpose of the region for the device type
+	pub fn id(&self) -> u8 {
+		self.id
 	}
-}
 
-impl core::ops::DerefMut for ShMem {
-	fn deref_mut(&mut self) -> &mut [u8] {
-		unsafe { core::slice::from_raw_parts_mut(self.ptr, self.len) }
+	/// Returns the virtual address, at which the region is mapped
+	pub fn addr(&self) -> VirtMemAddr {
+		self.mem_addr
 	}
-}
 
-// Upon drop the shared memory region is "deleted" with zeros.
-impl Drop for ShMem {
-	fn drop(&mut self) {
-		for i in 0..self.len {
-			unsafe {
-				*(self.ptr.add(i)) = 0;
-			}
-		}
+	pub fn len(&self) -> MemLen {
+		self.length
 	}
 }
 
@@ -1223,7 +1197,6 @@ pub fn map_caps(adapter: &PciAdapter) -> Result<UniCapsColl, PciError> {
 pub fn init_device(adapter: &PciAdapter) -> Result<VirtioDriver, DriverError> {
 	let virt_drv = match DevId::from(adapter.device_id) {
 		DevId::VIRTIO_TRANS_DEV_ID_NET
-		| DevId::VIRTIO_TRANS_DEV_ID_BLK
 		| DevId::VIRTIO_TRANS_DEV_ID_MEM_BALL
 		| DevId::VIRTIO_TRANS_DEV_ID_CONS
 		| DevId::VIRTIO_TRANS_DEV_ID_SCSI
@@ -1252,22 +1225,35 @@ pub fn init_device(adapter: &PciAdapter) -> Result<VirtioDriver, DriverError> {
 				Err(DriverError::InitVirtioDevFail(virtio_error))
 			}
 		},
-		DevId::VIRTIO_DEV_ID_FS => {
-			info!("Found Virtio-FS device!");
-			// TODO: check subclass
-			// TODO: proper error handling on driver creation fail
-			match virtio_fs::create_virtiofs_driver(adapter) {
-				Some(virt_fs_drv) => {
-					kernel_pci::register_driver(PciDriver::VirtioFs(SpinlockIrqSave::new(
-						virt_fs_drv,
-					)));
-					// initialize file system
-					virtio_fs::init_fs();
-					Ok(VirtioDriver::FileSystem)
+		// Transitional block devices also offer the modern interface, which the driver requires.
+		DevId::VIRTIO_TRANS_DEV_ID_BLK | DevId::VIRTIO_DEV_ID_BLK => {
+			match VirtioBlkDriver::init(adapter) {
+				Ok(virt_blk_drv) => {
+					info!("Virtio block driver initialized with Virtio block device.");
+					Ok(VirtioDriver::Block(virt_blk_drv))
+				}
+				Err(virtio_error) => {
+					error!(
+						"Virtio block driver could not be initialized with device: {:x}",
+						adapter.device_id
+					);
+					Err(DriverError::InitVirtioDevFail(virtio_error))
 				}
-				None => Err(DriverError::InitVirtioDevFail(VirtioError::Unknown)),
 			}
 		}
+		DevId::VIRTIO_DEV_ID_FS => match VirtioFsDriver::init(adapter) {
+			Ok(virt_fs_drv) => {
+				info!("Virtio filesystem driver initialized with Virtio filesystem device.");
+				Ok(VirtioDriver::FileSystem(virt_fs_drv))
+			}
+			Err(virtio_error) => {
+				error!(
+					"Virtio filesystem driver could not be initialized with device: {:x}",
+					adapter.device_id
+				);
+				Err(DriverError::InitVirtioDevFail(virtio_error))
+			}
+		},
 		_ => {
 			warn!(
 				"Virtio device with id: {:#x} is NOT supported, skipping!",
@@ -1283,25 +1269,44 @@ pub fn init_device(adapter: &PciAdapter) -> Result<VirtioDriver, DriverError> {
 
 	match virt_drv {
 		Ok(drv) => {
-			match &drv {
-				VirtioDriver::Network(_) => {
-					info!("Install virtio interrupt handler at line {}", adapter.irq);
-					// Install interrupt handler
-					irq_install_handler(adapter.irq as u32, network_irqhandler as usize);
-					add_irq_name(adapter.irq as u32, "virtio_net");
-
-					Ok(drv)
-				}
-				VirtioDriver::FileSystem => Ok(drv),
-			}
+			info!("Install virtio interrupt handler at line {}", adapter.irq);
+			// Install interrupt handler, which serves all virtio devices sharing the line
+			irq_install_handler(adapter.irq as u32, virtio_irqhandler as usize);
+			add_irq_name(adapter.irq as u32, "virtio");
+
+			Ok(drv)
 		}
 		Err(virt_err) => Err(virt_err),
 	}
 }
 
+/// Handles the interrupts of all virtio devices. Devices of any type may share an interrupt
+/// line (INTx), so a single handler is installed for every line and all devices check their status.
+/// The interrupt is counted once for its line, regardless of the number of devices.
+pub extern "x86-interrupt" fn virtio_irqhandler(_stack_frame: ExceptionStackFrame) {
+	debug!("Receive virtio interrupt");
+	let vector = apic::in_service_vector();
+	apic::eoi();
+	if let Some(vector) = vector {
+		increment_irq_counter(vector.into());
+	}
+
+	// every device has to be checked, even if an earlier one has woken a task
+	let woken = [
+		net::handle_interrupt(),
+		fs::handle_interrupts(),
+		block::handle_interrupts(),
+	];
+
+	if woken.contains(&true) {
+		core_scheduler().scheduler();
+	}
+}
+
 pub enum VirtioDriver {
 	Network(VirtioNetDriver),
-	FileSystem,
+	FileSystem(VirtioFsDriver),
+	Block(VirtioBlkDriver),
 }
 /// The module contains constants specific to PCI.
 #[allow(dead_code)]
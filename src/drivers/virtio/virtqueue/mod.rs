@@ -174,6 +174,20 @@ impl Virtq {
 	}
 }
 
+/// Returns the length of the physically contiguous memory area at the start of a non-empty `slice`.
+fn phys_contiguous_len(slice: &[u8]) -> usize {
+	let start = slice.as_ptr() as usize;
+	let start_phys = paging::virt_to_phys(VirtAddr::from(start));
+	let mut len = align_down!(start, BasePageSize::SIZE) + BasePageSize::SIZE - start;
+
+	while len < slice.len() && paging::virt_to_phys(VirtAddr::from(start + len)) == start_phys + len
+	{
+		len += BasePageSize::SIZE;
+	}
+
+	len.min(slice.len())
+}
+
 // Public interface of Virtq
 impl Virtq {
 	/// Enables interrupts for this virtqueue upon receiving a transfer
@@ -503,6 +517,50 @@ impl Virtq {
 		}
 	}
 
+	/// Provides the calley with a TransferToken, whose send and receive buffers refer to the given memory areas.
+	/// In contrast to `prep_transfer_from_raw()`, the areas do not need to be part of a single structure. This allows
+	/// to pass headers and payloads to the device without copying them into a common buffer.
+	///
+	/// **INFO:**
+	/// * Data behind the slices will NOT be deallocated. Under no circumstances.
+	/// * Calley is responsible for ensuring the slices remain valid until the [Transfer](Transfer) is closed.
+	/// * Slices which are not physically contiguous are split into several descriptors.
+	/// * Empty slices are skipped.
+	///
+	/// **Reasons for Failure:**
+	/// * Neither send nor receive areas contain any data.
+	/// * Queue does not have enough descriptors left.
+	pub fn prep_transfer_from_slices(
+		&self,
+		rc_self: Rc<Virtq>,
+		send: &[&[u8]],
+		recv: &[&mut [u8]],
+	) -> Result<TransferToken, VirtqError> {
+		let mem_pool = match self {
+			Virtq::Packed(vq) => vq.mem_pool(),
+			Virtq::Split(vq) => vq.mem_pool(),
+		};
+
+		let send_buff = Buffer::from_slices(&mem_pool, send.iter().copied())?;
+		let recv_buff = Buffer::from_slices(&mem_pool, recv.iter().map(|slice| &slice[..]))?;
+		if send_buff.is_none() && recv_buff.is_none() {
+			return Err(VirtqError::BufferNotSpecified);
+		}
+
+		Ok(TransferToken {
+			state: TransferState::Ready,
+			buff_tkn: Some(BufferToken {
+				send_buff,
+				recv_buff,
+				vq: rc_self,
+				ret_send: false,
+				ret_recv: false,
+				reusable: false,
+			}),
+			await_queue: None,
+		})
+	}
+
 	/// Early drop provides a mechanism for the queue to detect, if an ongoing transfer or a transfer not yet polled by the driver
 	/// has been dropped. The queue implementation is responsible for taking care what should happen to the respective TransferToken
 	/// and BufferToken.
@@ -1763,6 +1821,36 @@ enum Buffer {
 
 // Private Interface of Buffer
 impl Buffer {
+	/// Creates a buffer, which refers to the given memory areas. Each physically contiguous part of
+	/// an area consumes one descriptor. Returns `None`, if all areas are empty.
+	fn from_slices<'a>(
+		mem_pool: &Rc<MemPool>,
+		slices: impl Iterator<Item = &'a [u8]>,
+	) -> Result<Option<Buffer>, VirtqError> {
+		let mut desc_lst = Vec::new();
+		let mut len = 0usize;
+
+		for slice in slices {
+			let mut rest = slice;
+			while !rest.is_empty() {
+				let (part, next) = rest.split_at(phys_contiguous_len(rest));
+				desc_lst.push(mem_pool.pull_from_raw(Rc::clone(mem_pool), part)?);
+				rest = next;
+			}
+			len += slice.len();
+		}
+
+		if desc_lst.is_empty() {
+			Ok(None)
+		} else {
+			Ok(Some(Buffer::Multiple {
+				desc_lst: desc_lst.into_boxed_slice(),
+				len,
+				next_write: 0,
+			}))
+		}
+	}
+
 	/// Resets the Buffers length to the given len. This MUST be the length at initialization.
 	fn reset_len(&mut self, init_len: usize) {
 		match self {
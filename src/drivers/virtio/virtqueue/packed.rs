@@ -1204,6 +1204,11 @@ impl PackedVq {
 		self.index
 	}
 
+	/// Returns the memory pool, which tracks the descriptors of the queue.
+	pub(super) fn mem_pool(&self) -> Rc<MemPool> {
+		Rc::clone(&self.mem_pool)
+	}
+
 	/// See `Virtq::new()` documentation
 	pub fn new(
 		com_cfg: &mut ComCfg,
@@ -1276,7 +1281,7 @@ impl PackedVq {
 		let mut notif_ctrl = NotifCtrl::new(
 			(notif_cfg.base()
 				+ usize::try_from(vq_handler.notif_off()).unwrap()
-				+ usize::try_from(notif_cfg.multiplier()).unwrap()) as *mut usize,
+					* usize::try_from(notif_cfg.multiplier()).unwrap()) as *mut usize,
 		);
 
 		if feats & Features::VIRTIO_F_NOTIFICATION_DATA == Features::VIRTIO_F_NOTIFICATION_DATA {
@@ -368,6 +368,11 @@ impl SplitVq {
 		self.index
 	}
 
+	/// Returns the memory pool, which tracks the descriptors of the queue.
+	pub(super) fn mem_pool(&self) -> Rc<MemPool> {
+		Rc::clone(&self.mem_pool)
+	}
+
 	/// See `Virtq::new()` documentation
 	pub fn new(
 		com_cfg: &mut ComCfg,
@@ -454,7 +459,7 @@ impl SplitVq {
 		let notif_ctrl = NotifCtrl::new(
 			(notif_cfg.base()
 				+ usize::try_from(vq_handler.notif_off()).unwrap()
-				+ usize::try_from(notif_cfg.multiplier()).unwrap()) as *mut usize,
+					* usize::try_from(notif_cfg.multiplier()).unwrap()) as *mut usize,
 		);
 
 		// Initialize new memory pool.
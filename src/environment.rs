@@ -23,6 +23,48 @@ static mut COMMAND_LINE_CPU_FREQUENCY: u16 = 0;
 static mut IS_PROXY: bool = false;
 static mut COMMAND_LINE_APPLICATION: Option<Vec<String>> = None;
 static mut COMMAND_LINE_PATH: Option<String> = None;
+static mut COMMAND_LINE_MOUNTS: Vec<MountOption> = Vec::new();
+static mut COMMAND_LINE_PAGECACHE: Option<usize> = None;
+
+/// Mount point of a filesystem device, as given by `-mount <tag>=<path>[,ro][,dax]`
+pub struct MountOption {
+	/// Tag, which names the filesystem of the device
+	pub tag: String,
+	/// Absolute path of the mount point
+	pub path: String,
+	/// Modifications of the filesystem are rejected
+	pub readonly: bool,
+	/// File contents are accessed through the DAX window of the device instead of requests
+	pub dax: bool,
+}
+
+/// Parses the argument of `-mount`, which has the form `<tag>=<path>[,ro|,rw][,dax]`.
+fn parse_mount_option(arg: &str) -> Option<MountOption> {
+	let (tag, options) = arg.split_once('=')?;
+	let mut options = options.split(',');
+	let path = options.next().filter(|path| path.starts_with('/'))?;
+	if tag.is_empty() {
+		return None;
+	}
+
+	let mut readonly = false;
+	let mut dax = false;
+	for option in options {
+		match option {
+			"ro" => readonly = true,
+			"rw" => readonly = false,
+			"dax" => dax = true,
+			_ => warn!("Unknown mount option {} of {}", option, tag),
+		}
+	}
+
+	Some(MountOption {
+		tag: String::from(tag),
+		path: String::from(path),
+		readonly,
+		dax,
+	})
+}
 
 unsafe fn parse_command_line() {
 	let cmdsize = get_cmdsize();
@@ -51,6 +93,20 @@ unsafe fn parse_command_line() {
 			"-proxy" => {
 				IS_PROXY = true;
 			}
+			"-mount" => {
+				let arg = tokeniter.next().expect("Invalid -mount command line");
+				match parse_mount_option(&arg) {
+					Some(mount) => COMMAND_LINE_MOUNTS.push(mount),
+					None => warn!("Invalid -mount command line: {}", arg),
+				}
+			}
+			"-pagecache" => {
+				let mib_str = tokeniter.next().expect("Invalid -pagecache command line");
+				match mib_str.parse() {
+					Ok(mib) => COMMAND_LINE_PAGECACHE = Some(mib),
+					Err(_) => warn!("Invalid -pagecache command line: {}", mib_str),
+				}
+			}
 			"--" => {
 				// Collect remaining arguments as applications argv
 				//ToDo -> we know the length here, so we could (should convert this into a safe
@@ -69,11 +125,41 @@ unsafe fn parse_command_line() {
 	}
 }
 
+/// Returns the whole command line of the kernel.
+pub fn get_command_line() -> &'static str {
+	let cmdsize = get_cmdsize();
+	if cmdsize == 0 {
+		return "";
+	}
+
+	unsafe {
+		let slice = slice::from_raw_parts(get_cmdline().as_ptr::<u8>(), cmdsize);
+		str::from_utf8(slice).unwrap_or("")
+	}
+}
+
 /// Returns the cmdline argument passed in after "--"
 pub fn get_command_line_argv() -> Option<&'static [String]> {
 	unsafe { COMMAND_LINE_APPLICATION.as_deref() }
 }
 
+/// Returns the mount point of the filesystem `tag`, if given by `-mount`.
+/// If a tag is given several times, the last mount point is used.
+pub fn get_command_line_mount(tag: &str) -> Option<&'static MountOption> {
+	unsafe {
+		COMMAND_LINE_MOUNTS
+			.iter()
+			.rev()
+			.find(|mount| mount.tag == tag)
+	}
+}
+
+/// Size of the page cache in MiB if given through the -pagecache command-line parameter.
+/// A size of zero disables the cache.
+pub fn get_command_line_pagecache() -> Option<usize> {
+	unsafe { COMMAND_LINE_PAGECACHE }
+}
+
 #[allow(dead_code)]
 /// Returns the first cmdline argument, if not otherwise recognized. With qemu this is the host-path to the kernel (rusty-loader)
 pub fn get_command_line_path() -> Option<&'static str> {
@@ -104,3 +190,25 @@ pub fn get_command_line_cpu_frequency() -> u16 {
 pub fn is_proxy() -> bool {
 	unsafe { IS_PROXY }
 }
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::parse_mount_option;
+
+	#[test]
+	fn test_parse_mount_option() {
+		let mount = parse_mount_option("data=/mnt/data,ro").unwrap();
+		assert_eq!(mount.tag, "data");
+		assert_eq!(mount.path, "/mnt/data");
+		assert!(mount.readonly);
+		assert!(!mount.dax);
+
+		let mount = parse_mount_option("scratch=/scratch,dax").unwrap();
+		assert!(!mount.readonly);
+		assert!(mount.dax);
+		assert!(parse_mount_option("data").is_none());
+		assert!(parse_mount_option("=/mnt").is_none());
+		assert!(parse_mount_option("data=mnt").is_none());
+	}
+}
@@ -26,6 +26,7 @@
 #![feature(alloc_error_handler)]
 #![feature(vec_into_raw_parts)]
 #![feature(drain_filter)]
+#![feature(try_reserve)]
 #![feature(llvm_asm)]
 #![feature(global_asm)]
 #![no_std]
@@ -31,6 +31,8 @@ pub struct Heap {
 	index: usize,
 	bottom: usize,
 	size: usize,
+	/// Number of bytes allocated from the holes
+	used: usize,
 	#[cfg(any(target_os = "hermit", target_os = "none"))]
 	holes: HoleList,
 	#[cfg(not(any(target_os = "hermit", target_os = "none")))]
@@ -45,6 +47,7 @@ impl Heap {
 			index: 0,
 			bottom: 0,
 			size: 0,
+			used: 0,
 			holes: HoleList::empty(),
 		}
 	}
@@ -71,6 +74,7 @@ impl Heap {
 			index: 0,
 			bottom: heap_bottom,
 			size: heap_size,
+			used: 0,
 			holes: HoleList::new(heap_bottom, heap_size),
 		}
 	}
@@ -112,7 +116,11 @@ impl Heap {
 			)
 			.unwrap();
 
-			self.holes.allocate_first_fit(layout)
+			let result = self.holes.allocate_first_fit(layout);
+			if result.is_ok() {
+				self.used += size;
+			}
+			result
 		}
 	}
 
@@ -141,6 +149,7 @@ impl Heap {
 			.unwrap();
 
 			self.holes.deallocate(ptr, layout);
+			self.used -= size;
 		}
 	}
 
@@ -154,6 +163,11 @@ impl Heap {
 		self.size
 	}
 
+	/// Returns the number of bytes, which are allocated.
+	pub fn used(&self) -> usize {
+		self.used
+	}
+
 	/// Return the top address of the heap
 	pub fn top(&self) -> usize {
 		self.bottom + self.size
@@ -191,6 +205,7 @@ impl LockedHeap {
 			index: 0,
 			bottom: heap_bottom,
 			size: heap_size,
+			used: 0,
 			holes: HoleList::new(heap_bottom, heap_size),
 		}))
 	}
@@ -147,6 +147,11 @@ impl FreeList {
 		self.list.push_back(new_element);
 	}
 
+	/// Returns the number of bytes in the free list.
+	pub fn free_size(&self) -> usize {
+		self.list.iter().map(|node| node.end - node.start).sum()
+	}
+
 	pub fn print_information(&self, header: &str) {
 		infoheader!(header);
 
@@ -13,7 +13,7 @@ use crate::arch::mm::physicalmem::total_memory_size;
 use crate::arch::mm::virtualmem::kernel_heap_end;
 use crate::arch::mm::{PhysAddr, VirtAddr};
 use crate::environment;
-use core::mem;
+use core::{fmt, mem};
 
 /// Physical and virtual address of the first 2 MiB page that maps the kernel.
 /// Can be easily accessed through kernel_start_address()
@@ -223,6 +223,30 @@ pub fn print_information() {
 	arch::mm::virtualmem::print_information();
 }
 
+/// Writes the usage of physical memory, of the kernel heap and of the virtual address space
+/// of the kernel in the format of /proc/meminfo (sizes in KiB).
+pub fn write_information(f: &mut dyn fmt::Write) -> fmt::Result {
+	writeln!(f, "MemTotal:      {:>12} kB", total_memory_size() >> 10)?;
+	writeln!(
+		f,
+		"MemFree:       {:>12} kB",
+		arch::mm::physicalmem::free_memory_size() >> 10
+	)?;
+
+	#[cfg(any(target_os = "hermit", target_os = "none"))]
+	{
+		let heap = crate::ALLOCATOR.lock();
+		writeln!(f, "HeapTotal:     {:>12} kB", heap.size() >> 10)?;
+		writeln!(f, "HeapUsed:      {:>12} kB", heap.used() >> 10)?;
+	}
+
+	writeln!(
+		f,
+		"VirtualFree:   {:>12} kB",
+		arch::mm::virtualmem::free_size() >> 10
+	)
+}
+
 pub fn allocate(sz: usize, no_execution: bool) -> VirtAddr {
 	let size = align_up!(sz, BasePageSize::SIZE);
 	let physical_address = arch::mm::physicalmem::allocate(size).unwrap();
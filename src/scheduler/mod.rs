@@ -1,6 +1,8 @@
 use alloc::boxed::Box;
 use alloc::collections::{BTreeMap, VecDeque};
 use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cell::RefCell;
 #[cfg(feature = "smp")]
@@ -26,8 +28,23 @@ static NO_TASKS: AtomicU32 = AtomicU32::new(0);
 /// Map between Core ID and per-core scheduler
 static mut SCHEDULERS: Vec<&PerCoreScheduler> = Vec::new();
 /// Map between Task ID and Task Control Block
-static TASKS: SpinlockIrqSave<BTreeMap<TaskId, VecDeque<TaskHandle>>> =
-	SpinlockIrqSave::new(BTreeMap::new());
+static TASKS: SpinlockIrqSave<BTreeMap<TaskId, TaskEntry>> = SpinlockIrqSave::new(BTreeMap::new());
+
+/// Entry of a task in `TASKS`, which exists until the task is cleaned up
+struct TaskEntry {
+	info: Arc<TaskInfo>,
+	/// Tasks waiting for the termination of the task
+	waiters: VecDeque<TaskHandle>,
+}
+
+impl TaskEntry {
+	fn new(task: &Rc<RefCell<Task>>) -> Self {
+		Self {
+			info: task.borrow().info.clone(),
+			waiters: VecDeque::with_capacity(1),
+		}
+	}
+}
 
 /// Unique identifier for a core.
 pub type CoreId = u32;
@@ -95,12 +112,14 @@ impl PerCoreScheduler {
 			stack_size,
 		)));
 		task.borrow_mut().create_stack_frame(func, arg);
+		// The new task inherits the working directory of its creator.
+		task.borrow_mut().cwd = core_scheduler().get_current_task_cwd();
 
 		// Add it to the task lists.
 		let wakeup = {
 			#[cfg(feature = "smp")]
 			let mut input_locked = get_scheduler(core_id).input.lock();
-			TASKS.lock().insert(tid, VecDeque::with_capacity(1));
+			TASKS.lock().insert(tid, TaskEntry::new(&task));
 			NO_TASKS.fetch_add(1, Ordering::SeqCst);
 
 			#[cfg(feature = "smp")]
@@ -148,7 +167,7 @@ impl PerCoreScheduler {
 				"Finishing task {} with exit code {}",
 				current_task_borrowed.id, exit_code
 			);
-			current_task_borrowed.status = TaskStatus::Finished;
+			current_task_borrowed.set_status(TaskStatus::Finished);
 			NO_TASKS.fetch_sub(1, Ordering::SeqCst);
 		};
 
@@ -193,7 +212,7 @@ impl PerCoreScheduler {
 		let wakeup = {
 			#[cfg(feature = "smp")]
 			let mut input_locked = get_scheduler(core_id).input.lock();
-			TASKS.lock().insert(tid, VecDeque::with_capacity(1));
+			TASKS.lock().insert(tid, TaskEntry::new(&clone_task));
 			NO_TASKS.fetch_add(1, Ordering::SeqCst);
 			#[cfg(feature = "smp")]
 			if core_id != core_scheduler().core_id {
@@ -310,7 +329,17 @@ impl PerCoreScheduler {
 
 	#[inline]
 	pub fn set_current_task_wakeup_reason(&mut self, reason: WakeupReason) {
-		irqsave(|| self.current_task.borrow_mut().last_wakeup_reason = reason);
+		irqsave(|| self.current_task.borrow_mut().set_wakeup_reason(reason));
+	}
+
+	#[inline]
+	pub fn get_current_task_cwd(&self) -> Option<String> {
+		irqsave(|| self.current_task.borrow().cwd.clone())
+	}
+
+	#[inline]
+	pub fn set_current_task_cwd(&mut self, cwd: String) {
+		irqsave(|| self.current_task.borrow_mut().cwd = Some(cwd));
 	}
 
 	#[cfg(target_arch = "x86_64")]
@@ -363,8 +392,8 @@ impl PerCoreScheduler {
 			debug!("Cleaning up task {}", borrowed.id);
 
 			// wakeup tasks, which are waiting for task with the identifier id
-			if let Some(mut queue) = TASKS.lock().remove(&borrowed.id) {
-				while let Some(task) = queue.pop_front() {
+			if let Some(mut entry) = TASKS.lock().remove(&borrowed.id) {
+				while let Some(task) = entry.waiters.pop_front() {
 					result = true;
 					self.custom_wakeup(task);
 				}
@@ -456,7 +485,9 @@ impl PerCoreScheduler {
 		} else {
 			if status == TaskStatus::Finished {
 				// Mark the finished task as invalid and add it to the finished tasks for a later cleanup.
-				self.current_task.borrow_mut().status = TaskStatus::Invalid;
+				self.current_task
+					.borrow_mut()
+					.set_status(TaskStatus::Invalid);
 				self.finished_tasks.push_back(self.current_task.clone());
 			}
 
@@ -479,7 +510,7 @@ impl PerCoreScheduler {
 			// Handle the current task.
 			if status == TaskStatus::Running {
 				// Mark the running task as ready again and add it back to the queue.
-				self.current_task.borrow_mut().status = TaskStatus::Ready;
+				self.current_task.borrow_mut().set_status(TaskStatus::Ready);
 				self.ready_queue.push(self.current_task.clone());
 			}
 
@@ -488,7 +519,7 @@ impl PerCoreScheduler {
 				let mut borrowed = task.borrow_mut();
 				if borrowed.status != TaskStatus::Idle {
 					// Mark the new task as running.
-					borrowed.status = TaskStatus::Running;
+					borrowed.set_status(TaskStatus::Running);
 				}
 
 				(
@@ -553,7 +584,7 @@ pub fn add_current_core() {
 	let idle_task = Rc::new(RefCell::new(Task::new_idle(tid, core_id)));
 
 	// Add the ID -> Task mapping.
-	TASKS.lock().insert(tid, VecDeque::with_capacity(1));
+	TASKS.lock().insert(tid, TaskEntry::new(&idle_task));
 	// Initialize a scheduler for this core.
 	debug!(
 		"Initializing scheduler for core {} with idle task {}",
@@ -593,6 +624,15 @@ fn get_scheduler(core_id: CoreId) -> &'static PerCoreScheduler {
 	}
 }
 
+/// Returns the state of all tasks, which have not been cleaned up yet.
+pub fn get_tasks() -> Vec<Arc<TaskInfo>> {
+	TASKS
+		.lock()
+		.values()
+		.map(|entry| entry.info.clone())
+		.collect()
+}
+
 pub fn join(id: TaskId) -> Result<(), ()> {
 	let core_scheduler = core_scheduler();
 
@@ -605,8 +645,10 @@ pub fn join(id: TaskId) -> Result<(), ()> {
 	{
 		let mut guard = TASKS.lock();
 		match guard.get_mut(&id) {
-			Some(queue) => {
-				queue.push_back(core_scheduler.get_current_task_handle());
+			Some(entry) => {
+				entry
+					.waiters
+					.push_back(core_scheduler.get_current_task_handle());
 				core_scheduler.block_current_task(None);
 			}
 			_ => {
@@ -5,11 +5,14 @@ use crate::arch::scheduler::{TaskStacks, TaskTLS};
 use crate::scheduler::CoreId;
 use alloc::collections::{LinkedList, VecDeque};
 use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::sync::Arc;
 use core::cell::RefCell;
 use core::cmp::Ordering;
 use core::convert::TryInto;
 use core::fmt;
 use core::num::NonZeroU64;
+use core::sync::atomic::{AtomicU8, Ordering as AtomicOrdering};
 
 /// Returns the most significant bit.
 ///
@@ -37,12 +40,53 @@ pub enum TaskStatus {
 }
 
 /// Reason why wakeup() has been called on a task.
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum WakeupReason {
 	Custom,
 	Timer,
 }
 
+const TASK_STATUS: [TaskStatus; 6] = [
+	TaskStatus::Invalid,
+	TaskStatus::Ready,
+	TaskStatus::Running,
+	TaskStatus::Blocked,
+	TaskStatus::Finished,
+	TaskStatus::Idle,
+];
+
+const WAKEUP_REASONS: [WakeupReason; 2] = [WakeupReason::Custom, WakeupReason::Timer];
+
+/// State of a task, which can be read on all cores (e.g. by /proc/tasks).
+/// The task itself is only accessible on its core.
+pub struct TaskInfo {
+	pub id: TaskId,
+	pub prio: Priority,
+	pub core_id: CoreId,
+	status: AtomicU8,
+	last_wakeup_reason: AtomicU8,
+}
+
+impl TaskInfo {
+	fn new(id: TaskId, prio: Priority, core_id: CoreId, status: TaskStatus) -> Arc<Self> {
+		Arc::new(Self {
+			id,
+			prio,
+			core_id,
+			status: AtomicU8::new(status as u8),
+			last_wakeup_reason: AtomicU8::new(WakeupReason::Custom as u8),
+		})
+	}
+
+	pub fn status(&self) -> TaskStatus {
+		TASK_STATUS[usize::from(self.status.load(AtomicOrdering::Relaxed))]
+	}
+
+	pub fn last_wakeup_reason(&self) -> WakeupReason {
+		WAKEUP_REASONS[usize::from(self.last_wakeup_reason.load(AtomicOrdering::Relaxed))]
+	}
+}
+
 /// Unique identifier for a task (i.e. `pid`).
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
 pub struct TaskId(u32);
@@ -354,7 +398,7 @@ impl PriorityTaskQueue {
 pub struct Task {
 	/// The ID of this context
 	pub id: TaskId,
-	/// Status of a task, e.g. if the task is ready or blocked
+	/// Status of a task, e.g. if the task is ready or blocked. It is changed by `set_status`.
 	pub status: TaskStatus,
 	/// Task priority,
 	pub prio: Priority,
@@ -374,8 +418,12 @@ pub struct Task {
 	pub prev: Option<Rc<RefCell<Task>>>,
 	/// Task Thread-Local-Storage (TLS)
 	pub tls: Option<TaskTLS>,
-	/// Reason why wakeup() has been called the last time
+	/// Reason why wakeup() has been called the last time. It is changed by `set_wakeup_reason`.
 	pub last_wakeup_reason: WakeupReason,
+	/// State of the task, which is shared with other cores
+	pub info: Arc<TaskInfo>,
+	/// Current working directory, `None` selects the default working directory
+	pub cwd: Option<String>,
 	/// lwIP error code for this task
 	#[cfg(feature = "newlib")]
 	pub lwip_errno: i32,
@@ -409,6 +457,8 @@ impl Task {
 			prev: None,
 			tls: None,
 			last_wakeup_reason: WakeupReason::Custom,
+			info: TaskInfo::new(tid, task_prio, core_id, task_status),
+			cwd: None,
 			#[cfg(feature = "newlib")]
 			lwip_errno: 0,
 		}
@@ -430,6 +480,8 @@ impl Task {
 			prev: None,
 			tls: None,
 			last_wakeup_reason: WakeupReason::Custom,
+			info: TaskInfo::new(tid, IDLE_PRIO, core_id, TaskStatus::Idle),
+			cwd: None,
 			#[cfg(feature = "newlib")]
 			lwip_errno: 0,
 		}
@@ -451,10 +503,26 @@ impl Task {
 			prev: None,
 			tls: task.tls.clone(),
 			last_wakeup_reason: task.last_wakeup_reason,
+			info: TaskInfo::new(tid, task.prio, core_id, TaskStatus::Ready),
+			cwd: task.cwd.clone(),
 			#[cfg(feature = "newlib")]
 			lwip_errno: 0,
 		}
 	}
+
+	pub fn set_status(&mut self, status: TaskStatus) {
+		self.status = status;
+		self.info
+			.status
+			.store(status as u8, AtomicOrdering::Relaxed);
+	}
+
+	pub fn set_wakeup_reason(&mut self, reason: WakeupReason) {
+		self.last_wakeup_reason = reason;
+		self.info
+			.last_wakeup_reason
+			.store(reason as u8, AtomicOrdering::Relaxed);
+	}
 }
 
 /*impl Drop for Task {
@@ -506,8 +574,8 @@ impl BlockedTaskQueue {
 				"Trying to wake up task {} which is not blocked",
 				borrowed.id
 			);
-			borrowed.status = TaskStatus::Ready;
-			borrowed.last_wakeup_reason = reason;
+			borrowed.set_status(TaskStatus::Ready);
+			borrowed.set_wakeup_reason(reason);
 		}
 
 		// Add the task to the ready queue.
@@ -527,7 +595,7 @@ impl BlockedTaskQueue {
 				"Trying to block task {} which is not running",
 				borrowed.id
 			);
-			borrowed.status = TaskStatus::Blocked;
+			borrowed.set_status(TaskStatus::Blocked);
 		}
 
 		let new_node = BlockedTask::new(task, wakeup_time);
@@ -0,0 +1,755 @@
+//! Device filesystem (devfs)
+//!
+//! The filesystem is mounted at /dev and contains the character devices of the kernel:
+//! `null`, `zero`, `urandom` and `console`. Drivers add further devices with `register_device`
+//! and block devices with `register_block_device`, whose sectors are then accessible as a file.
+//! Files cannot be created or removed, but the devices can be opened for reading and writing.
+
+use crate::arch;
+use crate::console::CONSOLE;
+use crate::drivers::block::{BlockDevice, BlockError};
+use crate::synch::spinlock::Spinlock;
+use crate::syscalls::fs::{
+	seek_position, DirEntry, FileAttr, FileError, FilePerms, FileType, PollEvents, PosixFile,
+	PosixFileSystem, Registration, SeekWhence,
+};
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFCHR: u32 = 0o020000;
+const S_IFBLK: u32 = 0o060000;
+
+const ROOT_INO: u64 = 1;
+
+/// Major number of the devices of drivers, which is reserved for local use on Linux
+const DRIVER_MAJOR: u32 = 240;
+
+/// Encodes a device number like `makedev` of glibc
+const fn makedev(major: u32, minor: u32) -> u64 {
+	((major as u64 & 0xfffff000) << 32)
+		| ((major as u64 & 0xfff) << 8)
+		| ((minor as u64 & 0xffffff00) << 12)
+		| (minor as u64 & 0xff)
+}
+
+/// A character device, which is accessible as file in /dev.
+/// All open files of a device share the same instance.
+pub trait CharDevice {
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError>;
+	fn write(&self, buf: &[u8]) -> Result<usize, FileError>;
+
+	/// Like `PosixFile::poll`. By default, the device is always ready.
+	fn poll(&self, _registration: Option<Registration<'_>>) -> Result<PollEvents, FileError> {
+		Err(FileError::EPERM())
+	}
+}
+
+enum Device {
+	Char(Arc<dyn CharDevice + Send + Sync>),
+	/// Block devices are owned by their drivers, which live as long as the kernel.
+	Block(&'static (dyn BlockDevice + Sync)),
+}
+
+struct DeviceNode {
+	attr: FileAttr,
+	device: Device,
+}
+
+struct Registry {
+	nodes: BTreeMap<String, Arc<DeviceNode>>,
+	next_ino: u64,
+	next_minor: u32,
+}
+
+static DEVICES: Spinlock<Registry> = Spinlock::new(Registry {
+	nodes: BTreeMap::new(),
+	next_ino: ROOT_INO + 1,
+	next_minor: 0,
+});
+
+fn insert(name: &str, rdev: Option<u64>, device: Device) -> Result<(), FileError> {
+	if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+		return Err(FileError::EINVAL());
+	}
+
+	let mut registry = DEVICES.lock();
+	if registry.nodes.contains_key(name) {
+		return Err(FileError::EEXIST());
+	}
+
+	let rdev = rdev.unwrap_or_else(|| {
+		registry.next_minor += 1;
+		makedev(DRIVER_MAJOR, registry.next_minor - 1)
+	});
+	let attr = match device {
+		Device::Char(_) => FileAttr {
+			ino: registry.next_ino,
+			mode: S_IFCHR | 0o666,
+			nlink: 1,
+			rdev,
+			blksize: 4096,
+			..Default::default()
+		},
+		Device::Block(_) => FileAttr {
+			ino: registry.next_ino,
+			mode: S_IFBLK | 0o660,
+			nlink: 1,
+			rdev,
+			..Default::default()
+		},
+	};
+	registry.next_ino += 1;
+	registry
+		.nodes
+		.insert(name.to_owned(), Arc::new(DeviceNode { attr, device }));
+	Ok(())
+}
+
+/// Adds the device `/dev/<name>`, which gets a device number with the major number 240.
+/// Fails with EEXIST, if the name is already taken.
+pub fn register_device(
+	name: &str,
+	device: Arc<dyn CharDevice + Send + Sync>,
+) -> Result<(), FileError> {
+	insert(name, None, Device::Char(device))
+}
+
+/// Adds the block device `/dev/<name>` like `register_device`.
+/// Its sectors are read and written through the file at any offset.
+pub fn register_block_device(
+	name: &str,
+	device: &'static (dyn BlockDevice + Sync),
+) -> Result<(), FileError> {
+	insert(name, None, Device::Block(device))
+}
+
+/// Adds the devices of the kernel with their device numbers of Linux.
+pub(crate) fn register_kernel_devices() {
+	let devices: [(&str, u64, Arc<dyn CharDevice + Send + Sync>); 4] = [
+		("null", makedev(1, 3), Arc::new(Null)),
+		("zero", makedev(1, 5), Arc::new(Zero)),
+		("urandom", makedev(1, 9), Arc::new(Urandom)),
+		("console", makedev(5, 1), Arc::new(Console)),
+	];
+	for (name, rdev, device) in devices.iter() {
+		insert(name, Some(*rdev), Device::Char(device.clone()))
+			.expect("Registering a kernel device failed");
+	}
+}
+
+/// Discards all writes and reports the end of the file on reads
+struct Null;
+
+impl CharDevice for Null {
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Ok(0)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<usize, FileError> {
+		Ok(buf.len())
+	}
+}
+
+/// Discards all writes and returns zeros on reads
+struct Zero;
+
+impl CharDevice for Zero {
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		buf.fill(0);
+		Ok(buf.len())
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<usize, FileError> {
+		Ok(buf.len())
+	}
+}
+
+/// Returns the random numbers of the hardware, which back `sys_secure_rand64` (e.g. RDRAND).
+/// Reads fail with EIO, if the processor does not provide random numbers.
+struct Urandom;
+
+impl CharDevice for Urandom {
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		for chunk in buf.chunks_mut(size_of::<u64>()) {
+			let random = arch::processor::generate_random_number64().ok_or(FileError::EIO())?;
+			chunk.copy_from_slice(&random.to_ne_bytes()[..chunk.len()]);
+		}
+		Ok(buf.len())
+	}
+
+	/// Like on Linux, written data does not credit any entropy and is discarded.
+	fn write(&self, buf: &[u8]) -> Result<usize, FileError> {
+		Ok(buf.len())
+	}
+}
+
+/// The console of the kernel, which does not support input like the standard input
+struct Console;
+
+impl CharDevice for Console {
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Ok(0)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<usize, FileError> {
+		CONSOLE.lock().write_all(buf);
+		Ok(buf.len())
+	}
+}
+
+fn dir_attr() -> FileAttr {
+	FileAttr {
+		ino: ROOT_INO,
+		mode: S_IFDIR | 0o755,
+		nlink: 2,
+		..Default::default()
+	}
+}
+
+pub struct Devfs;
+
+impl Devfs {
+	pub fn new() -> Self {
+		Self
+	}
+
+	/// Resolves a path relative to the root of the filesystem to a device or `None` for the root.
+	fn lookup(&self, path: &str) -> Result<Option<Arc<DeviceNode>>, FileError> {
+		let mut names = path
+			.split('/')
+			.filter(|name| !name.is_empty() && *name != ".");
+		let name = match names.next() {
+			Some(name) => name,
+			None => return Ok(None),
+		};
+
+		let node = DEVICES
+			.lock()
+			.nodes
+			.get(name)
+			.cloned()
+			.ok_or(FileError::ENOENT())?;
+		if names.next().is_some() {
+			return Err(FileError::ENOTDIR());
+		}
+		Ok(Some(node))
+	}
+}
+
+impl Default for Devfs {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl PosixFileSystem for Devfs {
+	fn open(
+		&self,
+		path: &str,
+		perms: FilePerms,
+	) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		let node = match self.lookup(path) {
+			Ok(_) if perms.creat && perms.excl => return Err(FileError::EEXIST()),
+			Ok(node) => node,
+			Err(FileError::ENOENT()) if perms.creat => return Err(FileError::EPERM()),
+			Err(err) => return Err(err),
+		};
+
+		match node {
+			// truncating a device has no effect
+			Some(node) => match node.device {
+				Device::Char(_) => Ok(Box::new(DeviceFile { node })),
+				Device::Block(device) if perms.write && device.is_read_only() => {
+					Err(FileError::EROFS())
+				}
+				Device::Block(_) => Ok(Box::new(BlockFile {
+					node,
+					offset: Spinlock::new(0),
+				})),
+			},
+			None if perms.write => Err(FileError::EISDIR()),
+			None => self.opendir(path),
+		}
+	}
+
+	fn unlink(&self, path: &str) -> Result<(), FileError> {
+		self.lookup(path)?;
+		Err(FileError::EPERM())
+	}
+
+	fn opendir(&self, path: &str) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		if self.lookup(path)?.is_some() {
+			return Err(FileError::ENOTDIR());
+		}
+
+		let dots = [
+			(".", ROOT_INO, FileType::Directory),
+			("..", ROOT_INO, FileType::Directory),
+		];
+		let devices: Vec<(String, u64, FileType)> = DEVICES
+			.lock()
+			.nodes
+			.iter()
+			.map(|(name, node)| (name.clone(), node.attr.ino, node.attr.file_type()))
+			.collect();
+		let entries = dots
+			.iter()
+			.map(|(name, ino, file_type)| ((*name).to_owned(), *ino, *file_type))
+			.chain(devices)
+			.enumerate()
+			.map(|(idx, (name, ino, file_type))| DirEntry {
+				ino,
+				offset: idx as u64 + 1,
+				file_type,
+				name,
+			})
+			.collect();
+
+		Ok(Box::new(DevDir {
+			entries,
+			position: Spinlock::new(0),
+		}))
+	}
+
+	fn mkdir(&self, _path: &str, _mode: u32) -> Result<(), FileError> {
+		Err(FileError::EPERM())
+	}
+
+	fn rmdir(&self, path: &str) -> Result<(), FileError> {
+		match self.lookup(path)? {
+			Some(_) => Err(FileError::ENOTDIR()),
+			None => Err(FileError::EBUSY()),
+		}
+	}
+
+	fn lstat(&self, path: &str) -> Result<FileAttr, FileError> {
+		Ok(self.lookup(path)?.map_or_else(dir_attr, |node| node.attr))
+	}
+
+	fn rename(&self, _oldpath: &str, _newpath: &str) -> Result<(), FileError> {
+		Err(FileError::EPERM())
+	}
+
+	fn link(&self, _oldpath: &str, _newpath: &str) -> Result<(), FileError> {
+		Err(FileError::EPERM())
+	}
+
+	fn symlink(&self, _target: &str, _linkpath: &str) -> Result<(), FileError> {
+		Err(FileError::EPERM())
+	}
+
+	fn readlink(&self, path: &str) -> Result<String, FileError> {
+		self.lookup(path)?;
+		Err(FileError::EINVAL())
+	}
+}
+
+/// An open character device
+struct DeviceFile {
+	node: Arc<DeviceNode>,
+}
+
+impl DeviceFile {
+	fn device(&self) -> &(dyn CharDevice + Send + Sync) {
+		match &self.node.device {
+			Device::Char(device) => device.as_ref(),
+			Device::Block(_) => unreachable!("Block devices are opened as BlockFile"),
+		}
+	}
+}
+
+impl PosixFile for DeviceFile {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		self.device().read(buf)
+	}
+
+	/// Devices have no position, so the offset is ignored.
+	fn pread(&self, buf: &mut [u8], _offset: u64) -> Result<usize, FileError> {
+		self.device().read(buf)
+	}
+
+	fn pwrite(&self, buf: &[u8], _offset: u64) -> Result<u64, FileError> {
+		self.write(buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		self.device().write(buf).map(|len| len as u64)
+	}
+
+	/// Like on Linux, seeking on a device succeeds and the position stays at zero.
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Ok(0)
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(self.node.attr)
+	}
+
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn poll(&self, registration: Option<Registration<'_>>) -> Result<PollEvents, FileError> {
+		self.device().poll(registration)
+	}
+}
+
+impl From<BlockError> for FileError {
+	fn from(err: BlockError) -> Self {
+		match err {
+			BlockError::IoError => FileError::EIO(),
+			BlockError::Unsupported => FileError::EOPNOTSUPP(),
+			BlockError::InvalidRequest => FileError::EINVAL(),
+			BlockError::ReadOnly => FileError::EROFS(),
+		}
+	}
+}
+
+/// An open block device. Reads and writes may start at any offset, partially written
+/// sectors are read from the device first.
+struct BlockFile {
+	node: Arc<DeviceNode>,
+	offset: Spinlock<u64>,
+}
+
+impl BlockFile {
+	fn device(&self) -> &'static (dyn BlockDevice + Sync) {
+		match self.node.device {
+			Device::Block(device) => device,
+			Device::Char(_) => unreachable!("Character devices are opened as DeviceFile"),
+		}
+	}
+
+	/// Size of the device in bytes
+	fn size(&self) -> u64 {
+		let device = self.device();
+		device.capacity() * device.sector_size() as u64
+	}
+
+	/// Returns the first sector and the sector buffer, which cover `len` bytes at `offset`.
+	fn sectors(&self, offset: u64, len: usize) -> (u64, Vec<u8>) {
+		let sector_size = self.device().sector_size() as u64;
+		let first = offset / sector_size;
+		let end = (offset + len as u64 + sector_size - 1) / sector_size;
+		(first, vec![0; ((end - first) * sector_size) as usize])
+	}
+}
+
+impl PosixFile for BlockFile {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		let mut offset = self.offset.lock();
+		let len = self.pread(buf, *offset)?;
+		*offset += len as u64;
+
+		Ok(len)
+	}
+
+	fn pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		let len = buf.len().min(self.size().saturating_sub(offset) as usize);
+		if len == 0 {
+			return Ok(0);
+		}
+
+		let (first, mut sectors) = self.sectors(offset, len);
+		self.device().read_sectors(first, &mut sectors)?;
+		let start = (offset % self.device().sector_size() as u64) as usize;
+		buf[..len].copy_from_slice(&sectors[start..start + len]);
+
+		Ok(len)
+	}
+
+	fn pwrite(&self, buf: &[u8], offset: u64) -> Result<u64, FileError> {
+		let len = buf.len().min(self.size().saturating_sub(offset) as usize);
+		if len == 0 {
+			return if buf.is_empty() {
+				Ok(0)
+			} else {
+				Err(FileError::ENOSPC())
+			};
+		}
+
+		let device = self.device();
+		let sector_size = device.sector_size();
+		let (first, mut sectors) = self.sectors(offset, len);
+		let start = (offset % sector_size as u64) as usize;
+		let total = sectors.len();
+		let head_partial = start != 0;
+		let tail_partial = (start + len) % sector_size != 0;
+		if head_partial {
+			device.read_sectors(first, &mut sectors[..sector_size])?;
+		}
+		// a single sector has already been read as head
+		if tail_partial && !(head_partial && total == sector_size) {
+			let last = first + (total / sector_size) as u64 - 1;
+			device.read_sectors(last, &mut sectors[total - sector_size..])?;
+		}
+		sectors[start..start + len].copy_from_slice(&buf[..len]);
+		device.write_sectors(first, &sectors)?;
+
+		Ok(len as u64)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		let mut offset = self.offset.lock();
+		let len = self.pwrite(buf, *offset)?;
+		*offset += len;
+
+		Ok(len)
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let mut position = self.offset.lock();
+		*position = seek_position(*position as usize, offset, whence, self.size() as usize)? as u64;
+
+		Ok(*position as usize)
+	}
+
+	/// Like on Linux, the size is the size of the device.
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		let device = self.device();
+		let size = self.size();
+		Ok(FileAttr {
+			size,
+			blksize: device.sector_size() as u64,
+			blocks: size / 512,
+			..self.node.attr
+		})
+	}
+
+	/// Writes the volatile cache of the device back.
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		self.device().flush().map_err(FileError::from)
+	}
+}
+
+struct DevDir {
+	/// The entries are collected when the directory is opened, later devices are not listed.
+	entries: Vec<DirEntry>,
+	/// Index of the next entry
+	position: Spinlock<usize>,
+}
+
+impl PosixFile for DevDir {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn pread(&self, _buf: &mut [u8], _offset: u64) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let mut position = self.position.lock();
+		match whence {
+			SeekWhence::Set if offset >= 0 => *position = offset as usize,
+			SeekWhence::Cur if offset == 0 => {}
+			_ => return Err(FileError::EINVAL()),
+		}
+
+		Ok(*position)
+	}
+
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn readdir(&self) -> Result<Vec<DirEntry>, FileError> {
+		let mut position = self.position.lock();
+		let start = (*position).min(self.entries.len());
+		*position = self.entries.len();
+
+		Ok(self.entries[start..].to_vec())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(dir_attr())
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::drivers::block::{completion, BlockCompletion, BlockRequest};
+
+	struct Echo(Spinlock<Vec<u8>>);
+
+	impl CharDevice for Echo {
+		fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+			let mut data = self.0.lock();
+			let len = buf.len().min(data.len());
+			buf[..len].copy_from_slice(&data[..len]);
+			data.drain(..len);
+			Ok(len)
+		}
+
+		fn write(&self, buf: &[u8]) -> Result<usize, FileError> {
+			self.0.lock().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+	}
+
+	#[test]
+	fn test_register_device() {
+		let devfs = Devfs::new();
+		register_device("test-echo", Arc::new(Echo(Spinlock::new(Vec::new())))).unwrap();
+		assert_eq!(
+			register_device("test-echo", Arc::new(Null)),
+			Err(FileError::EEXIST())
+		);
+		assert_eq!(
+			register_device("a/b", Arc::new(Null)),
+			Err(FileError::EINVAL())
+		);
+
+		let perms = FilePerms {
+			write: true,
+			..Default::default()
+		};
+		let file = devfs.open("test-echo", perms).unwrap();
+		assert_eq!(file.write(b"ping"), Ok(4));
+		let mut buf = [0u8; 8];
+		assert_eq!(file.read(&mut buf), Ok(4));
+		assert_eq!(&buf[..4], b"ping");
+
+		let attr = devfs.lstat("/test-echo").unwrap();
+		assert_eq!(attr.file_type(), FileType::CharDevice);
+		assert_eq!(attr.rdev >> 8, u64::from(DRIVER_MAJOR));
+
+		let names: Vec<String> = devfs
+			.opendir("")
+			.unwrap()
+			.readdir()
+			.unwrap()
+			.into_iter()
+			.map(|entry| entry.name)
+			.collect();
+		assert!(names.iter().any(|name| name == "test-echo"));
+		assert_eq!(devfs.unlink("test-echo"), Err(FileError::EPERM()));
+		assert_eq!(
+			devfs
+				.open(
+					"missing",
+					FilePerms {
+						creat: true,
+						..perms
+					}
+				)
+				.err(),
+			Some(FileError::EPERM())
+		);
+	}
+
+	/// A block device in memory with sectors of 4 bytes
+	struct RamDisk(Spinlock<Vec<u8>>);
+
+	impl BlockDevice for RamDisk {
+		fn sector_size(&self) -> usize {
+			4
+		}
+
+		fn capacity(&self) -> u64 {
+			(self.0.lock().len() / 4) as u64
+		}
+
+		fn is_read_only(&self) -> bool {
+			false
+		}
+
+		fn max_transfer_sectors(&self) -> u64 {
+			2
+		}
+
+		fn submit(&self, request: BlockRequest) -> Result<BlockCompletion, BlockError> {
+			let (completion, completer) = completion();
+			let mut data = self.0.lock();
+			let result = match request {
+				BlockRequest::Read { sector, mut buffer } => {
+					let start = sector as usize * 4;
+					buffer.copy_from_slice(&data[start..start + buffer.len()]);
+					Ok(buffer)
+				}
+				BlockRequest::Write { sector, buffer } => {
+					let start = sector as usize * 4;
+					data[start..start + buffer.len()].copy_from_slice(&buffer);
+					Ok(buffer)
+				}
+				BlockRequest::Flush => Ok(Vec::new()),
+				_ => Err(BlockError::Unsupported),
+			};
+			completer.complete(result);
+			Ok(completion)
+		}
+	}
+
+	#[test]
+	fn test_block_device() {
+		let disk: &'static RamDisk = Box::leak(Box::new(RamDisk(Spinlock::new((0..16).collect()))));
+		register_block_device("test-ram", disk).unwrap();
+
+		let devfs = Devfs::new();
+		let attr = devfs.lstat("test-ram").unwrap();
+		assert_eq!(attr.file_type(), FileType::BlockDevice);
+
+		let perms = FilePerms {
+			write: true,
+			..Default::default()
+		};
+		let file = devfs.open("test-ram", perms).unwrap();
+		assert_eq!(file.fstat().unwrap().size, 16);
+
+		// unaligned accesses within one sector and across sectors
+		assert_eq!(file.pwrite(b"ab", 1), Ok(2));
+		assert_eq!(file.pwrite(b"cdefghi", 6), Ok(7));
+		let mut buf = [0u8; 16];
+		assert_eq!(file.pread(&mut buf, 0), Ok(16));
+		assert_eq!(
+			&buf,
+			&[0, b'a', b'b', 3, 4, 5, b'c', b'd', b'e', b'f', b'g', b'h', b'i', 13, 14, 15]
+		);
+
+		assert_eq!(file.lseek(-2, SeekWhence::End), Ok(14));
+		assert_eq!(file.write(b"xyz"), Ok(2));
+		assert_eq!(file.write(b"z"), Err(FileError::ENOSPC()));
+		assert_eq!(file.read(&mut buf), Ok(0));
+		assert_eq!(file.fsync(false), Ok(()));
+	}
+
+	#[test]
+	fn test_null_and_zero() {
+		let mut buf = [1u8; 5];
+		assert_eq!(Null.read(&mut buf), Ok(0));
+		assert_eq!(Null.write(&buf), Ok(5));
+		assert_eq!(Zero.read(&mut buf), Ok(5));
+		assert_eq!(buf, [0u8; 5]);
+	}
+
+	#[test]
+	fn test_makedev() {
+		assert_eq!(makedev(1, 3), 0x103);
+		assert_eq!(makedev(5, 1), 0x501);
+		assert_eq!(makedev(0x1000, 0x100), 0x0000_1000_0010_0000);
+	}
+}
@@ -0,0 +1,194 @@
+//! Event counters (eventfd)
+//!
+//! An eventfd is a 64-bit counter, which tasks use to signal events to each other. Writes add to the counter,
+//! reads return and reset it. In semaphore mode, reads decrement the counter by one instead.
+//! Reads block while the counter is zero and writes while the counter would overflow.
+
+use crate::arch::percore::*;
+use crate::scheduler::task::{TaskHandle, TaskHandlePriorityQueue, WakeupReason};
+use crate::synch::spinlock::{SpinlockIrqSave, SpinlockIrqSaveGuard};
+use crate::syscalls::fs::poll::{PollEvents, Registration, WaitQueue, POLLIN, POLLOUT};
+use crate::syscalls::fs::{FileAttr, FileError, PosixFile, SeekWhence};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::mem::size_of;
+
+/// Largest value of the counter
+const EVENTFD_MAX: u64 = u64::MAX - 1;
+
+struct EventFdState {
+	count: u64,
+	/// Tasks waiting for the counter to change
+	waiters: TaskHandlePriorityQueue,
+}
+
+impl EventFdState {
+	fn take_waiters(&mut self) -> Vec<TaskHandle> {
+		let mut waiters = Vec::new();
+		while let Some(task) = self.waiters.pop() {
+			waiters.push(task);
+		}
+		waiters
+	}
+}
+
+pub struct EventFd {
+	state: SpinlockIrqSave<EventFdState>,
+	/// Reads decrement the counter by one instead of resetting it (EFD_SEMAPHORE)
+	semaphore: bool,
+	/// Reads and writes fail with EAGAIN instead of blocking (EFD_NONBLOCK)
+	nonblocking: bool,
+	pollers: WaitQueue,
+}
+
+impl EventFd {
+	pub fn new(initval: u32, semaphore: bool, nonblocking: bool) -> Self {
+		Self {
+			state: SpinlockIrqSave::new(EventFdState {
+				count: initval.into(),
+				waiters: TaskHandlePriorityQueue::new(),
+			}),
+			semaphore,
+			nonblocking,
+			pollers: WaitQueue::new(),
+		}
+	}
+
+	/// Blocks the current task until the counter changes. The lock of the state is released before switching to another task.
+	fn wait(&self, mut state: SpinlockIrqSaveGuard<'_, EventFdState>) {
+		let core_scheduler = core_scheduler();
+		core_scheduler.set_current_task_wakeup_reason(WakeupReason::Custom);
+		core_scheduler.block_current_task(None);
+		state.waiters.push(core_scheduler.get_current_task_handle());
+		drop(state);
+		core_scheduler.reschedule();
+	}
+
+	/// Wakes up the tasks waiting for the counter and notifies the pollers.
+	fn changed(&self, mut state: SpinlockIrqSaveGuard<'_, EventFdState>) {
+		let waiters = state.take_waiters();
+		drop(state);
+		for task in waiters {
+			core_scheduler().custom_wakeup(task);
+		}
+		self.pollers.notify();
+	}
+}
+
+impl PosixFile for EventFd {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		if buf.len() < size_of::<u64>() {
+			return Err(FileError::EINVAL());
+		}
+
+		loop {
+			let mut state = self.state.lock();
+			if state.count > 0 {
+				let value = if self.semaphore { 1 } else { state.count };
+				state.count -= value;
+				self.changed(state);
+				buf[..size_of::<u64>()].copy_from_slice(&value.to_ne_bytes());
+				return Ok(size_of::<u64>());
+			} else if self.nonblocking {
+				return Err(FileError::EAGAIN());
+			}
+
+			self.wait(state);
+		}
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		let value = match buf.get(..size_of::<u64>()) {
+			Some(bytes) => u64::from_ne_bytes(bytes.try_into().unwrap()),
+			None => return Err(FileError::EINVAL()),
+		};
+		if value == u64::MAX {
+			return Err(FileError::EINVAL());
+		}
+
+		loop {
+			let mut state = self.state.lock();
+			if EVENTFD_MAX - state.count >= value {
+				state.count += value;
+				if value > 0 {
+					self.changed(state);
+				}
+				return Ok(size_of::<u64>() as u64);
+			} else if self.nonblocking {
+				return Err(FileError::EAGAIN());
+			}
+
+			self.wait(state);
+		}
+	}
+
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(FileAttr {
+			mode: 0o600,
+			nlink: 1,
+			..Default::default()
+		})
+	}
+
+	fn poll(&self, registration: Option<Registration<'_>>) -> Result<PollEvents, FileError> {
+		if let Some(registration) = registration {
+			self.pollers.register(registration);
+		}
+
+		let count = self.state.lock().count;
+		let mut events = 0;
+		if count > 0 {
+			events |= POLLIN;
+		}
+		if count < EVENTFD_MAX {
+			events |= POLLOUT;
+		}
+		Ok(events)
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn read(eventfd: &EventFd) -> Result<u64, FileError> {
+		let mut buf = [0u8; 8];
+		eventfd.read(&mut buf).map(|_| u64::from_ne_bytes(buf))
+	}
+
+	#[test]
+	fn test_counter() {
+		let eventfd = EventFd::new(2, false, true);
+		assert_eq!(eventfd.write(&3u64.to_ne_bytes()), Ok(8));
+		assert_eq!(eventfd.poll(None), Ok(POLLIN | POLLOUT));
+		assert_eq!(read(&eventfd), Ok(5));
+		assert_eq!(read(&eventfd), Err(FileError::EAGAIN()));
+		assert_eq!(eventfd.poll(None), Ok(POLLOUT));
+
+		assert_eq!(
+			eventfd.write(&u64::MAX.to_ne_bytes()),
+			Err(FileError::EINVAL())
+		);
+		assert_eq!(eventfd.write(&EVENTFD_MAX.to_ne_bytes()), Ok(8));
+		assert_eq!(eventfd.write(&1u64.to_ne_bytes()), Err(FileError::EAGAIN()));
+		assert_eq!(eventfd.poll(None), Ok(POLLIN));
+	}
+
+	#[test]
+	fn test_semaphore() {
+		let eventfd = EventFd::new(2, true, true);
+		assert_eq!(read(&eventfd), Ok(1));
+		assert_eq!(read(&eventfd), Ok(1));
+		assert_eq!(read(&eventfd), Err(FileError::EAGAIN()));
+		assert_eq!(eventfd.read(&mut [0u8; 4]), Err(FileError::EINVAL()));
+	}
+}
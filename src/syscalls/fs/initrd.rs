@@ -0,0 +1,682 @@
+//! Read-only filesystem backed by an initial ramdisk
+//!
+//! The ramdisk is either a cpio archive in the "newc" format (as created by `cpio -H newc`)
+//! or a ustar archive. It is either linked into the application by defining the symbols
+//! `__hermit_initrd_start` and `__hermit_initrd_end` or passed by the loader as first Multiboot module.
+//! File contents are never copied, they are read from the archive in place.
+
+use crate::synch::spinlock::Spinlock;
+use crate::syscalls::fs::lock::{self, LockTable, LockTables};
+use crate::syscalls::fs::{
+	seek_position, DirEntry, FileAttr, FileError, FileLock, FilePerms, FileType, LockOwner,
+	LockType, PosixFile, PosixFileSystem, SeekWhence, Timespec,
+};
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::{slice, str};
+
+extern "C" {
+	#[linkage = "extern_weak"]
+	static __hermit_initrd_start: *const u8;
+	#[linkage = "extern_weak"]
+	static __hermit_initrd_end: *const u8;
+}
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+const CPIO_HEADER_LEN: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+const TAR_BLOCK_LEN: usize = 512;
+
+/// Returns the initial ramdisk, if the application or the loader provides one.
+pub fn find_archive() -> Option<&'static [u8]> {
+	unsafe {
+		let start = __hermit_initrd_start;
+		let end = __hermit_initrd_end;
+		if !start.is_null() && end > start {
+			return Some(slice::from_raw_parts(start, end as usize - start as usize));
+		}
+	}
+
+	#[cfg(target_arch = "x86_64")]
+	if let Some((start, end)) = crate::arch::mm::get_initrd_region() {
+		// the ramdisk is identity mapped during the initialization of the page tables
+		// and its addresses are excluded from the free virtual memory
+		return Some(unsafe {
+			slice::from_raw_parts(
+				start.as_usize() as *const u8,
+				end.as_usize() - start.as_usize(),
+			)
+		});
+	}
+
+	None
+}
+
+enum Content {
+	File(&'static [u8]),
+	/// Maps the names of all entries to their index in `Initrd::nodes`
+	Directory(BTreeMap<String, usize>),
+	Symlink(String),
+}
+
+struct Node {
+	attr: FileAttr,
+	content: Content,
+}
+
+pub struct Initrd {
+	/// All nodes of the filesystem, the root directory is stored at index 0
+	nodes: Vec<Node>,
+	/// Advisory locks of the open files, keyed by inode number
+	locks: LockTables,
+}
+
+impl Initrd {
+	/// Parses a cpio (newc) or ustar archive.
+	pub fn new(archive: &'static [u8]) -> Result<Self, FileError> {
+		let mut initrd = Self {
+			nodes: Vec::new(),
+			locks: LockTables::new(),
+		};
+		initrd.nodes.push(Node {
+			attr: dir_attr(0o755, Timespec::default()),
+			content: Content::Directory(BTreeMap::new()),
+		});
+
+		if archive.starts_with(b"070701") || archive.starts_with(b"070702") {
+			initrd.parse_cpio(archive)?;
+		} else if archive.len() >= TAR_BLOCK_LEN && &archive[257..262] == b"ustar" {
+			initrd.parse_tar(archive)?;
+		} else {
+			warn!("Initial ramdisk is neither a cpio nor a ustar archive");
+			return Err(FileError::EINVAL());
+		}
+
+		for (idx, node) in initrd.nodes.iter_mut().enumerate() {
+			node.attr.ino = idx as u64 + 1;
+		}
+
+		Ok(initrd)
+	}
+
+	fn parse_cpio(&mut self, archive: &'static [u8]) -> Result<(), FileError> {
+		let mut pos = 0;
+
+		loop {
+			let header = archive
+				.get(pos..pos + CPIO_HEADER_LEN)
+				.ok_or(FileError::EINVAL())?;
+			if &header[..5] != b"07070" {
+				return Err(FileError::EINVAL());
+			}
+			let field = |idx: usize| parse_number(&header[6 + 8 * idx..14 + 8 * idx], 16);
+
+			let mode = field(1)? as u32;
+			let mtime = field(5)?;
+			let filesize = field(6)? as usize;
+			let namesize = field(11)? as usize;
+
+			let name_start = pos + CPIO_HEADER_LEN;
+			let name = archive
+				.get(name_start..name_start + namesize)
+				.ok_or(FileError::EINVAL())?;
+			let name = parse_string(name)?;
+			let data_start = align_up!(name_start + namesize, 4);
+			let data = archive
+				.get(data_start..data_start + filesize)
+				.ok_or(FileError::EINVAL())?;
+			pos = align_up!(data_start + filesize, 4);
+
+			if name == CPIO_TRAILER {
+				return Ok(());
+			}
+
+			let mut attr = FileAttr {
+				mode,
+				nlink: 1,
+				uid: field(2)? as u32,
+				gid: field(3)? as u32,
+				size: filesize as u64,
+				blksize: TAR_BLOCK_LEN as u64,
+				blocks: (filesize as u64 + 511) / 512,
+				mtime: Timespec {
+					sec: mtime as i64,
+					nsec: 0,
+				},
+				..Default::default()
+			};
+			attr.atime = attr.mtime;
+			attr.ctime = attr.mtime;
+
+			let content = match mode & S_IFMT {
+				S_IFREG => Content::File(data),
+				S_IFDIR => Content::Directory(BTreeMap::new()),
+				S_IFLNK => Content::Symlink(parse_string(data)?.to_owned()),
+				_ => {
+					debug!("Skipping special file {} in initial ramdisk", name);
+					continue;
+				}
+			};
+			self.insert(name, attr, content);
+		}
+	}
+
+	fn parse_tar(&mut self, archive: &'static [u8]) -> Result<(), FileError> {
+		let mut pos = 0;
+
+		while let Some(header) = archive.get(pos..pos + TAR_BLOCK_LEN) {
+			// the archive ends with blocks of zeros
+			if header[0] == 0 {
+				break;
+			}
+
+			let size = parse_number(&header[124..136], 8)? as usize;
+			let data_start = pos + TAR_BLOCK_LEN;
+			let data = archive
+				.get(data_start..data_start + size)
+				.ok_or(FileError::EINVAL())?;
+			pos = data_start + align_up!(size, TAR_BLOCK_LEN);
+
+			let name = parse_string(&header[0..100])?;
+			let prefix = parse_string(&header[345..500])?;
+			let mut path = String::from(prefix);
+			if !path.is_empty() {
+				path.push('/');
+			}
+			path.push_str(name);
+
+			let mtime = parse_number(&header[136..148], 8)?;
+			let mut attr = FileAttr {
+				mode: parse_number(&header[100..108], 8)? as u32 & 0o7777,
+				nlink: 1,
+				uid: parse_number(&header[108..116], 8)? as u32,
+				gid: parse_number(&header[116..124], 8)? as u32,
+				size: size as u64,
+				blksize: TAR_BLOCK_LEN as u64,
+				blocks: (size as u64 + 511) / 512,
+				mtime: Timespec {
+					sec: mtime as i64,
+					nsec: 0,
+				},
+				..Default::default()
+			};
+			attr.atime = attr.mtime;
+			attr.ctime = attr.mtime;
+
+			let linkname = parse_string(&header[157..257])?;
+			let content = match header[156] {
+				b'0' | 0 => {
+					attr.mode |= S_IFREG;
+					Content::File(data)
+				}
+				b'1' => {
+					// hard link to a previous entry of the archive
+					match self.lookup(linkname) {
+						Ok(idx) => {
+							self.link(&path, idx);
+							continue;
+						}
+						Err(_) => {
+							warn!("Hard link {} to unknown file {}", path, linkname);
+							continue;
+						}
+					}
+				}
+				b'2' => {
+					attr.mode |= S_IFLNK;
+					attr.size = linkname.len() as u64;
+					Content::Symlink(linkname.to_owned())
+				}
+				b'5' => {
+					attr.mode |= S_IFDIR;
+					Content::Directory(BTreeMap::new())
+				}
+				typ => {
+					debug!(
+						"Skipping entry {} of type {} in initial ramdisk",
+						path, typ as char
+					);
+					continue;
+				}
+			};
+			self.insert(&path, attr, content);
+		}
+
+		Ok(())
+	}
+
+	/// Adds a node to the tree. Missing parent directories are created implicitly.
+	fn insert(&mut self, path: &str, attr: FileAttr, content: Content) {
+		let (parent, name) = match path.trim_matches('/').rsplit_once('/') {
+			Some((parent, name)) => (self.create_dirs(parent), name),
+			None => (0, path.trim_matches('/')),
+		};
+		if name.is_empty() || name == "." {
+			// the archive contains the root directory itself
+			self.nodes[0].attr = attr;
+			return;
+		}
+
+		if let Some(idx) = self.child(parent, name) {
+			// directories may be listed after their content was implicitly created
+			let node = &mut self.nodes[idx];
+			node.attr = attr;
+			if !matches!(
+				(&node.content, &content),
+				(Content::Directory(_), Content::Directory(_))
+			) {
+				node.content = content;
+			}
+			return;
+		}
+
+		self.nodes.push(Node { attr, content });
+		let idx = self.nodes.len() - 1;
+		self.add_child(parent, name, idx);
+	}
+
+	/// Adds the hard link `path` to the node `idx`.
+	fn link(&mut self, path: &str, idx: usize) {
+		let (parent, name) = match path.trim_matches('/').rsplit_once('/') {
+			Some((parent, name)) => (self.create_dirs(parent), name),
+			None => (0, path.trim_matches('/')),
+		};
+		self.add_child(parent, name, idx);
+		self.nodes[idx].attr.nlink += 1;
+	}
+
+	/// Returns the directory at `path`, which is created, if it does not exist yet.
+	fn create_dirs(&mut self, path: &str) -> usize {
+		let mut dir = 0;
+
+		for name in path
+			.split('/')
+			.filter(|name| !name.is_empty() && *name != ".")
+		{
+			dir = match self.child(dir, name) {
+				Some(idx) => idx,
+				None => {
+					self.nodes.push(Node {
+						attr: dir_attr(0o755, self.nodes[dir].attr.mtime),
+						content: Content::Directory(BTreeMap::new()),
+					});
+					let idx = self.nodes.len() - 1;
+					self.add_child(dir, name, idx);
+					idx
+				}
+			};
+		}
+
+		dir
+	}
+
+	fn child(&self, dir: usize, name: &str) -> Option<usize> {
+		match &self.nodes[dir].content {
+			Content::Directory(entries) => entries.get(name).copied(),
+			_ => None,
+		}
+	}
+
+	fn add_child(&mut self, dir: usize, name: &str, idx: usize) {
+		if let Content::Directory(entries) = &mut self.nodes[dir].content {
+			entries.insert(name.to_owned(), idx);
+		}
+	}
+
+	/// Resolves a path relative to the root of the ramdisk to the index of its node.
+	fn lookup(&self, path: &str) -> Result<usize, FileError> {
+		path.split('/')
+			.filter(|name| !name.is_empty() && *name != ".")
+			.try_fold(0, |dir, name| match &self.nodes[dir].content {
+				Content::Directory(entries) => {
+					entries.get(name).copied().ok_or(FileError::ENOENT())
+				}
+				_ => Err(FileError::ENOTDIR()),
+			})
+	}
+}
+
+fn dir_attr(perm: u32, mtime: Timespec) -> FileAttr {
+	FileAttr {
+		mode: S_IFDIR | perm,
+		nlink: 2,
+		blksize: TAR_BLOCK_LEN as u64,
+		atime: mtime,
+		mtime,
+		ctime: mtime,
+		..Default::default()
+	}
+}
+
+/// Parses an ASCII number, which may be terminated by NUL bytes or spaces.
+fn parse_number(field: &[u8], radix: u32) -> Result<u64, FileError> {
+	let field = str::from_utf8(field).map_err(|_| FileError::EINVAL())?;
+	let field = field.trim_matches(|c| c == '\0' || c == ' ');
+	if field.is_empty() {
+		return Ok(0);
+	}
+	u64::from_str_radix(field, radix).map_err(|_| FileError::EINVAL())
+}
+
+/// Parses a string, which is terminated by a NUL byte or the end of the field.
+fn parse_string(field: &[u8]) -> Result<&str, FileError> {
+	let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+	str::from_utf8(&field[..len]).map_err(|_| FileError::EINVAL())
+}
+
+impl PosixFileSystem for Initrd {
+	fn open(
+		&self,
+		path: &str,
+		perms: FilePerms,
+	) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		let idx = match self.lookup(path) {
+			Ok(_) if perms.creat && perms.excl => return Err(FileError::EEXIST()),
+			Ok(idx) => idx,
+			Err(FileError::ENOENT()) if perms.creat => return Err(FileError::EROFS()),
+			Err(err) => return Err(err),
+		};
+		if perms.write || perms.trunc {
+			return Err(FileError::EROFS());
+		}
+
+		let node = &self.nodes[idx];
+		match &node.content {
+			Content::File(data) => Ok(Box::new(InitrdFile {
+				data: *data,
+				attr: node.attr,
+				offset: Spinlock::new(0),
+				locks: self.locks.get(node.attr.ino),
+			})),
+			Content::Directory(_) => self.opendir(path),
+			Content::Symlink(_) => Err(FileError::ELOOP()),
+		}
+	}
+
+	fn unlink(&self, _path: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn opendir(&self, path: &str) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		let node = &self.nodes[self.lookup(path)?];
+		let entries = match &node.content {
+			Content::Directory(entries) => entries,
+			_ => return Err(FileError::ENOTDIR()),
+		};
+
+		// the parent of a node is unknown, but `..` only has to exist in the listing
+		let dots = [(".", node.attr.ino), ("..", 0)];
+		let entries = dots
+			.iter()
+			.map(|(name, ino)| ((*name).to_owned(), *ino, FileType::Directory))
+			.chain(entries.iter().map(|(name, idx)| {
+				let attr = &self.nodes[*idx].attr;
+				(name.clone(), attr.ino, attr.file_type())
+			}))
+			.enumerate()
+			.map(|(idx, (name, ino, file_type))| DirEntry {
+				ino,
+				offset: idx as u64 + 1,
+				file_type,
+				name,
+			})
+			.collect();
+
+		Ok(Box::new(InitrdDir {
+			entries,
+			attr: node.attr,
+			position: Spinlock::new(0),
+			locks: self.locks.get(node.attr.ino),
+		}))
+	}
+
+	fn mkdir(&self, _path: &str, _mode: u32) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn rmdir(&self, _path: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn lstat(&self, path: &str) -> Result<FileAttr, FileError> {
+		Ok(self.nodes[self.lookup(path)?].attr)
+	}
+
+	fn rename(&self, _oldpath: &str, _newpath: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn link(&self, _oldpath: &str, _newpath: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn symlink(&self, _target: &str, _linkpath: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn readlink(&self, path: &str) -> Result<String, FileError> {
+		match &self.nodes[self.lookup(path)?].content {
+			Content::Symlink(target) => Ok(target.clone()),
+			_ => Err(FileError::EINVAL()),
+		}
+	}
+}
+
+struct InitrdFile {
+	data: &'static [u8],
+	attr: FileAttr,
+	offset: Spinlock<usize>,
+	locks: Arc<LockTable>,
+}
+
+impl PosixFile for InitrdFile {
+	fn close(&self) -> Result<(), FileError> {
+		self.locks.release(self.lock_owner());
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		let mut offset = self.offset.lock();
+		let len = self.pread(buf, *offset as u64)?;
+		*offset += len;
+
+		Ok(len)
+	}
+
+	fn pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		let start = (offset as usize).min(self.data.len());
+		let end = start.saturating_add(buf.len()).min(self.data.len());
+		buf[..end - start].copy_from_slice(&self.data[start..end]);
+
+		Ok(end - start)
+	}
+
+	fn pwrite(&self, _buf: &[u8], _offset: u64) -> Result<u64, FileError> {
+		Err(FileError::EBADF())
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		// files are always opened read-only
+		Err(FileError::EBADF())
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let mut position = self.offset.lock();
+		*position = seek_position(*position, offset, whence, self.data.len())?;
+
+		Ok(*position)
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(self.attr)
+	}
+
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn fallocate(&self, _mode: u32, _offset: u64, _len: u64) -> Result<(), FileError> {
+		Err(FileError::EBADF())
+	}
+
+	fn getlk(&self, lock: &FileLock) -> Result<Option<FileLock>, FileError> {
+		Ok(self.locks.get(lock))
+	}
+
+	fn setlk(&self, lock: &FileLock, wait: bool) -> Result<(), FileError> {
+		lock::check_access(lock, true, false)?;
+		self.locks.set(lock, wait)
+	}
+
+	fn flock(&self, typ: LockType, wait: bool) -> Result<(), FileError> {
+		self.locks.flock(typ, self.lock_owner(), wait)
+	}
+
+	fn release_locks(&self, owner: LockOwner) {
+		self.locks.release(owner);
+	}
+}
+
+struct InitrdDir {
+	/// The filesystem is immutable, so the entries are collected when the directory is opened.
+	entries: Vec<DirEntry>,
+	attr: FileAttr,
+	/// Index of the next entry
+	position: Spinlock<usize>,
+	locks: Arc<LockTable>,
+}
+
+impl PosixFile for InitrdDir {
+	fn close(&self) -> Result<(), FileError> {
+		self.locks.release(self.lock_owner());
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn pread(&self, _buf: &mut [u8], _offset: u64) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let mut position = self.position.lock();
+		match whence {
+			SeekWhence::Set if offset >= 0 => *position = offset as usize,
+			SeekWhence::Cur if offset == 0 => {}
+			_ => return Err(FileError::EINVAL()),
+		}
+
+		Ok(*position)
+	}
+
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn readdir(&self) -> Result<Vec<DirEntry>, FileError> {
+		let mut position = self.position.lock();
+		let start = (*position).min(self.entries.len());
+		*position = self.entries.len();
+
+		Ok(self.entries[start..].to_vec())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(self.attr)
+	}
+
+	fn flock(&self, typ: LockType, wait: bool) -> Result<(), FileError> {
+		self.locks.flock(typ, self.lock_owner(), wait)
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn cpio_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+		let header = format!(
+			"070701{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}{:08X}",
+			0,
+			mode,
+			0,
+			0,
+			1,
+			0,
+			data.len(),
+			0,
+			0,
+			0,
+			0,
+			name.len() + 1,
+			0
+		);
+		archive.extend_from_slice(header.as_bytes());
+		archive.extend_from_slice(name.as_bytes());
+		archive.push(0);
+		archive.resize(align_up!(archive.len(), 4), 0);
+		archive.extend_from_slice(data);
+		archive.resize(align_up!(archive.len(), 4), 0);
+	}
+
+	#[test]
+	fn test_parse_cpio() {
+		let mut archive = Vec::new();
+		cpio_entry(&mut archive, "etc", S_IFDIR | 0o755, b"");
+		cpio_entry(
+			&mut archive,
+			"etc/hosts",
+			S_IFREG | 0o644,
+			b"127.0.0.1 localhost\n",
+		);
+		cpio_entry(&mut archive, "data/a/b.txt", S_IFREG | 0o600, b"b");
+		cpio_entry(&mut archive, "hosts", S_IFLNK | 0o777, b"etc/hosts");
+		cpio_entry(&mut archive, CPIO_TRAILER, 0, b"");
+
+		let initrd = Initrd::new(Box::leak(archive.into_boxed_slice())).unwrap();
+
+		let hosts = initrd.lstat("etc/hosts").unwrap();
+		assert_eq!(hosts.file_type(), FileType::Regular);
+		assert_eq!(hosts.size, 20);
+		assert_eq!(
+			initrd.lstat("data/a").unwrap().file_type(),
+			FileType::Directory
+		);
+		assert_eq!(initrd.readlink("hosts").unwrap(), "etc/hosts");
+		assert!(initrd.lstat("etc/passwd").is_err());
+
+		let file = initrd.open("/etc/hosts", FilePerms::default()).unwrap();
+		let mut buf = [0u8; 100];
+		assert_eq!(file.read(&mut buf[..9]).unwrap(), 9);
+		assert_eq!(&buf[..9], b"127.0.0.1");
+		assert_eq!(file.pread(&mut buf, 10).unwrap(), 10);
+		assert_eq!(&buf[..10], b"localhost\n");
+		assert_eq!(file.lseek(0, SeekWhence::Cur).unwrap(), 9);
+		assert_eq!(file.lseek(-10, SeekWhence::End).unwrap(), 10);
+		assert_eq!(file.read(&mut buf).unwrap(), 10);
+
+		let dir = initrd.opendir("").unwrap();
+		let names: Vec<String> = dir
+			.readdir()
+			.unwrap()
+			.into_iter()
+			.map(|entry| entry.name)
+			.collect();
+		assert_eq!(names, [".", "..", "data", "etc", "hosts"]);
+	}
+}
@@ -0,0 +1,343 @@
+//! Advisory file locks
+//!
+//! Filesystems without a server of their own keep the locks of each file in a `LockTable`.
+//! Record locks (fcntl) and whole-file locks (flock) are independent of each other, as on Linux.
+//! A lock conflicts with the locks of other owners, if their ranges overlap and one of them is a write lock.
+//! Tasks waiting for a lock are blocked and woken up whenever locks of the file are released.
+
+use crate::arch::percore::*;
+use crate::scheduler::task::{TaskHandle, TaskHandlePriorityQueue, WakeupReason};
+use crate::synch::spinlock::{Spinlock, SpinlockIrqSave};
+use crate::syscalls::fs::FileError;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+/// Largest offset of a lock, a lock ending here extends to the end of the file
+pub const OFFSET_MAX: u64 = i64::MAX as u64;
+
+/// Owner of the classic POSIX record locks. There is only a single process, so its threads
+/// share the locks, like the threads of a process on Linux do.
+pub const PROCESS_LOCK_OWNER: LockOwner = 0;
+
+/// Identifies the owner of a lock. Locks of the same owner never conflict, a lock replaces
+/// the locks of its owner in the same range instead.
+pub type LockOwner = u64;
+
+/// Checks, that the open file may place `lock`. Read locks require a file opened for reading,
+/// write locks a file opened for writing.
+pub fn check_access(lock: &FileLock, readable: bool, writable: bool) -> Result<(), FileError> {
+	match lock.typ {
+		LockType::Read if !readable => Err(FileError::EBADF()),
+		LockType::Write if !writable => Err(FileError::EBADF()),
+		_ => Ok(()),
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockType {
+	Read,
+	Write,
+	Unlock,
+}
+
+/// A lock of the bytes from `start` to `end` (inclusive)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileLock {
+	pub typ: LockType,
+	pub start: u64,
+	pub end: u64,
+	pub owner: LockOwner,
+	/// Task, which has placed the lock. It is reported to other tasks, which wait for the lock.
+	pub pid: u32,
+}
+
+impl FileLock {
+	/// Returns a lock of the whole file
+	pub fn whole_file(typ: LockType, owner: LockOwner, pid: u32) -> Self {
+		Self {
+			typ,
+			start: 0,
+			end: OFFSET_MAX,
+			owner,
+			pid,
+		}
+	}
+
+	fn overlaps(&self, other: &FileLock) -> bool {
+		self.start <= other.end && other.start <= self.end
+	}
+
+	fn conflicts(&self, other: &FileLock) -> bool {
+		self.owner != other.owner
+			&& self.overlaps(other)
+			&& (self.typ == LockType::Write || other.typ == LockType::Write)
+	}
+}
+
+/// Replaces the locks of the owner of `lock` in its range by `lock`. Locks, which are only
+/// partially covered, are split. Adjacent locks of the same type are merged.
+fn apply(locks: &mut Vec<FileLock>, lock: &FileLock) {
+	let mut new = *lock;
+	let mut kept = Vec::with_capacity(locks.len() + 1);
+
+	for old in locks.drain(..) {
+		if old.owner != lock.owner {
+			kept.push(old);
+			continue;
+		}
+
+		let adjacent = old.end.checked_add(1) == Some(lock.start)
+			|| lock.end.checked_add(1) == Some(old.start);
+		if old.typ == lock.typ && (old.overlaps(lock) || adjacent) {
+			new.start = new.start.min(old.start);
+			new.end = new.end.max(old.end);
+		} else if old.overlaps(lock) {
+			if old.start < lock.start {
+				kept.push(FileLock {
+					end: lock.start - 1,
+					..old
+				});
+			}
+			if old.end > lock.end {
+				kept.push(FileLock {
+					start: lock.end + 1,
+					..old
+				});
+			}
+		} else {
+			kept.push(old);
+		}
+	}
+
+	if new.typ != LockType::Unlock {
+		kept.push(new);
+	}
+	*locks = kept;
+}
+
+struct LockState {
+	/// Record locks, which are placed by fcntl
+	records: Vec<FileLock>,
+	/// Whole-file locks, which are placed by flock
+	flocks: Vec<FileLock>,
+	/// Tasks waiting for the release of a conflicting lock
+	waiters: TaskHandlePriorityQueue,
+}
+
+impl LockState {
+	fn take_waiters(&mut self) -> Vec<TaskHandle> {
+		let mut waiters = Vec::new();
+		while let Some(task) = self.waiters.pop() {
+			waiters.push(task);
+		}
+		waiters
+	}
+}
+
+fn wakeup(tasks: Vec<TaskHandle>) {
+	for task in tasks {
+		core_scheduler().custom_wakeup(task);
+	}
+}
+
+/// The advisory locks of a file
+pub struct LockTable {
+	state: SpinlockIrqSave<LockState>,
+}
+
+impl LockTable {
+	pub const fn new() -> Self {
+		Self {
+			state: SpinlockIrqSave::new(LockState {
+				records: Vec::new(),
+				flocks: Vec::new(),
+				waiters: TaskHandlePriorityQueue::new(),
+			}),
+		}
+	}
+
+	/// Returns a record lock, which conflicts with `lock`, or `None`, if `lock` could be placed.
+	pub fn get(&self, lock: &FileLock) -> Option<FileLock> {
+		self.state
+			.lock()
+			.records
+			.iter()
+			.find(|other| other.conflicts(lock))
+			.copied()
+	}
+
+	/// Places the record lock `lock`. A lock of type `LockType::Unlock` removes the locks
+	/// of its owner in its range. If a conflicting lock is held, the call fails with EAGAIN
+	/// or, if `wait` is set, blocks until the conflicting lock has been released.
+	pub fn set(&self, lock: &FileLock, wait: bool) -> Result<(), FileError> {
+		self.place(lock, wait, |state| &mut state.records)
+	}
+
+	/// Places or removes the whole-file lock of `owner`. Converting a lock is not atomic,
+	/// the previous lock is released before waiting for the new one.
+	pub fn flock(&self, typ: LockType, owner: LockOwner, wait: bool) -> Result<(), FileError> {
+		let lock = FileLock::whole_file(typ, owner, 0);
+		if typ != LockType::Unlock && wait {
+			self.place(
+				&FileLock::whole_file(LockType::Unlock, owner, 0),
+				false,
+				|state| &mut state.flocks,
+			)?;
+		}
+		self.place(&lock, wait, |state| &mut state.flocks)
+	}
+
+	/// Removes all locks of `owner`, record locks as well as whole-file locks.
+	pub fn release(&self, owner: LockOwner) {
+		let waiters = {
+			let mut state = self.state.lock();
+			let count = state.records.len() + state.flocks.len();
+			state.records.retain(|lock| lock.owner != owner);
+			state.flocks.retain(|lock| lock.owner != owner);
+			if state.records.len() + state.flocks.len() != count {
+				state.take_waiters()
+			} else {
+				Vec::new()
+			}
+		};
+		wakeup(waiters);
+	}
+
+	fn place(
+		&self,
+		lock: &FileLock,
+		wait: bool,
+		locks: impl Fn(&mut LockState) -> &mut Vec<FileLock>,
+	) -> Result<(), FileError> {
+		loop {
+			let mut state = self.state.lock();
+
+			if lock.typ == LockType::Unlock || !locks(&mut state).iter().any(|l| l.conflicts(lock))
+			{
+				apply(locks(&mut state), lock);
+				// Waiting tasks check again, since a lock may have been released or downgraded.
+				let waiters = state.take_waiters();
+				drop(state);
+				wakeup(waiters);
+				return Ok(());
+			} else if !wait {
+				return Err(FileError::EAGAIN());
+			}
+
+			// Block the current task until locks of the file are released.
+			let core_scheduler = core_scheduler();
+			core_scheduler.set_current_task_wakeup_reason(WakeupReason::Custom);
+			core_scheduler.block_current_task(None);
+			state.waiters.push(core_scheduler.get_current_task_handle());
+			drop(state);
+			core_scheduler.reschedule();
+		}
+	}
+}
+
+impl Default for LockTable {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// The lock tables of the files of a filesystem, which keeps no state per file otherwise.
+/// A table exists as long as a file refers to it.
+pub struct LockTables {
+	tables: Spinlock<BTreeMap<u64, Weak<LockTable>>>,
+}
+
+impl LockTables {
+	pub const fn new() -> Self {
+		Self {
+			tables: Spinlock::new(BTreeMap::new()),
+		}
+	}
+
+	/// Returns the lock table of the file with inode number `ino`.
+	pub fn get(&self, ino: u64) -> Arc<LockTable> {
+		let mut tables = self.tables.lock();
+		if let Some(table) = tables.get(&ino).and_then(Weak::upgrade) {
+			return table;
+		}
+
+		tables.retain(|_, table| table.strong_count() > 0);
+		let table = Arc::new(LockTable::new());
+		tables.insert(ino, Arc::downgrade(&table));
+		table
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn lock(typ: LockType, start: u64, end: u64, owner: LockOwner) -> FileLock {
+		FileLock {
+			typ,
+			start,
+			end,
+			owner,
+			pid: 0,
+		}
+	}
+
+	#[test]
+	fn test_conflicts() {
+		let table = LockTable::new();
+		table.set(&lock(LockType::Read, 0, 99, 1), false).unwrap();
+		table.set(&lock(LockType::Read, 50, 149, 2), false).unwrap();
+
+		assert_eq!(
+			table.set(&lock(LockType::Write, 100, 199, 3), false),
+			Err(FileError::EAGAIN())
+		);
+		assert_eq!(table.get(&lock(LockType::Write, 150, 199, 3)), None);
+		assert_eq!(
+			table.get(&lock(LockType::Write, 0, 10, 2)),
+			Some(lock(LockType::Read, 0, 99, 1))
+		);
+	}
+
+	#[test]
+	fn test_split_and_merge() {
+		let mut locks = Vec::new();
+		apply(&mut locks, &lock(LockType::Write, 0, 99, 1));
+		apply(&mut locks, &lock(LockType::Read, 40, 59, 1));
+		locks.sort_by_key(|l| l.start);
+		assert_eq!(
+			locks,
+			[
+				lock(LockType::Write, 0, 39, 1),
+				lock(LockType::Read, 40, 59, 1),
+				lock(LockType::Write, 60, 99, 1),
+			]
+		);
+
+		apply(&mut locks, &lock(LockType::Write, 40, 59, 1));
+		assert_eq!(locks, [lock(LockType::Write, 0, 99, 1)]);
+
+		apply(&mut locks, &lock(LockType::Unlock, 0, OFFSET_MAX, 1));
+		assert!(locks.is_empty());
+	}
+
+	#[test]
+	fn test_flock_and_release() {
+		let table = LockTable::new();
+		table.flock(LockType::Write, 1, false).unwrap();
+		// record locks and whole-file locks do not interfere
+		table.set(&lock(LockType::Write, 0, 9, 2), false).unwrap();
+		assert_eq!(
+			table.flock(LockType::Read, 2, false),
+			Err(FileError::EAGAIN())
+		);
+
+		table.release(1);
+		table.flock(LockType::Read, 2, false).unwrap();
+		table.release(2);
+		table.flock(LockType::Write, 3, false).unwrap();
+		assert_eq!(table.get(&lock(LockType::Write, 0, OFFSET_MAX, 3)), None);
+	}
+}
@@ -0,0 +1,1288 @@
+use crate::arch::percore::core_scheduler;
+use crate::environment;
+use crate::errno;
+use crate::synch::spinlock::Spinlock;
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+pub use self::devfs::{register_block_device, register_device, CharDevice, Devfs};
+pub use self::eventfd::EventFd;
+pub use self::initrd::Initrd;
+pub use self::lock::{FileLock, LockOwner, LockType};
+pub use self::poll::{Epoll, EpollEvent, PollEvents, Registration};
+pub use self::procfs::Procfs;
+pub use self::stdio::{Stderr, Stdin, Stdout};
+pub use self::timerfd::{TimerFd, TimerSetting};
+pub use self::tmpfs::Tmpfs;
+pub use self::uhyve::UhyveFs;
+
+mod devfs;
+mod eventfd;
+mod initrd;
+pub(crate) mod lock;
+pub(crate) mod pagecache;
+mod pipe;
+pub(crate) mod poll;
+mod procfs;
+mod stdio;
+mod timerfd;
+mod tmpfs;
+mod uhyve;
+
+/*
+Design:
+- want to support different backends. One of them virtiofs, another one the in-memory tmpfs (mounted at /tmp on boot)
+  and the read-only initrd (mounted at /initrd, if the image contains an initial ramdisk).
+- want to support multiple mounted filesystems at once.
+- mount points can be nested arbitrarily deep (e.g. / and /data/cache). A path belongs to the mount point,
+  which is its longest prefix. No overlays: a mount point hides the directory of the same name on the parent fs.
+- paths are normalized (., .., repeated slashes) before they are passed to a backend. Relative paths are resolved
+  against the working directory of the current task.
+- manage all files in a global map. Open files are reference counted, so that duplicated fds share the same open file
+  (including its offset) and syscalls can operate on a file without holding the lock of the global map (get_file()).
+- mounted filesystems are reference counted as well. Path operations look up the mount under the lock of the global map
+  and call the backend after releasing it, since backends may block (e.g. FUSE requests waiting for the host).
+- stdin/stdout/stderr are regular files in this map (fds 0-2), which are backed by the console.
+
+- we internally treat all file systems as posix filesystems.
+- Have two traits. One representing a filesystem, another a file: PosixFileSystem and PosixFile
+- filesystem.open creates new file
+- trait methods like open return Result<....>, so we can catch errors on eg open() and NOT permanently assign an fd to it!
+
+- have a FUSE filesystem, which implements both PosixFileSystem and PosixFile
+- fuse can have various FuseInterface backends. These only have to provide fuse command send/receive capabilites.
+- virtiofs implements FuseInterface and sends commands via virtio queues.
+
+- fd management is only relevant for "user" facing code. We don't care how fuse etc. manages nodes internally.
+- But we still want to have a list of open files and mounted filesystems (here in fs.rs).
+
+Open Questions:
+- what is the maximum number of open files I want to support? if small, could have static allocation, no need for hashmap?
+- optimize callchain? how does LTO work here?:
+	- app calls rust.open (which is stdlib hermit/fs.rs) [https://github.com/rust-lang/rust/blob/master/src/libstd/sys/hermit/fs.rs#L267]
+	- abi::open() (hermit-sys crate)
+	- [KERNEL BORDER] (uses C-interface. needed? Could just be alternative to native rust?)
+	- hermit-lib/....rs/sys_open()
+	- SyscallInterface.open (via &'static dyn ref)
+	- Filesystem::open()
+	- Fuse::open()
+	- VirtiofsDriver::send_command(...)
+	- [HYPERVISOR BORDER] (via virtio)
+	- virtiofsd receives fuse command and sends reply
+
+TODO:
+- FileDescriptor newtype
+*/
+
+/// Default soft limit of open files, which is also the default of Linux
+const DEFAULT_NOFILE: u64 = 1024;
+/// Default hard limit of open files
+const DEFAULT_NOFILE_MAX: u64 = 4096;
+/// Upper bound for the hard limit of open files (corresponds to `nr_open` of Linux)
+const NR_OPEN: u64 = 1 << 20;
+
+// TODO: lazy static could be replaced with explicit init on OS boot.
+pub static FILESYSTEM: Spinlock<Filesystem> = Spinlock::new(Filesystem::new());
+
+/// A mounted filesystem. It is shared, so that it can be accessed after releasing the lock of the `Filesystem`.
+type MountedFs = Arc<dyn PosixFileSystem + Send + Sync>;
+
+/// A filesystem, which is mounted into the directory tree
+struct Mount {
+	fs: MountedFs,
+	/// Modifications of the filesystem are rejected with EROFS
+	readonly: bool,
+}
+
+pub struct Filesystem {
+	// Keep track of mount-points, keyed by their normalized absolute path
+	mounts: BTreeMap<String, Mount>,
+
+	// Keep track of open files. Several fds refer to the same file after dup().
+	files: BTreeMap<u64, Arc<OpenFile>>,
+
+	// Soft and hard limit of fds (like RLIMIT_NOFILE). All fds have to be smaller than the soft limit.
+	nofile: (u64, u64),
+}
+
+impl Filesystem {
+	pub const fn new() -> Self {
+		Self {
+			mounts: BTreeMap::new(),
+			files: BTreeMap::new(),
+			nofile: (DEFAULT_NOFILE, DEFAULT_NOFILE_MAX),
+		}
+	}
+
+	/// Returns the lowest unused file-descriptor, as required by POSIX. We map index in files BTreeMap as fd's.
+	/// The keys are iterated in ascending order, so the first fd, which differs from its index, marks a gap.
+	fn assign_new_fd(&self) -> Result<u64, FileError> {
+		let fd = self
+			.files
+			.keys()
+			.zip(0..)
+			.find(|(fd, idx)| **fd != *idx)
+			.map_or(self.files.len() as u64, |(_, idx)| idx);
+
+		if fd < self.nofile.0 {
+			Ok(fd)
+		} else {
+			Err(FileError::EMFILE())
+		}
+	}
+
+	/// Gets a new fd for a file and inserts it into open files.
+	/// Returns file descriptor
+	fn add_file(&mut self, file: Arc<OpenFile>) -> Result<u64, FileError> {
+		let fd = self.assign_new_fd()?;
+		self.files.insert(fd, file);
+		Ok(fd)
+	}
+
+	/// Returns the soft and hard limit of fds.
+	pub fn get_nofile_limit(&self) -> (u64, u64) {
+		self.nofile
+	}
+
+	/// Sets the soft and hard limit of fds. Already opened fds above the new limit stay valid.
+	/// Unlimited fds (RLIM_INFINITY) are limited to NR_OPEN nevertheless, like on Linux.
+	pub fn set_nofile_limit(&mut self, cur: u64, max: u64) -> Result<(), FileError> {
+		let cur = if cur == u64::MAX { NR_OPEN } else { cur };
+		let max = if max == u64::MAX { NR_OPEN } else { max };
+		if cur > max {
+			return Err(FileError::EINVAL());
+		} else if max > NR_OPEN {
+			return Err(FileError::EPERM());
+		}
+
+		self.nofile = (cur, max);
+		Ok(())
+	}
+
+	/// Returns the open file referenced by `fd`.
+	/// The file remains usable after releasing the lock of the filesystem, even if the fd is closed meanwhile.
+	pub fn get_file(&self, fd: u64) -> Result<Arc<dyn PosixFile + Send + Sync>, FileError> {
+		let file = self.files.get(&fd).ok_or(FileError::EBADF())?;
+		Ok(file.clone())
+	}
+
+	/// Duplicates `fd` to a new fd. Both fds share the same open file.
+	pub fn dup(&mut self, fd: u64) -> Result<u64, FileError> {
+		let file = self.files.get(&fd).cloned().ok_or(FileError::EBADF())?;
+		self.add_file(file)
+	}
+
+	/// Duplicates `oldfd` to `newfd`. Returns the file, which `newfd` referred to before.
+	/// The caller passes it to `close_file` after releasing the lock.
+	///
+	/// `oldfd` is validated first, then the range of `newfd`. Equal fds are left alone,
+	/// unless `distinct` is set (like dup3), which rejects them with EINVAL.
+	fn dup2(
+		&mut self,
+		oldfd: u64,
+		newfd: u64,
+		distinct: bool,
+	) -> Result<Option<Arc<OpenFile>>, FileError> {
+		let file = self.files.get(&oldfd).cloned().ok_or(FileError::EBADF())?;
+		if newfd >= self.nofile.0 {
+			return Err(FileError::EBADF());
+		}
+		if oldfd == newfd {
+			return if distinct {
+				Err(FileError::EINVAL())
+			} else {
+				Ok(None)
+			};
+		}
+		Ok(self.files.insert(newfd, file))
+	}
+
+	/// Creates a pipe and returns the fds of its read end and its write end.
+	pub fn pipe(&mut self, nonblocking: bool) -> Result<(u64, u64), FileError> {
+		let (reader, writer) = pipe::pipe(nonblocking);
+		let reader = self.add_file(OpenFile::new(Box::new(reader)))?;
+		match self.add_file(OpenFile::new(Box::new(writer))) {
+			Ok(writer) => Ok((reader, writer)),
+			Err(err) => {
+				let _ = self.remove_file(reader);
+				Err(err)
+			}
+		}
+	}
+
+	/// Creates an epoll instance and returns its fd.
+	pub fn epoll_create(&mut self) -> Result<u64, FileError> {
+		self.add_file(OpenFile::new(Box::new(Epoll::new())))
+	}
+
+	/// Creates an event counter and returns its fd.
+	pub fn eventfd(
+		&mut self,
+		initval: u32,
+		semaphore: bool,
+		nonblocking: bool,
+	) -> Result<u64, FileError> {
+		self.add_file(OpenFile::new(Box::new(EventFd::new(
+			initval,
+			semaphore,
+			nonblocking,
+		))))
+	}
+
+	/// Creates a disarmed timer of the clock `clock` and returns its fd.
+	pub fn timerfd_create(&mut self, clock: u64, nonblocking: bool) -> Result<u64, FileError> {
+		self.add_file(OpenFile::new(Box::new(TimerFd::new(clock, nonblocking))))
+	}
+
+	/// Looks up the mount point with the longest prefix of the absolute, normalized `path`.
+	/// Returns (PosixFileSystem, internal_path) or Error on failure.
+	/// The internal path is relative to the root of the mounted filesystem and has no leading slash.
+	fn find_mount<'a, 'b>(&'a self, path: &'b str) -> Result<(&'a Mount, &'b str), FileError> {
+		let mut prefix = path;
+
+		loop {
+			if let Some(mount) = self.mounts.get(prefix) {
+				let internal_path = path[prefix.len()..].trim_start_matches('/');
+				return Ok((mount, internal_path));
+			}
+
+			prefix = match prefix.rfind('/') {
+				Some(0) if prefix.len() > 1 => "/",
+				Some(idx) if idx > 0 => &prefix[..idx],
+				_ => break,
+			};
+		}
+
+		info!(
+			"Trying to access '{}', which is not on a mounted filesystem!",
+			path
+		);
+		Err(FileError::ENOENT())
+	}
+
+	/// Like `find_mount`, but fails with EROFS on read-only mounts.
+	fn find_writable_mount<'a, 'b>(
+		&'a self,
+		path: &'b str,
+	) -> Result<(&'a MountedFs, &'b str), FileError> {
+		match self.find_mount(path)? {
+			(mount, _) if mount.readonly => Err(FileError::EROFS()),
+			(mount, internal_path) => Ok((&mount.fs, internal_path)),
+		}
+	}
+
+	/// Returns the filesystem mounted closest to the absolute, normalized `path` and the path relative to it.
+	/// If `writable` is set, read-only mounts are rejected with EROFS. Used by all operations, which modify the filesystem.
+	fn resolve(&self, path: &str, writable: bool) -> Result<(MountedFs, String), FileError> {
+		let (fs, internal_path) = if writable {
+			self.find_writable_mount(path)?
+		} else {
+			let (mount, internal_path) = self.find_mount(path)?;
+			(&mount.fs, internal_path)
+		};
+		Ok((Arc::clone(fs), internal_path.to_owned()))
+	}
+
+	/// Removes `fd` from the open files and returns the file, which the caller passes to `close_file`
+	/// after releasing the lock.
+	fn remove_file(&mut self, fd: u64) -> Result<Arc<OpenFile>, FileError> {
+		self.files.remove(&fd).ok_or(FileError::EBADF())
+	}
+
+	/// Create new backing-fs at mountpoint mntpath.
+	/// Relative mount paths are interpreted relative to `/`, so mounting at `root` is equivalent to `/root`.
+	/// All modifications of a `readonly` mount are rejected, regardless of the backend.
+	pub fn mount(
+		&mut self,
+		mntpath: &str,
+		mntobj: Box<dyn PosixFileSystem + Send + Sync>,
+		readonly: bool,
+	) -> Result<(), ()> {
+		let mntpath = normalize_path("/", mntpath);
+		info!("Mounting {}", mntpath);
+
+		// if mounts contains path already abort
+		if self.mounts.contains_key(&mntpath) {
+			warn!("Mountpoint {} already exists!", mntpath);
+			return Err(());
+		}
+
+		// insert filesystem into mounts, done
+		self.mounts.insert(
+			mntpath,
+			Mount {
+				fs: Arc::from(mntobj),
+				readonly,
+			},
+		);
+		Ok(())
+	}
+}
+
+/// Returns the working directory of the current task.
+pub fn getcwd() -> String {
+	core_scheduler()
+		.get_current_task_cwd()
+		.unwrap_or_else(default_cwd)
+}
+
+/// Changes the working directory of the current task.
+/// The new directory has to exist on a mounted filesystem.
+pub fn chdir(path: &str) -> Result<(), FileError> {
+	let path = normalize_path(&getcwd(), path);
+	if stat(&path)?.file_type() != FileType::Directory {
+		return Err(FileError::ENOTDIR());
+	}
+
+	debug!("Changing working directory to {}", path);
+	core_scheduler().set_current_task_cwd(path);
+	Ok(())
+}
+
+/// Resolves `path` relative to the current working directory and returns the filesystem mounted
+/// closest to it and the path relative to it. If `writable` is set, read-only mounts fail with EROFS.
+/// The lock of the `Filesystem` is only held during the lookup, since operations of the backends
+/// may block (e.g. FUSE requests, which wait for the host).
+fn parse_path(path: &str, writable: bool) -> Result<(MountedFs, String), FileError> {
+	let path = normalize_path(&getcwd(), path);
+	FILESYSTEM.lock().resolve(&path, writable)
+}
+
+/// Opens the file at `path` and returns its fd.
+/// Looks up the mount point of the path, passes the path relative to it to the filesystem backend.
+pub fn open(path: &str, perms: FilePerms) -> Result<u64, FileError> {
+	if perms.directory {
+		return opendir(path);
+	}
+
+	debug!("Opening file {} {:?}", path, perms);
+	let path = follow_symlinks(path)?;
+	let (fs, internal_path) = parse_path(&path, perms.write || perms.creat || perms.trunc)?;
+	// check the limit beforehand, since a created file cannot be taken back
+	FILESYSTEM.lock().assign_new_fd()?;
+	let file = OpenFile::new(fs.open(&internal_path, perms)?);
+	// if the fds are exhausted meanwhile, the file is closed after releasing the lock
+	let fd = FILESYSTEM.lock().add_file(file)?;
+	Ok(fd)
+}
+
+/// Opens the directory at `path`.
+/// Returns the file descriptor of the directory, which can be passed to `PosixFile::readdir`.
+pub fn opendir(path: &str) -> Result<u64, FileError> {
+	debug!("Opening directory {}", path);
+	let path = follow_symlinks(path)?;
+	let (fs, internal_path) = parse_path(&path, false)?;
+	FILESYSTEM.lock().assign_new_fd()?;
+	let dir = OpenFile::new(fs.opendir(&internal_path)?);
+	let fd = FILESYSTEM.lock().add_file(dir)?;
+	Ok(fd)
+}
+
+/// Removes `fd` from the open files. The file itself is closed, when the last fd referring to it is removed.
+pub fn close(fd: u64) -> Result<(), FileError> {
+	debug!("Closing fd {}", fd);
+	let file = FILESYSTEM.lock().remove_file(fd)?;
+	close_file(file)
+}
+
+/// Duplicates `oldfd` to `newfd`. If `newfd` is already open, it is closed silently before.
+/// With `distinct` (like dup3), equal fds fail with EINVAL instead of being left alone.
+pub fn dup2(oldfd: u64, newfd: u64, distinct: bool) -> Result<u64, FileError> {
+	let replaced = FILESYSTEM.lock().dup2(oldfd, newfd, distinct)?;
+	if let Some(file) = replaced {
+		let _ = close_file(file);
+	}
+	Ok(newfd)
+}
+
+/// Creates a new directory at `path`
+pub fn mkdir(path: &str, mode: u32) -> Result<(), FileError> {
+	info!("Creating directory {}", path);
+	let (fs, internal_path) = parse_path(path, true)?;
+	fs.mkdir(&internal_path, mode)
+}
+
+/// Removes the empty directory at `path`
+pub fn rmdir(path: &str) -> Result<(), FileError> {
+	info!("Removing directory {}", path);
+	let (fs, internal_path) = parse_path(path, true)?;
+	fs.rmdir(&internal_path)
+}
+
+/// Returns the attributes of the file at `path`. Symbolic links are followed.
+pub fn stat(path: &str) -> Result<FileAttr, FileError> {
+	debug!("Getting attributes of {}", path);
+	let path = follow_symlinks(path)?;
+	lstat(&path)
+}
+
+/// Returns the attributes of the file at `path`. Symbolic links are not followed.
+pub fn lstat(path: &str) -> Result<FileAttr, FileError> {
+	debug!("Getting attributes of link {}", path);
+	let (fs, internal_path) = parse_path(path, false)?;
+	fs.lstat(&internal_path)
+}
+
+/// Renames `oldpath` to `newpath`, atomically replacing `newpath` if it already exists.
+/// Both paths have to be located on the same mounted filesystem.
+pub fn rename(oldpath: &str, newpath: &str) -> Result<(), FileError> {
+	info!("Renaming {} to {}", oldpath, newpath);
+	let (fs, old_internal) = parse_path(oldpath, true)?;
+	let (newfs, new_internal) = parse_path(newpath, true)?;
+	if !is_same_fs(&fs, &newfs) {
+		return Err(FileError::EXDEV());
+	}
+	fs.rename(&old_internal, &new_internal)
+}
+
+/// Creates the hard link `newpath`, which refers to the same file as `oldpath`.
+pub fn link(oldpath: &str, newpath: &str) -> Result<(), FileError> {
+	info!("Linking {} to {}", newpath, oldpath);
+	let (fs, old_internal) = parse_path(oldpath, false)?;
+	let (newfs, new_internal) = parse_path(newpath, true)?;
+	if !is_same_fs(&fs, &newfs) {
+		return Err(FileError::EXDEV());
+	}
+	fs.link(&old_internal, &new_internal)
+}
+
+/// Creates the symbolic link `linkpath`, which points to `target`.
+/// The target is stored verbatim and resolved, when the link is followed.
+pub fn symlink(target: &str, linkpath: &str) -> Result<(), FileError> {
+	info!("Creating symbolic link {} -> {}", linkpath, target);
+	let (fs, internal_path) = parse_path(linkpath, true)?;
+	fs.symlink(target, &internal_path)
+}
+
+/// Returns the target of the symbolic link at `path`.
+pub fn readlink(path: &str) -> Result<String, FileError> {
+	debug!("Reading symbolic link {}", path);
+	let (fs, internal_path) = parse_path(path, false)?;
+	fs.readlink(&internal_path)
+}
+
+/// Unlinks a file given by path
+pub fn unlink(path: &str) -> Result<(), FileError> {
+	info!("Unlinking file {}", path);
+	let (fs, internal_path) = parse_path(path, true)?;
+	fs.unlink(&internal_path)
+}
+
+/// Resolves the symbolic links in all components of `path` and returns the absolute path without links.
+/// Components, which do not exist (yet), are kept unchanged, so that the caller can report the error or create them.
+fn follow_symlinks(path: &str) -> Result<String, FileError> {
+	resolve_symlinks(&normalize_path(&getcwd(), path), |path| {
+		FILESYSTEM.lock().resolve(path, false)
+	})
+}
+
+/// Resolves the symbolic links in the normalized absolute `path` component by component.
+/// `resolve` returns the filesystem and the path relative to it, which contains a given path.
+/// The lock of the `Filesystem` is not held, while the backends look up the components.
+fn resolve_symlinks<F>(path: &str, resolve: F) -> Result<String, FileError>
+where
+	F: Fn(&str) -> Result<(MountedFs, String), FileError>,
+{
+	// the components, which still have to be resolved, in reverse order
+	let mut remaining: Vec<String> = path.rsplit('/').map(ToOwned::to_owned).collect();
+	let mut resolved = String::from("/");
+	let mut links = 0;
+
+	while let Some(component) = remaining.pop() {
+		match component.as_str() {
+			"" | "." => continue,
+			".." => {
+				resolved.truncate(resolved.rfind('/').unwrap_or(0).max(1));
+				continue;
+			}
+			_ => {}
+		}
+
+		let candidate = if resolved == "/" {
+			format!("/{}", component)
+		} else {
+			format!("{}/{}", resolved, component)
+		};
+		let (fs, internal_path) = resolve(&candidate)?;
+		match fs.lstat(&internal_path) {
+			Ok(attr) if attr.file_type() == FileType::Symlink => {}
+			_ => {
+				resolved = candidate;
+				continue;
+			}
+		}
+
+		links += 1;
+		if links > MAX_SYMLINK_DEPTH {
+			return Err(FileError::ELOOP());
+		}
+
+		// relative link targets are relative to the directory containing the link
+		let target = fs.readlink(&internal_path)?;
+		if target.starts_with('/') {
+			resolved = String::from("/");
+		}
+		remaining.extend(target.rsplit('/').map(ToOwned::to_owned));
+	}
+
+	Ok(resolved)
+}
+
+/// Closes an fd, which has been removed from the open files. The locks of the process are released,
+/// the file itself is closed, if this has been the last reference to it.
+/// Closing may wait for the backend, so the lock of the `Filesystem` must not be held.
+fn close_file(file: Arc<OpenFile>) -> Result<(), FileError> {
+	file.release_locks(lock::PROCESS_LOCK_OWNER);
+	release(file)
+}
+
+/// Drops a reference to an open file. If it was the last one, the file is closed and errors are reported.
+/// Otherwise, the file is closed, when the remaining holder drops its reference, e.g. a task,
+/// which is still waiting in a read of the file.
+fn release(file: Arc<OpenFile>) -> Result<(), FileError> {
+	match Arc::try_unwrap(file) {
+		Ok(mut file) => {
+			file.closed = true;
+			file.file.close()
+		}
+		Err(_) => Ok(()),
+	}
+}
+
+/// An entry of the table of open files, which is shared by duplicated fds.
+/// Besides the fds, tasks operating on the file hold references, so the file is closed,
+/// when the last reference is dropped.
+struct OpenFile {
+	file: Box<dyn PosixFile + Send + Sync>,
+	/// The file has already been closed by `release`
+	closed: bool,
+}
+
+impl OpenFile {
+	fn new(file: Box<dyn PosixFile + Send + Sync>) -> Arc<Self> {
+		Arc::new(Self {
+			file,
+			closed: false,
+		})
+	}
+}
+
+impl Drop for OpenFile {
+	fn drop(&mut self) {
+		if !self.closed {
+			if let Err(err) = self.file.close() {
+				debug!("Closing an open file failed: {:?}", err);
+			}
+		}
+	}
+}
+
+impl PosixFile for OpenFile {
+	/// The file is closed, when the last reference is dropped.
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		self.file.read(buf)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		self.file.write(buf)
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		self.file.lseek(offset, whence)
+	}
+
+	fn pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		self.file.pread(buf, offset)
+	}
+
+	fn pwrite(&self, buf: &[u8], offset: u64) -> Result<u64, FileError> {
+		self.file.pwrite(buf, offset)
+	}
+
+	fn ftruncate(&self, len: u64) -> Result<(), FileError> {
+		self.file.ftruncate(len)
+	}
+
+	fn fsync(&self, datasync: bool) -> Result<(), FileError> {
+		self.file.fsync(datasync)
+	}
+
+	fn fallocate(&self, mode: u32, offset: u64, len: u64) -> Result<(), FileError> {
+		self.file.fallocate(mode, offset, len)
+	}
+
+	fn readdir(&self) -> Result<Vec<DirEntry>, FileError> {
+		self.file.readdir()
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		self.file.fstat()
+	}
+
+	fn getlk(&self, lock: &FileLock) -> Result<Option<FileLock>, FileError> {
+		self.file.getlk(lock)
+	}
+
+	fn setlk(&self, lock: &FileLock, wait: bool) -> Result<(), FileError> {
+		self.file.setlk(lock, wait)
+	}
+
+	fn flock(&self, typ: LockType, wait: bool) -> Result<(), FileError> {
+		self.file.flock(typ, wait)
+	}
+
+	fn release_locks(&self, owner: LockOwner) {
+		self.file.release_locks(owner)
+	}
+
+	fn lock_owner(&self) -> LockOwner {
+		self.file.lock_owner()
+	}
+
+	fn poll(&self, registration: Option<Registration<'_>>) -> Result<PollEvents, FileError> {
+		self.file.poll(registration)
+	}
+
+	fn as_epoll(&self) -> Option<&Epoll> {
+		self.file.as_epoll()
+	}
+
+	fn as_timerfd(&self) -> Option<&TimerFd> {
+		self.file.as_timerfd()
+	}
+}
+
+/// Mounts the filesystems, which are available independent of any devices.
+pub(crate) fn init() {
+	if let Some(mib) = environment::get_command_line_pagecache() {
+		pagecache::PAGE_CACHE
+			.lock()
+			.set_capacity(mib * 1024 * 1024 / pagecache::PAGE_SIZE);
+	}
+
+	let mut fs = FILESYSTEM.lock();
+	fs.files.insert(0, OpenFile::new(Box::new(Stdin)));
+	fs.files.insert(1, OpenFile::new(Box::new(Stdout)));
+	fs.files.insert(2, OpenFile::new(Box::new(Stderr)));
+
+	if environment::is_uhyve() {
+		// Absolute paths are resolved by the host, relative paths against the working directory of uhyve.
+		fs.mount("/", Box::new(UhyveFs::new("/")), false)
+			.expect("Mounting the host filesystem at / failed");
+		fs.mount(&default_cwd(), Box::new(UhyveFs::new("")), false)
+			.expect("Mounting the working directory of uhyve failed");
+	}
+
+	fs.mount("/tmp", Box::new(Tmpfs::new()), false)
+		.expect("Mounting the tmpfs at /tmp failed");
+	fs.mount("/proc", Box::new(Procfs::new()), true)
+		.expect("Mounting the procfs at /proc failed");
+	// the devices are opened for writing, so the mount is writable, but the devfs rejects modifications
+	devfs::register_kernel_devices();
+	fs.mount("/dev", Box::new(Devfs::new()), false)
+		.expect("Mounting the devfs at /dev failed");
+
+	if let Some(archive) = initrd::find_archive() {
+		match Initrd::new(archive) {
+			Ok(initrd) => fs
+				.mount("/initrd", Box::new(initrd), true)
+				.expect("Mounting the initial ramdisk at /initrd failed"),
+			Err(err) => warn!("Unable to parse the initial ramdisk: {:?}", err),
+		}
+	}
+}
+
+/// Maximum number of symbolic links, which are followed while resolving a path
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Checks whether both references point to the same mounted filesystem.
+fn is_same_fs(a: &MountedFs, b: &MountedFs) -> bool {
+	// compare the data pointers only, vtable pointers of the same type may differ between codegen units
+	Arc::as_ptr(a) as *const u8 == Arc::as_ptr(b) as *const u8
+}
+
+/// Working directory of tasks, which never changed their directory.
+/// Relative paths used to be resolved against the mount point `HERMIT_WD` (default `root`),
+/// so we keep this directory as default.
+fn default_cwd() -> String {
+	let mut cwd = String::from("/");
+	cwd.push_str(option_env!("HERMIT_WD").unwrap_or("root"));
+	cwd
+}
+
+/// Joins `path` to the directory `cwd`, if it is relative, and resolves `.`, `..` and repeated slashes.
+/// The result is an absolute path without trailing slash (except for the root directory `/`).
+fn normalize_path(cwd: &str, path: &str) -> String {
+	let base = if path.starts_with('/') { "" } else { cwd };
+	let mut components: Vec<&str> = Vec::new();
+
+	for component in base.split('/').chain(path.split('/')) {
+		match component {
+			"" | "." => {}
+			".." => {
+				// `..` of the root directory is the root directory itself
+				components.pop();
+			}
+			_ => components.push(component),
+		}
+	}
+
+	if components.is_empty() {
+		return String::from("/");
+	}
+
+	let mut normalized = String::with_capacity(base.len() + path.len() + 1);
+	for component in components {
+		normalized.push('/');
+		normalized.push_str(component);
+	}
+	normalized
+}
+
+/// Declares `FileError` with one variant per errno value of the same name, so that errors
+/// can be converted losslessly between both representations.
+macro_rules! file_errors {
+	($($name:ident),* $(,)?) => {
+		#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+		pub enum FileError {
+			$($name(),)*
+		}
+
+		impl FileError {
+			/// Returns the (positive) errno value of the error.
+			pub fn errno(&self) -> i32 {
+				match self {
+					$(FileError::$name() => errno::$name,)*
+				}
+			}
+
+			/// Converts a (positive) errno value into an error. Unknown values are reported as I/O error.
+			pub fn from_errno(num: i32) -> Self {
+				match num {
+					$(errno::$name => FileError::$name(),)*
+					_ => {
+						debug!("Unknown errno {}", num);
+						FileError::EIO()
+					}
+				}
+			}
+		}
+	};
+}
+
+file_errors!(
+	EPERM,
+	ENOENT,
+	EINTR,
+	EIO,
+	ENXIO,
+	EBADF,
+	EAGAIN,
+	ENOMEM,
+	EACCES,
+	EFAULT,
+	EBUSY,
+	EEXIST,
+	EXDEV,
+	ENODEV,
+	ENOTDIR,
+	EISDIR,
+	EINVAL,
+	ENFILE,
+	EMFILE,
+	ENOTTY,
+	ETXTBSY,
+	EFBIG,
+	ENOSPC,
+	ESPIPE,
+	EROFS,
+	EMLINK,
+	EPIPE,
+	ERANGE,
+	EDEADLK,
+	ENAMETOOLONG,
+	ENOLCK,
+	ENOSYS,
+	ENOTEMPTY,
+	ELOOP,
+	ENODATA,
+	EOVERFLOW,
+	EOPNOTSUPP,
+	ENOTCONN,
+	ETIMEDOUT,
+	ESTALE,
+	EDQUOT,
+	ECANCELED,
+);
+
+pub trait PosixFileSystem {
+	fn open(
+		&self,
+		_path: &str,
+		_perms: FilePerms,
+	) -> Result<Box<dyn PosixFile + Send + Sync>, FileError>;
+	fn unlink(&self, _path: &str) -> Result<(), FileError>;
+
+	/// Opens the directory at `path`. Entries are read with `PosixFile::readdir`,
+	/// closing the returned handle closes the directory.
+	fn opendir(&self, _path: &str) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		Err(FileError::ENOSYS())
+	}
+	fn mkdir(&self, _path: &str, _mode: u32) -> Result<(), FileError> {
+		Err(FileError::ENOSYS())
+	}
+	fn rmdir(&self, _path: &str) -> Result<(), FileError> {
+		Err(FileError::ENOSYS())
+	}
+	/// Returns the attributes of the file at `path`. Symbolic links are not followed,
+	/// they are resolved by the `Filesystem`, since their targets may be located on other mounts.
+	fn lstat(&self, _path: &str) -> Result<FileAttr, FileError> {
+		Err(FileError::ENOSYS())
+	}
+	/// Renames `oldpath` to `newpath`, atomically replacing an existing `newpath`.
+	fn rename(&self, _oldpath: &str, _newpath: &str) -> Result<(), FileError> {
+		Err(FileError::ENOSYS())
+	}
+	fn link(&self, _oldpath: &str, _newpath: &str) -> Result<(), FileError> {
+		Err(FileError::ENOSYS())
+	}
+	fn symlink(&self, _target: &str, _linkpath: &str) -> Result<(), FileError> {
+		Err(FileError::ENOSYS())
+	}
+	fn readlink(&self, _path: &str) -> Result<String, FileError> {
+		Err(FileError::EINVAL())
+	}
+}
+
+/// An open file. Files are shared by duplicated fds, so implementations synchronize their state internally.
+/// Requests to a device may take a while, so no spinlock should be held while waiting for them.
+pub trait PosixFile {
+	fn close(&self) -> Result<(), FileError>;
+	/// Reads into `buf` at the current offset and advances it. Returns the number of bytes read,
+	/// which is 0 at the end of the file.
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError>;
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError>;
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError>;
+
+	/// Reads into `buf` at `offset` without using or changing the current offset.
+	fn pread(&self, _buf: &mut [u8], _offset: u64) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+	/// Writes `buf` at `offset` without using or changing the current offset.
+	fn pwrite(&self, _buf: &[u8], _offset: u64) -> Result<u64, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	/// Truncates or extends the file to `len` bytes.
+	fn ftruncate(&self, _len: u64) -> Result<(), FileError> {
+		Err(FileError::EINVAL())
+	}
+	/// Flushes the file to the underlying storage. If `datasync` is set, metadata is only flushed
+	/// if it is required to read the data again (like the size of the file).
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		Err(FileError::EINVAL())
+	}
+	/// Allocates the range of `len` bytes at `offset` or, depending on the FALLOC_FL_* flags in `mode`,
+	/// punches a hole into the range or zeroes it.
+	fn fallocate(&self, _mode: u32, _offset: u64, _len: u64) -> Result<(), FileError> {
+		Err(FileError::ENODEV())
+	}
+
+	/// Returns the next batch of entries of an opened directory. An empty batch marks the end of the directory.
+	/// The directory position can be restored by passing `DirEntry::offset` to `lseek(_, SeekWhence::Set)`.
+	fn readdir(&self) -> Result<Vec<DirEntry>, FileError> {
+		Err(FileError::ENOTDIR())
+	}
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Err(FileError::ENOSYS())
+	}
+
+	/// Returns a record lock, which prevents placing `lock`, or `None`, if `lock` could be placed.
+	fn getlk(&self, _lock: &FileLock) -> Result<Option<FileLock>, FileError> {
+		Err(FileError::ENOLCK())
+	}
+	/// Places the record lock `lock` or, if its type is `LockType::Unlock`, removes the locks of its owner
+	/// in its range. If a conflicting lock is held, the call fails with EAGAIN or waits for its release.
+	fn setlk(&self, _lock: &FileLock, _wait: bool) -> Result<(), FileError> {
+		Err(FileError::ENOLCK())
+	}
+	/// Places or removes a lock of the whole file, which is owned by the open file (like flock).
+	/// These locks are independent of record locks.
+	fn flock(&self, _typ: LockType, _wait: bool) -> Result<(), FileError> {
+		Err(FileError::ENOLCK())
+	}
+	/// Removes all record locks of `owner` on the file. This happens, whenever an fd referring to the file
+	/// is closed, since POSIX releases the locks of a process on every close.
+	fn release_locks(&self, _owner: LockOwner) {}
+	/// Returns the owner of the locks, which belong to the open file (flock and OFD locks).
+	/// Open files are shared by duplicated fds, so these locks are shared as well.
+	/// The owner is the address of the backend, which does not move while the file is open.
+	fn lock_owner(&self) -> LockOwner {
+		self as *const Self as *const u8 as usize as u64
+	}
+
+	/// Returns the POLL* events, which are ready. If `registration` is given, the waiter is registered
+	/// before checking the readiness, so it is notified of later changes. Files, which are always ready
+	/// (like regular files), fail with EPERM.
+	fn poll(&self, _registration: Option<Registration<'_>>) -> Result<PollEvents, FileError> {
+		Err(FileError::EPERM())
+	}
+	/// Returns the file as epoll instance, if it is one.
+	fn as_epoll(&self) -> Option<&Epoll> {
+		None
+	}
+	/// Returns the file as timer, if it is one.
+	fn as_timerfd(&self) -> Option<&TimerFd> {
+		None
+	}
+}
+
+/// Type of a file, encoded like the `d_type` field of a Linux dirent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FileType {
+	Unknown = 0,
+	Fifo = 1,
+	CharDevice = 2,
+	Directory = 4,
+	BlockDevice = 6,
+	Regular = 8,
+	Symlink = 10,
+	Socket = 12,
+}
+
+impl FileType {
+	/// Extracts the file type from the `S_IFMT` bits of a Posix file mode
+	pub fn from_mode(mode: u32) -> Self {
+		match (mode >> 12) & 0xF {
+			1 => FileType::Fifo,
+			2 => FileType::CharDevice,
+			4 => FileType::Directory,
+			6 => FileType::BlockDevice,
+			8 => FileType::Regular,
+			10 => FileType::Symlink,
+			12 => FileType::Socket,
+			_ => FileType::Unknown,
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+	/// Inode number of the entry
+	pub ino: u64,
+	/// Directory position directly after this entry
+	pub offset: u64,
+	pub file_type: FileType,
+	pub name: String,
+}
+
+/// Posix attributes of a file, as returned by `stat`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileAttr {
+	pub ino: u64,
+	/// File type and permissions, encoded like `st_mode`
+	pub mode: u32,
+	pub nlink: u64,
+	pub uid: u32,
+	pub gid: u32,
+	pub rdev: u64,
+	/// Size in bytes
+	pub size: u64,
+	/// Preferred block size for I/O
+	pub blksize: u64,
+	/// Number of allocated 512-byte blocks
+	pub blocks: u64,
+	pub atime: Timespec,
+	pub mtime: Timespec,
+	pub ctime: Timespec,
+}
+
+impl FileAttr {
+	pub fn file_type(&self) -> FileType {
+		FileType::from_mode(self.mode)
+	}
+}
+
+/// Point in time since the Unix epoch
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timespec {
+	pub sec: i64,
+	pub nsec: i64,
+}
+
+impl Timespec {
+	/// Returns the current time of the realtime clock
+	pub fn now() -> Self {
+		// unit tests run on the host, where the frequency of the timer has not been detected
+		#[cfg(not(test))]
+		let microseconds = crate::arch::processor::get_timer_ticks() + crate::arch::get_boot_time();
+		#[cfg(test)]
+		let microseconds = 0;
+		Self {
+			sec: (microseconds / 1_000_000) as i64,
+			nsec: ((microseconds % 1_000_000) * 1000) as i64,
+		}
+	}
+}
+
+// TODO: raw is partially redundant, create nicer interface
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FilePerms {
+	pub write: bool,
+	pub creat: bool,
+	pub excl: bool,
+	pub trunc: bool,
+	pub append: bool,
+	pub directio: bool,
+	pub directory: bool,
+	pub raw: u32,
+	pub mode: u32,
+}
+
+pub enum SeekWhence {
+	Set,
+	Cur,
+	End,
+	/// Next position at or behind the offset, which contains data
+	Data,
+	/// Next hole at or behind the offset. The end of a file counts as hole.
+	Hole,
+}
+
+/// Computes the new position of a file of `size` bytes for `lseek`, where `position` is the current one.
+/// The file is treated as not sparse, so the whole file is data followed by an implicit hole at its end.
+pub(crate) fn seek_position(
+	position: usize,
+	offset: isize,
+	whence: SeekWhence,
+	size: usize,
+) -> Result<usize, FileError> {
+	let base = match whence {
+		SeekWhence::Set => 0,
+		SeekWhence::Cur => position as isize,
+		SeekWhence::End => size as isize,
+		SeekWhence::Data | SeekWhence::Hole => {
+			if offset < 0 || offset as usize >= size {
+				return Err(FileError::ENXIO());
+			}
+			return match whence {
+				SeekWhence::Data => Ok(offset as usize),
+				_ => Ok(size),
+			};
+		}
+	};
+
+	match base.checked_add(offset) {
+		Some(new) if new >= 0 => Ok(new as usize),
+		_ => Err(FileError::EINVAL()),
+	}
+}
+
+/// Flags of `fallocate`, which select the operation on the range
+pub const FALLOC_FL_KEEP_SIZE: u32 = 0x01;
+pub const FALLOC_FL_PUNCH_HOLE: u32 = 0x02;
+pub const FALLOC_FL_ZERO_RANGE: u32 = 0x10;
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::{
+		close_file, normalize_path, resolve_symlinks, seek_position, FileError, FileLock,
+		FilePerms, Filesystem, LockType, OpenFile, PosixFile, PosixFileSystem, SeekWhence, Stdin,
+		Tmpfs,
+	};
+	use crate::errno::*;
+	use alloc::boxed::Box;
+	use alloc::sync::Arc;
+	use core::sync::atomic::{AtomicUsize, Ordering};
+
+	#[test]
+	fn test_normalize_path() {
+		assert_eq!(normalize_path("/", "/"), "/");
+		assert_eq!(normalize_path("/", ""), "/");
+		assert_eq!(normalize_path("/root", "foo/bar"), "/root/foo/bar");
+		assert_eq!(normalize_path("/root", "/data//cache/./x"), "/data/cache/x");
+		assert_eq!(normalize_path("/root", "../tmp/"), "/tmp");
+		assert_eq!(normalize_path("/root", "../../.."), "/");
+		assert_eq!(normalize_path("/a/b", "./c/../d"), "/a/b/d");
+	}
+	#[test]
+	fn test_errno_conversion() {
+		for errno in &[ENOENT, EBADF, EACCES, ENOSPC, ENAMETOOLONG, ESTALE] {
+			assert_eq!(FileError::from_errno(*errno).errno(), *errno);
+		}
+		assert_eq!(FileError::from_errno(EWOULDBLOCK), FileError::EAGAIN());
+		assert_eq!(FileError::from_errno(0x7fff), FileError::EIO());
+	}
+	#[test]
+	fn test_fd_allocation() {
+		let mut fs = Filesystem::new();
+		for fd in 0..4 {
+			assert_eq!(fs.add_file(OpenFile::new(Box::new(Stdin))), Ok(fd));
+		}
+
+		// the lowest closed fd is reused first
+		close_file(fs.remove_file(2).unwrap()).unwrap();
+		close_file(fs.remove_file(1).unwrap()).unwrap();
+		assert_eq!(fs.add_file(OpenFile::new(Box::new(Stdin))), Ok(1));
+
+		fs.set_nofile_limit(2, 4).unwrap();
+		assert_eq!(
+			fs.add_file(OpenFile::new(Box::new(Stdin))),
+			Err(FileError::EMFILE())
+		);
+		assert_eq!(fs.dup2(0, 3, false).err(), Some(FileError::EBADF()));
+		assert_eq!(fs.set_nofile_limit(5, 4), Err(FileError::EINVAL()));
+
+		fs.set_nofile_limit(u64::MAX, u64::MAX).unwrap();
+		assert_eq!(fs.get_nofile_limit(), (NR_OPEN, NR_OPEN));
+		assert_eq!(fs.set_nofile_limit(4, NR_OPEN + 1), Err(FileError::EPERM()));
+	}
+	#[test]
+	fn test_dup2_check_order() {
+		let mut fs = Filesystem::new();
+		fs.add_file(OpenFile::new(Box::new(Stdin))).unwrap();
+		fs.set_nofile_limit(2, 4).unwrap();
+
+		// an invalid oldfd is reported before an equal or too large newfd
+		assert_eq!(fs.dup2(1, 1, true).err(), Some(FileError::EBADF()));
+		assert_eq!(fs.dup2(1, u64::MAX, false).err(), Some(FileError::EBADF()));
+		assert_eq!(fs.dup2(0, 2, true).err(), Some(FileError::EBADF()));
+		assert_eq!(fs.dup2(0, 0, true).err(), Some(FileError::EINVAL()));
+		assert!(fs.dup2(0, 0, false).unwrap().is_none());
+		assert!(fs.dup2(0, 1, true).unwrap().is_none());
+	}
+	/// Counts, how often it has been closed
+	struct CloseCounter(Arc<AtomicUsize>);
+
+	impl PosixFile for CloseCounter {
+		fn close(&self) -> Result<(), FileError> {
+			self.0.fetch_add(1, Ordering::SeqCst);
+			Ok(())
+		}
+
+		fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+			Ok(0)
+		}
+
+		fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+			Ok(0)
+		}
+
+		fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+			Ok(0)
+		}
+	}
+
+	#[test]
+	fn test_close_on_last_reference() {
+		let closed = Arc::new(AtomicUsize::new(0));
+		let mut fs = Filesystem::new();
+		let fd = fs
+			.add_file(OpenFile::new(Box::new(CloseCounter(closed.clone()))))
+			.unwrap();
+		let dup = fs.dup(fd).unwrap();
+
+		// a task, which still operates on the file, keeps it open
+		let file = fs.get_file(fd).unwrap();
+		close_file(fs.remove_file(fd).unwrap()).unwrap();
+		close_file(fs.remove_file(dup).unwrap()).unwrap();
+		assert_eq!(closed.load(Ordering::SeqCst), 0);
+		drop(file);
+		assert_eq!(closed.load(Ordering::SeqCst), 1);
+
+		let fd = fs
+			.add_file(OpenFile::new(Box::new(CloseCounter(closed.clone()))))
+			.unwrap();
+		close_file(fs.remove_file(fd).unwrap()).unwrap();
+		assert_eq!(closed.load(Ordering::SeqCst), 2);
+	}
+	#[test]
+	fn test_ofd_lock_released_on_close() {
+		let tmpfs = Tmpfs::new();
+		let perms = FilePerms {
+			write: true,
+			creat: true,
+			mode: 0o644,
+			..Default::default()
+		};
+		let open = || OpenFile::new(tmpfs.open("/file", perms).unwrap());
+		let lock = |file: &OpenFile| FileLock::whole_file(LockType::Write, file.lock_owner(), 0);
+
+		let file = open();
+		assert_eq!(file.setlk(&lock(&file), false), Ok(()));
+		let other = open();
+		assert_eq!(other.setlk(&lock(&other), false), Err(FileError::EAGAIN()));
+
+		// closing the last fd of the open file releases its locks
+		close_file(file).unwrap();
+		assert_eq!(other.setlk(&lock(&other), false), Ok(()));
+		close_file(other).unwrap();
+
+		let file = open();
+		assert_eq!(file.setlk(&lock(&file), false), Ok(()));
+		close_file(file).unwrap();
+	}
+	#[test]
+	fn test_seek_position() {
+		assert_eq!(seek_position(5, -2, SeekWhence::Cur, 10), Ok(3));
+		assert_eq!(seek_position(5, -4, SeekWhence::End, 10), Ok(6));
+		assert_eq!(seek_position(5, 4, SeekWhence::Data, 10), Ok(4));
+		assert_eq!(seek_position(5, 4, SeekWhence::Hole, 10), Ok(10));
+		assert_eq!(
+			seek_position(5, 10, SeekWhence::Data, 10),
+			Err(FileError::ENXIO())
+		);
+		assert_eq!(
+			seek_position(5, -1, SeekWhence::Set, 10),
+			Err(FileError::EINVAL())
+		);
+	}
+	#[test]
+	fn test_readonly_mount() {
+		let mut fs = Filesystem::new();
+		fs.mount("/data", Box::new(Tmpfs::new()), true).unwrap();
+		fs.mount("/data/scratch", Box::new(Tmpfs::new()), false)
+			.unwrap();
+
+		assert!(fs.find_mount("/data/file").is_ok());
+		assert_eq!(
+			fs.find_writable_mount("/data/file").err(),
+			Some(FileError::EROFS())
+		);
+		assert_eq!(
+			fs.find_writable_mount("/data/scratch/file")
+				.map(|(_, path)| path)
+				.ok(),
+			Some("file")
+		);
+	}
+	#[test]
+	fn test_resolve_symlinks() {
+		let mut fs = Filesystem::new();
+		fs.mount("/", Box::new(Tmpfs::new()), false).unwrap();
+		let create = |path: &str| fs.resolve(path, true).unwrap();
+		let (root, dir) = create("/dir");
+		root.mkdir(&dir, 0o755).unwrap();
+		root.symlink("dir", &create("/link").1).unwrap();
+		root.symlink("../link", &create("/dir/up").1).unwrap();
+		root.symlink("/loop", &create("/loop").1).unwrap();
+
+		// links are resolved in intermediate directories as well
+		let resolve = |path: &str| resolve_symlinks(path, |path| fs.resolve(path, false));
+		assert_eq!(resolve("/link/file").as_deref(), Ok("/dir/file"));
+		assert_eq!(resolve("/dir/up/up/file").as_deref(), Ok("/dir/file"));
+		assert_eq!(resolve("/link/missing/x").as_deref(), Ok("/dir/missing/x"));
+		assert_eq!(resolve("/link/../link").as_deref(), Ok("/dir"));
+		assert_eq!(resolve("/loop/file"), Err(FileError::ELOOP()));
+	}
+}
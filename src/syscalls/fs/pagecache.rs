@@ -0,0 +1,471 @@
+//! Cache for the contents of files, which are stored outside of the kernel (e.g. on the host)
+//!
+//! Pages are keyed by mount, inode and page index and are evicted in least-recently-used order.
+//! Only clean pages are evicted. Dirty pages stay cached until their file writes them back,
+//! which happens on `fsync`, on `close` or when too many pages of all files are dirty.
+//! The cache copies data only, so its lock is never held while a backend waits for the host.
+
+use crate::synch::spinlock::Spinlock;
+use crate::syscalls::fs::FileError;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// Capacity of the cache, if not given by `-pagecache <MiB>`
+const DEFAULT_CAPACITY: usize = 32 * 1024 * 1024 / PAGE_SIZE;
+
+pub static PAGE_CACHE: Spinlock<PageCache> = Spinlock::new(PageCache::new(DEFAULT_CAPACITY));
+
+/// Writes the dirty pages of a file back to its backend, e.g. through an open file handle
+pub trait WriteBack: Send + Sync {
+	fn write_back(&self) -> Result<(), FileError>;
+}
+
+/// Writes dirty pages back, until at most half of the cache is dirty. The files of the least
+/// recently used dirty pages are written back first, regardless of the file, which has been modified.
+pub fn write_back_excess() {
+	loop {
+		let (writer, dirty) = {
+			let mut cache = PAGE_CACHE.lock();
+			if cache.dirty <= cache.capacity / 2 {
+				return;
+			}
+			match cache.oldest_writer() {
+				Some(writer) => (writer, cache.dirty),
+				None => return,
+			}
+		};
+
+		// the data is still cached, so it is written back on fsync or close
+		if let Err(err) = writer.write_back() {
+			warn!("Unable to write back dirty pages: {:?}", err);
+			return;
+		}
+		if PAGE_CACHE.lock().dirty >= dirty {
+			return;
+		}
+	}
+}
+
+/// Returns a new id, which distinguishes the inodes of a mount from those of other mounts.
+pub fn new_mount_id() -> u64 {
+	static NEXT_MOUNT_ID: AtomicU64 = AtomicU64::new(0);
+	NEXT_MOUNT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PageKey {
+	pub mount: u64,
+	pub ino: u64,
+	pub index: u64,
+}
+
+struct Page {
+	data: Box<[u8]>,
+	/// Number of valid bytes. A page, which is not full, contains the end of the file.
+	len: usize,
+	dirty: bool,
+	/// Position in the LRU list
+	last_use: u64,
+}
+
+pub struct PageCache {
+	pages: BTreeMap<PageKey, Page>,
+	/// Keys of all pages ordered by their last use
+	lru: BTreeMap<u64, PageKey>,
+	clock: u64,
+	/// Maximum number of pages
+	capacity: usize,
+	dirty: usize,
+	/// Write back the dirty pages of a file, keyed by mount and inode
+	writers: BTreeMap<(u64, u64), Weak<dyn WriteBack>>,
+}
+
+impl PageCache {
+	pub const fn new(capacity: usize) -> Self {
+		Self {
+			pages: BTreeMap::new(),
+			lru: BTreeMap::new(),
+			clock: 0,
+			capacity,
+			dirty: 0,
+			writers: BTreeMap::new(),
+		}
+	}
+
+	/// Returns the maximum number of cached pages. A capacity of zero disables the cache.
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	pub fn set_capacity(&mut self, capacity: usize) {
+		self.capacity = capacity;
+		self.evict();
+	}
+
+	/// Returns the number of pages, which have not been written back yet.
+	pub fn dirty_pages(&self) -> usize {
+		self.dirty
+	}
+
+	/// Sets `writer` to write back the dirty pages of inode `ino`, if too many pages are dirty.
+	/// It replaces a previous writer of the inode and is dropped, when its file is closed.
+	pub fn set_writer(&mut self, mount: u64, ino: u64, writer: &Arc<dyn WriteBack>) {
+		self.writers.retain(|_, writer| writer.strong_count() > 0);
+		self.writers.insert((mount, ino), Arc::downgrade(writer));
+	}
+
+	/// Returns the writer of the file, whose dirty page has been used least recently.
+	/// Files without a writer are skipped, their pages are written back by their next writer.
+	fn oldest_writer(&self) -> Option<Arc<dyn WriteBack>> {
+		self.lru
+			.values()
+			.filter(|key| self.pages[*key].dirty)
+			.find_map(|key| self.writers.get(&(key.mount, key.ino))?.upgrade())
+	}
+
+	/// Copies the cached data of page `key` starting at `offset` into `buf`.
+	/// Returns `None`, if the page is not cached. Fewer bytes than requested are
+	/// returned, if the page ends or contains the end of the file.
+	pub fn read(&mut self, key: PageKey, offset: usize, buf: &mut [u8]) -> Option<usize> {
+		let clock = self.tick();
+		let page = self.pages.get_mut(&key)?;
+		let len = buf.len().min(page.len.saturating_sub(offset));
+		buf[..len].copy_from_slice(&page.data[offset..offset + len]);
+		touch(&mut self.lru, key, page, clock);
+		Some(len)
+	}
+
+	pub fn contains(&self, key: PageKey) -> bool {
+		self.pages.contains_key(&key)
+	}
+
+	/// Caches the clean page `key`, which has been read from the backend. `data` is shorter
+	/// than a page, if the page contains the end of the file. Cached pages are not replaced,
+	/// since they may contain modifications, which have not been written back yet.
+	pub fn insert(&mut self, key: PageKey, data: &[u8]) {
+		if self.capacity == 0 || self.pages.contains_key(&key) {
+			return;
+		}
+
+		let mut page_data = zeroed_page();
+		page_data[..data.len()].copy_from_slice(data);
+		// Pages behind this one have been written, but not written back yet.
+		// Thus, the file does not end here and the rest of the page reads as zero.
+		let behind = PageKey {
+			index: key.index + 1,
+			..key
+		}..=PageKey {
+			index: u64::MAX,
+			..key
+		};
+		let len = if self.pages.range(behind).next().is_some() {
+			PAGE_SIZE
+		} else {
+			data.len()
+		};
+		self.insert_page(key, page_data, len, false);
+		self.evict();
+	}
+
+	/// Writes `data` at `offset` into the cached page `key` and marks it dirty.
+	/// Returns false, if the page is not cached.
+	pub fn write(&mut self, key: PageKey, offset: usize, data: &[u8]) -> bool {
+		let clock = self.tick();
+		let page = match self.pages.get_mut(&key) {
+			Some(page) => page,
+			None => return false,
+		};
+
+		page.data[offset..offset + data.len()].copy_from_slice(data);
+		// bytes between the old end of the file and `offset` read as zero
+		if page.len < offset {
+			page.data[page.len..offset].fill(0);
+		}
+		page.len = page.len.max(offset + data.len());
+		if !page.dirty {
+			page.dirty = true;
+			self.dirty += 1;
+		}
+		touch(&mut self.lru, key, page, clock);
+
+		self.extend_file(key);
+		true
+	}
+
+	/// Caches the dirty page `key`, whose previous content is not needed. This is the case, if
+	/// `data` covers the whole page or the page is located behind the end of the file.
+	pub fn insert_dirty(&mut self, key: PageKey, offset: usize, data: &[u8]) {
+		if !self.write(key, offset, data) {
+			let mut page_data = zeroed_page();
+			page_data[offset..offset + data.len()].copy_from_slice(data);
+			self.insert_page(key, page_data, offset + data.len(), true);
+			self.extend_file(key);
+			self.evict();
+		}
+	}
+
+	/// Marks all dirty pages of inode `ino` clean and returns copies of them in the order of their
+	/// index. If writing them back fails, they have to be passed to `insert_dirty` again.
+	pub fn take_dirty(&mut self, mount: u64, ino: u64) -> Vec<(u64, Vec<u8>)> {
+		let mut dirty = Vec::new();
+		for (key, page) in self.pages.range_mut(file_range(mount, ino)) {
+			if page.dirty {
+				page.dirty = false;
+				dirty.push((key.index, page.data[..page.len].to_vec()));
+			}
+		}
+		self.dirty -= dirty.len();
+		self.evict();
+		dirty
+	}
+
+	/// Caches `data` again as dirty page `key`, after writing it back has failed. The page is not
+	/// replaced, if it has been modified in the meantime.
+	pub fn restore_dirty(&mut self, key: PageKey, data: &[u8]) {
+		if self.pages.get(&key).map_or(false, |page| page.dirty) {
+			return;
+		}
+
+		self.remove_page(key);
+		let mut page_data = zeroed_page();
+		page_data[..data.len()].copy_from_slice(data);
+		self.insert_page(key, page_data, data.len(), true);
+	}
+
+	/// Removes all clean pages of inode `ino`, e.g. because the file has been modified
+	/// by the backend or by another client. Dirty pages are kept, since they contain writes
+	/// of this kernel, which are newer than the content of the backend. Callers, which need
+	/// the content of the backend, have to write them back first.
+	pub fn invalidate(&mut self, mount: u64, ino: u64) {
+		let keys: Vec<PageKey> = self
+			.pages
+			.range(file_range(mount, ino))
+			.filter(|(_, page)| !page.dirty)
+			.map(|(key, _)| *key)
+			.collect();
+		for key in keys {
+			self.remove_page(key);
+		}
+	}
+
+	fn tick(&mut self) -> u64 {
+		self.clock += 1;
+		self.clock
+	}
+
+	fn insert_page(&mut self, key: PageKey, data: Box<[u8]>, len: usize, dirty: bool) {
+		let last_use = self.tick();
+		self.lru.insert(last_use, key);
+		if dirty {
+			self.dirty += 1;
+		}
+		self.pages.insert(
+			key,
+			Page {
+				data,
+				len,
+				dirty,
+				last_use,
+			},
+		);
+	}
+
+	fn remove_page(&mut self, key: PageKey) {
+		if let Some(page) = self.pages.remove(&key) {
+			self.lru.remove(&page.last_use);
+			if page.dirty {
+				self.dirty -= 1;
+			}
+		}
+	}
+
+	/// The file has been extended up to page `key`, so the pages in front of it are no longer
+	/// the end of the file. Clean ones are dropped, dirty ones are filled up with zeros.
+	fn extend_file(&mut self, key: PageKey) {
+		let mut stale = Vec::new();
+		let range = PageKey { index: 0, ..key }..key;
+		for (key, page) in self.pages.range_mut(range) {
+			if page.len < PAGE_SIZE {
+				if page.dirty {
+					page.data[page.len..].fill(0);
+					page.len = PAGE_SIZE;
+				} else {
+					stale.push(*key);
+				}
+			}
+		}
+		for key in stale {
+			self.remove_page(key);
+		}
+	}
+
+	/// Drops the least recently used clean pages, until the cache fits into its capacity.
+	fn evict(&mut self) {
+		let excess = self.pages.len().saturating_sub(self.capacity);
+		if excess == 0 {
+			return;
+		}
+
+		let pages = &self.pages;
+		let victims: Vec<PageKey> = self
+			.lru
+			.values()
+			.filter(|key| !pages[*key].dirty)
+			.take(excess)
+			.copied()
+			.collect();
+		for key in victims {
+			self.remove_page(key);
+		}
+	}
+}
+
+fn zeroed_page() -> Box<[u8]> {
+	vec![0; PAGE_SIZE].into_boxed_slice()
+}
+
+/// Marks `page` as the most recently used one.
+fn touch(lru: &mut BTreeMap<u64, PageKey>, key: PageKey, page: &mut Page, clock: u64) {
+	lru.remove(&page.last_use);
+	lru.insert(clock, key);
+	page.last_use = clock;
+}
+
+/// Returns the range of keys, which belong to inode `ino`.
+fn file_range(mount: u64, ino: u64) -> core::ops::RangeInclusive<PageKey> {
+	PageKey {
+		mount,
+		ino,
+		index: 0,
+	}..=PageKey {
+		mount,
+		ino,
+		index: u64::MAX,
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::{PageCache, PageKey, WriteBack, PAGE_SIZE};
+	use crate::syscalls::fs::FileError;
+	use alloc::sync::Arc;
+
+	struct NoWriteBack;
+
+	impl WriteBack for NoWriteBack {
+		fn write_back(&self) -> Result<(), FileError> {
+			Ok(())
+		}
+	}
+
+	fn key(index: u64) -> PageKey {
+		PageKey {
+			mount: 0,
+			ino: 1,
+			index,
+		}
+	}
+
+	#[test]
+	fn test_read_short_page() {
+		let mut cache = PageCache::new(4);
+		let mut buf = [0u8; 16];
+		assert_eq!(cache.read(key(0), 0, &mut buf), None);
+
+		cache.insert(key(0), b"hello");
+		assert_eq!(cache.read(key(0), 1, &mut buf), Some(4));
+		assert_eq!(&buf[..4], b"ello");
+		assert_eq!(cache.read(key(0), 8, &mut buf), Some(0));
+	}
+
+	#[test]
+	fn test_lru_eviction_keeps_dirty_pages() {
+		let mut cache = PageCache::new(2);
+		let mut buf = [0u8; 1];
+		cache.insert_dirty(key(0), 0, &[1; PAGE_SIZE]);
+		cache.insert(key(1), &[2; PAGE_SIZE]);
+		cache.insert(key(2), &[3; PAGE_SIZE]);
+
+		// the clean page 1 is evicted instead of the older, but dirty page 0
+		assert_eq!(cache.read(key(1), 0, &mut buf), None);
+		assert_eq!(cache.read(key(0), 0, &mut buf), Some(1));
+		assert_eq!(cache.read(key(2), 0, &mut buf), Some(1));
+
+		let dirty = cache.take_dirty(0, 1);
+		assert_eq!(dirty.len(), 1);
+		assert_eq!(dirty[0].0, 0);
+		assert_eq!(cache.dirty_pages(), 0);
+	}
+
+	#[test]
+	fn test_write_extends_file() {
+		let mut cache = PageCache::new(8);
+		let mut buf = [0xffu8; 8];
+		cache.insert(key(0), b"abc");
+		cache.insert_dirty(key(2), 0, b"xyz");
+
+		// the clean end of the file is stale and has to be read again
+		assert_eq!(cache.read(key(0), 0, &mut buf), None);
+
+		cache.insert_dirty(key(3), 4, b"z");
+		assert_eq!(cache.read(key(2), PAGE_SIZE - 8, &mut buf), Some(8));
+		assert_eq!(buf, [0; 8]);
+		assert_eq!(cache.read(key(3), 0, &mut buf), Some(5));
+		assert_eq!(&buf[..5], &[0, 0, 0, 0, b'z']);
+	}
+
+	#[test]
+	fn test_hole_reads_zero() {
+		let mut cache = PageCache::new(8);
+		let mut buf = [0xffu8; 8];
+		cache.insert_dirty(key(2), 0, b"xyz");
+
+		// the backend still reports the old end of the file
+		cache.insert(key(1), b"");
+		assert_eq!(cache.read(key(1), 0, &mut buf), Some(8));
+		assert_eq!(buf, [0; 8]);
+	}
+
+	#[test]
+	fn test_invalidate_keeps_dirty_pages() {
+		let mut cache = PageCache::new(8);
+		let mut buf = [0u8; 1];
+		cache.insert(key(0), &[1; PAGE_SIZE]);
+		cache.insert_dirty(key(1), 0, &[2; PAGE_SIZE]);
+		cache.invalidate(0, 1);
+
+		assert_eq!(cache.read(key(0), 0, &mut buf), None);
+		assert_eq!(cache.read(key(1), 0, &mut buf), Some(1));
+	}
+
+	#[test]
+	fn test_oldest_writer() {
+		let mut cache = PageCache::new(8);
+		let other = PageKey {
+			mount: 0,
+			ino: 2,
+			index: 0,
+		};
+		let writer: Arc<dyn WriteBack> = Arc::new(NoWriteBack);
+		let other_writer: Arc<dyn WriteBack> = Arc::new(NoWriteBack);
+		cache.insert(key(0), &[1; PAGE_SIZE]);
+		cache.insert_dirty(other, 0, &[2; PAGE_SIZE]);
+		cache.insert_dirty(key(1), 0, &[3; PAGE_SIZE]);
+		assert!(cache.oldest_writer().is_none());
+
+		// the dirty page of the other file is older than the dirty page of the first file
+		cache.set_writer(0, 1, &writer);
+		cache.set_writer(0, 2, &other_writer);
+		assert!(Arc::ptr_eq(&cache.oldest_writer().unwrap(), &other_writer));
+
+		// writers of closed files are skipped
+		drop(other_writer);
+		assert!(Arc::ptr_eq(&cache.oldest_writer().unwrap(), &writer));
+	}
+}
@@ -0,0 +1,339 @@
+//! Anonymous pipes
+//!
+//! A pipe is a bounded byte stream between the tasks of the unikernel. Both ends are regular open files.
+//! Readers block while the pipe is empty and writers while it is full, unless the end is non-blocking.
+//! Once all write ends are closed, reads report the end of the file. Writes fail with EPIPE,
+//! once all read ends are closed.
+
+use crate::arch::percore::*;
+use crate::scheduler::task::{TaskHandle, TaskHandlePriorityQueue, WakeupReason};
+use crate::synch::spinlock::{SpinlockIrqSave, SpinlockIrqSaveGuard};
+use crate::syscalls::fs::poll::{
+	PollEvents, Registration, WaitQueue, POLLERR, POLLHUP, POLLIN, POLLOUT,
+};
+use crate::syscalls::fs::{FileAttr, FileError, PosixFile, SeekWhence};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const S_IFIFO: u32 = 0o010000;
+
+/// Capacity of a pipe, which is the default of Linux
+pub const PIPE_CAPACITY: usize = 64 * 1024;
+/// Writes of up to `PIPE_BUF` bytes are atomic, they are not interleaved with other writes.
+pub const PIPE_BUF: usize = 4096;
+
+struct PipeState {
+	buffer: VecDeque<u8>,
+	readers: usize,
+	writers: usize,
+	/// Tasks waiting for data or for the last writer to close the pipe
+	read_waiters: TaskHandlePriorityQueue,
+	/// Tasks waiting for free space or for the last reader to close the pipe
+	write_waiters: TaskHandlePriorityQueue,
+}
+
+fn take_waiters(queue: &mut TaskHandlePriorityQueue) -> Vec<TaskHandle> {
+	let mut waiters = Vec::new();
+	while let Some(task) = queue.pop() {
+		waiters.push(task);
+	}
+	waiters
+}
+
+fn wakeup(tasks: Vec<TaskHandle>) {
+	for task in tasks {
+		core_scheduler().custom_wakeup(task);
+	}
+}
+
+/// The buffer of a pipe, which is shared by both ends
+struct Pipe {
+	state: SpinlockIrqSave<PipeState>,
+	/// Tasks polling either end of the pipe
+	pollers: WaitQueue,
+}
+
+impl Pipe {
+	/// Blocks the current task in `queue`. The lock of the state is released before switching to another task.
+	fn wait(
+		&self,
+		mut state: SpinlockIrqSaveGuard<'_, PipeState>,
+		queue: fn(&mut PipeState) -> &mut TaskHandlePriorityQueue,
+	) {
+		let core_scheduler = core_scheduler();
+		core_scheduler.set_current_task_wakeup_reason(WakeupReason::Custom);
+		core_scheduler.block_current_task(None);
+		queue(&mut state).push(core_scheduler.get_current_task_handle());
+		drop(state);
+		core_scheduler.reschedule();
+	}
+
+	fn read(&self, buf: &mut [u8], nonblocking: bool) -> Result<usize, FileError> {
+		if buf.is_empty() {
+			return Ok(0);
+		}
+
+		loop {
+			let mut state = self.state.lock();
+
+			if !state.buffer.is_empty() {
+				let len = buf.len().min(state.buffer.len());
+				for (dst, src) in buf.iter_mut().zip(state.buffer.drain(..len)) {
+					*dst = src;
+				}
+				let waiters = take_waiters(&mut state.write_waiters);
+				drop(state);
+				wakeup(waiters);
+				self.pollers.notify();
+				return Ok(len);
+			} else if state.writers == 0 {
+				return Ok(0);
+			} else if nonblocking {
+				return Err(FileError::EAGAIN());
+			}
+
+			self.wait(state, |state| &mut state.read_waiters);
+		}
+	}
+
+	fn write(&self, buf: &[u8], nonblocking: bool) -> Result<usize, FileError> {
+		let mut written = 0;
+
+		while written < buf.len() {
+			let mut state = self.state.lock();
+			if state.readers == 0 {
+				return if written > 0 {
+					Ok(written)
+				} else {
+					Err(FileError::EPIPE())
+				};
+			}
+
+			// small writes are only performed at once
+			let free = PIPE_CAPACITY - state.buffer.len();
+			let remaining = buf.len() - written;
+			if free > 0 && (free >= remaining || buf.len() > PIPE_BUF) {
+				let len = free.min(remaining);
+				state.buffer.extend(&buf[written..written + len]);
+				written += len;
+				let waiters = take_waiters(&mut state.read_waiters);
+				drop(state);
+				wakeup(waiters);
+				self.pollers.notify();
+				continue;
+			}
+
+			if nonblocking {
+				return if written > 0 {
+					Ok(written)
+				} else {
+					Err(FileError::EAGAIN())
+				};
+			}
+
+			self.wait(state, |state| &mut state.write_waiters);
+		}
+
+		Ok(written)
+	}
+
+	/// Closes one end of the pipe and wakes up the tasks waiting at the other end.
+	fn close(&self, reader: bool) {
+		let waiters = {
+			let mut state = self.state.lock();
+			if reader {
+				state.readers -= 1;
+				if state.readers == 0 {
+					// unread data is not needed anymore
+					state.buffer = VecDeque::new();
+				}
+				take_waiters(&mut state.write_waiters)
+			} else {
+				state.writers -= 1;
+				take_waiters(&mut state.read_waiters)
+			}
+		};
+		wakeup(waiters);
+		self.pollers.notify();
+	}
+
+	/// Returns the events of the read end (`reader`) or of the write end.
+	fn poll(&self, reader: bool, registration: Option<Registration<'_>>) -> PollEvents {
+		if let Some(registration) = registration {
+			self.pollers.register(registration);
+		}
+
+		let state = self.state.lock();
+		let mut events = 0;
+		if reader {
+			if !state.buffer.is_empty() {
+				events |= POLLIN;
+			}
+			if state.writers == 0 {
+				events |= POLLHUP;
+			}
+		} else {
+			if PIPE_CAPACITY - state.buffer.len() >= PIPE_BUF {
+				events |= POLLOUT;
+			}
+			if state.readers == 0 {
+				events |= POLLERR;
+			}
+		}
+		events
+	}
+}
+
+/// Attributes of both ends of a pipe
+fn pipe_attr() -> FileAttr {
+	FileAttr {
+		mode: S_IFIFO | 0o600,
+		nlink: 1,
+		blksize: PIPE_BUF as u64,
+		..Default::default()
+	}
+}
+
+/// Creates a pipe and returns its read end and its write end.
+pub fn pipe(nonblocking: bool) -> (PipeReader, PipeWriter) {
+	let pipe = Arc::new(Pipe {
+		state: SpinlockIrqSave::new(PipeState {
+			buffer: VecDeque::new(),
+			readers: 1,
+			writers: 1,
+			read_waiters: TaskHandlePriorityQueue::new(),
+			write_waiters: TaskHandlePriorityQueue::new(),
+		}),
+		pollers: WaitQueue::new(),
+	});
+
+	(
+		PipeReader {
+			pipe: pipe.clone(),
+			nonblocking,
+		},
+		PipeWriter { pipe, nonblocking },
+	)
+}
+
+/// The read end of a pipe
+pub struct PipeReader {
+	pipe: Arc<Pipe>,
+	/// Reads from an empty pipe fail with EAGAIN instead of blocking (O_NONBLOCK)
+	nonblocking: bool,
+}
+
+impl PosixFile for PipeReader {
+	fn close(&self) -> Result<(), FileError> {
+		self.pipe.close(true);
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		self.pipe.read(buf, self.nonblocking)
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EBADF())
+	}
+
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(pipe_attr())
+	}
+
+	fn poll(&self, registration: Option<Registration<'_>>) -> Result<PollEvents, FileError> {
+		Ok(self.pipe.poll(true, registration))
+	}
+}
+
+/// The write end of a pipe
+pub struct PipeWriter {
+	pipe: Arc<Pipe>,
+	/// Writes to a full pipe fail with EAGAIN instead of blocking (O_NONBLOCK)
+	nonblocking: bool,
+}
+
+impl PosixFile for PipeWriter {
+	fn close(&self) -> Result<(), FileError> {
+		self.pipe.close(false);
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EBADF())
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		self.pipe.write(buf, self.nonblocking).map(|len| len as u64)
+	}
+
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(pipe_attr())
+	}
+
+	fn poll(&self, registration: Option<Registration<'_>>) -> Result<PollEvents, FileError> {
+		Ok(self.pipe.poll(false, registration))
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_pipe() {
+		let (reader, writer) = pipe(true);
+		let mut buf = [0u8; 8];
+
+		assert_eq!(reader.read(&mut buf), Err(FileError::EAGAIN()));
+		assert_eq!(writer.write(b"hello"), Ok(5));
+		assert_eq!(reader.read(&mut buf[..3]), Ok(3));
+		assert_eq!(&buf[..3], b"hel");
+
+		writer.close().unwrap();
+		assert_eq!(reader.read(&mut buf), Ok(2));
+		assert_eq!(&buf[..2], b"lo");
+		// all writers are closed, so the end of the file is reached
+		assert_eq!(reader.read(&mut buf), Ok(0));
+	}
+
+	#[test]
+	fn test_full_pipe() {
+		let (reader, writer) = pipe(true);
+		let data = vec![1u8; PIPE_CAPACITY + PIPE_BUF];
+
+		// large writes are split, small writes are performed at once or not at all
+		assert_eq!(writer.write(&data), Ok(PIPE_CAPACITY as u64));
+		assert_eq!(writer.write(&data[..1]), Err(FileError::EAGAIN()));
+		let mut buf = [0u8; 16];
+		assert_eq!(reader.read(&mut buf), Ok(16));
+		assert_eq!(writer.write(&data[..32]), Err(FileError::EAGAIN()));
+		assert_eq!(writer.write(&data[..16]), Ok(16));
+
+		reader.close().unwrap();
+		assert_eq!(writer.write(&data[..1]), Err(FileError::EPIPE()));
+	}
+
+	#[test]
+	fn test_poll_pipe() {
+		let (reader, writer) = pipe(true);
+		assert_eq!(reader.poll(None), Ok(0));
+		assert_eq!(writer.poll(None), Ok(POLLOUT));
+
+		writer.write(b"data").unwrap();
+		assert_eq!(reader.poll(None), Ok(POLLIN));
+
+		writer.close().unwrap();
+		assert_eq!(reader.poll(None), Ok(POLLIN | POLLHUP));
+	}
+}
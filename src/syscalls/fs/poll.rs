@@ -0,0 +1,466 @@
+//! Readiness of open files
+//!
+//! A task waiting for several files (poll, epoll_wait) registers a `Waiter` in the `WaitQueue` of each file.
+//! A file notifies its queue, whenever its readiness may have changed. A registration is kept, until its waiter
+//! is dropped, so a waiter registers once before it checks the readiness of a file. Waiting is based on a semaphore,
+//! so neither a notification between the check and blocking nor a timeout is missed.
+//! Files, which become ready at a certain time (like timers), ask the waiter to notify itself at that time.
+//! Since such a time changes, notified files are checked with their registration again.
+//!
+//! Sockets are not covered. The network stack runs in the application on top of `sys_netwait`,
+//! so its sockets are not open files of the kernel and their readiness is unknown here.
+//! poll fails with EINVAL and epoll_ctl with EPERM for sockets.
+
+use crate::arch;
+use crate::synch::semaphore::Semaphore;
+use crate::synch::spinlock::{Spinlock, SpinlockIrqSave};
+use crate::syscalls::fs::{FileError, PosixFile, SeekWhence};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Readiness events of a file, encoded like the events of poll and epoll
+pub type PollEvents = u32;
+
+pub const POLLIN: PollEvents = 0x001;
+pub const POLLOUT: PollEvents = 0x004;
+pub const POLLERR: PollEvents = 0x008;
+pub const POLLHUP: PollEvents = 0x010;
+pub const POLLNVAL: PollEvents = 0x020;
+
+/// Flags of epoll, which select how events are reported
+pub const EPOLLONESHOT: u32 = 1 << 30;
+pub const EPOLLET: u32 = 1 << 31;
+
+/// A task waiting for events of several files
+pub struct Waiter {
+	sem: Semaphore,
+	/// Keys of the registrations, which have been notified
+	notified: SpinlockIrqSave<Vec<u64>>,
+	/// Times (in timer ticks), at which registrations are notified, and their keys
+	timers: SpinlockIrqSave<Vec<(u64, u64)>>,
+}
+
+impl Waiter {
+	pub fn new() -> Arc<Self> {
+		Arc::new(Self {
+			sem: Semaphore::new(0),
+			notified: SpinlockIrqSave::new(Vec::new()),
+			timers: SpinlockIrqSave::new(Vec::new()),
+		})
+	}
+
+	/// Notifies the registration `key` at `time` (in timer ticks). It replaces an earlier time of the registration.
+	pub fn notify_at(&self, time: u64, key: u64) {
+		let mut timers = self.timers.lock();
+		timers.retain(|(_, other)| *other != key);
+		timers.push((time, key));
+	}
+
+	/// Moves the registrations, whose time has passed, to the notified ones. Returns the time of the next timer.
+	fn expire_timers(&self, now: u64) -> (bool, Option<u64>) {
+		let mut expired = false;
+		let mut timers = self.timers.lock();
+		timers.retain(|(time, key)| {
+			if *time <= now {
+				self.notified.lock().push(*key);
+				expired = true;
+				false
+			} else {
+				true
+			}
+		});
+		(expired, timers.iter().map(|(time, _)| *time).min())
+	}
+
+	/// Blocks until a file has been notified or the `deadline` (in timer ticks) has passed.
+	/// Returns false, if the deadline has passed.
+	pub fn wait(&self, deadline: Option<u64>) -> bool {
+		loop {
+			let now = arch::processor::get_timer_ticks();
+			let (expired, timer) = self.expire_timers(now);
+			if expired {
+				return true;
+			} else if deadline.map_or(false, |deadline| deadline <= now) {
+				return false;
+			}
+
+			// the semaphore counts in milliseconds, so round up to not wake up early
+			let wakeup = match (deadline, timer) {
+				(Some(deadline), Some(timer)) => Some(deadline.min(timer)),
+				(deadline, timer) => deadline.or(timer),
+			};
+			if self
+				.sem
+				.acquire(wakeup.map(|wakeup| (wakeup - now + 999) / 1000))
+			{
+				// several notifications are handled at once
+				while self.sem.try_acquire() {}
+				return true;
+			}
+		}
+	}
+
+	/// Returns the keys of the notified registrations since the last call.
+	pub fn take_notified(&self) -> Vec<u64> {
+		core::mem::take(&mut *self.notified.lock())
+	}
+}
+
+/// Registration of a waiter at a file. The key tells the waiter, which file has been notified.
+#[derive(Clone, Copy)]
+pub struct Registration<'a> {
+	pub waiter: &'a Arc<Waiter>,
+	pub key: u64,
+}
+
+/// The waiters of a file
+pub struct WaitQueue {
+	waiters: SpinlockIrqSave<Vec<(Weak<Waiter>, u64)>>,
+}
+
+impl WaitQueue {
+	pub const fn new() -> Self {
+		Self {
+			waiters: SpinlockIrqSave::new(Vec::new()),
+		}
+	}
+
+	/// Registers a waiter, which is notified on every call of `notify` until it is dropped.
+	/// Registering a waiter again with the same key has no effect.
+	pub fn register(&self, registration: Registration<'_>) {
+		let mut waiters = self.waiters.lock();
+		// waiters, which have given up, are removed
+		waiters.retain(|(waiter, _)| waiter.strong_count() > 0);
+		let waiter = Arc::downgrade(registration.waiter);
+		if !waiters
+			.iter()
+			.any(|(other, key)| other.ptr_eq(&waiter) && *key == registration.key)
+		{
+			waiters.push((waiter, registration.key));
+		}
+	}
+
+	/// Wakes up all registered waiters.
+	pub fn notify(&self) {
+		let waiters: Vec<(Arc<Waiter>, u64)> = {
+			let mut waiters = self.waiters.lock();
+			waiters.retain(|(waiter, _)| waiter.strong_count() > 0);
+			waiters
+				.iter()
+				.filter_map(|(waiter, key)| Some((waiter.upgrade()?, *key)))
+				.collect()
+		};
+		for (waiter, key) in waiters {
+			waiter.notified.lock().push(key);
+			waiter.sem.release();
+		}
+	}
+}
+
+impl Default for WaitQueue {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// Converts a timeout in milliseconds into a deadline in timer ticks. A negative timeout waits forever.
+pub fn deadline(timeout: i32) -> Option<u64> {
+	u64::try_from(timeout)
+		.ok()
+		.map(|ms| arch::processor::get_timer_ticks() + ms * 1000)
+}
+
+/// Returns the events of `file`, which are ready. Files, which do not support polling,
+/// are always ready for reading and writing, like regular files on Linux.
+pub fn poll_file(
+	file: &(dyn PosixFile + Send + Sync),
+	registration: Option<Registration<'_>>,
+) -> PollEvents {
+	match file.poll(registration) {
+		Ok(events) => events,
+		Err(FileError::EPERM()) => POLLIN | POLLOUT,
+		Err(_) => POLLERR,
+	}
+}
+
+/// An event of epoll, laid out like `struct epoll_event` on x86_64 Linux
+#[repr(C, packed)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EpollEvent {
+	pub events: u32,
+	pub data: u64,
+}
+
+struct EpollEntry {
+	/// The entry is removed, once the file is closed
+	file: Weak<dyn PosixFile + Send + Sync>,
+	/// Key of the registration at the file. The registration outlives a deleted entry,
+	/// so every entry gets a new key and notifications of deleted entries are ignored.
+	key: u64,
+	/// Events of interest and flags
+	events: u32,
+	data: u64,
+	/// The file has been notified since the last report of an edge-triggered event
+	armed: bool,
+	/// The event of a one-shot entry has been reported, so it is disabled until it is modified
+	disabled: bool,
+}
+
+/// An epoll instance, which watches a set of files. The files are identified by their fds,
+/// but an entry is kept as long as its open file is open, even if the fd is closed.
+/// Epoll instances cannot be nested, since they do not support polling.
+pub struct Epoll {
+	interest: Spinlock<BTreeMap<i32, EpollEntry>>,
+	/// Key of the next entry
+	next_key: AtomicU64,
+	/// Registered at all files of interest. Notifications, which occur between two calls of
+	/// epoll_wait, are recorded for the edge-triggered entries.
+	waiter: Arc<Waiter>,
+}
+
+impl Epoll {
+	pub fn new() -> Self {
+		Self {
+			interest: Spinlock::new(BTreeMap::new()),
+			next_key: AtomicU64::new(0),
+			waiter: Waiter::new(),
+		}
+	}
+
+	/// Adds `file` with the fd `fd` to the interest list.
+	pub fn add(
+		&self,
+		fd: i32,
+		file: &Arc<dyn PosixFile + Send + Sync>,
+		event: &EpollEvent,
+	) -> Result<(), FileError> {
+		// files without support for polling are always ready, which cannot be waited for
+		file.poll(None)?;
+
+		let mut interest = self.interest.lock();
+		interest.retain(|_, entry| entry.file.strong_count() > 0);
+		if interest.contains_key(&fd) {
+			return Err(FileError::EEXIST());
+		}
+		interest.insert(
+			fd,
+			EpollEntry {
+				file: Arc::downgrade(file),
+				key: self.next_key.fetch_add(1, Ordering::Relaxed),
+				events: event.events,
+				data: event.data,
+				armed: true,
+				disabled: false,
+			},
+		);
+		drop(interest);
+
+		// a task waiting in epoll_wait has to consider the new file
+		self.waiter.sem.release();
+		Ok(())
+	}
+
+	/// Changes the events of interest of the entry of `fd`.
+	pub fn modify(&self, fd: i32, event: &EpollEvent) -> Result<(), FileError> {
+		let mut interest = self.interest.lock();
+		let entry = interest.get_mut(&fd).ok_or(FileError::ENOENT())?;
+		entry.events = event.events;
+		entry.data = event.data;
+		entry.armed = true;
+		entry.disabled = false;
+		drop(interest);
+
+		self.waiter.sem.release();
+		Ok(())
+	}
+
+	/// Removes the entry of `fd` from the interest list.
+	pub fn delete(&self, fd: i32) -> Result<(), FileError> {
+		self.interest
+			.lock()
+			.remove(&fd)
+			.map(|_| ())
+			.ok_or(FileError::ENOENT())
+	}
+
+	/// Collects up to `max` ready events. The waiter of the instance is registered at all files of interest.
+	fn collect(&self, max: usize) -> Vec<EpollEvent> {
+		let mut interest = self.interest.lock();
+		for key in self.waiter.take_notified() {
+			if let Some(entry) = interest.values_mut().find(|entry| entry.key == key) {
+				entry.armed = true;
+			}
+		}
+
+		let mut ready = Vec::new();
+		let mut closed = Vec::new();
+		// The last reference to a file closes it, which must not happen while the interest list is locked.
+		let mut files = Vec::new();
+		for (fd, entry) in interest.iter_mut() {
+			let file = match entry.file.upgrade() {
+				Some(file) => file,
+				None => {
+					closed.push(*fd);
+					continue;
+				}
+			};
+			if entry.disabled {
+				continue;
+			}
+
+			let registration = Registration {
+				waiter: &self.waiter,
+				key: entry.key,
+			};
+			// errors and hangups are reported, even if they are not of interest
+			let events = poll_file(&*file, Some(registration)) & (entry.events | POLLERR | POLLHUP);
+			files.push(file);
+			if events == 0 || ready.len() >= max {
+				continue;
+			}
+
+			if entry.events & EPOLLET != 0 {
+				if !entry.armed {
+					continue;
+				}
+				entry.armed = false;
+			}
+			if entry.events & EPOLLONESHOT != 0 {
+				entry.disabled = true;
+			}
+			ready.push(EpollEvent {
+				events,
+				data: entry.data,
+			});
+		}
+
+		for fd in closed {
+			interest.remove(&fd);
+		}
+		drop(interest);
+		drop(files);
+
+		ready
+	}
+
+	/// Waits until events are ready or the `deadline` (in timer ticks) has passed
+	/// and returns up to `max` events.
+	pub fn wait(&self, max: usize, deadline: Option<u64>) -> Vec<EpollEvent> {
+		loop {
+			let ready = self.collect(max);
+			if !ready.is_empty() || !self.waiter.wait(deadline) {
+				return ready;
+			}
+		}
+	}
+}
+
+impl Default for Epoll {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl PosixFile for Epoll {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EINVAL())
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EINVAL())
+	}
+
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	fn as_epoll(&self) -> Option<&Epoll> {
+		Some(self)
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::syscalls::fs::pipe::pipe;
+
+	#[test]
+	fn test_epoll_modes() {
+		let (reader, writer) = pipe(true);
+		let reader: Arc<dyn PosixFile + Send + Sync> = Arc::new(reader);
+		let writer: Arc<dyn PosixFile + Send + Sync> = Arc::new(writer);
+		let event = |events, data| EpollEvent { events, data };
+
+		let level = Epoll::new();
+		let edge = Epoll::new();
+		let oneshot = Epoll::new();
+		level.add(3, &reader, &event(POLLIN, 1)).unwrap();
+		edge.add(3, &reader, &event(POLLIN | EPOLLET, 2)).unwrap();
+		oneshot
+			.add(3, &reader, &event(POLLIN | EPOLLONESHOT, 3))
+			.unwrap();
+		assert_eq!(
+			level.add(3, &reader, &event(POLLIN, 1)),
+			Err(FileError::EEXIST())
+		);
+		assert!(level.collect(8).is_empty());
+		assert!(edge.collect(8).is_empty());
+
+		writer.write(b"data").unwrap();
+		for _ in 0..2 {
+			assert_eq!(level.collect(8).len(), 1);
+		}
+		// edge-triggered events are only reported again after the next change
+		assert_eq!(edge.collect(8).len(), 1);
+		assert!(edge.collect(8).is_empty());
+		writer.write(b"more").unwrap();
+		assert_eq!(edge.collect(8).len(), 1);
+
+		assert_eq!(oneshot.collect(8).len(), 1);
+		assert!(oneshot.collect(8).is_empty());
+		oneshot.modify(3, &event(POLLIN, 3)).unwrap();
+		assert_eq!(oneshot.collect(8).len(), 1);
+
+		// closed files are removed from the interest list
+		drop(reader);
+		assert!(level.collect(8).is_empty());
+		assert_eq!(level.delete(3), Err(FileError::ENOENT()));
+	}
+
+	#[test]
+	fn test_registration_persists() {
+		let queue = WaitQueue::new();
+		let waiter = Waiter::new();
+		let registration = Registration {
+			waiter: &waiter,
+			key: 7,
+		};
+		queue.register(registration);
+		queue.register(registration);
+		queue.notify();
+		queue.notify();
+		assert_eq!(waiter.take_notified(), [7, 7]);
+
+		// dropping the waiter removes its registration
+		drop(waiter);
+		queue.notify();
+		assert!(queue.waiters.lock().is_empty());
+	}
+
+	#[test]
+	fn test_unpollable() {
+		let epoll = Epoll::new();
+		let other: Arc<dyn PosixFile + Send + Sync> = Arc::new(Epoll::new());
+		let event = EpollEvent::default();
+		assert_eq!(epoll.add(4, &other, &event), Err(FileError::EPERM()));
+		assert_eq!(poll_file(&*other, None), POLLIN | POLLOUT);
+	}
+}
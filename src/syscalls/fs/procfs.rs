@@ -0,0 +1,392 @@
+//! Introspection filesystem (procfs)
+//!
+//! A read-only filesystem, which is mounted at /proc and shows the state of the kernel as plain text:
+//! tasks, interrupts, memory usage, PCI devices, the command line and the processor.
+//! The content of a file is generated, when the file is opened, so all reads of an open file see the same snapshot.
+
+use crate::arch;
+use crate::environment;
+use crate::mm;
+use crate::scheduler;
+use crate::synch::spinlock::Spinlock;
+use crate::syscalls::fs::{
+	seek_position, DirEntry, FileAttr, FileError, FilePerms, FileType, PosixFile, PosixFileSystem,
+	SeekWhence,
+};
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// Inode number of the root directory, the files follow in the order of `FILES`
+const ROOT_INO: u64 = 1;
+
+/// Writes the content of a file
+type Generator = fn(&mut String) -> fmt::Result;
+
+const FILES: [(&str, Generator); 7] = [
+	("cmdline", cmdline),
+	("cpuinfo", cpuinfo),
+	("interrupts", interrupts),
+	("meminfo", meminfo),
+	("pci", pci),
+	("tasks", tasks),
+	("uptime", uptime),
+];
+
+fn cmdline(f: &mut String) -> fmt::Result {
+	writeln!(f, "{}", environment::get_command_line())
+}
+
+fn cpuinfo(f: &mut String) -> fmt::Result {
+	arch::processor::write_information(f)?;
+	writeln!(f, "cores: {}", arch::get_processor_count())
+}
+
+fn interrupts(f: &mut String) -> fmt::Result {
+	arch::kernel::write_statistics(f)
+}
+
+fn meminfo(f: &mut String) -> fmt::Result {
+	mm::write_information(f)
+}
+
+fn pci(_f: &mut String) -> fmt::Result {
+	#[cfg(target_arch = "x86_64")]
+	arch::kernel::pci::write_information(_f)?;
+	Ok(())
+}
+
+fn tasks(f: &mut String) -> fmt::Result {
+	writeln!(
+		f,
+		"{:>6} {:>4} {:>4} {:<8} {:<6}",
+		"TID", "PRIO", "CORE", "STATUS", "WAKEUP"
+	)?;
+	for task in scheduler::get_tasks() {
+		// the widths are only applied to strings and numbers
+		writeln!(
+			f,
+			"{:>6} {:>4} {:>4} {:<8} {:<6}",
+			task.id.into(),
+			task.prio.into(),
+			task.core_id,
+			format!("{:?}", task.status()),
+			format!("{:?}", task.last_wakeup_reason())
+		)?;
+	}
+	Ok(())
+}
+
+/// Time since the boot in seconds
+fn uptime(f: &mut String) -> fmt::Result {
+	let ticks = arch::processor::get_timer_ticks();
+	writeln!(
+		f,
+		"{}.{:02}",
+		ticks / 1_000_000,
+		(ticks % 1_000_000) / 10_000
+	)
+}
+
+fn dir_attr() -> FileAttr {
+	FileAttr {
+		ino: ROOT_INO,
+		mode: S_IFDIR | 0o555,
+		nlink: 2,
+		..Default::default()
+	}
+}
+
+/// Attributes of a file. Like on Linux, the size is zero, since the content is generated on open.
+fn file_attr(idx: usize) -> FileAttr {
+	FileAttr {
+		ino: ROOT_INO + 1 + idx as u64,
+		mode: S_IFREG | 0o444,
+		nlink: 1,
+		..Default::default()
+	}
+}
+
+pub struct Procfs;
+
+impl Procfs {
+	pub fn new() -> Self {
+		Self
+	}
+
+	/// Resolves a path relative to the root of the filesystem to the index of a file or `None` for the root.
+	fn lookup(&self, path: &str) -> Result<Option<usize>, FileError> {
+		let mut names = path
+			.split('/')
+			.filter(|name| !name.is_empty() && *name != ".");
+		let name = match names.next() {
+			Some(name) => name,
+			None => return Ok(None),
+		};
+
+		let idx = FILES
+			.iter()
+			.position(|(file, _)| *file == name)
+			.ok_or(FileError::ENOENT())?;
+		if names.next().is_some() {
+			return Err(FileError::ENOTDIR());
+		}
+		Ok(Some(idx))
+	}
+}
+
+impl Default for Procfs {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl PosixFileSystem for Procfs {
+	fn open(
+		&self,
+		path: &str,
+		perms: FilePerms,
+	) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		let idx = match self.lookup(path) {
+			Ok(_) if perms.creat && perms.excl => return Err(FileError::EEXIST()),
+			Ok(idx) => idx,
+			Err(FileError::ENOENT()) if perms.creat => return Err(FileError::EROFS()),
+			Err(err) => return Err(err),
+		};
+		if perms.write || perms.trunc {
+			return Err(FileError::EROFS());
+		}
+
+		match idx {
+			Some(idx) => {
+				let mut content = String::new();
+				(FILES[idx].1)(&mut content).map_err(|_| FileError::EIO())?;
+				Ok(Box::new(ProcFile {
+					data: content.into_bytes(),
+					attr: file_attr(idx),
+					offset: Spinlock::new(0),
+				}))
+			}
+			None => self.opendir(path),
+		}
+	}
+
+	fn unlink(&self, _path: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn opendir(&self, path: &str) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		if self.lookup(path)?.is_some() {
+			return Err(FileError::ENOTDIR());
+		}
+
+		let dots = [(".", FileType::Directory), ("..", FileType::Directory)];
+		let entries = dots
+			.iter()
+			.map(|(name, file_type)| ((*name).to_owned(), ROOT_INO, *file_type))
+			.chain(FILES.iter().enumerate().map(|(idx, (name, _))| {
+				((*name).to_owned(), file_attr(idx).ino, FileType::Regular)
+			}))
+			.enumerate()
+			.map(|(idx, (name, ino, file_type))| DirEntry {
+				ino,
+				offset: idx as u64 + 1,
+				file_type,
+				name,
+			})
+			.collect();
+
+		Ok(Box::new(ProcDir {
+			entries,
+			position: Spinlock::new(0),
+		}))
+	}
+
+	fn mkdir(&self, _path: &str, _mode: u32) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn rmdir(&self, _path: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn lstat(&self, path: &str) -> Result<FileAttr, FileError> {
+		Ok(self.lookup(path)?.map_or_else(dir_attr, file_attr))
+	}
+
+	fn rename(&self, _oldpath: &str, _newpath: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn link(&self, _oldpath: &str, _newpath: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn symlink(&self, _target: &str, _linkpath: &str) -> Result<(), FileError> {
+		Err(FileError::EROFS())
+	}
+
+	fn readlink(&self, path: &str) -> Result<String, FileError> {
+		self.lookup(path)?;
+		Err(FileError::EINVAL())
+	}
+}
+
+struct ProcFile {
+	/// Snapshot of the content, which is taken when the file is opened
+	data: Vec<u8>,
+	attr: FileAttr,
+	offset: Spinlock<usize>,
+}
+
+impl PosixFile for ProcFile {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		let mut offset = self.offset.lock();
+		let len = self.pread(buf, *offset as u64)?;
+		*offset += len;
+
+		Ok(len)
+	}
+
+	fn pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		let start = (offset as usize).min(self.data.len());
+		let end = start.saturating_add(buf.len()).min(self.data.len());
+		buf[..end - start].copy_from_slice(&self.data[start..end]);
+
+		Ok(end - start)
+	}
+
+	fn pwrite(&self, _buf: &[u8], _offset: u64) -> Result<u64, FileError> {
+		Err(FileError::EBADF())
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		// files are always opened read-only
+		Err(FileError::EBADF())
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let mut position = self.offset.lock();
+		*position = seek_position(*position, offset, whence, self.data.len())?;
+
+		Ok(*position)
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(self.attr)
+	}
+
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		Ok(())
+	}
+}
+
+struct ProcDir {
+	/// The entries are fixed, so they are collected when the directory is opened.
+	entries: Vec<DirEntry>,
+	/// Index of the next entry
+	position: Spinlock<usize>,
+}
+
+impl PosixFile for ProcDir {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn pread(&self, _buf: &mut [u8], _offset: u64) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let mut position = self.position.lock();
+		match whence {
+			SeekWhence::Set if offset >= 0 => *position = offset as usize,
+			SeekWhence::Cur if offset == 0 => {}
+			_ => return Err(FileError::EINVAL()),
+		}
+
+		Ok(*position)
+	}
+
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn readdir(&self) -> Result<Vec<DirEntry>, FileError> {
+		let mut position = self.position.lock();
+		let start = (*position).min(self.entries.len());
+		*position = self.entries.len();
+
+		Ok(self.entries[start..].to_vec())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(dir_attr())
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_lookup() {
+		let procfs = Procfs::new();
+		assert_eq!(procfs.lookup(""), Ok(None));
+		assert_eq!(procfs.lookup("/tasks"), Ok(Some(5)));
+		assert_eq!(procfs.lookup("missing"), Err(FileError::ENOENT()));
+		assert_eq!(procfs.lookup("tasks/1"), Err(FileError::ENOTDIR()));
+
+		assert_eq!(procfs.lstat("").unwrap().file_type(), FileType::Directory);
+		assert_eq!(
+			procfs.lstat("tasks").unwrap().file_type(),
+			FileType::Regular
+		);
+		let perms = FilePerms {
+			write: true,
+			..Default::default()
+		};
+		assert_eq!(procfs.open("tasks", perms).err(), Some(FileError::EROFS()));
+	}
+
+	#[test]
+	fn test_readdir() {
+		let dir = Procfs::new().opendir("").unwrap();
+		let names: Vec<String> = dir.readdir().unwrap().into_iter().map(|e| e.name).collect();
+		assert_eq!(names.len(), FILES.len() + 2);
+		assert_eq!(names[2], "cmdline");
+		assert!(dir.readdir().unwrap().is_empty());
+	}
+
+	#[test]
+	fn test_read_snapshot() {
+		let file = ProcFile {
+			data: b"0.42\n".to_vec(),
+			attr: file_attr(6),
+			offset: Spinlock::new(0),
+		};
+		let mut buf = [0u8; 3];
+		assert_eq!(file.read(&mut buf), Ok(3));
+		assert_eq!(file.read(&mut buf), Ok(2));
+		assert_eq!(&buf[..2], b"2\n");
+		assert_eq!(file.read(&mut buf), Ok(0));
+	}
+}
@@ -0,0 +1,104 @@
+//! Standard streams, which are connected to the console.
+//! uhyve forwards the output to the standard streams of the host instead.
+
+use crate::console::CONSOLE;
+use crate::environment;
+use crate::syscalls::fs::uhyve::host_write;
+use crate::syscalls::fs::{FileAttr, FileError, PosixFile, SeekWhence};
+
+const S_IFCHR: u32 = 0o020000;
+
+/// Attributes of the console, which is a character device
+fn console_attr() -> FileAttr {
+	FileAttr {
+		mode: S_IFCHR | 0o620,
+		nlink: 1,
+		blksize: 1024,
+		..Default::default()
+	}
+}
+
+/// Writes `buf` to the host stream `fd` under uhyve and to the console otherwise
+fn write_output(fd: i32, buf: &[u8]) -> Result<u64, FileError> {
+	if environment::is_uhyve() {
+		host_write(fd, buf)
+	} else {
+		CONSOLE.lock().write_all(buf);
+		Ok(buf.len() as u64)
+	}
+}
+
+/// Standard input. The console does not support input, so reading always reports end of file.
+pub struct Stdin;
+
+impl PosixFile for Stdin {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Ok(0)
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EBADF())
+	}
+
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(console_attr())
+	}
+}
+
+/// Standard output, which is written to the console
+pub struct Stdout;
+
+impl PosixFile for Stdout {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EBADF())
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		write_output(1, buf)
+	}
+
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(console_attr())
+	}
+}
+
+/// Standard error, which shares the console with the standard output (except under uhyve)
+pub struct Stderr;
+
+impl PosixFile for Stderr {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EBADF())
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		write_output(2, buf)
+	}
+
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(console_attr())
+	}
+}
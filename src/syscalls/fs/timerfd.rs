@@ -0,0 +1,234 @@
+//! Timers (timerfd)
+//!
+//! A timer expires once at a given time and, if an interval is set, periodically afterwards.
+//! Reads return the number of expirations since the last read and block until the timer has expired.
+//! Expirations are counted, whenever the timer is accessed, so a running timer does not need any work
+//! of the kernel. Waiting tasks are woken up by the tickless timer of the scheduler at the next expiration.
+
+use crate::arch;
+use crate::arch::percore::*;
+use crate::scheduler::task::{TaskHandlePriorityQueue, WakeupReason};
+use crate::synch::spinlock::SpinlockIrqSave;
+use crate::syscalls::fs::poll::{PollEvents, Registration, WaitQueue, POLLIN};
+use crate::syscalls::fs::{FileAttr, FileError, PosixFile, SeekWhence};
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// Setting of a timer in timer ticks (microseconds)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimerSetting {
+	/// Time until the next expiration, zero if the timer is disarmed
+	pub value: u64,
+	/// Period of the timer, zero if it expires only once
+	pub interval: u64,
+}
+
+struct TimerFdState {
+	/// Time of the next expiration, `None` if the timer is disarmed
+	expiry: Option<u64>,
+	interval: u64,
+	/// Expirations since the last read
+	expirations: u64,
+	/// Tasks waiting for the next expiration
+	waiters: TaskHandlePriorityQueue,
+}
+
+impl TimerFdState {
+	/// Counts the expirations until `now` and advances the time of the next expiration.
+	fn expire(&mut self, now: u64) {
+		match self.expiry {
+			Some(expiry) if expiry <= now => {
+				if self.interval > 0 {
+					let count = (now - expiry) / self.interval + 1;
+					self.expirations = self.expirations.saturating_add(count);
+					self.expiry = Some(expiry + count * self.interval);
+				} else {
+					self.expirations = self.expirations.saturating_add(1);
+					self.expiry = None;
+				}
+			}
+			_ => {}
+		}
+	}
+
+	/// Returns the setting relative to `now`.
+	fn setting(&self, now: u64) -> TimerSetting {
+		TimerSetting {
+			value: self.expiry.map_or(0, |expiry| expiry - now),
+			interval: self.interval,
+		}
+	}
+}
+
+pub struct TimerFd {
+	state: SpinlockIrqSave<TimerFdState>,
+	/// Clock of the timer, which is needed to interpret absolute times
+	clock: u64,
+	/// Reads fail with EAGAIN instead of blocking (TFD_NONBLOCK)
+	nonblocking: bool,
+	pollers: WaitQueue,
+}
+
+impl TimerFd {
+	/// Creates a disarmed timer
+	pub fn new(clock: u64, nonblocking: bool) -> Self {
+		Self {
+			state: SpinlockIrqSave::new(TimerFdState {
+				expiry: None,
+				interval: 0,
+				expirations: 0,
+				waiters: TaskHandlePriorityQueue::new(),
+			}),
+			clock,
+			nonblocking,
+			pollers: WaitQueue::new(),
+		}
+	}
+
+	pub fn clock(&self) -> u64 {
+		self.clock
+	}
+
+	/// Returns the current setting of the timer.
+	pub fn get(&self) -> TimerSetting {
+		let now = arch::processor::get_timer_ticks();
+		let mut state = self.state.lock();
+		state.expire(now);
+		state.setting(now)
+	}
+
+	/// Arms the timer to expire at `expiry` (in timer ticks) or disarms it, if `expiry` is `None`.
+	/// Expirations, which have not been read, are discarded. Returns the previous setting.
+	pub fn set(&self, expiry: Option<u64>, interval: u64) -> TimerSetting {
+		let now = arch::processor::get_timer_ticks();
+		let (old, waiters) = {
+			let mut state = self.state.lock();
+			state.expire(now);
+			let old = state.setting(now);
+			state.expiry = expiry;
+			state.interval = interval;
+			state.expirations = 0;
+
+			let mut waiters = Vec::new();
+			while let Some(task) = state.waiters.pop() {
+				waiters.push(task);
+			}
+			(old, waiters)
+		};
+
+		// waiting tasks and pollers have to wait for the new expiration
+		for task in waiters {
+			core_scheduler().custom_wakeup(task);
+		}
+		self.pollers.notify();
+		old
+	}
+}
+
+impl PosixFile for TimerFd {
+	fn close(&self) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		if buf.len() < size_of::<u64>() {
+			return Err(FileError::EINVAL());
+		}
+
+		loop {
+			let mut state = self.state.lock();
+			state.expire(arch::processor::get_timer_ticks());
+			if state.expirations > 0 {
+				buf[..size_of::<u64>()].copy_from_slice(&state.expirations.to_ne_bytes());
+				state.expirations = 0;
+				return Ok(size_of::<u64>());
+			} else if self.nonblocking {
+				return Err(FileError::EAGAIN());
+			}
+
+			// Block the current task until the next expiration or until the timer is changed.
+			let core_scheduler = core_scheduler();
+			let handle = core_scheduler.get_current_task_handle();
+			core_scheduler.set_current_task_wakeup_reason(WakeupReason::Custom);
+			core_scheduler.block_current_task(state.expiry);
+			state.waiters.push(handle);
+			drop(state);
+			core_scheduler.reschedule();
+
+			// the task is still queued, if it has been woken up by the timer
+			self.state.lock().waiters.remove(handle);
+		}
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EINVAL())
+	}
+
+	fn lseek(&self, _offset: isize, _whence: SeekWhence) -> Result<usize, FileError> {
+		Err(FileError::ESPIPE())
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(FileAttr {
+			mode: 0o600,
+			nlink: 1,
+			..Default::default()
+		})
+	}
+
+	fn poll(&self, registration: Option<Registration<'_>>) -> Result<PollEvents, FileError> {
+		if let Some(registration) = registration {
+			self.pollers.register(registration);
+		}
+
+		let mut state = self.state.lock();
+		state.expire(arch::processor::get_timer_ticks());
+		if state.expirations > 0 {
+			return Ok(POLLIN);
+		}
+		if let (Some(expiry), Some(registration)) = (state.expiry, registration) {
+			registration.waiter.notify_at(expiry, registration.key);
+		}
+		Ok(0)
+	}
+
+	fn as_timerfd(&self) -> Option<&TimerFd> {
+		Some(self)
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn state(expiry: Option<u64>, interval: u64) -> TimerFdState {
+		TimerFdState {
+			expiry,
+			interval,
+			expirations: 0,
+			waiters: TaskHandlePriorityQueue::new(),
+		}
+	}
+
+	#[test]
+	fn test_oneshot() {
+		let mut state = state(Some(100), 0);
+		state.expire(99);
+		assert_eq!(state.expirations, 0);
+		state.expire(100);
+		state.expire(1000);
+		assert_eq!(state.expirations, 1);
+		assert_eq!(state.expiry, None);
+	}
+
+	#[test]
+	fn test_periodic() {
+		let mut state = state(Some(100), 50);
+		state.expire(100);
+		assert_eq!((state.expirations, state.expiry), (1, Some(150)));
+		// missed expirations are counted at once
+		state.expire(320);
+		assert_eq!((state.expirations, state.expiry), (5, Some(350)));
+	}
+}
@@ -0,0 +1,788 @@
+//! In-memory filesystem
+//!
+//! All files and directories of a `Tmpfs` are kept on the kernel heap and are lost on shutdown.
+//! Every node is reference counted, so that open files remain usable after they have been unlinked.
+
+use crate::synch::spinlock::Spinlock;
+use crate::syscalls::fs::lock::{self, LockTable};
+use crate::syscalls::fs::{
+	seek_position, DirEntry, FileAttr, FileError, FileLock, FilePerms, FileType, LockOwner,
+	LockType, PosixFile, PosixFileSystem, SeekWhence, Timespec, FALLOC_FL_KEEP_SIZE,
+	FALLOC_FL_PUNCH_HOLE, FALLOC_FL_ZERO_RANGE,
+};
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+/// Block size reported by `stat`
+const BLOCK_SIZE: u64 = 4096;
+
+type NodeRef = Arc<Spinlock<Node>>;
+
+enum Content {
+	File(Vec<u8>),
+	Directory(BTreeMap<String, NodeRef>),
+	Symlink(String),
+}
+
+struct Node {
+	ino: u64,
+	/// Permission bits, the file type is derived from `content`
+	perm: u32,
+	nlink: u64,
+	atime: Timespec,
+	mtime: Timespec,
+	ctime: Timespec,
+	content: Content,
+	/// Advisory locks, which are shared by all open files of the node
+	locks: Arc<LockTable>,
+}
+
+impl Node {
+	fn new(ino: u64, perm: u32, content: Content) -> Self {
+		let now = Timespec::now();
+		Self {
+			ino,
+			perm: perm & 0o7777,
+			nlink: 1,
+			atime: now,
+			mtime: now,
+			ctime: now,
+			content,
+			locks: Arc::new(LockTable::new()),
+		}
+	}
+
+	fn file_type(&self) -> FileType {
+		match self.content {
+			Content::File(_) => FileType::Regular,
+			Content::Directory(_) => FileType::Directory,
+			Content::Symlink(_) => FileType::Symlink,
+		}
+	}
+
+	fn attr(&self) -> FileAttr {
+		let (typ, size, nlink) = match &self.content {
+			Content::File(data) => (S_IFREG, data.len() as u64, self.nlink),
+			Content::Symlink(target) => (S_IFLNK, target.len() as u64, self.nlink),
+			Content::Directory(entries) => {
+				// every subdirectory links back to its parent with `..`
+				let subdirs = entries
+					.values()
+					.filter(|node| node.lock().file_type() == FileType::Directory)
+					.count();
+				(S_IFDIR, BLOCK_SIZE, 2 + subdirs as u64)
+			}
+		};
+
+		FileAttr {
+			ino: self.ino,
+			mode: typ | self.perm,
+			nlink,
+			size,
+			blksize: BLOCK_SIZE,
+			blocks: (size + 511) / 512,
+			atime: self.atime,
+			mtime: self.mtime,
+			ctime: self.ctime,
+			..Default::default()
+		}
+	}
+
+	/// Marks the node as modified
+	fn touch(&mut self) {
+		let now = Timespec::now();
+		self.mtime = now;
+		self.ctime = now;
+	}
+}
+
+/// Resizes the content of a file to `len` bytes and fills new bytes with zeros.
+/// The size is chosen by the application, so a failed allocation is reported instead of aborting the kernel.
+fn resize(data: &mut Vec<u8>, len: u64) -> Result<(), FileError> {
+	let len = match usize::try_from(len) {
+		Ok(len) if len <= isize::MAX as usize => len,
+		_ => return Err(FileError::EFBIG()),
+	};
+	if len > data.len() {
+		data.try_reserve(len - data.len())
+			.map_err(|_| FileError::ENOSPC())?;
+	}
+	data.resize(len, 0);
+
+	Ok(())
+}
+
+/// Splits `path` into the path of its parent directory and the name of the last component.
+fn split_parent(path: &str) -> (&str, &str) {
+	match path.rfind('/') {
+		Some(idx) => (&path[..idx], &path[idx + 1..]),
+		None => ("", path),
+	}
+}
+
+/// Returns true, if `path` is inside the directory `dir`, directly or in a subdirectory.
+fn contains(dir: &str, path: &str) -> bool {
+	let dir = dir.trim_matches('/');
+	let path = path.trim_matches('/');
+	dir != path
+		&& (dir.is_empty()
+			|| (path.starts_with(dir) && path.as_bytes().get(dir.len()) == Some(&b'/')))
+}
+
+/// Returns the entries of a directory.
+fn entries(node: &mut Node) -> Result<&mut BTreeMap<String, NodeRef>, FileError> {
+	match &mut node.content {
+		Content::Directory(entries) => Ok(entries),
+		_ => Err(FileError::ENOTDIR()),
+	}
+}
+
+pub struct Tmpfs {
+	root: NodeRef,
+	next_ino: AtomicU64,
+}
+
+impl Tmpfs {
+	pub fn new() -> Self {
+		Self {
+			root: Arc::new(Spinlock::new(Node::new(
+				1,
+				0o1777,
+				Content::Directory(BTreeMap::new()),
+			))),
+			next_ino: AtomicU64::new(2),
+		}
+	}
+
+	fn new_node(&self, perm: u32, content: Content) -> NodeRef {
+		let ino = self.next_ino.fetch_add(1, Ordering::Relaxed);
+		Arc::new(Spinlock::new(Node::new(ino, perm, content)))
+	}
+
+	/// Resolves a path relative to the root of the filesystem to its node.
+	fn lookup(&self, path: &str) -> Result<NodeRef, FileError> {
+		let mut node = self.root.clone();
+
+		for name in path.split('/').filter(|name| !name.is_empty()) {
+			let child = match &node.lock().content {
+				Content::Directory(entries) => {
+					entries.get(name).cloned().ok_or(FileError::ENOENT())?
+				}
+				_ => return Err(FileError::ENOTDIR()),
+			};
+			node = child;
+		}
+
+		Ok(node)
+	}
+
+	/// Resolves the parent directory of `path` and returns it together with the name of the last component.
+	fn lookup_parent<'a>(&self, path: &'a str) -> Result<(NodeRef, &'a str), FileError> {
+		let (parent, name) = split_parent(path);
+		if name.is_empty() {
+			// the root directory has no parent
+			return Err(FileError::EBUSY());
+		}
+
+		let parent = self.lookup(parent)?;
+		if parent.lock().file_type() != FileType::Directory {
+			return Err(FileError::ENOTDIR());
+		}
+
+		Ok((parent, name))
+	}
+
+	/// Inserts a new entry `name` into the directory `parent`. Fails, if the entry already exists.
+	fn insert(&self, parent: &NodeRef, name: &str, node: NodeRef) -> Result<(), FileError> {
+		let mut parent = parent.lock();
+		match &mut parent.content {
+			Content::Directory(entries) => {
+				if entries.contains_key(name) {
+					return Err(FileError::EEXIST());
+				}
+				entries.insert(name.to_owned(), node);
+			}
+			_ => return Err(FileError::ENOTDIR()),
+		}
+		parent.touch();
+
+		Ok(())
+	}
+
+	/// Removes the entry `name` of `parent`, if `check` accepts the node.
+	fn remove(
+		&self,
+		path: &str,
+		check: impl FnOnce(&Node) -> Result<(), FileError>,
+	) -> Result<(), FileError> {
+		let (parent, name) = self.lookup_parent(path)?;
+		let mut parent = parent.lock();
+		let entries = entries(&mut parent)?;
+		let (name, node) = entries.remove_entry(name).ok_or(FileError::ENOENT())?;
+		let checked = check(&node.lock());
+		if let Err(err) = checked {
+			entries.insert(name, node);
+			return Err(err);
+		}
+		parent.touch();
+
+		let mut node = node.lock();
+		node.nlink = node.nlink.saturating_sub(1);
+		node.ctime = Timespec::now();
+
+		Ok(())
+	}
+}
+
+impl Default for Tmpfs {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl PosixFileSystem for Tmpfs {
+	fn open(
+		&self,
+		path: &str,
+		perms: FilePerms,
+	) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		let node = match self.lookup(path) {
+			Ok(node) => {
+				if perms.creat && perms.excl {
+					return Err(FileError::EEXIST());
+				}
+				node
+			}
+			Err(FileError::ENOENT()) if perms.creat => {
+				let (parent, name) = self.lookup_parent(path)?;
+				let node = self.new_node(perms.mode, Content::File(Vec::new()));
+				self.insert(&parent, name, node.clone())?;
+				node
+			}
+			Err(err) => return Err(err),
+		};
+
+		let (file_type, locks) = {
+			let guard = node.lock();
+			(guard.file_type(), guard.locks.clone())
+		};
+		match file_type {
+			FileType::Directory => {
+				if perms.write {
+					return Err(FileError::EISDIR());
+				}
+				return Ok(Box::new(TmpDir {
+					node,
+					position: Spinlock::new(0),
+					locks,
+				}));
+			}
+			FileType::Symlink => return Err(FileError::ELOOP()),
+			_ => {}
+		}
+
+		if perms.trunc && perms.write {
+			let mut guard = node.lock();
+			if let Content::File(data) = &mut guard.content {
+				if !data.is_empty() {
+					data.clear();
+					guard.touch();
+				}
+			}
+		}
+
+		Ok(Box::new(TmpFile {
+			node,
+			locks,
+			offset: Spinlock::new(0),
+			// O_WRONLY is the only access mode, which does not allow reading
+			readable: perms.raw & 0b11 != 0b01,
+			writable: perms.write,
+			append: perms.append,
+		}))
+	}
+
+	fn unlink(&self, path: &str) -> Result<(), FileError> {
+		self.remove(path, |node| match node.content {
+			Content::Directory(_) => Err(FileError::EISDIR()),
+			_ => Ok(()),
+		})
+	}
+
+	fn opendir(&self, path: &str) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		let node = self.lookup(path)?;
+		let locks = {
+			let guard = node.lock();
+			if guard.file_type() != FileType::Directory {
+				return Err(FileError::ENOTDIR());
+			}
+			guard.locks.clone()
+		};
+
+		Ok(Box::new(TmpDir {
+			node,
+			position: Spinlock::new(0),
+			locks,
+		}))
+	}
+
+	fn mkdir(&self, path: &str, mode: u32) -> Result<(), FileError> {
+		let (parent, name) = self.lookup_parent(path)?;
+		let node = self.new_node(mode, Content::Directory(BTreeMap::new()));
+		self.insert(&parent, name, node)
+	}
+
+	fn rmdir(&self, path: &str) -> Result<(), FileError> {
+		self.remove(path, |node| match &node.content {
+			Content::Directory(entries) if entries.is_empty() => Ok(()),
+			Content::Directory(_) => Err(FileError::ENOTEMPTY()),
+			_ => Err(FileError::ENOTDIR()),
+		})
+	}
+
+	fn lstat(&self, path: &str) -> Result<FileAttr, FileError> {
+		Ok(self.lookup(path)?.lock().attr())
+	}
+
+	fn rename(&self, oldpath: &str, newpath: &str) -> Result<(), FileError> {
+		if oldpath == newpath {
+			return Ok(());
+		}
+		if contains(oldpath, newpath) {
+			// a directory cannot become a subdirectory of itself
+			return Err(FileError::EINVAL());
+		} else if contains(newpath, oldpath) {
+			// the replaced directory contains the renamed entry
+			return Err(FileError::ENOTEMPTY());
+		}
+
+		let (oldparent, oldname) = self.lookup_parent(oldpath)?;
+		let (newparent, newname) = self.lookup_parent(newpath)?;
+
+		// Both directories are locked for the whole operation. An ancestor is locked before its
+		// descendants, like during a lookup, unrelated directories in the order of their addresses.
+		let same = Arc::ptr_eq(&oldparent, &newparent);
+		let (olddir, newdir) = (split_parent(oldpath).0, split_parent(newpath).0);
+		let old_first = same
+			|| contains(olddir, newdir)
+			|| (!contains(newdir, olddir) && Arc::as_ptr(&oldparent) < Arc::as_ptr(&newparent));
+		let (mut first, mut second) = if old_first {
+			(oldparent.lock(), (!same).then(|| newparent.lock()))
+		} else {
+			(newparent.lock(), Some(oldparent.lock()))
+		};
+		// `new` is `None`, if both entries are in the same directory
+		let (old, mut new) = if old_first {
+			(&mut *first, second.as_deref_mut())
+		} else {
+			(second.as_deref_mut().unwrap(), Some(&mut *first))
+		};
+
+		let existing = match &mut new {
+			Some(new) => entries(new)?.get(newname).cloned(),
+			None => entries(old)?.get(newname).cloned(),
+		};
+		let node = entries(old)?
+			.get(oldname)
+			.cloned()
+			.ok_or(FileError::ENOENT())?;
+		let is_dir = node.lock().file_type() == FileType::Directory;
+
+		// check, whether the node is allowed to replace an existing entry
+		if let Some(existing) = existing {
+			if Arc::ptr_eq(&existing, &node) {
+				// both paths are hard links to the same file
+				return Ok(());
+			}
+			match (&existing.lock().content, is_dir) {
+				(Content::Directory(entries), true) if !entries.is_empty() => {
+					return Err(FileError::ENOTEMPTY())
+				}
+				(Content::Directory(_), true) => {}
+				(Content::Directory(_), false) => return Err(FileError::EISDIR()),
+				(_, true) => return Err(FileError::ENOTDIR()),
+				(_, false) => {}
+			}
+		}
+
+		entries(old)?.remove(oldname);
+		old.touch();
+		let replaced = match new {
+			Some(new) => {
+				new.touch();
+				entries(new)?.insert(newname.to_owned(), node.clone())
+			}
+			None => entries(old)?.insert(newname.to_owned(), node.clone()),
+		};
+		if let Some(replaced) = replaced {
+			let mut replaced = replaced.lock();
+			replaced.nlink = replaced.nlink.saturating_sub(1);
+		}
+		node.lock().ctime = Timespec::now();
+
+		Ok(())
+	}
+
+	fn link(&self, oldpath: &str, newpath: &str) -> Result<(), FileError> {
+		let node = self.lookup(oldpath)?;
+		if node.lock().file_type() == FileType::Directory {
+			return Err(FileError::EPERM());
+		}
+
+		let (parent, name) = self.lookup_parent(newpath)?;
+		self.insert(&parent, name, node.clone())?;
+
+		let mut node = node.lock();
+		node.nlink += 1;
+		node.ctime = Timespec::now();
+
+		Ok(())
+	}
+
+	fn symlink(&self, target: &str, linkpath: &str) -> Result<(), FileError> {
+		let (parent, name) = self.lookup_parent(linkpath)?;
+		let node = self.new_node(0o777, Content::Symlink(target.to_owned()));
+		self.insert(&parent, name, node)
+	}
+
+	fn readlink(&self, path: &str) -> Result<String, FileError> {
+		match &self.lookup(path)?.lock().content {
+			Content::Symlink(target) => Ok(target.clone()),
+			_ => Err(FileError::EINVAL()),
+		}
+	}
+}
+
+struct TmpFile {
+	node: NodeRef,
+	/// Locks of the node, which may be waited for without holding the lock of the node
+	locks: Arc<LockTable>,
+	offset: Spinlock<usize>,
+	readable: bool,
+	writable: bool,
+	append: bool,
+}
+
+impl TmpFile {
+	/// Copies the content at `offset` into `buf` and returns the number of copied bytes.
+	fn read_at(&self, buf: &mut [u8], offset: usize) -> Result<usize, FileError> {
+		if !self.readable {
+			return Err(FileError::EBADF());
+		}
+
+		let mut node = self.node.lock();
+		let len = match &node.content {
+			Content::File(data) => {
+				let start = offset.min(data.len());
+				let end = start.saturating_add(buf.len()).min(data.len());
+				buf[..end - start].copy_from_slice(&data[start..end]);
+				end - start
+			}
+			_ => return Err(FileError::EISDIR()),
+		};
+		node.atime = Timespec::now();
+
+		Ok(len)
+	}
+
+	/// Writes `buf` at `offset` or, if no offset is given, at the end of the file.
+	/// Returns the offset behind the written data.
+	fn write_at(&self, buf: &[u8], offset: Option<usize>) -> Result<usize, FileError> {
+		if !self.writable {
+			return Err(FileError::EBADF());
+		}
+
+		let mut node = self.node.lock();
+		let end = match &mut node.content {
+			Content::File(data) => {
+				let start = offset.unwrap_or_else(|| data.len());
+				let end = start.checked_add(buf.len()).ok_or(FileError::EFBIG())?;
+				if end > data.len() {
+					// writing behind the end of the file fills the gap with zeros
+					resize(data, end as u64)?;
+				}
+				data[start..end].copy_from_slice(buf);
+				end
+			}
+			_ => return Err(FileError::EISDIR()),
+		};
+		node.touch();
+
+		Ok(end)
+	}
+}
+
+impl PosixFile for TmpFile {
+	fn close(&self) -> Result<(), FileError> {
+		self.locks.release(self.lock_owner());
+		Ok(())
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		let mut offset = self.offset.lock();
+		let len = self.read_at(buf, *offset)?;
+		*offset += len;
+
+		Ok(len)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		let mut offset = self.offset.lock();
+		*offset = self.write_at(buf, if self.append { None } else { Some(*offset) })?;
+
+		Ok(buf.len() as u64)
+	}
+
+	fn pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		self.read_at(buf, offset as usize)
+	}
+
+	fn pwrite(&self, buf: &[u8], offset: u64) -> Result<u64, FileError> {
+		self.write_at(buf, Some(offset as usize))?;
+
+		Ok(buf.len() as u64)
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let mut position = self.offset.lock();
+		let size = match &self.node.lock().content {
+			Content::File(data) => data.len(),
+			_ => return Err(FileError::EINVAL()),
+		};
+		*position = seek_position(*position, offset, whence, size)?;
+
+		Ok(*position)
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(self.node.lock().attr())
+	}
+
+	fn ftruncate(&self, len: u64) -> Result<(), FileError> {
+		if !self.writable {
+			return Err(FileError::EINVAL());
+		}
+
+		let mut node = self.node.lock();
+		match &mut node.content {
+			Content::File(data) => resize(data, len)?,
+			_ => return Err(FileError::EINVAL()),
+		}
+		node.touch();
+
+		Ok(())
+	}
+
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		// there is no storage behind the tmpfs
+		Ok(())
+	}
+
+	fn fallocate(&self, mode: u32, offset: u64, len: u64) -> Result<(), FileError> {
+		if !self.writable {
+			return Err(FileError::EBADF());
+		} else if mode & !(FALLOC_FL_KEEP_SIZE | FALLOC_FL_PUNCH_HOLE | FALLOC_FL_ZERO_RANGE) != 0 {
+			return Err(FileError::EOPNOTSUPP());
+		}
+
+		let start = offset as usize;
+		let end = start.checked_add(len as usize).ok_or(FileError::EFBIG())?;
+		let mut node = self.node.lock();
+		match &mut node.content {
+			Content::File(data) => {
+				// files are not sparse, so a hole is filled with zeros just like a zeroed range
+				if mode & (FALLOC_FL_PUNCH_HOLE | FALLOC_FL_ZERO_RANGE) != 0 {
+					let zero_end = end.min(data.len());
+					if start < zero_end {
+						data[start..zero_end].fill(0);
+					}
+				}
+				if mode & FALLOC_FL_KEEP_SIZE == 0 && end > data.len() {
+					resize(data, end as u64)?;
+				}
+			}
+			_ => return Err(FileError::ENODEV()),
+		}
+		node.touch();
+
+		Ok(())
+	}
+
+	fn getlk(&self, lock: &FileLock) -> Result<Option<FileLock>, FileError> {
+		Ok(self.locks.get(lock))
+	}
+
+	fn setlk(&self, lock: &FileLock, wait: bool) -> Result<(), FileError> {
+		lock::check_access(lock, self.readable, self.writable)?;
+		self.locks.set(lock, wait)
+	}
+
+	fn flock(&self, typ: LockType, wait: bool) -> Result<(), FileError> {
+		self.locks.flock(typ, self.lock_owner(), wait)
+	}
+
+	fn release_locks(&self, owner: LockOwner) {
+		self.locks.release(owner);
+	}
+}
+
+struct TmpDir {
+	node: NodeRef,
+	/// Index of the next entry, including `.` and `..`
+	position: Spinlock<u64>,
+	locks: Arc<LockTable>,
+}
+
+impl PosixFile for TmpDir {
+	fn close(&self) -> Result<(), FileError> {
+		self.locks.release(self.lock_owner());
+		Ok(())
+	}
+
+	fn read(&self, _buf: &mut [u8]) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn pread(&self, _buf: &mut [u8], _offset: u64) -> Result<usize, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn write(&self, _buf: &[u8]) -> Result<u64, FileError> {
+		Err(FileError::EISDIR())
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let mut position = self.position.lock();
+		match whence {
+			SeekWhence::Set if offset >= 0 => *position = offset as u64,
+			SeekWhence::Cur if offset == 0 => {}
+			_ => return Err(FileError::EINVAL()),
+		}
+
+		Ok(*position as usize)
+	}
+
+	fn fsync(&self, _datasync: bool) -> Result<(), FileError> {
+		Ok(())
+	}
+
+	fn readdir(&self) -> Result<Vec<DirEntry>, FileError> {
+		let mut position = self.position.lock();
+		let node = self.node.lock();
+		let entries = match &node.content {
+			Content::Directory(entries) => entries,
+			_ => return Err(FileError::ENOTDIR()),
+		};
+
+		// The parent is not known to the directory, but `..` only has to exist in the listing.
+		let dots = [
+			(".", node.ino, FileType::Directory),
+			("..", 0, FileType::Directory),
+		];
+		let batch: Vec<DirEntry> = dots
+			.iter()
+			.map(|(name, ino, file_type)| ((*name).to_owned(), *ino, *file_type))
+			.chain(entries.iter().map(|(name, child)| {
+				let child = child.lock();
+				(name.clone(), child.ino, child.file_type())
+			}))
+			.enumerate()
+			.skip(*position as usize)
+			.map(|(idx, (name, ino, file_type))| DirEntry {
+				ino,
+				offset: idx as u64 + 1,
+				file_type,
+				name,
+			})
+			.collect();
+
+		*position += batch.len() as u64;
+		Ok(batch)
+	}
+
+	fn fstat(&self) -> Result<FileAttr, FileError> {
+		Ok(self.node.lock().attr())
+	}
+
+	fn flock(&self, typ: LockType, wait: bool) -> Result<(), FileError> {
+		self.locks.flock(typ, self.lock_owner(), wait)
+	}
+}
+
+#[cfg(not(any(target_os = "hermit", target_os = "none")))]
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn create() -> FilePerms {
+		FilePerms {
+			write: true,
+			creat: true,
+			raw: 0o2,
+			mode: 0o644,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_read_write_truncate() {
+		let fs = Tmpfs::new();
+		let file = fs.open("/file", create()).unwrap();
+		assert_eq!(file.write(b"hello world"), Ok(11));
+
+		let mut buf = [0u8; 16];
+		assert_eq!(file.pread(&mut buf, 6), Ok(5));
+		assert_eq!(&buf[..5], b"world");
+		assert_eq!(file.lseek(0, SeekWhence::Set), Ok(0));
+		assert_eq!(file.read(&mut buf), Ok(11));
+		assert_eq!(file.read(&mut buf), Ok(0));
+
+		// shrinking cuts off the content, growing fills the gap with zeros
+		file.ftruncate(5).unwrap();
+		file.ftruncate(8).unwrap();
+		assert_eq!(file.fstat().unwrap().size, 8);
+		assert_eq!(file.pread(&mut buf, 0), Ok(8));
+		assert_eq!(&buf[..8], b"hello\0\0\0");
+		file.close().unwrap();
+
+		// the content outlives the open file
+		let file = fs.open("/file", FilePerms::default()).unwrap();
+		assert_eq!(file.read(&mut buf), Ok(8));
+		assert_eq!(file.write(b"x").err(), Some(FileError::EBADF()));
+		file.close().unwrap();
+	}
+
+	#[test]
+	fn test_rename_and_rmdir() {
+		let fs = Tmpfs::new();
+		fs.mkdir("/a", 0o755).unwrap();
+		fs.mkdir("/b", 0o755).unwrap();
+		fs.open("/a/file", create()).unwrap().close().unwrap();
+
+		fs.rename("/a/file", "/b/file").unwrap();
+		assert_eq!(fs.lstat("/a/file").err(), Some(FileError::ENOENT()));
+		assert!(fs.lstat("/b/file").is_ok());
+
+		assert_eq!(fs.rename("/b", "/b/c"), Err(FileError::EINVAL()));
+		assert_eq!(fs.rename("/b/file", "/b"), Err(FileError::ENOTEMPTY()));
+		assert_eq!(fs.rename("/a", "/b"), Err(FileError::ENOTEMPTY()));
+		assert_eq!(fs.rename("/a", "/b/file/a"), Err(FileError::ENOTDIR()));
+		assert_eq!(fs.rename("/b/file", "/a"), Err(FileError::EISDIR()));
+
+		assert_eq!(fs.rmdir("/b"), Err(FileError::ENOTEMPTY()));
+		assert_eq!(fs.rmdir("/b/file"), Err(FileError::ENOTDIR()));
+		fs.unlink("/b/file").unwrap();
+		fs.rmdir("/b").unwrap();
+		assert_eq!(fs.lstat("/b").err(), Some(FileError::ENOENT()));
+	}
+}
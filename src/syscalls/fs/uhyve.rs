@@ -0,0 +1,447 @@
+//! Files of the host, which are accessed through the hypercalls of uhyve
+//!
+//! uhyve forwards open, read, write, lseek, close and unlink to the host. The host assigns
+//! its own descriptors to open files, which are wrapped by `UhyveFile`. Positional I/O is
+//! emulated by seeking. All other operations (e.g. stat) are not supported, since uhyve
+//! does not forward them.
+//!
+//! Reads go through the page cache. The cached pages of a file are dropped, when it is opened
+//! again, so that modifications by the host are visible after reopening the file.
+
+use crate::arch::mm::paging;
+use crate::arch::mm::{PhysAddr, VirtAddr};
+use crate::synch::spinlock::Spinlock;
+use crate::syscalls::fs::pagecache::{self, PageKey, PAGE_CACHE, PAGE_SIZE};
+use crate::syscalls::fs::{FileError, FilePerms, PosixFile, PosixFileSystem, SeekWhence};
+use crate::syscalls::interfaces::uhyve_send;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+const UHYVE_PORT_WRITE: u16 = 0x400;
+const UHYVE_PORT_OPEN: u16 = 0x440;
+const UHYVE_PORT_CLOSE: u16 = 0x480;
+const UHYVE_PORT_READ: u16 = 0x500;
+const UHYVE_PORT_LSEEK: u16 = 0x580;
+const UHYVE_PORT_UNLINK: u16 = 0x840;
+
+/// Maximum readahead, which is read by a single hypercall
+const MAX_READAHEAD_PAGES: usize = 32;
+
+const SEEK_SET: i32 = 0;
+const SEEK_END: i32 = 2;
+const SEEK_DATA: i32 = 3;
+const SEEK_HOLE: i32 = 4;
+
+#[repr(C, packed)]
+struct SysUnlink {
+	name: PhysAddr,
+	ret: i32,
+}
+
+impl SysUnlink {
+	fn new(name: VirtAddr) -> SysUnlink {
+		SysUnlink {
+			name: paging::virtual_to_physical(name),
+			ret: -1,
+		}
+	}
+}
+
+#[repr(C, packed)]
+struct SysOpen {
+	name: PhysAddr,
+	flags: i32,
+	mode: i32,
+	ret: i32,
+}
+
+impl SysOpen {
+	fn new(name: VirtAddr, flags: i32, mode: i32) -> SysOpen {
+		SysOpen {
+			name: paging::virtual_to_physical(name),
+			flags,
+			mode,
+			ret: -1,
+		}
+	}
+}
+
+#[repr(C, packed)]
+struct SysClose {
+	fd: i32,
+	ret: i32,
+}
+
+impl SysClose {
+	fn new(fd: i32) -> SysClose {
+		SysClose { fd, ret: -1 }
+	}
+}
+
+#[repr(C, packed)]
+struct SysRead {
+	fd: i32,
+	buf: *const u8,
+	len: usize,
+	ret: isize,
+}
+
+impl SysRead {
+	fn new(fd: i32, buf: *const u8, len: usize) -> SysRead {
+		SysRead {
+			fd,
+			buf,
+			len,
+			ret: -1,
+		}
+	}
+}
+
+#[repr(C, packed)]
+struct SysWrite {
+	fd: i32,
+	buf: *const u8,
+	len: usize,
+}
+
+impl SysWrite {
+	fn new(fd: i32, buf: *const u8, len: usize) -> SysWrite {
+		SysWrite { fd, buf, len }
+	}
+}
+
+#[repr(C, packed)]
+struct SysLseek {
+	fd: i32,
+	offset: isize,
+	whence: i32,
+}
+
+impl SysLseek {
+	fn new(fd: i32, offset: isize, whence: i32) -> SysLseek {
+		SysLseek { fd, offset, whence }
+	}
+}
+
+/// Translates the return value of a hypercall into a `FileError`.
+/// uhyve reports failures as negated errno.
+fn check(ret: isize) -> Result<usize, FileError> {
+	if ret < 0 {
+		Err(FileError::from_errno(-ret as i32))
+	} else {
+		Ok(ret as usize)
+	}
+}
+
+fn host_open(path: &str, flags: i32, mode: i32) -> Result<i32, FileError> {
+	// the host expects a NUL-terminated string
+	let mut name = String::from(path);
+	name.push('\0');
+
+	let mut sysopen = SysOpen::new(VirtAddr(name.as_ptr() as u64), flags, mode);
+	uhyve_send(UHYVE_PORT_OPEN, &mut sysopen);
+
+	check(sysopen.ret as isize).map(|fd| fd as i32)
+}
+
+fn host_close(fd: i32) -> Result<(), FileError> {
+	let mut sysclose = SysClose::new(fd);
+	uhyve_send(UHYVE_PORT_CLOSE, &mut sysclose);
+
+	check(sysclose.ret as isize).map(|_| ())
+}
+
+fn host_lseek(fd: i32, offset: isize, whence: i32) -> Result<usize, FileError> {
+	let mut syslseek = SysLseek::new(fd, offset, whence);
+	uhyve_send(UHYVE_PORT_LSEEK, &mut syslseek);
+
+	check(syslseek.offset)
+}
+
+/// Writes `buf` to the host file `fd` at its current offset.
+pub(super) fn host_write(fd: i32, buf: &[u8]) -> Result<u64, FileError> {
+	let mut syswrite = SysWrite::new(fd, buf.as_ptr(), buf.len());
+	uhyve_send(UHYVE_PORT_WRITE, &mut syswrite);
+
+	// uhyve returns the number of written bytes in `len`
+	check(syswrite.len as isize).map(|len| len as u64)
+}
+
+/// The host's filesystem as seen by uhyve. Paths are passed to the host with `prefix` prepended,
+/// so that a mount at `/` can forward absolute paths and a mount at the working directory
+/// can forward paths relative to the working directory of uhyve.
+pub struct UhyveFs {
+	prefix: &'static str,
+	/// Mount of the filesystem, which is part of the keys of cached pages
+	mount: u64,
+	/// uhyve does not report inode numbers, so the pages of a file are cached under an id of its path.
+	inodes: Spinlock<BTreeMap<String, u64>>,
+}
+
+impl UhyveFs {
+	pub fn new(prefix: &'static str) -> Self {
+		Self {
+			prefix,
+			mount: pagecache::new_mount_id(),
+			inodes: Spinlock::new(BTreeMap::new()),
+		}
+	}
+
+	fn host_path(&self, path: &str) -> String {
+		let mut host_path = String::from(self.prefix);
+		host_path.push_str(path);
+		host_path
+	}
+
+	/// Returns the id of the file at `host_path`, under which its pages are cached.
+	fn inode(&self, host_path: &str) -> u64 {
+		static NEXT_INODE: AtomicU64 = AtomicU64::new(1);
+
+		*self
+			.inodes
+			.lock()
+			.entry(String::from(host_path))
+			.or_insert_with(|| NEXT_INODE.fetch_add(1, Ordering::Relaxed))
+	}
+}
+
+impl PosixFileSystem for UhyveFs {
+	fn open(
+		&self,
+		path: &str,
+		perms: FilePerms,
+	) -> Result<Box<dyn PosixFile + Send + Sync>, FileError> {
+		let host_path = self.host_path(path);
+		let fd = host_open(&host_path, perms.raw as i32, perms.mode as i32)?;
+
+		// The file may have been modified by the host since it has been cached.
+		let ino = self.inode(&host_path);
+		PAGE_CACHE.lock().invalidate(self.mount, ino);
+
+		Ok(Box::new(UhyveFile {
+			fd,
+			mount: self.mount,
+			ino,
+			append: perms.append,
+			cached: !perms.directio,
+			offset: AtomicUsize::new(0),
+			host: Spinlock::new(()),
+			ra_next: AtomicU64::new(0),
+			ra_pages: AtomicUsize::new(1),
+		}))
+	}
+
+	fn unlink(&self, path: &str) -> Result<(), FileError> {
+		let mut name = self.host_path(path);
+		if let Some(ino) = self.inodes.lock().remove(&name) {
+			PAGE_CACHE.lock().invalidate(self.mount, ino);
+		}
+		name.push('\0');
+
+		let mut sysunlink = SysUnlink::new(VirtAddr(name.as_ptr() as u64));
+		uhyve_send(UHYVE_PORT_UNLINK, &mut sysunlink);
+
+		check(sysunlink.ret as isize).map(|_| ())
+	}
+}
+
+/// A file, which is opened by the host. Reads go through the page cache, writes go directly
+/// to the host. Thus, the cache never contains dirty pages of these files.
+struct UhyveFile {
+	/// Descriptor of the file on the host
+	fd: i32,
+	mount: u64,
+	ino: u64,
+	/// Writes always go to the end of the file (O_APPEND)
+	append: bool,
+	/// Reads go through the page cache, unless the file has been opened with O_DIRECT.
+	cached: bool,
+	/// The offset is maintained by the kernel, so that cached reads do not need the host.
+	offset: AtomicUsize,
+	/// uhyve does not forward positional I/O, so each transfer moves the offset of the
+	/// host descriptor first. This lock serializes these transfers.
+	host: Spinlock<()>,
+	/// Page, which is expected to be read next by sequential reads
+	ra_next: AtomicU64,
+	/// Size of the last readahead in pages
+	ra_pages: AtomicUsize,
+}
+
+impl UhyveFile {
+	fn page_key(&self, index: u64) -> PageKey {
+		PageKey {
+			mount: self.mount,
+			ino: self.ino,
+			index,
+		}
+	}
+
+	/// Reads from the host at `offset` without caching the data.
+	fn read_direct(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		let _guard = self.host.lock();
+		host_lseek(self.fd, offset as isize, SEEK_SET)?;
+
+		let mut sysread = SysRead::new(self.fd, buf.as_ptr(), buf.len());
+		uhyve_send(UHYVE_PORT_READ, &mut sysread);
+
+		check(sysread.ret)
+	}
+
+	/// Writes to the host at `offset`, or at the end of the file for O_APPEND.
+	/// Returns the number of written bytes and the offset behind them.
+	fn write_direct(&self, buf: &[u8], offset: u64) -> Result<(u64, usize), FileError> {
+		let guard = self.host.lock();
+		let position = if self.append {
+			host_lseek(self.fd, 0, SEEK_END)?
+		} else {
+			host_lseek(self.fd, offset as isize, SEEK_SET)?
+		};
+		let ret = host_write(self.fd, buf);
+		drop(guard);
+
+		// the cached pages are outdated now
+		PAGE_CACHE.lock().invalidate(self.mount, self.ino);
+		let len = ret?;
+		Ok((len, position + len as usize))
+	}
+
+	/// Returns the number of pages to read starting at page `index`, if `wanted` pages are requested.
+	/// The readahead doubles as long as the file is read sequentially.
+	fn readahead_pages(&self, index: u64, wanted: usize) -> usize {
+		let pages = if index == self.ra_next.load(Ordering::Relaxed) {
+			(self.ra_pages.load(Ordering::Relaxed) * 2).max(wanted)
+		} else {
+			wanted
+		}
+		.clamp(1, MAX_READAHEAD_PAGES);
+		self.ra_pages.store(pages, Ordering::Relaxed);
+
+		pages
+	}
+
+	/// Reads up to `pages` pages starting at page `index` by a single hypercall and caches them.
+	/// Returns the content of page `index`, which is shorter than a page at the end of the file.
+	fn fill(&self, index: u64, pages: usize) -> Result<Vec<u8>, FileError> {
+		let count = {
+			let cache = PAGE_CACHE.lock();
+			(1..pages)
+				.find(|i| cache.contains(self.page_key(index + *i as u64)))
+				.unwrap_or(pages)
+		};
+
+		let mut data = vec![0; count * PAGE_SIZE];
+		let len = self.read_direct(&mut data, index * PAGE_SIZE as u64)?;
+
+		let mut cache = PAGE_CACHE.lock();
+		for i in 0..count {
+			let start = i * PAGE_SIZE;
+			let end = len.min(start + PAGE_SIZE);
+			if start > len {
+				break;
+			}
+			// a short (or empty) page marks the end of the file
+			cache.insert(self.page_key(index + i as u64), &data[start..end]);
+			if end - start < PAGE_SIZE {
+				break;
+			}
+		}
+		drop(cache);
+
+		data.truncate(len.min(PAGE_SIZE));
+		Ok(data)
+	}
+
+	fn cached_pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		let mut total = 0;
+		while total < buf.len() {
+			let position = offset + total as u64;
+			let index = position / PAGE_SIZE as u64;
+			let in_page = (position % PAGE_SIZE as u64) as usize;
+			let remaining = buf.len() - total;
+			let chunk = &mut buf[total..total + remaining.min(PAGE_SIZE - in_page)];
+
+			let cached = PAGE_CACHE.lock().read(self.page_key(index), in_page, chunk);
+			let len = match cached {
+				Some(len) => len,
+				None => {
+					let wanted = align_up!(in_page + remaining, PAGE_SIZE) / PAGE_SIZE;
+					let page = match self.fill(index, self.readahead_pages(index, wanted)) {
+						Ok(page) => page,
+						Err(_) if total > 0 => break,
+						Err(err) => return Err(err),
+					};
+					let len = chunk.len().min(page.len().saturating_sub(in_page));
+					chunk[..len].copy_from_slice(&page[in_page..in_page + len]);
+					len
+				}
+			};
+			self.ra_next.store(index + 1, Ordering::Relaxed);
+
+			total += len;
+			// a short page means the end of the file
+			if len < chunk.len() {
+				break;
+			}
+		}
+
+		Ok(total)
+	}
+}
+
+impl PosixFile for UhyveFile {
+	fn close(&self) -> Result<(), FileError> {
+		host_close(self.fd)
+	}
+
+	fn read(&self, buf: &mut [u8]) -> Result<usize, FileError> {
+		let len = self.pread(buf, self.offset.load(Ordering::Relaxed) as u64)?;
+		self.offset.fetch_add(len, Ordering::Relaxed);
+		Ok(len)
+	}
+
+	fn write(&self, buf: &[u8]) -> Result<u64, FileError> {
+		let (len, end) = self.write_direct(buf, self.offset.load(Ordering::Relaxed) as u64)?;
+		self.offset.store(end, Ordering::Relaxed);
+		Ok(len)
+	}
+
+	fn lseek(&self, offset: isize, whence: SeekWhence) -> Result<usize, FileError> {
+		let position = match whence {
+			SeekWhence::Set => offset,
+			SeekWhence::Cur => (self.offset.load(Ordering::Relaxed) as isize)
+				.checked_add(offset)
+				.ok_or(FileError::EOVERFLOW())?,
+			// these depend on the file on the host
+			SeekWhence::End | SeekWhence::Data | SeekWhence::Hole => {
+				let whence = match whence {
+					SeekWhence::End => SEEK_END,
+					SeekWhence::Data => SEEK_DATA,
+					_ => SEEK_HOLE,
+				};
+				let _guard = self.host.lock();
+				host_lseek(self.fd, offset, whence)? as isize
+			}
+		};
+		if position < 0 {
+			return Err(FileError::EINVAL());
+		}
+
+		self.offset.store(position as usize, Ordering::Relaxed);
+		Ok(position as usize)
+	}
+
+	fn pread(&self, buf: &mut [u8], offset: u64) -> Result<usize, FileError> {
+		if self.cached {
+			self.cached_pread(buf, offset)
+		} else {
+			self.read_direct(buf, offset)
+		}
+	}
+
+	fn pwrite(&self, buf: &[u8], offset: u64) -> Result<u64, FileError> {
+		self.write_direct(buf, offset).map(|(len, _)| len)
+	}
+}
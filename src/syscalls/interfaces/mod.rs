@@ -1,14 +1,22 @@
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::convert::{TryFrom, TryInto};
 use core::{isize, ptr, slice, str};
 
 use crate::arch;
-use crate::console::CONSOLE;
+use crate::arch::percore::core_scheduler;
 use crate::environment;
 use crate::errno::*;
 use crate::ffi::CStr;
-use crate::syscalls::fs::{self, FilePerms, PosixFile, SeekWhence};
+use crate::syscalls::fs::lock;
+use crate::syscalls::fs::poll::{self, Registration, Waiter, POLLERR, POLLHUP, POLLNVAL};
+use crate::syscalls::fs::{
+	self, DirEntry, EpollEvent, FileAttr, FileError, FileLock, FilePerms, LockOwner, LockType,
+	PosixFile, SeekWhence, TimerSetting, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE,
+	FALLOC_FL_ZERO_RANGE,
+};
+use crate::syscalls::{timespec, CLOCK_MONOTONIC, CLOCK_REALTIME};
 
 pub use self::generic::*;
 pub use self::uhyve::*;
@@ -19,6 +27,8 @@ mod uhyve;
 const SEEK_SET: i32 = 0;
 const SEEK_CUR: i32 = 1;
 const SEEK_END: i32 = 2;
+const SEEK_DATA: i32 = 3;
+const SEEK_HOLE: i32 = 4;
 
 impl TryFrom<i32> for SeekWhence {
 	type Error = &'static str;
@@ -28,6 +38,8 @@ impl TryFrom<i32> for SeekWhence {
 			SEEK_CUR => Ok(SeekWhence::Cur),
 			SEEK_SET => Ok(SeekWhence::Set),
 			SEEK_END => Ok(SeekWhence::End),
+			SEEK_DATA => Ok(SeekWhence::Data),
+			SEEK_HOLE => Ok(SeekWhence::Hole),
 			_ => Err("Got invalid seek whence parameter!"),
 		}
 	}
@@ -41,7 +53,47 @@ const O_CREAT: i32 = 0o0100;
 const O_EXCL: i32 = 0o0200;
 const O_TRUNC: i32 = 0o1000;
 const O_APPEND: i32 = 0o2000;
+const O_NONBLOCK: i32 = 0o4000;
 const O_DIRECT: i32 = 0o40000;
+const O_DIRECTORY: i32 = 0o200000;
+const O_CLOEXEC: i32 = 0o2000000;
+
+const IOV_MAX: i32 = 1024;
+
+/// Commands of fcntl, which operate on record locks
+const F_GETLK: i32 = 5;
+const F_SETLK: i32 = 6;
+const F_SETLKW: i32 = 7;
+const F_OFD_GETLK: i32 = 36;
+const F_OFD_SETLK: i32 = 37;
+const F_OFD_SETLKW: i32 = 38;
+
+/// Values of `Flock::l_type`
+const F_RDLCK: i16 = 0;
+const F_WRLCK: i16 = 1;
+const F_UNLCK: i16 = 2;
+
+/// Operations of flock
+const LOCK_SH: i32 = 1;
+const LOCK_EX: i32 = 2;
+const LOCK_NB: i32 = 4;
+const LOCK_UN: i32 = 8;
+
+/// Operations of epoll_ctl
+const EPOLL_CTL_ADD: i32 = 1;
+const EPOLL_CTL_DEL: i32 = 2;
+const EPOLL_CTL_MOD: i32 = 3;
+
+/// Flags of eventfd, EFD_NONBLOCK and EFD_CLOEXEC equal O_NONBLOCK and O_CLOEXEC
+const EFD_SEMAPHORE: i32 = 1;
+
+/// Flags of timerfd_settime
+const TFD_TIMER_ABSTIME: i32 = 1;
+const TFD_TIMER_CANCEL_ON_SET: i32 = 2;
+
+const RLIMIT_NOFILE: i32 = 7;
+const RLIM_NLIMITS: i32 = 16;
+const RLIM_INFINITY: u64 = u64::MAX;
 
 fn open_flags_to_perm(flags: i32, mode: u32) -> FilePerms {
 	// mode is passed in as hex (0x777). Linux/Fuse expects octal (0o777).
@@ -68,12 +120,347 @@ fn open_flags_to_perm(flags: i32, mode: u32) -> FilePerms {
 	perms.trunc = flags & (O_TRUNC) != 0;
 	perms.append = flags & (O_APPEND) != 0;
 	perms.directio = flags & (O_DIRECT) != 0;
-	if flags & !(O_WRONLY | O_RDWR | O_CREAT | O_EXCL | O_TRUNC | O_APPEND | O_DIRECT) != 0 {
+	perms.directory = flags & (O_DIRECTORY) != 0;
+	if flags & !(O_WRONLY | O_RDWR | O_CREAT | O_EXCL | O_TRUNC | O_APPEND | O_DIRECT | O_DIRECTORY)
+		!= 0
+	{
 		warn!("Unknown file flags used! {}", flags);
 	}
 	perms
 }
 
+/// Header of a `struct linux_dirent64`, the name follows directly after `d_type`
+#[repr(C, packed)]
+struct Dirent64 {
+	d_ino: u64,
+	d_off: i64,
+	d_reclen: u16,
+	d_type: u8,
+}
+
+/// Serializes as many directory entries as fit into `buf`.
+/// Returns the number of entries and bytes written.
+fn write_dirents(entries: &[DirEntry], buf: &mut [u8]) -> (usize, usize) {
+	let mut written = 0;
+
+	for (i, entry) in entries.iter().enumerate() {
+		// header + name + terminating NUL, aligned to 8 bytes
+		let reclen = align_up!(
+			core::mem::size_of::<Dirent64>() + entry.name.len() + 1,
+			core::mem::size_of::<u64>()
+		);
+		if written + reclen > buf.len() {
+			return (i, written);
+		}
+
+		let dirent = Dirent64 {
+			d_ino: entry.ino,
+			d_off: entry.offset as i64,
+			d_reclen: reclen as u16,
+			d_type: entry.file_type as u8,
+		};
+		let record = &mut buf[written..written + reclen];
+		unsafe {
+			ptr::write_unaligned(record.as_mut_ptr() as *mut Dirent64, dirent);
+		}
+		let name_start = core::mem::size_of::<Dirent64>();
+		record[name_start..name_start + entry.name.len()].copy_from_slice(entry.name.as_bytes());
+		for b in &mut record[name_start + entry.name.len()..] {
+			*b = 0;
+		}
+
+		written += reclen;
+	}
+
+	(entries.len(), written)
+}
+
+/// Resource limit as used by `getrlimit` and `setrlimit`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rlimit {
+	pub rlim_cur: u64,
+	pub rlim_max: u64,
+}
+
+/// Buffer description for vectored I/O, laid out like `struct iovec`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IoVec {
+	pub iov_base: *mut u8,
+	pub iov_len: usize,
+}
+
+/// A file of interest of `poll`, laid out like `struct pollfd`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PollFd {
+	pub fd: i32,
+	pub events: i16,
+	pub revents: i16,
+}
+
+/// Setting of a timer, laid out like `struct itimerspec`
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ItimerSpec {
+	pub it_interval: timespec,
+	pub it_value: timespec,
+}
+
+/// Description of a record lock, laid out like `struct flock` on x86_64 Linux
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Flock {
+	pub l_type: i16,
+	pub l_whence: i16,
+	pub l_start: i64,
+	pub l_len: i64,
+	pub l_pid: i32,
+}
+
+/// File status as returned by `stat`, laid out like `struct stat` on x86_64 Linux
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct Stat {
+	pub st_dev: u64,
+	pub st_ino: u64,
+	pub st_nlink: u64,
+	pub st_mode: u32,
+	pub st_uid: u32,
+	pub st_gid: u32,
+	__pad0: i32,
+	pub st_rdev: u64,
+	pub st_size: i64,
+	pub st_blksize: i64,
+	pub st_blocks: i64,
+	pub st_atime: i64,
+	pub st_atime_nsec: i64,
+	pub st_mtime: i64,
+	pub st_mtime_nsec: i64,
+	pub st_ctime: i64,
+	pub st_ctime_nsec: i64,
+	__unused: [i64; 3],
+}
+
+impl From<&FileAttr> for Stat {
+	fn from(attr: &FileAttr) -> Self {
+		Self {
+			st_ino: attr.ino,
+			st_nlink: attr.nlink,
+			st_mode: attr.mode,
+			st_uid: attr.uid,
+			st_gid: attr.gid,
+			st_rdev: attr.rdev,
+			st_size: attr.size as i64,
+			st_blksize: attr.blksize as i64,
+			st_blocks: attr.blocks as i64,
+			st_atime: attr.atime.sec,
+			st_atime_nsec: attr.atime.nsec,
+			st_mtime: attr.mtime.sec,
+			st_mtime_nsec: attr.mtime.nsec,
+			st_ctime: attr.ctime.sec,
+			st_ctime_nsec: attr.ctime.nsec,
+			..Default::default()
+		}
+	}
+}
+
+/// Copies `attr` to the user-provided `st` and returns the result of a stat call.
+fn write_stat(attr: Result<FileAttr, FileError>, st: *mut Stat) -> i32 {
+	if st.is_null() {
+		return -EFAULT;
+	}
+
+	match attr {
+		Ok(attr) => {
+			unsafe {
+				*st = Stat::from(&attr);
+			}
+			0
+		}
+		Err(err) => -err.errno(),
+	}
+}
+
+/// Converts a NUL-terminated path, which is passed in by the application, to a string slice.
+fn path_from_ptr<'a>(path: *const u8) -> Result<&'a str, FileError> {
+	if path.is_null() {
+		return Err(FileError::EFAULT());
+	}
+	unsafe { CStr::from_ptr(path as _) }
+		.to_str()
+		.map_err(|_| FileError::EINVAL())
+}
+
+/// Converts the array of `iovcnt` buffer descriptions at `iov` to a slice and checks,
+/// that their total length does not overflow the return value of `readv`/`writev`.
+fn iovec_from_ptr<'a>(iov: *const IoVec, iovcnt: i32) -> Result<&'a [IoVec], FileError> {
+	if iovcnt < 0 || iovcnt > IOV_MAX {
+		return Err(FileError::EINVAL());
+	} else if iovcnt == 0 {
+		return Ok(&[]);
+	} else if iov.is_null() {
+		return Err(FileError::EFAULT());
+	}
+
+	let iov = unsafe { slice::from_raw_parts(iov, iovcnt as usize) };
+	iov.iter()
+		.try_fold(0usize, |total, vec| total.checked_add(vec.iov_len))
+		.filter(|total| *total <= isize::MAX as usize)
+		.ok_or(FileError::EINVAL())?;
+
+	Ok(iov)
+}
+
+/// Converts the user-provided `fl` into a lock of `owner`. The start of the lock is relative to
+/// `l_whence`, a negative `l_len` locks the bytes before the start and 0 extends the lock to the end of the file.
+fn lock_from_flock(
+	file: &(dyn PosixFile + Send + Sync),
+	fl: &Flock,
+	owner: LockOwner,
+) -> Result<FileLock, FileError> {
+	let typ = match fl.l_type {
+		F_RDLCK => LockType::Read,
+		F_WRLCK => LockType::Write,
+		F_UNLCK => LockType::Unlock,
+		_ => return Err(FileError::EINVAL()),
+	};
+	let base = match i32::from(fl.l_whence) {
+		SEEK_SET => 0,
+		SEEK_CUR => file.lseek(0, SeekWhence::Cur)? as i64,
+		SEEK_END => file.fstat()?.size as i64,
+		_ => return Err(FileError::EINVAL()),
+	};
+
+	let start = base.checked_add(fl.l_start).ok_or(FileError::EOVERFLOW())?;
+	let (start, end) = if fl.l_len > 0 {
+		let end = start
+			.checked_add(fl.l_len - 1)
+			.ok_or(FileError::EOVERFLOW())?;
+		(start, end)
+	} else if fl.l_len == 0 {
+		(start, i64::MAX)
+	} else {
+		let begin = start.checked_add(fl.l_len).ok_or(FileError::EOVERFLOW())?;
+		(begin, start - 1)
+	};
+	if start < 0 {
+		return Err(FileError::EINVAL());
+	}
+
+	Ok(FileLock {
+		typ,
+		start: start as u64,
+		end: end as u64,
+		owner,
+		pid: core_scheduler().get_current_task_id().into(),
+	})
+}
+
+/// Describes the conflicting lock `lock` in `fl`, as F_GETLK does.
+fn write_flock(lock: Option<FileLock>, fl: &mut Flock) {
+	let lock = match lock {
+		Some(lock) => lock,
+		None => {
+			fl.l_type = F_UNLCK;
+			return;
+		}
+	};
+
+	fl.l_type = match lock.typ {
+		LockType::Read => F_RDLCK,
+		LockType::Write => F_WRLCK,
+		LockType::Unlock => F_UNLCK,
+	};
+	fl.l_whence = SEEK_SET as i16;
+	fl.l_start = lock.start as i64;
+	fl.l_len = if lock.end >= lock::OFFSET_MAX {
+		0
+	} else {
+		(lock.end - lock.start + 1) as i64
+	};
+	// locks of open files do not belong to a process
+	fl.l_pid = if lock.owner == lock::PROCESS_LOCK_OWNER {
+		lock.pid as i32
+	} else {
+		-1
+	};
+}
+
+/// Converts a time into microseconds. Times, which are not a multiple of a microsecond, are rounded up,
+/// so that a short time does not disarm a timer.
+fn timespec_to_micros(ts: &timespec) -> Result<u64, FileError> {
+	if ts.tv_sec < 0 || ts.tv_nsec < 0 || ts.tv_nsec > 999_999_999 {
+		return Err(FileError::EINVAL());
+	}
+	(ts.tv_sec as u64)
+		.checked_mul(1_000_000)
+		.and_then(|micros| micros.checked_add((ts.tv_nsec as u64 + 999) / 1000))
+		.ok_or(FileError::EINVAL())
+}
+
+fn micros_to_timespec(micros: u64) -> timespec {
+	timespec {
+		tv_sec: (micros / 1_000_000) as i64,
+		tv_nsec: ((micros % 1_000_000) * 1000) as i64,
+	}
+}
+
+fn write_itimerspec(setting: TimerSetting, spec: *mut ItimerSpec) {
+	if let Some(spec) = unsafe { spec.as_mut() } {
+		spec.it_interval = micros_to_timespec(setting.interval);
+		spec.it_value = micros_to_timespec(setting.value);
+	}
+}
+
+/// Looks up the open file referenced by `fd`. The lock of the filesystem is released before returning,
+/// so that the caller does not block other file operations while accessing the file.
+fn get_file(fd: i32) -> Result<Arc<dyn PosixFile + Send + Sync>, FileError> {
+	if fd < 0 {
+		return Err(FileError::EBADF());
+	}
+	fs::FILESYSTEM.lock().get_file(fd as u64)
+}
+
+/// Returns true, if `fd` is a socket of the network stack instead of an open file of the kernel.
+#[cfg(feature = "newlib")]
+fn is_socket(fd: i32) -> bool {
+	fd >= 0 && fd & crate::syscalls::LWIP_FD_BIT != 0
+}
+
+/// The network stack of Rust applications does not use fds of the kernel for its sockets.
+#[cfg(not(feature = "newlib"))]
+fn is_socket(_fd: i32) -> bool {
+	false
+}
+
+/// Reads from the open file `fd`. Shared by the interfaces, which override `read` for other kinds of descriptors.
+fn read_file(fd: i32, buf: *mut u8, len: usize) -> isize {
+	if len > isize::MAX as usize {
+		return -EINVAL as isize;
+	}
+	let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+
+	match get_file(fd).and_then(|file| file.read(buf)) {
+		Ok(read_bytes) => read_bytes as isize,
+		Err(err) => -err.errno() as isize,
+	}
+}
+
+/// Writes to the open file `fd`. Shared by the interfaces, which override `write` for other kinds of descriptors.
+fn write_file(fd: i32, buf: *const u8, len: usize) -> isize {
+	if len > isize::MAX as usize {
+		return -EINVAL as isize;
+	}
+	let buf = unsafe { slice::from_raw_parts(buf, len) };
+
+	match get_file(fd).and_then(|file| file.write(buf)) {
+		Ok(written_bytes) => written_bytes as isize,
+		Err(err) => -err.errno() as isize,
+	}
+}
+
 pub trait SyscallInterface: Send + Sync {
 	fn init(&self) {
 		// Interface-specific initialization steps.
@@ -199,14 +586,16 @@ pub trait SyscallInterface: Send + Sync {
 
 	#[cfg(target_arch = "x86_64")]
 	fn unlink(&self, name: *const u8) -> i32 {
-		let name = unsafe { CStr::from_ptr(name as _) }.to_str().unwrap();
+		let name = match path_from_ptr(name) {
+			Ok(name) => name,
+			Err(err) => return -err.errno(),
+		};
 		debug!("unlink {}", name);
 
-		fs::FILESYSTEM
-			.lock()
-			.unlink(name)
-			.expect("Unlinking failed!"); // TODO: error handling
-		0
+		match fs::unlink(name) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
 	}
 
 	#[cfg(not(target_arch = "x86_64"))]
@@ -221,28 +610,341 @@ pub trait SyscallInterface: Send + Sync {
 		//! flags is bitmask of O_DEC_* defined above.
 		//! (taken from rust stdlib/sys hermit target )
 
-		let name = unsafe { CStr::from_ptr(name as _) }.to_str().unwrap();
+		let name = match path_from_ptr(name) {
+			Ok(name) => name,
+			Err(err) => return -err.errno(),
+		};
 		debug!("Open {}, {}, {}", name, flags, mode);
 
-		let mut fs = fs::FILESYSTEM.lock();
-		let fd = fs.open(name, open_flags_to_perm(flags, mode as u32));
+		match fs::open(name, open_flags_to_perm(flags, mode as u32)) {
+			Ok(fd) => fd as i32,
+			Err(err) => -err.errno(),
+		}
+	}
 
-		if let Ok(fd) = fd {
-			fd as i32
+	fn close(&self, fd: i32) -> i32 {
+		if fd < 0 {
+			return -EBADF;
+		}
+
+		match fs::close(fd as u64) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn dup(&self, fd: i32) -> i32 {
+		debug!("dup {}", fd);
+		if fd < 0 {
+			return -EBADF;
+		}
+
+		match fs::FILESYSTEM.lock().dup(fd as u64) {
+			Ok(newfd) => newfd as i32,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn dup2(&self, oldfd: i32, newfd: i32) -> i32 {
+		debug!("dup2 {} {}", oldfd, newfd);
+		if oldfd < 0 {
+			return -EBADF;
+		}
+
+		// a negative newfd exceeds any limit of fds and is rejected after validating oldfd
+		match fs::dup2(oldfd as u64, newfd as u64, false) {
+			Ok(newfd) => newfd as i32,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn dup3(&self, oldfd: i32, newfd: i32, flags: i32) -> i32 {
+		debug!("dup3 {} {} {:#x}", oldfd, newfd, flags);
+		// There is no exec, so O_CLOEXEC does not have any effect.
+		if flags & !O_CLOEXEC != 0 {
+			return -EINVAL;
+		} else if oldfd < 0 {
+			return -EBADF;
+		}
+
+		match fs::dup2(oldfd as u64, newfd as u64, true) {
+			Ok(newfd) => newfd as i32,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	/// Creates a pipe and stores the fds of its read end and its write end in `fds`.
+	/// O_NONBLOCK applies to both ends, O_CLOEXEC does not have any effect, since there is no exec.
+	fn pipe2(&self, fds: *mut i32, flags: i32) -> i32 {
+		debug!("pipe2 {:#x}", flags);
+		if fds.is_null() {
+			return -EFAULT;
+		} else if flags & !(O_CLOEXEC | O_NONBLOCK) != 0 {
+			return -EINVAL;
+		}
+
+		match fs::FILESYSTEM.lock().pipe(flags & O_NONBLOCK != 0) {
+			Ok((reader, writer)) => {
+				unsafe {
+					*fds = reader as i32;
+					*fds.add(1) = writer as i32;
+				}
+				0
+			}
+			Err(err) => -err.errno(),
+		}
+	}
+
+	/// Waits until one of the files in `fds` is ready or `timeout` (in ms) has passed. A negative timeout waits forever.
+	/// Files, which do not support polling (like regular files), are always ready for reading and writing.
+	/// Sockets of the network stack are not open files of the kernel and their readiness is unknown,
+	/// so polling them fails with EINVAL.
+	fn poll(&self, fds: *mut PollFd, nfds: usize, timeout: i32) -> i32 {
+		debug!("poll {} {}", nfds, timeout);
+		if nfds > fs::FILESYSTEM.lock().get_nofile_limit().0 as usize {
+			return -EINVAL;
+		} else if fds.is_null() && nfds > 0 {
+			return -EFAULT;
+		}
+		let fds = if nfds > 0 {
+			unsafe { slice::from_raw_parts_mut(fds, nfds) }
 		} else {
-			-1
+			&mut []
+		};
+
+		if fds.iter().any(|pollfd| is_socket(pollfd.fd)) {
+			return -EINVAL;
+		}
+
+		// negative fds are ignored, fds, which are not open, are reported as invalid
+		let files: Vec<_> = fds
+			.iter()
+			.map(|pollfd| (pollfd.fd >= 0).then(|| get_file(pollfd.fd)))
+			.collect();
+		// The waiter is registered at all files in the first round. Its registrations are removed,
+		// when it is dropped on return.
+		let waiter = Waiter::new();
+		let deadline = poll::deadline(timeout);
+		let mut notified: Vec<u64> = (0..fds.len() as u64).collect();
+
+		loop {
+			let mut ready = 0;
+			for (key, (pollfd, file)) in fds.iter_mut().zip(files.iter()).enumerate() {
+				let key = key as u64;
+				// notified files may have to renew the time, at which they notify the waiter
+				let registration =
+					(timeout != 0 && notified.contains(&key)).then(|| Registration {
+						waiter: &waiter,
+						key,
+					});
+				let events = match file {
+					None => 0,
+					Some(Err(_)) => POLLNVAL,
+					Some(Ok(file)) => {
+						poll::poll_file(&**file, registration)
+							& (pollfd.events as u16 as u32 | POLLERR | POLLHUP)
+					}
+				};
+				pollfd.revents = events as i16;
+				if events != 0 {
+					ready += 1;
+				}
+			}
+
+			if ready > 0 || !waiter.wait(deadline) {
+				return ready;
+			}
+			notified = waiter.take_notified();
 		}
 	}
 
-	fn close(&self, fd: i32) -> i32 {
-		// we don't have to close standard descriptors
-		if fd < 3 {
-			return 0;
+	/// Creates an epoll instance. O_CLOEXEC is the only flag, it does not have any effect, since there is no exec.
+	fn epoll_create1(&self, flags: i32) -> i32 {
+		debug!("epoll_create1 {:#x}", flags);
+		if flags & !O_CLOEXEC != 0 {
+			return -EINVAL;
 		}
 
-		let mut fs = fs::FILESYSTEM.lock();
-		fs.close(fd as u64);
-		0
+		match fs::FILESYSTEM.lock().epoll_create() {
+			Ok(fd) => fd as i32,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	/// Creates an epoll instance. `size` is only checked for compatibility, the number of files is not limited.
+	fn epoll_create(&self, size: i32) -> i32 {
+		if size <= 0 {
+			return -EINVAL;
+		}
+		self.epoll_create1(0)
+	}
+
+	/// Adds, modifies or removes the file `fd` in the interest list of the epoll instance `epfd`.
+	/// Files, which do not support polling, cannot be added and fail with EPERM, like regular files on Linux.
+	/// Sockets of the network stack are not open files of the kernel and their readiness is unknown,
+	/// so they fail with EPERM as well.
+	fn epoll_ctl(&self, epfd: i32, op: i32, fd: i32, event: *const EpollEvent) -> i32 {
+		debug!("epoll_ctl {} {} {}", epfd, op, fd);
+		if is_socket(fd) {
+			return -EPERM;
+		}
+
+		let ret = get_file(epfd).and_then(|epoll_file| {
+			let file = get_file(fd)?;
+			let epoll = epoll_file.as_epoll().ok_or(FileError::EINVAL())?;
+			if epfd == fd {
+				return Err(FileError::EINVAL());
+			}
+
+			match op {
+				EPOLL_CTL_DEL => epoll.delete(fd),
+				EPOLL_CTL_ADD | EPOLL_CTL_MOD => {
+					if event.is_null() {
+						return Err(FileError::EFAULT());
+					}
+					let event = unsafe { &*event };
+					if op == EPOLL_CTL_ADD {
+						epoll.add(fd, &file, event)
+					} else {
+						epoll.modify(fd, event)
+					}
+				}
+				_ => Err(FileError::EINVAL()),
+			}
+		});
+
+		match ret {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	/// Waits until files of the epoll instance `epfd` are ready or `timeout` (in ms) has passed
+	/// and stores up to `maxevents` events in `events`. A negative timeout waits forever.
+	fn epoll_wait(&self, epfd: i32, events: *mut EpollEvent, maxevents: i32, timeout: i32) -> i32 {
+		debug!("epoll_wait {} {} {}", epfd, maxevents, timeout);
+		if maxevents <= 0 {
+			return -EINVAL;
+		} else if events.is_null() {
+			return -EFAULT;
+		}
+
+		let epoll_file = match get_file(epfd) {
+			Ok(file) => file,
+			Err(err) => return -err.errno(),
+		};
+		let epoll = match epoll_file.as_epoll() {
+			Some(epoll) => epoll,
+			None => return -EINVAL,
+		};
+
+		let ready = epoll.wait(maxevents as usize, poll::deadline(timeout));
+		let events = unsafe { slice::from_raw_parts_mut(events, ready.len()) };
+		events.copy_from_slice(&ready);
+		ready.len() as i32
+	}
+
+	/// Creates an event counter with the initial value `initval`.
+	fn eventfd(&self, initval: u32, flags: i32) -> i32 {
+		debug!("eventfd {} {:#x}", initval, flags);
+		if flags & !(EFD_SEMAPHORE | O_NONBLOCK | O_CLOEXEC) != 0 {
+			return -EINVAL;
+		}
+
+		match fs::FILESYSTEM.lock().eventfd(
+			initval,
+			flags & EFD_SEMAPHORE != 0,
+			flags & O_NONBLOCK != 0,
+		) {
+			Ok(fd) => fd as i32,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	/// Creates a disarmed timer. TFD_NONBLOCK and TFD_CLOEXEC equal O_NONBLOCK and O_CLOEXEC.
+	fn timerfd_create(&self, clock_id: u64, flags: i32) -> i32 {
+		debug!("timerfd_create {} {:#x}", clock_id, flags);
+		if clock_id != CLOCK_REALTIME && clock_id != CLOCK_MONOTONIC
+			|| flags & !(O_NONBLOCK | O_CLOEXEC) != 0
+		{
+			return -EINVAL;
+		}
+
+		match fs::FILESYSTEM
+			.lock()
+			.timerfd_create(clock_id, flags & O_NONBLOCK != 0)
+		{
+			Ok(fd) => fd as i32,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	/// Arms or disarms the timer `fd` and stores its previous setting in `old_value`, if it is given.
+	/// The realtime clock cannot be set, so TFD_TIMER_CANCEL_ON_SET does not have any effect.
+	fn timerfd_settime(
+		&self,
+		fd: i32,
+		flags: i32,
+		new_value: *const ItimerSpec,
+		old_value: *mut ItimerSpec,
+	) -> i32 {
+		debug!("timerfd_settime {} {:#x}", fd, flags);
+		if flags & !(TFD_TIMER_ABSTIME | TFD_TIMER_CANCEL_ON_SET) != 0 {
+			return -EINVAL;
+		}
+		let new_value = match unsafe { new_value.as_ref() } {
+			Some(new_value) => new_value,
+			None => return -EFAULT,
+		};
+
+		let ret = get_file(fd).and_then(|file| {
+			let timer = file.as_timerfd().ok_or(FileError::EINVAL())?;
+			let value = timespec_to_micros(&new_value.it_value)?;
+			let interval = timespec_to_micros(&new_value.it_interval)?;
+
+			let expiry = if value == 0 {
+				None
+			} else if flags & TFD_TIMER_ABSTIME != 0 {
+				// the timer ticks count from the boot time
+				let boot_time = if timer.clock() == CLOCK_REALTIME {
+					arch::get_boot_time()
+				} else {
+					0
+				};
+				Some(value.saturating_sub(boot_time))
+			} else {
+				Some(arch::processor::get_timer_ticks() + value)
+			};
+			Ok(timer.set(expiry, interval))
+		});
+
+		match ret {
+			Ok(old) => {
+				write_itimerspec(old, old_value);
+				0
+			}
+			Err(err) => -err.errno(),
+		}
+	}
+
+	/// Stores the time until the next expiration of the timer `fd` and its interval in `curr_value`.
+	fn timerfd_gettime(&self, fd: i32, curr_value: *mut ItimerSpec) -> i32 {
+		debug!("timerfd_gettime {}", fd);
+		if curr_value.is_null() {
+			return -EFAULT;
+		}
+
+		match get_file(fd).and_then(|file| {
+			file.as_timerfd()
+				.map(|timer| timer.get())
+				.ok_or(FileError::EINVAL())
+		}) {
+			Ok(setting) => {
+				write_itimerspec(setting, curr_value);
+				0
+			}
+			Err(err) => -err.errno(),
+		}
 	}
 
 	#[cfg(not(target_arch = "x86_64"))]
@@ -254,56 +956,458 @@ pub trait SyscallInterface: Send + Sync {
 	#[cfg(target_arch = "x86_64")]
 	fn read(&self, fd: i32, buf: *mut u8, len: usize) -> isize {
 		debug!("Read! {}, {}", fd, len);
+		read_file(fd, buf, len)
+	}
 
-		let mut fs = fs::FILESYSTEM.lock();
-		let mut read_bytes = 0;
-		fs.fd_op(fd as u64, |file: &mut Box<dyn PosixFile + Send>| {
-			let dat = file.read(len as u32).unwrap(); // TODO: might fail
+	fn write(&self, fd: i32, buf: *const u8, len: usize) -> isize {
+		write_file(fd, buf, len)
+	}
 
-			read_bytes = dat.len();
-			unsafe {
-				core::slice::from_raw_parts_mut(buf, read_bytes).copy_from_slice(&dat);
-			}
-		});
+	fn pread(&self, fd: i32, buf: *mut u8, len: usize, offset: i64) -> isize {
+		debug!("pread! {}, {}, {}", fd, len, offset);
 
-		read_bytes as isize
+		if len > isize::MAX as usize || offset < 0 {
+			return -EINVAL as isize;
+		}
+		let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+
+		match get_file(fd).and_then(|file| file.pread(buf, offset as u64)) {
+			Ok(read_bytes) => read_bytes as isize,
+			Err(err) => -err.errno() as isize,
+		}
 	}
 
-	fn write(&self, fd: i32, buf: *const u8, len: usize) -> isize {
-		assert!(len <= isize::MAX as usize);
+	fn pwrite(&self, fd: i32, buf: *const u8, len: usize, offset: i64) -> isize {
+		debug!("pwrite! {}, {}, {}", fd, len, offset);
+
+		if len > isize::MAX as usize || offset < 0 {
+			return -EINVAL as isize;
+		}
 		let buf = unsafe { slice::from_raw_parts(buf, len) };
 
-		if fd > 2 {
-			// Normal file
-			let mut written_bytes = 0;
-			let mut fs = fs::FILESYSTEM.lock();
-			fs.fd_op(fd as u64, |file: &mut Box<dyn PosixFile + Send>| {
-				written_bytes = file.write(buf).unwrap(); // TODO: might fail
-			});
-			debug!("Write done! {}", written_bytes);
-			written_bytes as isize
-		} else {
-			// stdin/err/out all go to console
-			CONSOLE.lock().write_all(buf);
+		match get_file(fd).and_then(|file| file.pwrite(buf, offset as u64)) {
+			Ok(written_bytes) => written_bytes as isize,
+			Err(err) => -err.errno() as isize,
+		}
+	}
+
+	/// Reads into the buffers described by `iov` one after another, until a read returns less than requested.
+	/// If an error occurs after some data has been read, the number of bytes read so far is returned.
+	fn readv(&self, fd: i32, iov: *const IoVec, iovcnt: i32) -> isize {
+		let iov = match iovec_from_ptr(iov, iovcnt) {
+			Ok(iov) => iov,
+			Err(err) => return -err.errno() as isize,
+		};
 
-			len as isize
+		let mut total = 0;
+		for vec in iov {
+			let ret = self.read(fd, vec.iov_base, vec.iov_len);
+			if ret < 0 {
+				return if total > 0 { total } else { ret };
+			}
+			total += ret;
+			if (ret as usize) < vec.iov_len {
+				break;
+			}
 		}
+		total
+	}
+
+	/// Writes the buffers described by `iov` one after another, until a write is short.
+	/// If an error occurs after some data has been written, the number of bytes written so far is returned.
+	fn writev(&self, fd: i32, iov: *const IoVec, iovcnt: i32) -> isize {
+		let iov = match iovec_from_ptr(iov, iovcnt) {
+			Ok(iov) => iov,
+			Err(err) => return -err.errno() as isize,
+		};
+
+		let mut total = 0;
+		for vec in iov {
+			let ret = self.write(fd, vec.iov_base, vec.iov_len);
+			if ret < 0 {
+				return if total > 0 { total } else { ret };
+			}
+			total += ret;
+			if (ret as usize) < vec.iov_len {
+				break;
+			}
+		}
+		total
 	}
 
 	fn lseek(&self, fd: i32, offset: isize, whence: i32) -> isize {
 		debug!("lseek! {}, {}, {}", fd, offset, whence);
 
-		let mut fs = fs::FILESYSTEM.lock();
-		let mut ret = 0;
-		fs.fd_op(fd as u64, |file: &mut Box<dyn PosixFile + Send>| {
-			ret = file.lseek(offset, whence.try_into().unwrap()).unwrap(); // TODO: might fail
+		let whence = match whence.try_into() {
+			Ok(whence) => whence,
+			Err(_) => return -EINVAL as isize,
+		};
+		match get_file(fd).and_then(|file| file.lseek(offset, whence)) {
+			Ok(offset) => offset as isize,
+			Err(err) => -err.errno() as isize,
+		}
+	}
+
+	fn ftruncate(&self, fd: i32, len: i64) -> i32 {
+		debug!("ftruncate {} {}", fd, len);
+		if len < 0 {
+			return -EINVAL;
+		}
+
+		match get_file(fd).and_then(|file| file.ftruncate(len as u64)) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn fsync(&self, fd: i32) -> i32 {
+		debug!("fsync {}", fd);
+
+		match get_file(fd).and_then(|file| file.fsync(false)) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn fdatasync(&self, fd: i32) -> i32 {
+		debug!("fdatasync {}", fd);
+
+		match get_file(fd).and_then(|file| file.fsync(true)) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn fallocate(&self, fd: i32, mode: i32, offset: i64, len: i64) -> i32 {
+		debug!("fallocate {} {:#x} {} {}", fd, mode, offset, len);
+		let mode = mode as u32;
+		if offset < 0 || len <= 0 {
+			return -EINVAL;
+		} else if offset.checked_add(len).is_none() {
+			return -EFBIG;
+		} else if mode & FALLOC_FL_PUNCH_HOLE != 0
+			&& (mode & FALLOC_FL_KEEP_SIZE == 0 || mode & FALLOC_FL_ZERO_RANGE != 0)
+		{
+			// a hole never changes the size of the file and cannot be combined with zeroing
+			return -EOPNOTSUPP;
+		}
+
+		match get_file(fd).and_then(|file| file.fallocate(mode, offset as u64, len as u64)) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn stat(&self, file: *const u8, st: *mut Stat) -> i32 {
+		let file = match path_from_ptr(file) {
+			Ok(file) => file,
+			Err(err) => return -err.errno(),
+		};
+		debug!("stat {}", file);
+
+		write_stat(fs::stat(file), st)
+	}
+
+	fn lstat(&self, file: *const u8, st: *mut Stat) -> i32 {
+		let file = match path_from_ptr(file) {
+			Ok(file) => file,
+			Err(err) => return -err.errno(),
+		};
+		debug!("lstat {}", file);
+
+		write_stat(fs::lstat(file), st)
+	}
+
+	fn fstat(&self, fd: i32, st: *mut Stat) -> i32 {
+		debug!("fstat {}", fd);
+
+		write_stat(get_file(fd).and_then(|file| file.fstat()), st)
+	}
+
+	/// Only the commands, which operate on record locks, are supported. Classic record locks are shared by all tasks,
+	/// since they belong to the process. Locks of the F_OFD_* commands belong to the open file instead.
+	fn fcntl(&self, fd: i32, cmd: i32, arg: usize) -> i32 {
+		debug!("fcntl {} {} {:#x}", fd, cmd, arg);
+
+		let ofd = match cmd {
+			F_GETLK | F_SETLK | F_SETLKW => false,
+			F_OFD_GETLK | F_OFD_SETLK | F_OFD_SETLKW => true,
+			_ => return -EINVAL,
+		};
+		let fl = arg as *mut Flock;
+		if fl.is_null() {
+			return -EFAULT;
+		}
+		let fl = unsafe { &mut *fl };
+		if ofd && fl.l_pid != 0 {
+			return -EINVAL;
+		}
+
+		let file = match get_file(fd) {
+			Ok(file) => file,
+			Err(err) => return -err.errno(),
+		};
+		let owner = if ofd {
+			file.lock_owner()
+		} else {
+			lock::PROCESS_LOCK_OWNER
+		};
+		let ret = lock_from_flock(&*file, fl, owner).and_then(|lock| match cmd {
+			F_GETLK | F_OFD_GETLK => file.getlk(&lock).map(|conflict| write_flock(conflict, fl)),
+			_ => file.setlk(&lock, cmd == F_SETLKW || cmd == F_OFD_SETLKW),
 		});
 
-		ret as isize
+		match ret {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
 	}
 
-	fn stat(&self, _file: *const u8, _st: usize) -> i32 {
-		info!("stat is unimplemented");
-		-ENOSYS
+	fn flock(&self, fd: i32, operation: i32) -> i32 {
+		debug!("flock {} {:#x}", fd, operation);
+
+		let typ = match operation & !LOCK_NB {
+			LOCK_SH => LockType::Read,
+			LOCK_EX => LockType::Write,
+			LOCK_UN => LockType::Unlock,
+			_ => return -EINVAL,
+		};
+
+		match get_file(fd).and_then(|file| file.flock(typ, operation & LOCK_NB == 0)) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn mkdir(&self, name: *const u8, mode: u32) -> i32 {
+		let name = match path_from_ptr(name) {
+			Ok(name) => name,
+			Err(err) => return -err.errno(),
+		};
+		debug!("mkdir {}, {:o}", name, mode);
+
+		match fs::mkdir(name, mode & 0o7777) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn rmdir(&self, name: *const u8) -> i32 {
+		let name = match path_from_ptr(name) {
+			Ok(name) => name,
+			Err(err) => return -err.errno(),
+		};
+		debug!("rmdir {}", name);
+
+		match fs::rmdir(name) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn opendir(&self, name: *const u8) -> i32 {
+		let name = match path_from_ptr(name) {
+			Ok(name) => name,
+			Err(err) => return -err.errno(),
+		};
+		debug!("opendir {}", name);
+
+		match fs::opendir(name) {
+			Ok(fd) => fd as i32,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn getdents64(&self, fd: i32, dirp: *mut u8, count: usize) -> i64 {
+		debug!("getdents64! {}, {}", fd, count);
+		if dirp.is_null() {
+			return -i64::from(EFAULT);
+		}
+
+		let buf = unsafe { slice::from_raw_parts_mut(dirp, count) };
+		let file = match get_file(fd) {
+			Ok(file) => file,
+			Err(err) => return -i64::from(err.errno()),
+		};
+
+		// remember the position of the batch, in case that no entry fits into the buffer
+		let start = file.lseek(0, SeekWhence::Cur).unwrap_or(0);
+		let entries = match file.readdir() {
+			Ok(entries) => entries,
+			Err(err) => return -i64::from(err.errno()),
+		};
+
+		let (consumed, written) = write_dirents(&entries, buf);
+
+		// rewind the directory to the first entry, which did not fit into the buffer
+		if consumed < entries.len() {
+			let position = if consumed > 0 {
+				entries[consumed - 1].offset as usize
+			} else {
+				start
+			};
+			let _ = file.lseek(position as isize, SeekWhence::Set);
+		}
+
+		if consumed == 0 && !entries.is_empty() {
+			// not even a single entry fits into the buffer
+			-i64::from(EINVAL)
+		} else {
+			written as i64
+		}
+	}
+
+	fn rename(&self, oldpath: *const u8, newpath: *const u8) -> i32 {
+		let oldpath = match path_from_ptr(oldpath) {
+			Ok(oldpath) => oldpath,
+			Err(err) => return -err.errno(),
+		};
+		let newpath = match path_from_ptr(newpath) {
+			Ok(newpath) => newpath,
+			Err(err) => return -err.errno(),
+		};
+		debug!("rename {} {}", oldpath, newpath);
+
+		match fs::rename(oldpath, newpath) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn link(&self, oldpath: *const u8, newpath: *const u8) -> i32 {
+		let oldpath = match path_from_ptr(oldpath) {
+			Ok(oldpath) => oldpath,
+			Err(err) => return -err.errno(),
+		};
+		let newpath = match path_from_ptr(newpath) {
+			Ok(newpath) => newpath,
+			Err(err) => return -err.errno(),
+		};
+		debug!("link {} {}", oldpath, newpath);
+
+		match fs::link(oldpath, newpath) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn symlink(&self, target: *const u8, linkpath: *const u8) -> i32 {
+		let target = match path_from_ptr(target) {
+			Ok(target) => target,
+			Err(err) => return -err.errno(),
+		};
+		let linkpath = match path_from_ptr(linkpath) {
+			Ok(linkpath) => linkpath,
+			Err(err) => return -err.errno(),
+		};
+		debug!("symlink {} {}", target, linkpath);
+
+		match fs::symlink(target, linkpath) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	fn readlink(&self, path: *const u8, buf: *mut u8, bufsiz: usize) -> isize {
+		let path = match path_from_ptr(path) {
+			Ok(path) => path,
+			Err(err) => return -err.errno() as isize,
+		};
+		debug!("readlink {}", path);
+
+		match fs::readlink(path) {
+			Ok(target) => {
+				// like Linux, we silently truncate the target and do not append a NUL byte
+				let len = target.len().min(bufsiz);
+				let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+				buf.copy_from_slice(&target.as_bytes()[..len]);
+				len as isize
+			}
+			Err(err) => -err.errno() as isize,
+		}
+	}
+
+	fn chdir(&self, path: *const u8) -> i32 {
+		let path = match path_from_ptr(path) {
+			Ok(path) => path,
+			Err(err) => return -err.errno(),
+		};
+		debug!("chdir {}", path);
+
+		match fs::chdir(path) {
+			Ok(()) => 0,
+			Err(err) => -err.errno(),
+		}
+	}
+
+	/// Stores the current working directory in `buf` and returns its length including the
+	/// terminating NUL byte, like the getcwd system call of Linux. The C library returns `buf` then.
+	fn getcwd(&self, buf: *mut u8, size: usize) -> i32 {
+		debug!("getcwd {}", size);
+		let cwd = fs::getcwd();
+
+		// the buffer has to hold the path and the terminating NUL byte
+		if size == 0 {
+			return -EINVAL;
+		} else if buf.is_null() {
+			return -EFAULT;
+		} else if cwd.len() >= size {
+			return -ERANGE;
+		}
+
+		let buf = unsafe { slice::from_raw_parts_mut(buf, size) };
+		buf[..cwd.len()].copy_from_slice(cwd.as_bytes());
+		buf[cwd.len()] = 0;
+		(cwd.len() + 1) as i32
+	}
+
+	fn getrlimit(&self, resource: i32, rlim: *mut Rlimit) -> i32 {
+		debug!("getrlimit {}", resource);
+		if rlim.is_null() {
+			return -EFAULT;
+		}
+
+		let limit = match resource {
+			RLIMIT_NOFILE => {
+				let (cur, max) = fs::FILESYSTEM.lock().get_nofile_limit();
+				Rlimit {
+					rlim_cur: cur,
+					rlim_max: max,
+				}
+			}
+			// all other resources are not limited
+			_ if (0..RLIM_NLIMITS).contains(&resource) => Rlimit {
+				rlim_cur: RLIM_INFINITY,
+				rlim_max: RLIM_INFINITY,
+			},
+			_ => return -EINVAL,
+		};
+
+		unsafe {
+			*rlim = limit;
+		}
+		0
+	}
+
+	fn setrlimit(&self, resource: i32, rlim: *const Rlimit) -> i32 {
+		debug!("setrlimit {}", resource);
+		if rlim.is_null() {
+			return -EFAULT;
+		}
+
+		let limit = unsafe { *rlim };
+		match resource {
+			RLIMIT_NOFILE => {
+				match fs::FILESYSTEM
+					.lock()
+					.set_nofile_limit(limit.rlim_cur, limit.rlim_max)
+				{
+					Ok(()) => 0,
+					Err(err) => -err.errno(),
+				}
+			}
+			_ if (0..RLIM_NLIMITS).contains(&resource) => -ENOSYS,
+			_ => -EINVAL,
+		}
 	}
 }
@@ -7,21 +7,15 @@ use x86::io::*;
 use crate::arch;
 use crate::arch::mm::paging;
 use crate::arch::mm::{PhysAddr, VirtAddr};
-use crate::syscalls::interfaces::SyscallInterface;
+use crate::syscalls::interfaces::{read_file, write_file, SyscallInterface};
 #[cfg(feature = "newlib")]
 use crate::syscalls::lwip::sys_lwip_get_errno;
 #[cfg(feature = "newlib")]
 use crate::syscalls::{LWIP_FD_BIT, LWIP_LOCK};
 
-const UHYVE_PORT_WRITE: u16 = 0x400;
-const UHYVE_PORT_OPEN: u16 = 0x440;
-const UHYVE_PORT_CLOSE: u16 = 0x480;
-const UHYVE_PORT_READ: u16 = 0x500;
 const UHYVE_PORT_EXIT: u16 = 0x540;
-const UHYVE_PORT_LSEEK: u16 = 0x580;
 const UHYVE_PORT_CMDSIZE: u16 = 0x740;
 const UHYVE_PORT_CMDVAL: u16 = 0x780;
-const UHYVE_PORT_UNLINK: u16 = 0x840;
 
 #[cfg(feature = "newlib")]
 extern "C" {
@@ -31,7 +25,7 @@ extern "C" {
 
 /// forward a request to the hypervisor uhyve
 #[inline]
-fn uhyve_send<T>(port: u16, data: &mut T) {
+pub(crate) fn uhyve_send<T>(port: u16, data: &mut T) {
 	let ptr = VirtAddr(data as *mut _ as u64);
 	let physical_address = paging::virtual_to_physical(ptr);
 
@@ -88,121 +82,9 @@ impl SysExit {
 	}
 }
 
-#[repr(C, packed)]
-struct SysUnlink {
-	name: PhysAddr,
-	ret: i32,
-}
-
-impl SysUnlink {
-	fn new(name: VirtAddr) -> SysUnlink {
-		SysUnlink {
-			name: paging::virtual_to_physical(name),
-			ret: -1,
-		}
-	}
-}
-
-#[repr(C, packed)]
-struct SysOpen {
-	name: PhysAddr,
-	flags: i32,
-	mode: i32,
-	ret: i32,
-}
-
-impl SysOpen {
-	fn new(name: VirtAddr, flags: i32, mode: i32) -> SysOpen {
-		SysOpen {
-			name: paging::virtual_to_physical(name),
-			flags,
-			mode,
-			ret: -1,
-		}
-	}
-}
-
-#[repr(C, packed)]
-struct SysClose {
-	fd: i32,
-	ret: i32,
-}
-
-impl SysClose {
-	fn new(fd: i32) -> SysClose {
-		SysClose { fd, ret: -1 }
-	}
-}
-
-#[repr(C, packed)]
-struct SysRead {
-	fd: i32,
-	buf: *const u8,
-	len: usize,
-	ret: isize,
-}
-
-impl SysRead {
-	fn new(fd: i32, buf: *const u8, len: usize) -> SysRead {
-		SysRead {
-			fd,
-			buf,
-			len,
-			ret: -1,
-		}
-	}
-}
-
-#[repr(C, packed)]
-struct SysWrite {
-	fd: i32,
-	buf: *const u8,
-	len: usize,
-}
-
-impl SysWrite {
-	fn new(fd: i32, buf: *const u8, len: usize) -> SysWrite {
-		SysWrite { fd, buf, len }
-	}
-}
-
-#[repr(C, packed)]
-struct SysLseek {
-	fd: i32,
-	offset: isize,
-	whence: i32,
-}
-
-impl SysLseek {
-	fn new(fd: i32, offset: isize, whence: i32) -> SysLseek {
-		SysLseek { fd, offset, whence }
-	}
-}
-
 pub struct Uhyve;
 
 impl SyscallInterface for Uhyve {
-	fn open(&self, name: *const u8, flags: i32, mode: i32) -> i32 {
-		let mut sysopen = SysOpen::new(VirtAddr(name as u64), flags, mode);
-		uhyve_send(UHYVE_PORT_OPEN, &mut sysopen);
-
-		sysopen.ret
-	}
-
-	fn unlink(&self, name: *const u8) -> i32 {
-		let mut sysunlink = SysUnlink::new(VirtAddr(name as u64));
-		uhyve_send(UHYVE_PORT_UNLINK, &mut sysunlink);
-
-		sysunlink.ret
-	}
-
-	fn close(&self, fd: i32) -> i32 {
-		let mut sysclose = SysClose::new(fd);
-		uhyve_send(UHYVE_PORT_CLOSE, &mut sysclose);
-
-		sysclose.ret
-	}
-
 	/// ToDo: This function needs a description - also applies to trait in src/syscalls/interfaces/mod.rs
 	///
 	/// ToDo: Add Safety section under which circumctances this is safe/unsafe to use
@@ -282,10 +164,7 @@ impl SyscallInterface for Uhyve {
 			}
 		}
 
-		let mut sysread = SysRead::new(fd, buf, len);
-		uhyve_send(UHYVE_PORT_READ, &mut sysread);
-
-		sysread.ret
+		read_file(fd, buf, len)
 	}
 
 	fn write(&self, fd: i32, buf: *const u8, len: usize) -> isize {
@@ -308,16 +187,6 @@ impl SyscallInterface for Uhyve {
 			}
 		}
 
-		let mut syswrite = SysWrite::new(fd, buf, len);
-		uhyve_send(UHYVE_PORT_WRITE, &mut syswrite);
-
-		syswrite.len as isize
-	}
-
-	fn lseek(&self, fd: i32, offset: isize, whence: i32) -> isize {
-		let mut syslseek = SysLseek::new(fd, offset, whence);
-		uhyve_send(UHYVE_PORT_LSEEK, &mut syslseek);
-
-		syslseek.offset
+		write_file(fd, buf, len)
 	}
 }
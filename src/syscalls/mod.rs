@@ -5,7 +5,8 @@ use crate::drivers::net::*;
 use crate::environment;
 #[cfg(feature = "newlib")]
 use crate::synch::spinlock::SpinlockIrqSave;
-use crate::syscalls::interfaces::SyscallInterface;
+use crate::syscalls::fs::EpollEvent;
+use crate::syscalls::interfaces::{IoVec, ItimerSpec, PollFd, Rlimit, Stat, SyscallInterface};
 #[cfg(any(target_os = "hermit", target_os = "none"))]
 use crate::{__sys_free, __sys_malloc, __sys_realloc};
 
@@ -56,6 +57,8 @@ pub fn init() {
 		SYS.init();
 	}
 
+	fs::init();
+
 	random_init();
 	#[cfg(feature = "newlib")]
 	sbrk_init();
@@ -206,6 +209,148 @@ pub extern "C" fn sys_close(fd: i32) -> i32 {
 	kernel_function!(__sys_close(fd))
 }
 
+extern "C" fn __sys_dup(fd: i32) -> i32 {
+	unsafe { SYS.dup(fd) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_dup(fd: i32) -> i32 {
+	kernel_function!(__sys_dup(fd))
+}
+
+extern "C" fn __sys_dup2(oldfd: i32, newfd: i32) -> i32 {
+	unsafe { SYS.dup2(oldfd, newfd) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_dup2(oldfd: i32, newfd: i32) -> i32 {
+	kernel_function!(__sys_dup2(oldfd, newfd))
+}
+
+extern "C" fn __sys_dup3(oldfd: i32, newfd: i32, flags: i32) -> i32 {
+	unsafe { SYS.dup3(oldfd, newfd, flags) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_dup3(oldfd: i32, newfd: i32, flags: i32) -> i32 {
+	kernel_function!(__sys_dup3(oldfd, newfd, flags))
+}
+
+extern "C" fn __sys_pipe2(fds: *mut i32, flags: i32) -> i32 {
+	unsafe { SYS.pipe2(fds, flags) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_pipe2(fds: *mut i32, flags: i32) -> i32 {
+	kernel_function!(__sys_pipe2(fds, flags))
+}
+
+#[no_mangle]
+pub extern "C" fn sys_pipe(fds: *mut i32) -> i32 {
+	kernel_function!(__sys_pipe2(fds, 0))
+}
+
+extern "C" fn __sys_poll(fds: *mut PollFd, nfds: usize, timeout: i32) -> i32 {
+	unsafe { SYS.poll(fds, nfds, timeout) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_poll(fds: *mut PollFd, nfds: usize, timeout: i32) -> i32 {
+	kernel_function!(__sys_poll(fds, nfds, timeout))
+}
+
+extern "C" fn __sys_epoll_create1(flags: i32) -> i32 {
+	unsafe { SYS.epoll_create1(flags) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_epoll_create1(flags: i32) -> i32 {
+	kernel_function!(__sys_epoll_create1(flags))
+}
+
+extern "C" fn __sys_epoll_create(size: i32) -> i32 {
+	unsafe { SYS.epoll_create(size) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_epoll_create(size: i32) -> i32 {
+	kernel_function!(__sys_epoll_create(size))
+}
+
+extern "C" fn __sys_epoll_ctl(epfd: i32, op: i32, fd: i32, event: *const EpollEvent) -> i32 {
+	unsafe { SYS.epoll_ctl(epfd, op, fd, event) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_epoll_ctl(epfd: i32, op: i32, fd: i32, event: *const EpollEvent) -> i32 {
+	kernel_function!(__sys_epoll_ctl(epfd, op, fd, event))
+}
+
+extern "C" fn __sys_epoll_wait(
+	epfd: i32,
+	events: *mut EpollEvent,
+	maxevents: i32,
+	timeout: i32,
+) -> i32 {
+	unsafe { SYS.epoll_wait(epfd, events, maxevents, timeout) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_epoll_wait(
+	epfd: i32,
+	events: *mut EpollEvent,
+	maxevents: i32,
+	timeout: i32,
+) -> i32 {
+	kernel_function!(__sys_epoll_wait(epfd, events, maxevents, timeout))
+}
+
+extern "C" fn __sys_eventfd(initval: u32, flags: i32) -> i32 {
+	unsafe { SYS.eventfd(initval, flags) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_eventfd(initval: u32, flags: i32) -> i32 {
+	kernel_function!(__sys_eventfd(initval, flags))
+}
+
+extern "C" fn __sys_timerfd_create(clock_id: u64, flags: i32) -> i32 {
+	unsafe { SYS.timerfd_create(clock_id, flags) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_timerfd_create(clock_id: u64, flags: i32) -> i32 {
+	kernel_function!(__sys_timerfd_create(clock_id, flags))
+}
+
+extern "C" fn __sys_timerfd_settime(
+	fd: i32,
+	flags: i32,
+	new_value: *const ItimerSpec,
+	old_value: *mut ItimerSpec,
+) -> i32 {
+	unsafe { SYS.timerfd_settime(fd, flags, new_value, old_value) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_timerfd_settime(
+	fd: i32,
+	flags: i32,
+	new_value: *const ItimerSpec,
+	old_value: *mut ItimerSpec,
+) -> i32 {
+	kernel_function!(__sys_timerfd_settime(fd, flags, new_value, old_value))
+}
+
+extern "C" fn __sys_timerfd_gettime(fd: i32, curr_value: *mut ItimerSpec) -> i32 {
+	unsafe { SYS.timerfd_gettime(fd, curr_value) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_timerfd_gettime(fd: i32, curr_value: *mut ItimerSpec) -> i32 {
+	kernel_function!(__sys_timerfd_gettime(fd, curr_value))
+}
+
 extern "C" fn __sys_read(fd: i32, buf: *mut u8, len: usize) -> isize {
 	unsafe { SYS.read(fd, buf, len) }
 }
@@ -223,6 +368,42 @@ pub extern "C" fn sys_write(fd: i32, buf: *const u8, len: usize) -> isize {
 	kernel_function!(__sys_write(fd, buf, len))
 }
 
+extern "C" fn __sys_pread(fd: i32, buf: *mut u8, len: usize, offset: i64) -> isize {
+	unsafe { SYS.pread(fd, buf, len, offset) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_pread(fd: i32, buf: *mut u8, len: usize, offset: i64) -> isize {
+	kernel_function!(__sys_pread(fd, buf, len, offset))
+}
+
+extern "C" fn __sys_pwrite(fd: i32, buf: *const u8, len: usize, offset: i64) -> isize {
+	unsafe { SYS.pwrite(fd, buf, len, offset) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_pwrite(fd: i32, buf: *const u8, len: usize, offset: i64) -> isize {
+	kernel_function!(__sys_pwrite(fd, buf, len, offset))
+}
+
+extern "C" fn __sys_readv(fd: i32, iov: *const IoVec, iovcnt: i32) -> isize {
+	unsafe { SYS.readv(fd, iov, iovcnt) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_readv(fd: i32, iov: *const IoVec, iovcnt: i32) -> isize {
+	kernel_function!(__sys_readv(fd, iov, iovcnt))
+}
+
+extern "C" fn __sys_writev(fd: i32, iov: *const IoVec, iovcnt: i32) -> isize {
+	unsafe { SYS.writev(fd, iov, iovcnt) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_writev(fd: i32, iov: *const IoVec, iovcnt: i32) -> isize {
+	kernel_function!(__sys_writev(fd, iov, iovcnt))
+}
+
 extern "C" fn __sys_lseek(fd: i32, offset: isize, whence: i32) -> isize {
 	unsafe { SYS.lseek(fd, offset, whence) }
 }
@@ -232,11 +413,191 @@ pub extern "C" fn sys_lseek(fd: i32, offset: isize, whence: i32) -> isize {
 	kernel_function!(__sys_lseek(fd, offset, whence))
 }
 
-extern "C" fn __sys_stat(file: *const u8, st: usize) -> i32 {
+extern "C" fn __sys_ftruncate(fd: i32, len: i64) -> i32 {
+	unsafe { SYS.ftruncate(fd, len) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_ftruncate(fd: i32, len: i64) -> i32 {
+	kernel_function!(__sys_ftruncate(fd, len))
+}
+
+extern "C" fn __sys_fsync(fd: i32) -> i32 {
+	unsafe { SYS.fsync(fd) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_fsync(fd: i32) -> i32 {
+	kernel_function!(__sys_fsync(fd))
+}
+
+extern "C" fn __sys_fdatasync(fd: i32) -> i32 {
+	unsafe { SYS.fdatasync(fd) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_fdatasync(fd: i32) -> i32 {
+	kernel_function!(__sys_fdatasync(fd))
+}
+
+extern "C" fn __sys_fallocate(fd: i32, mode: i32, offset: i64, len: i64) -> i32 {
+	unsafe { SYS.fallocate(fd, mode, offset, len) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_fallocate(fd: i32, mode: i32, offset: i64, len: i64) -> i32 {
+	kernel_function!(__sys_fallocate(fd, mode, offset, len))
+}
+
+extern "C" fn __sys_stat(file: *const u8, st: *mut Stat) -> i32 {
 	unsafe { SYS.stat(file, st) }
 }
 
 #[no_mangle]
-pub extern "C" fn sys_stat(file: *const u8, st: usize) -> i32 {
+pub extern "C" fn sys_stat(file: *const u8, st: *mut Stat) -> i32 {
 	kernel_function!(__sys_stat(file, st))
 }
+
+extern "C" fn __sys_lstat(file: *const u8, st: *mut Stat) -> i32 {
+	unsafe { SYS.lstat(file, st) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_lstat(file: *const u8, st: *mut Stat) -> i32 {
+	kernel_function!(__sys_lstat(file, st))
+}
+
+extern "C" fn __sys_fstat(fd: i32, st: *mut Stat) -> i32 {
+	unsafe { SYS.fstat(fd, st) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_fstat(fd: i32, st: *mut Stat) -> i32 {
+	kernel_function!(__sys_fstat(fd, st))
+}
+
+extern "C" fn __sys_fcntl(fd: i32, cmd: i32, arg: usize) -> i32 {
+	unsafe { SYS.fcntl(fd, cmd, arg) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_fcntl(fd: i32, cmd: i32, arg: usize) -> i32 {
+	kernel_function!(__sys_fcntl(fd, cmd, arg))
+}
+
+extern "C" fn __sys_flock(fd: i32, operation: i32) -> i32 {
+	unsafe { SYS.flock(fd, operation) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_flock(fd: i32, operation: i32) -> i32 {
+	kernel_function!(__sys_flock(fd, operation))
+}
+
+extern "C" fn __sys_mkdir(name: *const u8, mode: u32) -> i32 {
+	unsafe { SYS.mkdir(name, mode) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_mkdir(name: *const u8, mode: u32) -> i32 {
+	kernel_function!(__sys_mkdir(name, mode))
+}
+
+extern "C" fn __sys_rmdir(name: *const u8) -> i32 {
+	unsafe { SYS.rmdir(name) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_rmdir(name: *const u8) -> i32 {
+	kernel_function!(__sys_rmdir(name))
+}
+
+extern "C" fn __sys_opendir(name: *const u8) -> i32 {
+	unsafe { SYS.opendir(name) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_opendir(name: *const u8) -> i32 {
+	kernel_function!(__sys_opendir(name))
+}
+
+extern "C" fn __sys_getdents64(fd: i32, dirp: *mut u8, count: usize) -> i64 {
+	unsafe { SYS.getdents64(fd, dirp, count) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_getdents64(fd: i32, dirp: *mut u8, count: usize) -> i64 {
+	kernel_function!(__sys_getdents64(fd, dirp, count))
+}
+
+extern "C" fn __sys_rename(oldpath: *const u8, newpath: *const u8) -> i32 {
+	unsafe { SYS.rename(oldpath, newpath) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_rename(oldpath: *const u8, newpath: *const u8) -> i32 {
+	kernel_function!(__sys_rename(oldpath, newpath))
+}
+
+extern "C" fn __sys_link(oldpath: *const u8, newpath: *const u8) -> i32 {
+	unsafe { SYS.link(oldpath, newpath) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_link(oldpath: *const u8, newpath: *const u8) -> i32 {
+	kernel_function!(__sys_link(oldpath, newpath))
+}
+
+extern "C" fn __sys_symlink(target: *const u8, linkpath: *const u8) -> i32 {
+	unsafe { SYS.symlink(target, linkpath) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_symlink(target: *const u8, linkpath: *const u8) -> i32 {
+	kernel_function!(__sys_symlink(target, linkpath))
+}
+
+extern "C" fn __sys_readlink(path: *const u8, buf: *mut u8, bufsiz: usize) -> isize {
+	unsafe { SYS.readlink(path, buf, bufsiz) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_readlink(path: *const u8, buf: *mut u8, bufsiz: usize) -> isize {
+	kernel_function!(__sys_readlink(path, buf, bufsiz))
+}
+
+extern "C" fn __sys_chdir(path: *const u8) -> i32 {
+	unsafe { SYS.chdir(path) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_chdir(path: *const u8) -> i32 {
+	kernel_function!(__sys_chdir(path))
+}
+
+extern "C" fn __sys_getcwd(buf: *mut u8, size: usize) -> i32 {
+	unsafe { SYS.getcwd(buf, size) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_getcwd(buf: *mut u8, size: usize) -> i32 {
+	kernel_function!(__sys_getcwd(buf, size))
+}
+
+extern "C" fn __sys_getrlimit(resource: i32, rlim: *mut Rlimit) -> i32 {
+	unsafe { SYS.getrlimit(resource, rlim) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_getrlimit(resource: i32, rlim: *mut Rlimit) -> i32 {
+	kernel_function!(__sys_getrlimit(resource, rlim))
+}
+
+extern "C" fn __sys_setrlimit(resource: i32, rlim: *const Rlimit) -> i32 {
+	unsafe { SYS.setrlimit(resource, rlim) }
+}
+
+#[no_mangle]
+pub extern "C" fn sys_setrlimit(resource: i32, rlim: *const Rlimit) -> i32 {
+	kernel_function!(__sys_setrlimit(resource, rlim))
+}